@@ -0,0 +1,19 @@
+#[cfg(feature = "gemini")]
+use langchain_rust::embedding::{embedder_trait::Embedder, gemini::GeminiEmbedder};
+
+#[cfg(feature = "gemini")]
+#[tokio::main]
+async fn main() {
+    let gemini = GeminiEmbedder::default();
+
+    let embedding = gemini.embed_query("Why is the sky blue?").await.unwrap();
+
+    println!("{:?}", embedding);
+}
+
+#[cfg(not(feature = "gemini"))]
+fn main() {
+    println!("This example requires the 'gemini' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example embedding_gemini --features=gemini");
+}
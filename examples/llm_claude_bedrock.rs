@@ -0,0 +1,23 @@
+#[cfg(feature = "bedrock")]
+use langchain_rust::{
+    language_models::llm::LLM,
+    llm::{BedrockTransport, Claude},
+};
+
+// Needs AWS_REGION, AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY
+#[cfg(feature = "bedrock")]
+#[tokio::main]
+async fn main() {
+    let claude = Claude::default()
+        .with_model("anthropic.claude-3-5-sonnet-20240620-v1:0")
+        .with_transport(BedrockTransport::from_env().unwrap());
+    let response = claude.invoke("hola").await.unwrap();
+    println!("{}", response);
+}
+
+#[cfg(not(feature = "bedrock"))]
+fn main() {
+    println!("This example requires the 'bedrock' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example llm_claude_bedrock --features=bedrock");
+}
@@ -0,0 +1,20 @@
+#[cfg(feature = "gemini")]
+use langchain_rust::{
+    language_models::llm::LLM,
+    llm::{Gemini, GeminiModel},
+};
+
+#[cfg(feature = "gemini")]
+#[tokio::main]
+async fn main() {
+    let gemini = Gemini::default().with_model(GeminiModel::Gemini15Flash.to_string());
+    let response = gemini.invoke("hola").await.unwrap();
+    println!("{}", response);
+}
+
+#[cfg(not(feature = "gemini"))]
+fn main() {
+    println!("This example requires the 'gemini' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example llm_gemini --features=gemini");
+}
@@ -0,0 +1,26 @@
+#[cfg(feature = "gguf")]
+use langchain_rust::{
+    language_models::{llm::LLM, options::CallOptions},
+    llm::Gguf,
+};
+
+#[cfg(feature = "gguf")]
+#[tokio::main]
+async fn main() {
+    // A small instruct model, e.g. qwen2.5-0.5b-instruct-q4_k_m.gguf and its tokenizer.json
+    let model = std::env::args().nth(1).expect("path to a .gguf model");
+    let tokenizer = std::env::args().nth(2).expect("path to tokenizer.json");
+
+    let llm = Gguf::load(model, tokenizer)
+        .unwrap()
+        .with_options(CallOptions::new().with_max_tokens(128));
+    let response = llm.invoke("hola").await.unwrap();
+    println!("{}", response);
+}
+
+#[cfg(not(feature = "gguf"))]
+fn main() {
+    println!("This example requires the 'gguf' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example llm_gguf --features=gguf -- model.gguf tokenizer.json");
+}
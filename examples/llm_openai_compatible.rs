@@ -0,0 +1,29 @@
+use langchain_rust::{
+    language_models::llm::LLM,
+    llm::{AuthScheme, Capabilities, OpenAICompatible},
+};
+
+#[tokio::main]
+async fn main() {
+    // Presets exist for Groq, Together, OpenRouter, vLLM, llama.cpp and LM Studio.
+    // Requires GROQ_API_KEY environment variable to be set
+    let groq = OpenAICompatible::groq().with_model("llama-3.3-70b-versatile");
+    let response = groq.invoke("Why is the sky blue?").await.unwrap();
+    println!("Groq: {}", response);
+
+    // Any other OpenAI compatible server can be described by hand
+    let local = OpenAICompatible::new("My server", "http://localhost:8080/v1")
+        .with_auth(AuthScheme::Header {
+            name: "api-key".to_string(),
+            value: "secret".to_string(),
+        })
+        .with_model("small")
+        .with_model_name("small", "qwen2.5-7b-instruct")
+        .with_capabilities(
+            Capabilities::new()
+                .with_tools(false)
+                .with_reasoning_field("reasoning_content"),
+        );
+    let response = local.invoke("Why is the sky blue?").await.unwrap();
+    println!("Local: {}", response);
+}
@@ -0,0 +1,44 @@
+#[cfg(feature = "schemars")]
+use langchain_rust::{
+    language_models::structured::StructuredOutput, llm::Claude, schemas::Message,
+};
+#[cfg(feature = "schemars")]
+use schemars::JsonSchema;
+#[cfg(feature = "schemars")]
+use serde::Deserialize;
+
+/// A movie and its cast
+#[cfg(feature = "schemars")]
+#[derive(Deserialize, JsonSchema)]
+struct Movie {
+    title: String,
+    year: u32,
+    cast: Vec<String>,
+}
+
+#[cfg(feature = "schemars")]
+#[tokio::main]
+async fn main() {
+    // Any LLM works: OpenAI and Gemini use their JSON schema mode, Claude a forced tool call
+    // and the others the prompt
+    let claude = Claude::default().with_model("claude-3-5-sonnet-20240620");
+    let movie: Movie = claude
+        .generate_typed(&[Message::new_human_message(
+            "Which movie did Al Pacino and Robert De Niro star in together in 1995?",
+        )])
+        .await
+        .unwrap();
+    println!(
+        "{} ({}): {}",
+        movie.title,
+        movie.year,
+        movie.cast.join(", ")
+    );
+}
+
+#[cfg(not(feature = "schemars"))]
+fn main() {
+    println!("This example requires the 'schemars' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example structured_output --features=schemars");
+}
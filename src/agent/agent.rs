@@ -3,6 +3,7 @@ use std::sync::Arc;
 use async_trait::async_trait;
 
 use crate::{
+    language_models::options::CallOptions,
     prompt::PromptArgs,
     schemas::agent::{AgentAction, AgentEvent},
     tools::Tool,
@@ -18,5 +19,20 @@ pub trait Agent: Send + Sync {
         inputs: PromptArgs,
     ) -> Result<AgentEvent, AgentError>;
 
+    /// Like [`Agent::plan`], with `options` overriding the options of the model call, see
+    /// [`Chain::call_with_options`](crate::chain::Chain::call_with_options).
+    ///
+    /// Defaults to an error, for agents that cannot pass the options on to their model.
+    async fn plan_with_options(
+        &self,
+        _intermediate_steps: &[(AgentAction, String)],
+        _inputs: PromptArgs,
+        _options: &CallOptions,
+    ) -> Result<AgentEvent, AgentError> {
+        Err(AgentError::OtherError(
+            "This agent does not support per-call options".to_string(),
+        ))
+    }
+
     fn get_tools(&self) -> Vec<Arc<dyn Tool>>;
 }
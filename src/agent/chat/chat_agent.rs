@@ -6,6 +6,7 @@ use serde_json::json;
 use crate::{
     agent::{agent::Agent, chat::prompt::FORMAT_INSTRUCTIONS, AgentError},
     chain::chain_trait::Chain,
+    language_models::options::CallOptions,
     message_formatter,
     prompt::{
         HumanMessagePromptTemplate, MessageFormatterStruct, MessageOrTemplate, PromptArgs,
@@ -55,7 +56,7 @@ impl ConversationalAgent {
 
         let sufix_prompt = sufix_prompt.format(input_variables_fstring)?;
         let formatter = message_formatter![
-            MessageOrTemplate::Message(Message::new_system_message(prefix)),
+            MessageOrTemplate::Message(Message::new_system_message(prefix).with_cache_breakpoint()),
             MessageOrTemplate::MessagesPlaceholder("chat_history".to_string()),
             MessageOrTemplate::Template(
                 HumanMessagePromptTemplate::new(template_jinja2!(
@@ -82,22 +83,44 @@ impl ConversationalAgent {
         }
         Ok(thoughts)
     }
-}
 
-#[async_trait]
-impl Agent for ConversationalAgent {
-    async fn plan(
+    async fn plan_with(
         &self,
         intermediate_steps: &[(AgentAction, String)],
         inputs: PromptArgs,
+        options: Option<&CallOptions>,
     ) -> Result<AgentEvent, AgentError> {
         let scratchpad = self.construct_scratchpad(intermediate_steps)?;
         let mut inputs = inputs.clone();
         inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
-        let output = self.chain.call(inputs.clone()).await?.generation;
-        let parsed_output = self.output_parser.parse(&output)?;
+        let output = match options {
+            Some(options) => self.chain.call_with_options(inputs, options).await?,
+            None => self.chain.call(inputs).await?,
+        };
+        let parsed_output = self.output_parser.parse(&output.generation)?;
         Ok(parsed_output)
     }
+}
+
+#[async_trait]
+impl Agent for ConversationalAgent {
+    async fn plan(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+    ) -> Result<AgentEvent, AgentError> {
+        self.plan_with(intermediate_steps, inputs, None).await
+    }
+
+    async fn plan_with_options(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<AgentEvent, AgentError> {
+        self.plan_with(intermediate_steps, inputs, Some(options))
+            .await
+    }
 
     fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
         self.tools.clone()
@@ -8,7 +8,7 @@ use super::{agent::Agent, AgentError};
 use crate::schemas::{LogTools, Message};
 use crate::{
     chain::{chain_trait::Chain, ChainError},
-    language_models::GenerateResult,
+    language_models::{options::CallOptions, GenerateResult},
     memory::SimpleMemory,
     prompt::PromptArgs,
     schemas::{
@@ -64,14 +64,12 @@ where
         }
         name_to_tool
     }
-}
 
-#[async_trait]
-impl<A> Chain for AgentExecutor<A>
-where
-    A: Agent + Send + Sync,
-{
-    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+    async fn run(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<GenerateResult, ChainError> {
         let mut input_variables = input_variables.clone();
         let name_to_tools = self.get_name_to_tools();
         let mut steps: Vec<(AgentAction, String)> = Vec::new();
@@ -87,11 +85,15 @@ where
         }
 
         loop {
-            let agent_event = self
-                .agent
-                .plan(&steps, input_variables.clone())
-                .await
-                .map_err(|e| ChainError::AgentError(format!("Error in agent planning: {}", e)))?;
+            let agent_event = match options {
+                Some(options) => {
+                    self.agent
+                        .plan_with_options(&steps, input_variables.clone(), options)
+                        .await
+                }
+                None => self.agent.plan(&steps, input_variables.clone()).await,
+            }
+            .map_err(|e| ChainError::AgentError(format!("Error in agent planning: {}", e)))?;
             match agent_event {
                 AgentEvent::Action(actions) => {
                     for action in actions {
@@ -137,11 +139,17 @@ where
 
                         let mut tools_ai_message_seen: HashMap<String, ()> = HashMap::default();
                         for (action, observation) in steps {
-                            let LogTools { tool_id, tools } = serde_json::from_str(&action.log)?;
+                            let LogTools {
+                                tool_id,
+                                tools,
+                                reasoning,
+                            } = serde_json::from_str(&action.log)?;
                             let tools_value: serde_json::Value = serde_json::from_str(&tools)?;
                             if tools_ai_message_seen.insert(tools, ()).is_none() {
                                 memory.add_message(
-                                    Message::new_ai_message("").with_tool_calls(tools_value),
+                                    Message::new_ai_message("")
+                                        .with_tool_calls(tools_value)
+                                        .with_reasoning(reasoning),
                                 );
                             }
                             memory.add_message(Message::new_tool_message(observation, tool_id));
@@ -166,9 +174,127 @@ where
             }
         }
     }
+}
+
+#[async_trait]
+impl<A> Chain for AgentExecutor<A>
+where
+    A: Agent + Send + Sync,
+{
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        self.run(input_variables, None).await
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        self.run(input_variables, Some(options)).await
+    }
 
     async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
         let result = self.call(input_variables).await?;
         Ok(result.generation)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        agent::OpenAiToolAgentBuilder,
+        llm::{FakeLLM, FakeResponse},
+        memory::SimpleMemory,
+        prompt_args,
+        schemas::MessageType,
+    };
+
+    use super::*;
+
+    struct Calc {}
+
+    #[async_trait]
+    impl Tool for Calc {
+        fn name(&self) -> String {
+            "Calculator".to_string()
+        }
+        fn description(&self) -> String {
+            "Usefull to make calculations".to_string()
+        }
+        async fn run(&self, _input: Value) -> Result<String, Box<dyn Error>> {
+            Ok("25".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runs_tool_calls_until_finish() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::tool_call("Calculator", json!({"input": "20 + 5"})),
+            FakeResponse::text("The result is 25"),
+        ]);
+        let agent = OpenAiToolAgentBuilder::new()
+            .tools(&[Arc::new(Calc {})])
+            .build(llm.clone())
+            .unwrap();
+        let memory = SimpleMemory::new().into();
+        let executor = AgentExecutor::from_agent(agent).with_memory(memory);
+
+        let output = executor
+            .invoke(prompt_args! {"input" => "How much is 20 + 5?"})
+            .await
+            .unwrap();
+
+        assert_eq!(output, "The result is 25");
+        let second_request = &llm.requests()[1];
+        assert_eq!(second_request[0].message_type, MessageType::SystemMessage);
+        assert!(second_request[0].cache_control.is_some());
+        let tool_message = second_request.last().unwrap();
+        assert_eq!(tool_message.message_type, MessageType::ToolMessage);
+        assert_eq!(tool_message.content, "25");
+        assert_eq!(tool_message.id.as_deref(), Some("call_0"));
+
+        let messages = executor.memory.unwrap().lock().await.messages();
+        let types: Vec<MessageType> = messages.into_iter().map(|m| m.message_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                MessageType::HumanMessage,
+                MessageType::AIMessage,
+                MessageType::ToolMessage,
+                MessageType::AIMessage,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forwards_call_options_to_the_agent() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::tool_call("Calculator", json!({"input": "20 + 5"})),
+            FakeResponse::text("The result is 25"),
+        ]);
+        let agent = OpenAiToolAgentBuilder::new()
+            .tools(&[Arc::new(Calc {})])
+            .build(llm.clone())
+            .unwrap();
+        let executor = AgentExecutor::from_agent(agent);
+
+        let result = executor
+            .call_with_options(
+                prompt_args! {"input" => "How much is 20 + 5?"},
+                &CallOptions::new().with_temperature(0.0),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.generation, "The result is 25");
+        assert_eq!(llm.call_count(), 2);
+        let options = llm.last_options().unwrap();
+        assert_eq!(options.temperature, Some(0.0));
+        // The tools of the agent are still offered
+        assert_eq!(options.functions.unwrap()[0].name, "Calculator");
+    }
+}
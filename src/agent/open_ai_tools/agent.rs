@@ -7,12 +7,14 @@ use serde_json::json;
 use crate::{
     agent::{Agent, AgentError},
     chain::Chain,
-    fmt_message, fmt_placeholder, fmt_template, message_formatter,
+    fmt_message, fmt_placeholder, fmt_template,
+    language_models::options::CallOptions,
+    message_formatter,
     prompt::{HumanMessagePromptTemplate, MessageFormatterStruct, PromptArgs},
     schemas::{
         agent::{AgentAction, AgentEvent, AgentFinish, LogTools},
         messages::Message,
-        FunctionCallResponse,
+        FunctionCallResponse, ReasoningRetention,
     },
     template_jinja2,
     tools::Tool,
@@ -21,12 +23,13 @@ use crate::{
 pub struct OpenAiToolAgent {
     pub(crate) chain: Box<dyn Chain>,
     pub(crate) tools: Vec<Arc<dyn Tool>>,
+    pub(crate) reasoning_retention: ReasoningRetention,
 }
 
 impl OpenAiToolAgent {
     pub fn create_prompt(prefix: &str) -> Result<MessageFormatterStruct, AgentError> {
         let prompt = message_formatter![
-            fmt_message!(Message::new_system_message(prefix)),
+            fmt_message!(Message::new_system_message(prefix).with_cache_breakpoint()),
             fmt_placeholder!("chat_history"),
             fmt_template!(HumanMessagePromptTemplate::new(template_jinja2!(
                 "{{input}}",
@@ -48,14 +51,22 @@ impl OpenAiToolAgent {
         for (action, observation) in intermediate_steps {
             // Deserialize directly and embed in method calls to streamline code.
             // Extract the tool ID and tool calls from the log.
-            let LogTools { tool_id, tools } = serde_json::from_str(&action.log)?;
+            let LogTools {
+                tool_id,
+                tools,
+                reasoning,
+            } = serde_json::from_str(&action.log)?;
             let tools_vec: Vec<FunctionCallResponse> = serde_json::from_str(&tools)?;
 
             // one action can trigger multiple observations.  But make sure we add it to
             // the scratchpad before the related observations.  There can also be multiple
             // different actions in the same thought chain.
             if tools_ai_message_seen.insert(tools, ()).is_none() {
-                thoughts.push(Message::new_ai_message("").with_tool_calls(json!(tools_vec)));
+                thoughts.push(
+                    Message::new_ai_message("")
+                        .with_tool_calls(json!(tools_vec))
+                        .with_reasoning(reasoning),
+                );
             }
 
             // Add a tool message for each observation. Observation is the ouput of the tool call.
@@ -65,39 +76,65 @@ impl OpenAiToolAgent {
 
         Ok(thoughts)
     }
-}
 
-#[async_trait]
-impl Agent for OpenAiToolAgent {
-    async fn plan(
+    async fn plan_with(
         &self,
         intermediate_steps: &[(AgentAction, String)],
         inputs: PromptArgs,
+        options: Option<&CallOptions>,
     ) -> Result<AgentEvent, AgentError> {
         let mut inputs = inputs.clone();
         let scratchpad = self.construct_scratchpad(intermediate_steps)?;
         inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
-        let output = self.chain.call(inputs).await?.generation;
-        match serde_json::from_str::<Vec<FunctionCallResponse>>(&output) {
-            Ok(tools) => {
-                let mut actions: Vec<AgentAction> = Vec::new();
-                for tool in tools {
-                    //Log tools will be send as log
-                    let log: LogTools = LogTools {
-                        tool_id: tool.id.clone(),
-                        tools: output.clone(), //We send the complete tools ouput, we will need it in
-                                               //the open ai call
-                    };
-                    actions.push(AgentAction {
-                        tool: tool.function.name.clone(),
-                        tool_input: tool.function.arguments.clone(),
-                        log: serde_json::to_string(&log)?, //We send this as string to minimise changes
-                    });
-                }
-                return Ok(AgentEvent::Action(actions));
-            }
-            Err(_) => return Ok(AgentEvent::Finish(AgentFinish { output })),
+        let output = match options {
+            Some(options) => self.chain.call_with_options(inputs, options).await?,
+            None => self.chain.call(inputs).await?,
+        };
+        if output.tool_calls.is_empty() {
+            return Ok(AgentEvent::Finish(AgentFinish {
+                output: output.generation,
+            }));
+        }
+
+        //We send the complete tools ouput, we will need it in the open ai call
+        let tools = serde_json::to_string(&output.tool_calls)?;
+        let reasoning = output.reasoning(self.reasoning_retention);
+        let mut actions: Vec<AgentAction> = Vec::new();
+        for tool in output.tool_calls {
+            //Log tools will be send as log
+            let log: LogTools = LogTools {
+                tool_id: tool.id.clone(),
+                tools: tools.clone(),
+                reasoning: reasoning.clone(),
+            };
+            actions.push(AgentAction {
+                tool: tool.function.name.clone(),
+                tool_input: tool.function.arguments.clone(),
+                log: serde_json::to_string(&log)?, //We send this as string to minimise changes
+            });
         }
+        Ok(AgentEvent::Action(actions))
+    }
+}
+
+#[async_trait]
+impl Agent for OpenAiToolAgent {
+    async fn plan(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+    ) -> Result<AgentEvent, AgentError> {
+        self.plan_with(intermediate_steps, inputs, None).await
+    }
+
+    async fn plan_with_options(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<AgentEvent, AgentError> {
+        self.plan_with(intermediate_steps, inputs, Some(options))
+            .await
     }
 
     fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
@@ -4,7 +4,7 @@ use crate::{
     agent::AgentError,
     chain::{options::ChainCallOptions, LLMChainBuilder},
     language_models::{llm::LLM, options::CallOptions},
-    schemas::FunctionDefinition,
+    schemas::{FunctionDefinition, ReasoningRetention},
     tools::Tool,
 };
 
@@ -14,6 +14,7 @@ pub struct OpenAiToolAgentBuilder {
     tools: Option<Vec<Arc<dyn Tool>>>,
     prefix: Option<String>,
     options: Option<ChainCallOptions>,
+    reasoning_retention: ReasoningRetention,
 }
 
 impl OpenAiToolAgentBuilder {
@@ -22,6 +23,7 @@ impl OpenAiToolAgentBuilder {
             tools: None,
             prefix: None,
             options: None,
+            reasoning_retention: ReasoningRetention::default(),
         }
     }
 
@@ -40,6 +42,14 @@ impl OpenAiToolAgentBuilder {
         self
     }
 
+    /// Whether the reasoning behind tool calls is kept in the scratchpad and memory, and sent
+    /// back to the model. Claude needs [`ReasoningRetention::Resend`] to think across tool
+    /// calls.
+    pub fn reasoning_retention(mut self, reasoning_retention: ReasoningRetention) -> Self {
+        self.reasoning_retention = reasoning_retention;
+        self
+    }
+
     pub fn build<L: LLM + 'static>(self, llm: L) -> Result<OpenAiToolAgent, AgentError> {
         let tools = self.tools.unwrap_or_default();
         let prefix = self.prefix.unwrap_or_else(|| PREFIX.to_string());
@@ -60,6 +70,10 @@ impl OpenAiToolAgentBuilder {
                 .build()?,
         );
 
-        Ok(OpenAiToolAgent { chain, tools })
+        Ok(OpenAiToolAgent {
+            chain,
+            tools,
+            reasoning_retention: self.reasoning_retention,
+        })
     }
 }
@@ -1,15 +1,25 @@
 use std::{collections::HashMap, pin::Pin};
 
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{stream, Stream, StreamExt};
 use serde_json::{json, Value};
 
-use crate::{language_models::GenerateResult, prompt::PromptArgs, schemas::StreamData};
+use crate::{
+    language_models::{
+        llm::{stream_events, DEFAULT_BATCH_CONCURRENCY},
+        options::CallOptions,
+        GenerateResult,
+    },
+    prompt::PromptArgs,
+    schemas::{StreamData, StreamEvent},
+};
 
 use super::ChainError;
 
 pub(crate) const DEFAULT_OUTPUT_KEY: &str = "output";
 pub(crate) const DEFAULT_RESULT_KEY: &str = "generate_result";
+/// The output key of the per-document results of the chains combining documents.
+pub(crate) const DEFAULT_INTERMEDIATE_STEPS_KEY: &str = "intermediate_steps";
 
 #[async_trait]
 pub trait Chain: Sync + Send {
@@ -78,6 +88,51 @@ pub trait Chain: Sync + Send {
             .map(|result| result.generation)
     }
 
+    /// Like [`Chain::call`], with `options` overriding the options of the model calls made for
+    /// this invocation only, so that a chain shared between requests can use a different
+    /// temperature, stop words or tools for each of them.
+    ///
+    /// Chains that do not support it return an error rather than ignore the options.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let result = chain
+    ///     .call_with_options(
+    ///         prompt_args! { "input" => "Im from Peru" },
+    ///         &CallOptions::new().with_temperature(0.0),
+    ///     )
+    ///     .await?;
+    /// ```
+    async fn call_with_options(
+        &self,
+        _input_variables: PromptArgs,
+        _options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        Err(ChainError::OtherError(
+            "call_with_options is not supported by this chain".to_string(),
+        ))
+    }
+
+    /// Call the `Chain` once for each set of input variables and receive the results in the
+    /// same order. Each call gets its own result, so that one failure does not lose the others.
+    /// By default [`DEFAULT_BATCH_CONCURRENCY`] calls run at a time, see [`call_concurrently`]
+    /// for another bound.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let results = chain
+    ///     .batch(vec![
+    ///         prompt_args! { "input" => "Im from Peru" },
+    ///         prompt_args! { "input" => "Im from Chile" },
+    ///     ])
+    ///     .await;
+    /// ```
+    async fn batch(&self, inputs: Vec<PromptArgs>) -> Vec<Result<GenerateResult, ChainError>> {
+        call_concurrently(self, inputs, DEFAULT_BATCH_CONCURRENCY).await
+    }
+
     /// Execute the `Chain` and return the result of the generation process
     /// along with additional information like token consumption formatted as a `HashMap`.
     /// The input is a set of variables passed as a `PromptArgs` hashmap.
@@ -116,15 +171,18 @@ pub trait Chain: Sync + Send {
     ) -> Result<HashMap<String, Value>, ChainError> {
         log::info!("Using default implementation");
         let result = self.call(input_variables.clone()).await?;
-        let mut output = HashMap::new();
-        let output_key = self
-            .get_output_keys()
-            .first()
-            .unwrap_or(&DEFAULT_OUTPUT_KEY.to_string())
-            .clone();
-        output.insert(output_key, json!(result.generation));
-        output.insert(DEFAULT_RESULT_KEY.to_string(), json!(result));
-        Ok(output)
+        Ok(result_output(&self.get_output_keys(), result))
+    }
+
+    /// Like [`Chain::execute`], with `options` overriding the options of the model calls made
+    /// for this invocation only, see [`Chain::call_with_options`].
+    async fn execute_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let result = self.call_with_options(input_variables, options).await?;
+        Ok(result_output(&self.get_output_keys(), result))
     }
     /// Stream the `Chain` and get an asynchronous stream of chain generations.
     /// The input is a set of variables passed as a `PromptArgs` hashmap.
@@ -180,6 +238,28 @@ pub trait Chain: Sync + Send {
         unimplemented!()
     }
 
+    /// Like [`Chain::stream`], with `options` overriding the options of the model calls made
+    /// for this invocation only, see [`Chain::call_with_options`].
+    async fn stream_with_options(
+        &self,
+        _input_variables: PromptArgs,
+        _options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        Err(ChainError::OtherError(
+            "stream_with_options is not supported by this chain".to_string(),
+        ))
+    }
+
+    /// Like [`Chain::stream`], flattened into the [`StreamEvent`]s of the underlying LLM.
+    async fn stream_events(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, ChainError>> + Send>>, ChainError>
+    {
+        Ok(Box::pin(stream_events(self.stream(input_variables).await?)))
+    }
+
     // Get the input keys of the prompt
     fn get_input_keys(&self) -> Vec<String> {
         log::info!("Using default implementation");
@@ -195,6 +275,47 @@ pub trait Chain: Sync + Send {
     }
 }
 
+/// The output of [`Chain::execute`] for a chain returning just `result`: the generation under
+/// the first output key, and the whole result under `generate_result`.
+fn result_output(output_keys: &[String], result: GenerateResult) -> HashMap<String, Value> {
+    let output_key = output_keys
+        .first()
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_OUTPUT_KEY.to_string());
+    let mut output = HashMap::new();
+    output.insert(output_key, json!(result.generation));
+    output.insert(DEFAULT_RESULT_KEY.to_string(), json!(result));
+    output
+}
+
+/// A stream holding the whole of `result` in a single item, for chains whose output is only
+/// known once every call is done.
+pub(crate) fn single_item_stream(
+    result: GenerateResult,
+) -> Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>> {
+    Box::pin(stream::once(async move {
+        Ok(StreamData::new(
+            json!(result),
+            result.tokens.clone(),
+            result.generation,
+        ))
+    }))
+}
+
+/// Calls `chain` for each set of input variables, at most `concurrency` at a time, and returns
+/// the results in the order of `inputs`.
+pub async fn call_concurrently<C: Chain + ?Sized>(
+    chain: &C,
+    inputs: Vec<PromptArgs>,
+    concurrency: usize,
+) -> Vec<Result<GenerateResult, ChainError>> {
+    stream::iter(inputs)
+        .map(|input_variables| chain.call(input_variables))
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
 impl<C> From<C> for Box<dyn Chain>
 where
     C: Chain + 'static,
@@ -0,0 +1,18 @@
+use crate::{chain::ChainError, prompt::PromptArgs, schemas::Document};
+
+pub(crate) const COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY: &str = "input_documents";
+pub(crate) const COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME: &str = "context";
+
+/// The documents the chains combining documents take from the `input_key` variable.
+pub(crate) fn input_documents(
+    input_variables: &PromptArgs,
+    input_key: &str,
+) -> Result<Vec<Document>, ChainError> {
+    let docs = input_variables
+        .get(input_key)
+        .ok_or_else(|| ChainError::MissingInputVariable(input_key.to_string()))?;
+    serde_json::from_value(docs.clone()).map_err(|e| ChainError::IncorrectInputVariable {
+        source: e,
+        expected_type: "Vec<Document>".to_string(),
+    })
+}
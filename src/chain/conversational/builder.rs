@@ -10,7 +10,7 @@ use crate::{
     memory::SimpleMemory,
     output_parsers::OutputParser,
     prompt::{FormatPrompter, HumanMessagePromptTemplate},
-    schemas::memory::BaseMemory,
+    schemas::{memory::BaseMemory, ReasoningRetention},
     template_fstring,
 };
 
@@ -24,6 +24,7 @@ pub struct ConversationalChainBuilder {
     output_parser: Option<Box<dyn OutputParser>>,
     input_key: Option<String>,
     prompt: Option<Box<dyn FormatPrompter>>,
+    reasoning_retention: ReasoningRetention,
 }
 
 impl ConversationalChainBuilder {
@@ -36,6 +37,7 @@ impl ConversationalChainBuilder {
             output_parser: None,
             input_key: None,
             prompt: None,
+            reasoning_retention: ReasoningRetention::default(),
         }
     }
 
@@ -75,6 +77,13 @@ impl ConversationalChainBuilder {
         self
     }
 
+    /// Whether the reasoning of thinking models is stored in memory with each answer, and
+    /// sent back with it. Discarded by default.
+    pub fn reasoning_retention(mut self, reasoning_retention: ReasoningRetention) -> Self {
+        self.reasoning_retention = reasoning_retention;
+        self
+    }
+
     pub fn build(self) -> Result<ConversationalChain, ChainError> {
         let llm = self
             .llm
@@ -114,6 +123,7 @@ impl ConversationalChainBuilder {
             input_key: self
                 .input_key
                 .unwrap_or_else(|| DEFAULT_INPUT_VARIABLE.to_string()),
+            reasoning_retention: self.reasoning_retention,
         })
     }
 }
@@ -7,10 +7,10 @@ use futures_util::{pin_mut, StreamExt};
 use tokio::sync::Mutex;
 
 use crate::{
-    language_models::GenerateResult,
+    language_models::{options::CallOptions, GenerateResult},
     prompt::PromptArgs,
     prompt_args,
-    schemas::{memory::BaseMemory, messages::Message, StreamData},
+    schemas::{memory::BaseMemory, messages::Message, ReasoningRetention, StreamData},
 };
 
 const DEFAULT_INPUT_VARIABLE: &str = "input";
@@ -48,6 +48,7 @@ pub struct ConversationalChain {
     llm: LLMChain,
     input_key: String,
     pub memory: Arc<Mutex<dyn BaseMemory>>,
+    reasoning_retention: ReasoningRetention,
 }
 
 //Conversational Chain is a simple chain to interact with ai as a string of messages
@@ -60,6 +61,15 @@ impl ConversationalChain {
 #[async_trait]
 impl Chain for ConversationalChain {
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        self.call_with_options(input_variables, &CallOptions::new())
+            .await
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
         let input_variable = &input_variables
             .get(&self.input_key)
             .ok_or(ChainError::MissingInputVariable(self.input_key.clone()))?;
@@ -71,11 +81,17 @@ impl Chain for ConversationalChain {
         };
         let mut input_variables = input_variables;
         input_variables.insert("history".to_string(), history.into());
-        let result = self.llm.call(input_variables.clone()).await?;
+        let result = self
+            .llm
+            .call_with_options(input_variables.clone(), options)
+            .await?;
 
         let mut memory = self.memory.lock().await;
         memory.add_message(human_message);
-        memory.add_message(Message::new_ai_message(&result.generation));
+        memory.add_message(
+            Message::new_ai_message(&result.generation)
+                .with_reasoning(result.reasoning(self.reasoning_retention)),
+        );
         Ok(result)
     }
 
@@ -83,6 +99,16 @@ impl Chain for ConversationalChain {
         &self,
         input_variables: PromptArgs,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.stream_with_options(input_variables, &CallOptions::new())
+            .await
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
         let input_variable = &input_variables
             .get(&self.input_key)
@@ -97,12 +123,16 @@ impl Chain for ConversationalChain {
         let mut input_variables = input_variables;
         input_variables.insert("history".to_string(), history.into());
 
-        let complete_ai_message = Arc::new(Mutex::new(String::new()));
+        let complete_ai_message = Arc::new(Mutex::new(GenerateResult::default()));
         let complete_ai_message_clone = complete_ai_message.clone();
 
         let memory = self.memory.clone();
+        let reasoning_retention = self.reasoning_retention;
 
-        let stream = self.llm.stream(input_variables).await?;
+        let stream = self
+            .llm
+            .stream_with_options(input_variables, options)
+            .await?;
         let output_stream = stream! {
             pin_mut!(stream);
             while let Some(result) = stream.next().await {
@@ -110,7 +140,7 @@ impl Chain for ConversationalChain {
                     Ok(data) => {
                         let mut complete_ai_message_clone =
                             complete_ai_message_clone.lock().await;
-                        complete_ai_message_clone.push_str(&data.content);
+                        complete_ai_message_clone.merge_stream_data(&data);
 
                         yield Ok(data);
                     },
@@ -122,7 +152,11 @@ impl Chain for ConversationalChain {
 
             let mut memory = memory.lock().await;
             memory.add_message(human_message);
-            memory.add_message(Message::new_ai_message(&complete_ai_message.lock().await));
+            let complete_ai_message = complete_ai_message.lock().await;
+            memory.add_message(
+                Message::new_ai_message(&complete_ai_message.generation)
+                    .with_reasoning(complete_ai_message.reasoning(reasoning_retention)),
+            );
         };
 
         Ok(Box::pin(output_stream))
@@ -137,12 +171,93 @@ impl Chain for ConversationalChain {
 mod tests {
     use crate::{
         chain::conversational::builder::ConversationalChainBuilder,
+        language_models::{llm::LLM, LLMError},
         llm::openai::{OpenAI, OpenAIModel},
         prompt_args,
+        schemas::ReasoningBlock,
     };
+    use serde_json::Value;
 
     use super::*;
 
+    #[derive(Clone)]
+    struct ThinkingLLM;
+
+    #[async_trait]
+    impl LLM for ThinkingLLM {
+        async fn generate(&self, _messages: &[Message]) -> Result<GenerateResult, LLMError> {
+            Ok(GenerateResult {
+                generation: "Lima".to_string(),
+                reasoning_content: Some("The capital of Peru is Lima".to_string()),
+                reasoning_blocks: vec![ReasoningBlock::Signed {
+                    content: "The capital of Peru is Lima".to_string(),
+                    signature: "sig_01".to_string(),
+                }],
+                ..Default::default()
+            })
+        }
+
+        async fn stream(
+            &self,
+            _messages: &[Message],
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError>
+        {
+            let chunks = vec![
+                Ok(StreamData::new(Value::Null, None, "")
+                    .with_reasoning_content(Some("The capital".to_string()))),
+                Ok(StreamData::new(Value::Null, None, "")
+                    .with_reasoning_content(Some(" of Peru is Lima".to_string()))),
+                Ok(StreamData::new(Value::Null, None, "Lima")),
+            ];
+            Ok(Box::pin(futures::stream::iter(chunks)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_retention() {
+        let chain = ConversationalChainBuilder::new()
+            .llm(ThinkingLLM)
+            .build()
+            .unwrap();
+        chain
+            .call(prompt_args! { "input" => "Capital of Peru?" })
+            .await
+            .unwrap();
+        assert!(chain.memory.lock().await.messages()[1].reasoning.is_none());
+
+        let chain = ConversationalChainBuilder::new()
+            .llm(ThinkingLLM)
+            .reasoning_retention(ReasoningRetention::Resend)
+            .build()
+            .unwrap();
+        chain
+            .call(prompt_args! { "input" => "Capital of Peru?" })
+            .await
+            .unwrap();
+        let mut stream = chain
+            .stream(prompt_args! { "input" => "And its population?" })
+            .await
+            .unwrap();
+        while stream.next().await.is_some() {}
+
+        let messages = chain.memory.lock().await.messages();
+        let reasoning = messages[1].reasoning.clone().unwrap();
+        assert_eq!(reasoning.content, "The capital of Peru is Lima");
+        assert_eq!(
+            reasoning.blocks,
+            vec![ReasoningBlock::Signed {
+                content: "The capital of Peru is Lima".to_string(),
+                signature: "sig_01".to_string(),
+            }]
+        );
+        assert!(reasoning.resend);
+        assert_eq!(messages[3].content, "Lima");
+        assert_eq!(
+            messages[3].reasoning.as_ref().unwrap().content,
+            "The capital of Peru is Lima"
+        );
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_invoke_conversational() {
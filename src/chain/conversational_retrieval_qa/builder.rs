@@ -93,12 +93,19 @@ impl ConversationalRetrieverChainBuilder {
         self
     }
 
+    ///Builds the combine documents and condense question chains, unless they are set with
+    ///[`combine_documents_chain`](Self::combine_documents_chain) and
+    ///[`condense_question_chain`](Self::condense_question_chain), whatever the order of the
+    ///calls.
     pub fn llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
         self.llm = Some(llm.into());
         self
     }
 
-    ///Chain designed to take the documents and the question and generate an output
+    ///Chain designed to take the documents and the question and generate an output.
+    ///Defaults to a [`StuffDocument`](crate::chain::StuffDocument), use a
+    ///[`MapReduceDocuments`](crate::chain::MapReduceDocuments) when the retrieved documents
+    ///do not fit in a single prompt.
     pub fn combine_documents_chain<C: Into<Box<dyn Chain>>>(
         mut self,
         combine_documents_chain: C,
@@ -127,17 +134,20 @@ impl ConversationalRetrieverChainBuilder {
     }
 
     pub fn build(mut self) -> Result<ConversationalRetrieverChain, ChainError> {
+        // Chains set explicitly take precedence over the ones built from the llm
         if let Some(llm) = self.llm {
-            let combine_documents_chain = {
+            if self.combine_documents_chain.is_none() {
                 let mut builder = StuffDocumentBuilder::new().llm(llm.clone_box());
                 if let Some(prompt) = self.prompt {
                     builder = builder.prompt(prompt);
                 }
-                builder.build()?
-            };
-            let condense_question_chain = CondenseQuestionGeneratorChain::new(llm.clone_box());
-            self.combine_documents_chain = Some(Box::new(combine_documents_chain));
-            self.condense_question_chain = Some(Box::new(condense_question_chain));
+                self.combine_documents_chain = Some(Box::new(builder.build()?));
+            }
+            if self.condense_question_chain.is_none() {
+                self.condense_question_chain = Some(Box::new(CondenseQuestionGeneratorChain::new(
+                    llm.clone_box(),
+                )));
+            }
         }
 
         let retriever = self
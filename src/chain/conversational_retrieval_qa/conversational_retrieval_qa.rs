@@ -11,7 +11,7 @@ use crate::{
     chain::{
         Chain, ChainError, CondenseQuestionPromptBuilder, StuffQAPromptBuilder, DEFAULT_RESULT_KEY,
     },
-    language_models::{GenerateResult, TokenUsage},
+    language_models::{options::CallOptions, GenerateResult, TokenUsage},
     prompt::PromptArgs,
     schemas::{BaseMemory, Message, Retriever, StreamData},
 };
@@ -38,6 +38,7 @@ impl ConversationalRetrieverChain {
         &self,
         history: &[Message],
         input: &str,
+        options: Option<&CallOptions>,
     ) -> Result<(String, Option<TokenUsage>), ChainError> {
         if history.is_empty() {
             return Ok((input.to_string(), None));
@@ -45,15 +46,18 @@ impl ConversationalRetrieverChain {
         let mut token_usage: Option<TokenUsage> = None;
         let question = match self.rephrase_question {
             true => {
-                let result = self
-                    .condense_question_chain
-                    .call(
-                        CondenseQuestionPromptBuilder::new()
-                            .question(input)
-                            .chat_history(history)
-                            .build(),
-                    )
-                    .await?;
+                let prompt = CondenseQuestionPromptBuilder::new()
+                    .question(input)
+                    .chat_history(history)
+                    .build();
+                let result = match options {
+                    Some(options) => {
+                        self.condense_question_chain
+                            .call_with_options(prompt, options)
+                            .await?
+                    }
+                    None => self.condense_question_chain.call(prompt).await?,
+                };
                 if let Some(tokens) = result.tokens {
                     token_usage = Some(tokens);
                 };
@@ -64,19 +68,11 @@ impl ConversationalRetrieverChain {
 
         Ok((question, token_usage))
     }
-}
 
-#[async_trait]
-impl Chain for ConversationalRetrieverChain {
-    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let output = self.execute(input_variables).await?;
-        let result: GenerateResult = serde_json::from_value(output[DEFAULT_RESULT_KEY].clone())?;
-        Ok(result)
-    }
-
-    async fn execute(
+    async fn run(
         &self,
         input_variables: PromptArgs,
+        options: Option<&CallOptions>,
     ) -> Result<HashMap<String, Value>, ChainError> {
         let mut token_usage: Option<TokenUsage> = None;
         let input_variable = &input_variables
@@ -89,7 +85,9 @@ impl Chain for ConversationalRetrieverChain {
             memory.messages()
         };
 
-        let (question, token) = self.get_question(&history, &human_message.content).await?;
+        let (question, token) = self
+            .get_question(&history, &human_message.content, options)
+            .await?;
         if let Some(token) = token {
             token_usage = Some(token);
         }
@@ -100,15 +98,18 @@ impl Chain for ConversationalRetrieverChain {
             .await
             .map_err(|e| ChainError::RetrieverError(e.to_string()))?;
 
-        let mut output = self
-            .combine_documents_chain
-            .call(
-                StuffQAPromptBuilder::new()
-                    .documents(&documents)
-                    .question(question.clone())
-                    .build(),
-            )
-            .await?;
+        let prompt = StuffQAPromptBuilder::new()
+            .documents(&documents)
+            .question(question.clone())
+            .build();
+        let mut output = match options {
+            Some(options) => {
+                self.combine_documents_chain
+                    .call_with_options(prompt, options)
+                    .await?
+            }
+            None => self.combine_documents_chain.call(prompt).await?,
+        };
 
         match &output.tokens {
             Some(tokens) => {
@@ -148,9 +149,10 @@ impl Chain for ConversationalRetrieverChain {
         Ok(result)
     }
 
-    async fn stream(
+    async fn stream_answer(
         &self,
         input_variables: PromptArgs,
+        options: Option<&CallOptions>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
         let input_variable = &input_variables
@@ -163,7 +165,9 @@ impl Chain for ConversationalRetrieverChain {
             memory.messages()
         };
 
-        let (question, _) = self.get_question(&history, &human_message.content).await?;
+        let (question, _) = self
+            .get_question(&history, &human_message.content, options)
+            .await?;
 
         let documents = self
             .retriever
@@ -171,15 +175,18 @@ impl Chain for ConversationalRetrieverChain {
             .await
             .map_err(|e| ChainError::RetrieverError(e.to_string()))?;
 
-        let stream = self
-            .combine_documents_chain
-            .stream(
-                StuffQAPromptBuilder::new()
-                    .documents(&documents)
-                    .question(question.clone())
-                    .build(),
-            )
-            .await?;
+        let prompt = StuffQAPromptBuilder::new()
+            .documents(&documents)
+            .question(question.clone())
+            .build();
+        let stream = match options {
+            Some(options) => {
+                self.combine_documents_chain
+                    .stream_with_options(prompt, options)
+                    .await?
+            }
+            None => self.combine_documents_chain.stream(prompt).await?,
+        };
 
         let memory = self.memory.clone();
         let complete_ai_message = Arc::new(Mutex::new(String::new()));
@@ -208,6 +215,57 @@ impl Chain for ConversationalRetrieverChain {
 
         Ok(Box::pin(output_stream))
     }
+}
+
+#[async_trait]
+impl Chain for ConversationalRetrieverChain {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        let output = self.execute(input_variables).await?;
+        let result: GenerateResult = serde_json::from_value(output[DEFAULT_RESULT_KEY].clone())?;
+        Ok(result)
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        let output = self.execute_with_options(input_variables, options).await?;
+        let result: GenerateResult = serde_json::from_value(output[DEFAULT_RESULT_KEY].clone())?;
+        Ok(result)
+    }
+
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.run(input_variables, None).await
+    }
+
+    async fn execute_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.run(input_variables, Some(options)).await
+    }
+
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.stream_answer(input_variables, None).await
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.stream_answer(input_variables, Some(options)).await
+    }
 
     fn get_input_keys(&self) -> Vec<String> {
         vec![self.input_key.clone()]
@@ -235,8 +293,13 @@ mod tests {
     use std::error::Error;
 
     use crate::{
-        chain::ConversationalRetrieverChainBuilder,
-        llm::openai::{OpenAI, OpenAIModel},
+        chain::{
+            CondenseQuestionGeneratorChain, ConversationalRetrieverChainBuilder, MapReduceDocuments,
+        },
+        llm::{
+            openai::{OpenAI, OpenAIModel},
+            FakeLLM, FakeResponse,
+        },
         memory::SimpleMemory,
         prompt_args,
         schemas::Document,
@@ -272,6 +335,128 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_map_reduce_combine_documents_chain() {
+        let llm = FakeLLM::new()
+            .with_response_when_contains("Relevant text", FakeResponse::text("Luis is 24"))
+            .with_response_when_contains("Helpful Answer", FakeResponse::text("24"));
+        let chain = ConversationalRetrieverChainBuilder::new()
+            .llm(llm.clone())
+            .combine_documents_chain(MapReduceDocuments::load_map_reduce_qa(llm.clone()))
+            .retriever(RetrieverTest {})
+            .memory(SimpleMemory::new().into())
+            .rephrase_question(false)
+            .build()
+            .unwrap();
+
+        let result = chain
+            .call(prompt_args! {"question" => "How old is Luis?"})
+            .await
+            .unwrap();
+
+        assert_eq!(result.generation, "24");
+        // One map call per retrieved document and the reduce
+        assert_eq!(llm.call_count(), 5);
+        assert_eq!(result.tokens.unwrap().completion_tokens, 4 * 3 + 1);
+    }
+
+    #[tokio::test]
+    async fn test_chains_set_explicitly_take_precedence_over_the_llm() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("Pan con chicharron"));
+        let condense_llm = FakeLLM::new()
+            .with_default_response(FakeResponse::text("What is the favorite food of Luis?"));
+        let mut memory = SimpleMemory::new();
+        memory.add_user_message(&"Hola");
+        memory.add_ai_message(&"Hola! How can I help?");
+        let chain = ConversationalRetrieverChainBuilder::new()
+            .condense_question_chain(CondenseQuestionGeneratorChain::new(condense_llm.clone()))
+            .llm(llm.clone())
+            .retriever(RetrieverTest {})
+            .memory(memory.into())
+            .build()
+            .unwrap();
+
+        let answer = chain
+            .invoke(prompt_args! {"question" => "Cual es su comida favorita"})
+            .await
+            .unwrap();
+
+        assert_eq!(answer, "Pan con chicharron");
+        assert_eq!(condense_llm.call_count(), 1);
+        assert_eq!(llm.call_count(), 1);
+        let qa_prompt: String = llm
+            .last_request()
+            .unwrap()
+            .iter()
+            .map(|m| m.content.clone())
+            .collect();
+        assert!(qa_prompt.contains("What is the favorite food of Luis?"));
+    }
+
+    #[tokio::test]
+    async fn test_rephrases_follow_up_questions() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::text("Hola! How can I help?"),
+            FakeResponse::text("What is the favorite food of Luis?"),
+            FakeResponse::text("Pan con chicharron"),
+        ]);
+        let chain = ConversationalRetrieverChainBuilder::new()
+            .llm(llm.clone())
+            .retriever(RetrieverTest {})
+            .memory(SimpleMemory::new().into())
+            .build()
+            .unwrap();
+
+        chain
+            .invoke(prompt_args! {"question" => "Hola"})
+            .await
+            .unwrap();
+        let answer = chain
+            .invoke(prompt_args! {"question" => "Cual es su comida favorita"})
+            .await
+            .unwrap();
+
+        assert_eq!(answer, "Pan con chicharron");
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 3);
+        let condense_prompt = requests[1].last().unwrap();
+        assert!(condense_prompt.content.contains("Hola! How can I help?"));
+        assert!(condense_prompt
+            .content
+            .contains("Cual es su comida favorita"));
+        let qa_prompt: String = requests[2].iter().map(|m| m.content.clone()).collect();
+        assert!(qa_prompt.contains("What is the favorite food of Luis?"));
+        assert!(qa_prompt.contains("Pan con chicharron"));
+        assert_eq!(chain.memory.lock().await.messages().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_forwards_call_options() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("24"));
+        let chain = ConversationalRetrieverChainBuilder::new()
+            .llm(llm.clone())
+            .retriever(RetrieverTest {})
+            .memory(SimpleMemory::new().into())
+            .build()
+            .unwrap();
+        let options = CallOptions::new().with_temperature(0.0);
+
+        chain
+            .call_with_options(prompt_args! {"question" => "How old is Luis?"}, &options)
+            .await
+            .unwrap();
+        assert_eq!(llm.last_options().unwrap().temperature, Some(0.0));
+
+        let stream = chain
+            .stream_with_options(prompt_args! {"question" => "And Luis?"}, &options)
+            .await
+            .unwrap();
+        let _: Vec<_> = stream.collect().await;
+        // The condense question call and the answer
+        assert_eq!(llm.call_count(), 3);
+        assert_eq!(llm.last_options().unwrap().temperature, Some(0.0));
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_invoke_retriever_conversational() {
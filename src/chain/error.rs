@@ -39,4 +39,7 @@ pub enum ChainError {
 
     #[error("Agent error: {0}")]
     AgentError(String),
+
+    #[error("Graph error: {0}")]
+    GraphError(String),
 }
@@ -5,10 +5,10 @@ use futures::Stream;
 use futures_util::TryStreamExt;
 
 use crate::{
-    language_models::{llm::LLM, GenerateResult},
+    language_models::{llm::LLM, options::CallOptions, GenerateResult},
     output_parsers::{OutputParser, SimpleParser},
     prompt::{FormatPrompter, PromptArgs},
-    schemas::StreamData,
+    schemas::{Message, StreamData},
 };
 
 use super::{chain_trait::Chain, options::ChainCallOptions, ChainError};
@@ -65,7 +65,9 @@ impl LLMChainBuilder {
             .llm
             .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
 
+        let mut check_context_window = false;
         if let Some(options) = self.options {
+            check_context_window = options.check_context_window.unwrap_or_default();
             let llm_options = ChainCallOptions::to_llm_options(options);
             llm.add_options(llm_options);
         }
@@ -74,6 +76,7 @@ impl LLMChainBuilder {
             prompt,
             llm,
             output_key: self.output_key.unwrap_or("output".to_string()),
+            check_context_window,
             output_parser: self
                 .output_parser
                 .unwrap_or_else(|| Box::new(SimpleParser::default())),
@@ -88,6 +91,20 @@ pub struct LLMChain {
     llm: Box<dyn LLM>,
     output_key: String,
     output_parser: Box<dyn OutputParser>,
+    check_context_window: bool,
+}
+
+impl LLMChain {
+    fn prepare_messages(&self, input_variables: PromptArgs) -> Result<Vec<Message>, ChainError> {
+        let prompt = self.prompt.format_prompt(input_variables)?;
+        log::debug!("Prompt: {:?}", prompt);
+        let messages = prompt.to_chat_messages();
+        if self.check_context_window {
+            let prompt_tokens = self.llm.check_context_window(&messages)?;
+            log::debug!("Prompt tokens: {}", prompt_tokens);
+        }
+        Ok(messages)
+    }
 }
 
 #[async_trait]
@@ -101,22 +118,74 @@ impl Chain for LLMChain {
     }
 
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let prompt = self.prompt.format_prompt(input_variables.clone())?;
-        log::debug!("Prompt: {:?}", prompt);
-        let mut output = self.llm.generate(&prompt.to_chat_messages()).await?;
+        let messages = self.prepare_messages(input_variables)?;
+        let mut output = self.llm.generate(&messages).await?;
+        output.generation = self.output_parser.parse(&output.generation).await?;
+
+        Ok(output)
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        let messages = self.prepare_messages(input_variables)?;
+        let mut output = self.llm.generate_with_options(&messages, options).await?;
         output.generation = self.output_parser.parse(&output.generation).await?;
 
         Ok(output)
     }
 
+    async fn batch(&self, inputs: Vec<PromptArgs>) -> Vec<Result<GenerateResult, ChainError>> {
+        // Going through the LLM lets providers use their batch APIs
+        let prepared: Vec<Result<Vec<Message>, ChainError>> = inputs
+            .into_iter()
+            .map(|input_variables| self.prepare_messages(input_variables))
+            .collect();
+        let batch: Vec<Vec<Message>> = prepared
+            .iter()
+            .filter_map(|messages| messages.as_ref().ok().cloned())
+            .collect();
+        let mut outputs = match self.llm.generate_batch(&batch).await {
+            Ok(outputs) => outputs.into_iter(),
+            Err(e) => {
+                let message = e.to_string();
+                return prepared
+                    .into_iter()
+                    .map(|messages| messages.and(Err(ChainError::OtherError(message.clone()))))
+                    .collect();
+            }
+        };
+
+        let mut results = Vec::with_capacity(prepared.len());
+        for messages in prepared {
+            let result = match messages {
+                Err(e) => Err(e),
+                Ok(_) => match outputs.next() {
+                    Some(Ok(mut output)) => {
+                        match self.output_parser.parse(&output.generation).await {
+                            Ok(generation) => {
+                                output.generation = generation;
+                                Ok(output)
+                            }
+                            Err(e) => Err(e.into()),
+                        }
+                    }
+                    Some(Err(e)) => Err(e.into()),
+                    None => Err(ChainError::OtherError(
+                        "The LLM returned fewer results than prompts".to_string(),
+                    )),
+                },
+            };
+            results.push(result);
+        }
+        results
+    }
+
     async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
-        let prompt = self.prompt.format_prompt(input_variables.clone())?;
-        log::debug!("Prompt: {:?}", prompt);
-        let output = self
-            .llm
-            .generate(&prompt.to_chat_messages())
-            .await?
-            .generation;
+        let messages = self.prepare_messages(input_variables)?;
+        let output = self.llm.generate(&messages).await?.generation;
         Ok(output)
     }
 
@@ -125,29 +194,218 @@ impl Chain for LLMChain {
         input_variables: PromptArgs,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
-        let prompt = self.prompt.format_prompt(input_variables.clone())?;
-        log::debug!("Prompt: {:?}", prompt);
-        let llm_stream = self.llm.stream(&prompt.to_chat_messages()).await?;
+        let messages = self.prepare_messages(input_variables)?;
+        let llm_stream = self.llm.stream(&messages).await?;
 
         // Map the errors from LLMError to ChainError
         let mapped_stream = llm_stream.map_err(ChainError::from);
 
         Ok(Box::pin(mapped_stream))
     }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let messages = self.prepare_messages(input_variables)?;
+        let llm_stream = self.llm.stream_with_options(&messages, options).await?;
+
+        Ok(Box::pin(llm_stream.map_err(ChainError::from)))
+    }
+}
+
+/// A chain sending its `input` variable to a [`FakeLLM`](crate::llm::FakeLLM) that always
+/// answers with `response`.
+#[cfg(test)]
+pub(crate) fn fake_chain(response: crate::llm::FakeResponse) -> LLMChain {
+    LLMChainBuilder::new()
+        .prompt(crate::template_jinja2!("{{input}}", "input"))
+        .llm(crate::llm::FakeLLM::new().with_default_response(response))
+        .build()
+        .unwrap()
 }
 
 #[cfg(test)]
 mod tests {
+    use futures::StreamExt;
+    use serde_json::Value;
+
     use crate::{
         chain::options::ChainCallOptions,
-        llm::openai::{OpenAI, OpenAIModel},
+        language_models::FinishReason,
+        llm::{
+            openai::{OpenAI, OpenAIModel},
+            FakeLLM, FakeResponse,
+        },
         message_formatter,
         prompt::{HumanMessagePromptTemplate, MessageOrTemplate},
-        prompt_args, template_fstring,
+        prompt_args,
+        schemas::{StreamEvent, ToolCallAccumulator, ToolCallDelta},
+        template_fstring,
     };
 
     use super::*;
 
+    #[tokio::test]
+    async fn test_batch_keeps_order_and_item_errors() {
+        let llm = FakeLLM::new()
+            .with_response_when_contains("Peru", FakeResponse::text("Lima"))
+            .with_response_when_contains("France", FakeResponse::text("Paris"))
+            .with_response_when_contains("Atlantis", FakeResponse::error("unknown country"));
+        let chain = LLMChainBuilder::new()
+            .prompt(message_formatter![MessageOrTemplate::Template(
+                HumanMessagePromptTemplate::new(template_fstring!("{input}", "input")).into()
+            )])
+            .llm(llm.clone())
+            .build()
+            .unwrap();
+
+        let results = chain
+            .batch(vec![
+                prompt_args! {"input" => "Capital of Peru?"},
+                prompt_args! {"question" => "no input here"},
+                prompt_args! {"input" => "Capital of Atlantis?"},
+                prompt_args! {"input" => "Capital of France?"},
+            ])
+            .await;
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().generation, "Lima");
+        assert!(results[1].is_err());
+        assert!(matches!(&results[2], Err(ChainError::LLMError(_))));
+        assert_eq!(results[3].as_ref().unwrap().generation, "Paris");
+        // The input failing to format never reaches the model
+        assert_eq!(llm.call_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_call_with_options_does_not_change_the_llm() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("ok"));
+        let chain = LLMChainBuilder::new()
+            .prompt(message_formatter![MessageOrTemplate::Template(
+                HumanMessagePromptTemplate::new(template_fstring!("{input}", "input")).into()
+            )])
+            .llm(llm.clone())
+            .options(ChainCallOptions::new().with_max_tokens(100))
+            .build()
+            .unwrap();
+        let options = CallOptions::new()
+            .with_temperature(0.1)
+            .with_stop_words(vec!["END".to_string()]);
+
+        let result = chain
+            .call_with_options(prompt_args! {"input" => "hi"}, &options)
+            .await
+            .unwrap();
+        assert_eq!(result.generation, "ok");
+        let used = llm.last_options().unwrap();
+        assert_eq!(used.temperature, Some(0.1));
+        assert_eq!(used.stop_words, Some(vec!["END".to_string()]));
+        // Options of the chain still apply
+        assert_eq!(used.max_tokens, Some(100));
+
+        let mut stream = chain
+            .stream_with_options(
+                prompt_args! {"input" => "hi"},
+                &CallOptions::new().with_temperature(0.9),
+            )
+            .await
+            .unwrap();
+        while stream.next().await.is_some() {}
+        assert_eq!(llm.last_options().unwrap().temperature, Some(0.9));
+
+        chain.invoke(prompt_args! {"input" => "hi"}).await.unwrap();
+        let used = llm.last_options().unwrap();
+        assert_eq!(used.temperature, None);
+        assert_eq!(used.stop_words, None);
+    }
+
+    #[tokio::test]
+    async fn test_check_context_window() {
+        let chain = |check: bool| {
+            LLMChainBuilder::new()
+                .prompt(message_formatter![MessageOrTemplate::Template(
+                    HumanMessagePromptTemplate::new(template_fstring!("{input}", "input")).into()
+                )])
+                .llm(
+                    FakeLLM::new()
+                        .with_default_response(FakeResponse::text("ok"))
+                        .with_context_window(20, 5),
+                )
+                .options(ChainCallOptions::new().with_check_context_window(check))
+                .build()
+                .unwrap()
+        };
+
+        let short = chain(true).invoke(prompt_args! {"input" => "hi"}).await;
+        assert_eq!(short.unwrap(), "ok");
+
+        let long_input = "lorem ipsum ".repeat(20);
+        let long = chain(true)
+            .invoke(prompt_args! {"input" => long_input.clone()})
+            .await;
+        assert!(matches!(
+            long,
+            Err(ChainError::LLMError(
+                crate::language_models::LLMError::ContextWindowExceeded {
+                    context_window: 20,
+                    max_output_tokens: 5,
+                    ..
+                }
+            ))
+        ));
+
+        let unchecked = chain(false)
+            .invoke(prompt_args! {"input" => long_input})
+            .await;
+        assert!(unchecked.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stream_events() {
+        let chain = LLMChainBuilder::new()
+            .prompt(message_formatter![MessageOrTemplate::Template(
+                HumanMessagePromptTemplate::new(template_fstring!("{input}", "input")).into()
+            )])
+            .llm(FakeLLM::new().with_response(FakeResponse::Chunks(vec![
+                StreamData::new(Value::Null, None, "On it").with_tool_call_deltas(vec![
+                    ToolCallDelta::new(0)
+                        .with_id("call_1")
+                        .with_name("search")
+                        .with_arguments("{\"q\":"),
+                ]),
+                StreamData::new(Value::Null, None, "")
+                    .with_tool_call_deltas(vec![ToolCallDelta::new(0).with_arguments("\"rust\"}")])
+                    .with_finish_reason(Some(FinishReason::ToolCalls)),
+            ])))
+            .build()
+            .unwrap();
+
+        let events: Vec<StreamEvent> = chain
+            .stream_events(prompt_args! {"input" => "Search for rust"})
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(events[0], StreamEvent::TextDelta("On it".to_string()));
+        assert_eq!(events[3], StreamEvent::Stop(FinishReason::ToolCalls));
+        let mut tool_calls = ToolCallAccumulator::new();
+        for event in &events {
+            if let StreamEvent::ToolCallDelta(delta) = event {
+                tool_calls.push(delta);
+            }
+        }
+        assert_eq!(tool_calls.tool_calls()[0].function.name, "search");
+        assert_eq!(
+            tool_calls.tool_calls()[0].function.arguments,
+            r#"{"q":"rust"}"#
+        );
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_invoke_chain() {
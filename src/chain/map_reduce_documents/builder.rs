@@ -0,0 +1,191 @@
+use crate::{
+    chain::{
+        combine_documents::COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY, options::ChainCallOptions,
+        question_answering::stuff_qa_prompt, Chain, ChainError, StuffDocumentBuilder,
+    },
+    language_models::{
+        llm::{DEFAULT_BATCH_CONCURRENCY, LLM},
+        tokens::Tokenizer,
+    },
+    prompt::FormatPrompter,
+    template_jinja2,
+};
+
+use super::MapReduceDocuments;
+
+const MAP_REDUCE_DEFAULT_TOKEN_MAX: usize = 3000;
+
+pub struct MapReduceDocumentsBuilder {
+    llm: Option<Box<dyn LLM>>,
+    options: Option<ChainCallOptions>,
+    map_prompt: Option<Box<dyn FormatPrompter>>,
+    collapse_prompt: Option<Box<dyn FormatPrompter>>,
+    reduce_prompt: Option<Box<dyn FormatPrompter>>,
+    map_chain: Option<Box<dyn Chain>>,
+    collapse_chain: Option<Box<dyn Chain>>,
+    reduce_chain: Option<Box<dyn Chain>>,
+    token_max: usize,
+    documents_per_map: usize,
+    concurrency: usize,
+    tokenizer: Tokenizer,
+}
+
+impl Default for MapReduceDocumentsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapReduceDocumentsBuilder {
+    pub fn new() -> Self {
+        Self {
+            llm: None,
+            options: None,
+            map_prompt: None,
+            collapse_prompt: None,
+            reduce_prompt: None,
+            map_chain: None,
+            collapse_chain: None,
+            reduce_chain: None,
+            token_max: MAP_REDUCE_DEFAULT_TOKEN_MAX,
+            documents_per_map: 1,
+            concurrency: DEFAULT_BATCH_CONCURRENCY,
+            tokenizer: Tokenizer::Cl100kBase,
+        }
+    }
+
+    /// The LLM of the chains built from prompts.
+    pub fn llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llm = Some(llm.into());
+        self
+    }
+
+    /// Options of the reduce chain, which makes the final call.
+    pub fn options(mut self, options: ChainCallOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    ///Prompt applied to each document, with the `context` and `question` variables by default.
+    pub fn map_prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.map_prompt = Some(prompt.into());
+        self
+    }
+
+    ///Prompt combining groups of outputs that exceed `token_max`. Defaults to the map prompt.
+    pub fn collapse_prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.collapse_prompt = Some(prompt.into());
+        self
+    }
+
+    ///Prompt producing the final output from the map outputs.
+    pub fn reduce_prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.reduce_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Chain applied to each document instead of one built from the map prompt.
+    pub fn map_chain<C: Into<Box<dyn Chain>>>(mut self, chain: C) -> Self {
+        self.map_chain = Some(chain.into());
+        self
+    }
+
+    /// Chain collapsing groups of outputs instead of one built from the collapse prompt.
+    pub fn collapse_chain<C: Into<Box<dyn Chain>>>(mut self, chain: C) -> Self {
+        self.collapse_chain = Some(chain.into());
+        self
+    }
+
+    /// Chain producing the final output instead of one built from the reduce prompt.
+    pub fn reduce_chain<C: Into<Box<dyn Chain>>>(mut self, chain: C) -> Self {
+        self.reduce_chain = Some(chain.into());
+        self
+    }
+
+    /// The most tokens of documents the reduce chain receives, 3000 by default.
+    pub fn token_max(mut self, token_max: usize) -> Self {
+        self.token_max = token_max;
+        self
+    }
+
+    /// How many documents each map call receives, 1 by default.
+    pub fn documents_per_map(mut self, documents_per_map: usize) -> Self {
+        self.documents_per_map = documents_per_map;
+        self
+    }
+
+    /// How many map or collapse calls run at a time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// The encoding counting the tokens of the documents, `cl100k_base` by default.
+    pub fn tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    pub fn build(self) -> Result<MapReduceDocuments, ChainError> {
+        let llm = self.llm;
+        let build_chain = |prompt: Box<dyn FormatPrompter>,
+                           options: Option<ChainCallOptions>|
+         -> Result<Box<dyn Chain>, ChainError> {
+            let llm = llm
+                .as_ref()
+                .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
+            let mut builder = StuffDocumentBuilder::new()
+                .llm(llm.clone_box())
+                .prompt(prompt);
+            if let Some(options) = options {
+                builder = builder.options(options);
+            }
+            Ok(Box::new(builder.build()?))
+        };
+
+        let map_chain = match self.map_chain {
+            Some(chain) => chain,
+            None => build_chain(
+                self.map_prompt
+                    .unwrap_or_else(|| Box::new(default_map_prompt())),
+                None,
+            )?,
+        };
+        let collapse_chain = match (self.collapse_chain, self.collapse_prompt) {
+            (Some(chain), _) => Some(chain),
+            (None, Some(prompt)) => Some(build_chain(prompt, None)?),
+            (None, None) => None,
+        };
+        let reduce_chain = match self.reduce_chain {
+            Some(chain) => chain,
+            None => build_chain(
+                self.reduce_prompt
+                    .unwrap_or_else(|| Box::new(stuff_qa_prompt())),
+                self.options,
+            )?,
+        };
+
+        Ok(MapReduceDocuments {
+            map_chain,
+            collapse_chain,
+            reduce_chain,
+            input_key: COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY.to_string(),
+            token_max: self.token_max,
+            documents_per_map: self.documents_per_map,
+            concurrency: self.concurrency,
+            tokenizer: self.tokenizer,
+        })
+    }
+}
+
+fn default_map_prompt() -> impl FormatPrompter {
+    template_jinja2!(DEFAULT_MAP_QA_TEMPLATE, "context", "question")
+}
+
+const DEFAULT_MAP_QA_TEMPLATE: &str = r#"Use the following portion of a long document to see if any of the text is relevant to answer the question. Return any relevant text verbatim.
+
+{{context}}
+
+Question: {{question}}
+Relevant text, if any:
+"#;
@@ -0,0 +1,385 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
+
+use crate::{
+    chain::{combine_documents::input_documents, Chain, ChainError, StuffQAPromptBuilder},
+    language_models::{
+        llm::LLM,
+        options::CallOptions,
+        tokens::{count_text_tokens, Tokenizer},
+        GenerateResult, TokenUsage,
+    },
+    prompt::PromptArgs,
+    schemas::{Document, StreamData},
+};
+
+use super::MapReduceDocumentsBuilder;
+
+/// Combines documents that do not fit in a single prompt.
+///
+/// The map chain runs over each document, or batch of documents, on its own. While the
+/// outputs are longer than `token_max` they are grouped and collapsed by the collapse chain,
+/// the map chain unless set, and the reduce chain combines what is left into the final
+/// answer. The three chains take their documents from the `input_documents` variable, like
+/// [`StuffDocument`](crate::chain::StuffDocument), and receive the other input variables,
+/// such as the question, unchanged.
+///
+/// The token usage of every call is added up in the result.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let chain = MapReduceDocuments::load_map_reduce_qa(llm);
+///
+/// let input = chain
+///     .qa_prompt_builder()
+///     .documents(&documents)
+///     .question("How old is luis and whats his favorite text editor")
+///     .build();
+///
+/// let ouput = chain.invoke(input).await.unwrap();
+/// ```
+pub struct MapReduceDocuments {
+    pub(crate) map_chain: Box<dyn Chain>,
+    pub(crate) collapse_chain: Option<Box<dyn Chain>>,
+    pub(crate) reduce_chain: Box<dyn Chain>,
+    pub(crate) input_key: String,
+    pub(crate) token_max: usize,
+    pub(crate) documents_per_map: usize,
+    pub(crate) concurrency: usize,
+    pub(crate) tokenizer: Tokenizer,
+}
+
+impl MapReduceDocuments {
+    /// Builds the chain from existing chains, collapsing with `map_chain`. See
+    /// [`MapReduceDocumentsBuilder`] to set the other options.
+    pub fn new<M: Into<Box<dyn Chain>>, R: Into<Box<dyn Chain>>>(
+        map_chain: M,
+        reduce_chain: R,
+    ) -> Self {
+        MapReduceDocumentsBuilder::new()
+            .map_chain(map_chain)
+            .reduce_chain(reduce_chain)
+            .build()
+            .unwrap() // Its safe to unwrap here because both chains are set.
+    }
+
+    /// load_map_reduce_qa returns an instance of MapReduceDocuments with prompts designed
+    /// for question answering: the map step extracts the text relevant to the question from
+    /// each document, and the reduce step answers from the extracts.
+    pub fn load_map_reduce_qa<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        MapReduceDocumentsBuilder::new().llm(llm).build().unwrap() // Its safe to unwrap here because the LLM is set.
+    }
+
+    ///Only use this if you use the default prompts
+    pub fn qa_prompt_builder<'a>(&self) -> StuffQAPromptBuilder<'a> {
+        StuffQAPromptBuilder::new()
+    }
+
+    /// Maps and collapses the documents, returning the input of the reduce chain and the
+    /// token usage so far.
+    async fn map_and_collapse(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<(PromptArgs, Option<TokenUsage>), ChainError> {
+        let documents = input_documents(&input_variables, &self.input_key)?;
+
+        let mut tokens = None;
+        let batches = documents
+            .chunks(self.documents_per_map.max(1))
+            .map(<[Document]>::to_vec)
+            .collect();
+        let mut documents = self
+            .combine_batches(
+                &*self.map_chain,
+                batches,
+                &input_variables,
+                options,
+                &mut tokens,
+            )
+            .await?;
+
+        let collapse_chain = self.collapse_chain.as_deref().unwrap_or(&*self.map_chain);
+        while self.count_tokens(&documents) > self.token_max {
+            let groups = self.split_documents(documents);
+            if groups.iter().all(|group| group.len() == 1) {
+                return Err(ChainError::OtherError(format!(
+                    "A document is longer than the token_max of {} tokens",
+                    self.token_max
+                )));
+            }
+            documents = self
+                .combine_batches(
+                    collapse_chain,
+                    groups,
+                    &input_variables,
+                    options,
+                    &mut tokens,
+                )
+                .await?;
+        }
+
+        Ok((self.with_documents(&input_variables, &documents)?, tokens))
+    }
+
+    /// Calls `chain` on each batch of documents, `concurrency` at a time, returning one
+    /// document per batch with its output.
+    async fn combine_batches(
+        &self,
+        chain: &dyn Chain,
+        batches: Vec<Vec<Document>>,
+        input_variables: &PromptArgs,
+        options: Option<&CallOptions>,
+        tokens: &mut Option<TokenUsage>,
+    ) -> Result<Vec<Document>, ChainError> {
+        let calls = batches
+            .iter()
+            .map(|batch| {
+                let input_variables = self.with_documents(input_variables, batch);
+                async move {
+                    match options {
+                        Some(options) => chain.call_with_options(input_variables?, options).await,
+                        None => chain.call(input_variables?).await,
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let results: Vec<Result<GenerateResult, ChainError>> = stream::iter(calls)
+            .buffered(self.concurrency.max(1))
+            .collect()
+            .await;
+
+        batches
+            .into_iter()
+            .zip(results)
+            .map(|(batch, result)| {
+                let result = result?;
+                add_tokens(tokens, result.tokens.as_ref());
+                let mut document = Document::new(result.generation);
+                // A single document keeps its source metadata
+                if let [source] = batch.as_slice() {
+                    document.metadata = source.metadata.clone();
+                }
+                Ok(document)
+            })
+            .collect()
+    }
+
+    /// Groups consecutive documents so that each group fits in `token_max`.
+    fn split_documents(&self, documents: Vec<Document>) -> Vec<Vec<Document>> {
+        let mut groups: Vec<Vec<Document>> = Vec::new();
+        let mut group_tokens = 0;
+        for document in documents {
+            let tokens = self.count_tokens(std::slice::from_ref(&document));
+            match groups.last_mut() {
+                Some(group) if group_tokens + tokens <= self.token_max => {
+                    group_tokens += tokens;
+                    group.push(document);
+                }
+                _ => {
+                    group_tokens = tokens;
+                    groups.push(vec![document]);
+                }
+            }
+        }
+        groups
+    }
+
+    fn count_tokens(&self, documents: &[Document]) -> usize {
+        documents
+            .iter()
+            .map(|document| count_text_tokens(self.tokenizer, &document.page_content))
+            .sum()
+    }
+
+    fn with_documents(
+        &self,
+        input_variables: &PromptArgs,
+        documents: &[Document],
+    ) -> Result<PromptArgs, ChainError> {
+        let mut input_variables = input_variables.clone();
+        input_variables.insert(self.input_key.clone(), serde_json::to_value(documents)?);
+        Ok(input_variables)
+    }
+
+    async fn reduce(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<GenerateResult, ChainError> {
+        let (input_variables, tokens) = self.map_and_collapse(input_variables, options).await?;
+        let mut result = match options {
+            Some(options) => {
+                self.reduce_chain
+                    .call_with_options(input_variables, options)
+                    .await?
+            }
+            None => self.reduce_chain.call(input_variables).await?,
+        };
+        add_tokens(&mut result.tokens, tokens.as_ref());
+        Ok(result)
+    }
+
+    async fn stream_reduce(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let (input_variables, tokens) = self.map_and_collapse(input_variables, options).await?;
+        let stream = match options {
+            Some(options) => {
+                self.reduce_chain
+                    .stream_with_options(input_variables, options)
+                    .await?
+            }
+            None => self.reduce_chain.stream(input_variables).await?,
+        };
+        // The usage reported by the reduce stream covers its own call only
+        Ok(Box::pin(stream.map(move |data| {
+            data.map(|mut data| {
+                if data.tokens.is_some() {
+                    add_tokens(&mut data.tokens, tokens.as_ref());
+                }
+                data
+            })
+        })))
+    }
+}
+
+fn add_tokens(total: &mut Option<TokenUsage>, tokens: Option<&TokenUsage>) {
+    if let Some(tokens) = tokens {
+        total.get_or_insert_with(TokenUsage::default).add(tokens);
+    }
+}
+
+#[async_trait]
+impl Chain for MapReduceDocuments {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        self.reduce(input_variables, None).await
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        self.reduce(input_variables, Some(options)).await
+    }
+
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.stream_reduce(input_variables, None).await
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.stream_reduce(input_variables, Some(options)).await
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        vec![self.input_key.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        llm::{FakeLLM, FakeResponse},
+        schemas::MessageType,
+    };
+
+    use super::*;
+
+    fn documents() -> Vec<Document> {
+        [
+            "Luis is 24",
+            "Luis uses Nvim",
+            "Luis lives in Peru",
+            "Luis likes pan",
+        ]
+        .into_iter()
+        .map(Document::new)
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn test_map_collapse_and_reduce() {
+        let llm = FakeLLM::new()
+            .with_response_when_contains(
+                "Relevant text",
+                FakeResponse::text("Luis is 24 years old"),
+            )
+            .with_response_when_contains("Helpful Answer", FakeResponse::text("He is 24"));
+        let chain = MapReduceDocumentsBuilder::new()
+            .llm(llm.clone())
+            // Room for two map outputs
+            .token_max(2 * count_text_tokens(Tokenizer::Cl100kBase, "Luis is 24 years old"))
+            .concurrency(2)
+            .build()
+            .unwrap();
+        let documents = documents();
+        let input = chain
+            .qa_prompt_builder()
+            .documents(&documents)
+            .question("How old is Luis?")
+            .build();
+
+        let result = chain.call(input).await.unwrap();
+
+        assert_eq!(result.generation, "He is 24");
+        // 4 maps, 2 collapses of 2 outputs each and the reduce
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 7);
+        assert!(requests[0][0].content.contains("Luis is 24"));
+        assert!(requests[0][0].content.contains("How old is Luis?"));
+        assert_eq!(
+            requests[4][0]
+                .content
+                .matches("Luis is 24 years old")
+                .count(),
+            2
+        );
+        let reduce_prompt = &requests[6];
+        assert_eq!(reduce_prompt[0].message_type, MessageType::HumanMessage);
+        assert_eq!(
+            reduce_prompt[0]
+                .content
+                .matches("Luis is 24 years old")
+                .count(),
+            2
+        );
+        assert_eq!(result.tokens.unwrap().completion_tokens, 6 * 5 + 3);
+    }
+
+    #[tokio::test]
+    async fn test_document_longer_than_token_max() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("Luis is 24 years old"));
+        let chain = MapReduceDocumentsBuilder::new()
+            .llm(llm)
+            .token_max(3)
+            .build()
+            .unwrap();
+        let documents = documents();
+        let input = chain
+            .qa_prompt_builder()
+            .documents(&documents)
+            .question("How old is Luis?")
+            .build();
+
+        assert!(matches!(
+            chain.call(input).await,
+            Err(ChainError::OtherError(_))
+        ));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{
+    chain::{ChainError, LLMChainBuilder},
+    language_models::llm::LLM,
+    prompt::FormatPrompter,
+    template_jinja2,
+};
+
+use super::MapRerankDocuments;
+
+pub struct MapRerankDocumentsBuilder {
+    llm: Option<Box<dyn LLM>>,
+    prompt: Option<Box<dyn FormatPrompter>>,
+    concurrency: Option<usize>,
+}
+
+impl Default for MapRerankDocumentsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapRerankDocumentsBuilder {
+    pub fn new() -> Self {
+        Self {
+            llm: None,
+            prompt: None,
+            concurrency: None,
+        }
+    }
+
+    pub fn llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llm = Some(llm.into());
+        self
+    }
+
+    ///If you want to add a custom prompt, keep in mind that the output must end with a
+    ///`Score: <number>` line.
+    pub fn prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// How many documents are answered at a time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    pub fn build(self) -> Result<MapRerankDocuments, ChainError> {
+        let llm = self
+            .llm
+            .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
+        let prompt = self.prompt.unwrap_or_else(|| {
+            Box::new(template_jinja2!(
+                DEFAULT_MAP_RERANK_QA_TEMPLATE,
+                "context",
+                "question"
+            ))
+        });
+
+        let llm_chain = LLMChainBuilder::new().prompt(prompt).llm(llm).build()?;
+
+        let mut chain = MapRerankDocuments::new(llm_chain);
+        if let Some(concurrency) = self.concurrency {
+            chain.concurrency = concurrency;
+        }
+        Ok(chain)
+    }
+}
+
+const DEFAULT_MAP_RERANK_QA_TEMPLATE: &str = r#"Use the following pieces of context to answer the question at the end. If you don't know the answer, just say that you don't know, don't try to make up an answer.
+
+In addition to giving an answer, also return a score of how fully it answered the user's question. This should be in the following format:
+
+Question: [question here]
+Helpful Answer: [answer here]
+Score: [score between 0 and 100]
+
+How to determine the score:
+- Higher is a better answer
+- Better responds fully to the asked question, with sufficient level of detail
+- If you do not know the answer based on the context, that should be a score of 0
+- Don't be overconfident!
+
+Begin!
+
+Context:
+---------
+{{context}}
+---------
+Question: {{question}}
+Helpful Answer:"#;
@@ -0,0 +1,308 @@
+use std::{collections::HashMap, pin::Pin};
+
+use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    chain::{
+        chain_trait::single_item_stream,
+        combine_documents::{
+            input_documents, COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME,
+            COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY,
+        },
+        Chain, ChainError, LLMChain, StuffQAPromptBuilder, DEFAULT_INTERMEDIATE_STEPS_KEY,
+        DEFAULT_OUTPUT_KEY, DEFAULT_RESULT_KEY,
+    },
+    language_models::{
+        llm::{DEFAULT_BATCH_CONCURRENCY, LLM},
+        options::CallOptions,
+        GenerateResult, TokenUsage,
+    },
+    prompt::PromptArgs,
+    schemas::StreamData,
+};
+
+use super::MapRerankDocumentsBuilder;
+
+const MAP_RERANK_DEFAULT_SCORE_KEY: &str = "score";
+
+/// The answer from one document and how fully it answers the question, as reported by the
+/// model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RankedAnswer {
+    pub answer: String,
+    pub score: f64,
+}
+
+impl RankedAnswer {
+    /// Parses an output ending in a `Score: <number>` line. Outputs without a score keep
+    /// all their text as the answer with a score of 0.
+    pub fn parse(output: &str) -> Self {
+        let re = Regex::new(r"(?s)^(.*?)\s*Score:\s*(\d+(?:\.\d+)?)\s*$").unwrap();
+        match re.captures(output.trim()) {
+            Some(captures) => Self {
+                answer: captures[1].trim().to_string(),
+                score: captures[2].parse().unwrap_or_default(),
+            },
+            None => Self {
+                answer: output.trim().to_string(),
+                score: 0.0,
+            },
+        }
+    }
+}
+
+/// Answers from each document on its own, asking the model to score its answer, and returns
+/// the answer with the highest score.
+///
+/// The prompt receives each document as `context` and must ask for the score on the last
+/// line, see [`RankedAnswer::parse`]. [`Chain::execute`] returns the best score under
+/// `score` and the answer from every document under `intermediate_steps`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let chain = MapRerankDocuments::load_map_rerank_qa(llm);
+///
+/// let input = chain
+///     .qa_prompt_builder()
+///     .documents(&documents)
+///     .question("How old is luis and whats his favorite text editor")
+///     .build();
+///
+/// let output = chain.execute(input).await.unwrap();
+/// println!("{} ({})", output["output"], output["score"]);
+/// ```
+pub struct MapRerankDocuments {
+    pub(crate) llm_chain: LLMChain,
+    pub(crate) input_key: String,
+    pub(crate) document_variable_name: String,
+    pub(crate) concurrency: usize,
+}
+
+impl MapRerankDocuments {
+    pub fn new(llm_chain: LLMChain) -> Self {
+        Self {
+            llm_chain,
+            input_key: COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY.to_string(),
+            document_variable_name: COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME.to_string(),
+            concurrency: DEFAULT_BATCH_CONCURRENCY,
+        }
+    }
+
+    /// load_map_rerank_qa returns an instance of MapRerankDocuments with a prompt designed
+    /// for question answering.
+    pub fn load_map_rerank_qa<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        MapRerankDocumentsBuilder::new().llm(llm).build().unwrap() // Its safe to unwrap here because the LLM is set.
+    }
+
+    ///Only use this if you use the default prompt
+    pub fn qa_prompt_builder<'a>(&self) -> StuffQAPromptBuilder<'a> {
+        StuffQAPromptBuilder::new()
+    }
+
+    /// Answers from every document, returning the best result with the usage of all the
+    /// calls, and the answers in document order.
+    async fn rerank(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<(GenerateResult, RankedAnswer, Vec<RankedAnswer>), ChainError> {
+        let documents = input_documents(&input_variables, &self.input_key)?;
+
+        let calls = documents
+            .into_iter()
+            .map(|document| {
+                let mut input_variables = input_variables.clone();
+                input_variables.insert(
+                    self.document_variable_name.clone(),
+                    Value::String(document.page_content),
+                );
+                async move {
+                    match options {
+                        Some(options) => {
+                            self.llm_chain
+                                .call_with_options(input_variables, options)
+                                .await
+                        }
+                        None => self.llm_chain.call(input_variables).await,
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        let results = stream::iter(calls)
+            .buffered(self.concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<GenerateResult>, ChainError>>()?;
+
+        let mut tokens: Option<TokenUsage> = None;
+        for usage in results.iter().filter_map(|result| result.tokens.as_ref()) {
+            tokens.get_or_insert_with(TokenUsage::default).add(usage);
+        }
+        let answers = results
+            .iter()
+            .map(|result| RankedAnswer::parse(&result.generation))
+            .collect::<Vec<_>>();
+        // The first of the best scores wins ties
+        let best = (0..answers.len())
+            .reduce(|best, i| {
+                if answers[i].score > answers[best].score {
+                    i
+                } else {
+                    best
+                }
+            })
+            .ok_or_else(|| ChainError::OtherError("No documents to answer from".to_string()))?;
+
+        let best_answer = answers[best].clone();
+        let mut result = results.into_iter().nth(best).unwrap_or_default();
+        result.generation = best_answer.answer.clone();
+        result.tokens = tokens;
+        Ok((result, best_answer, answers))
+    }
+
+    async fn execute_rerank(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let (result, best, answers) = self.rerank(input_variables, options).await?;
+        let mut output = HashMap::new();
+        output.insert(DEFAULT_OUTPUT_KEY.to_string(), json!(result.generation));
+        output.insert(DEFAULT_RESULT_KEY.to_string(), json!(result));
+        output.insert(MAP_RERANK_DEFAULT_SCORE_KEY.to_string(), json!(best.score));
+        output.insert(DEFAULT_INTERMEDIATE_STEPS_KEY.to_string(), json!(answers));
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl Chain for MapRerankDocuments {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        Ok(self.rerank(input_variables, None).await?.0)
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        Ok(self.rerank(input_variables, Some(options)).await?.0)
+    }
+
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.execute_rerank(input_variables, None).await
+    }
+
+    async fn execute_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.execute_rerank(input_variables, Some(options)).await
+    }
+
+    /// The answer is only known once every document is scored, so the stream holds it in a
+    /// single item.
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let result = self.call(input_variables).await?;
+        Ok(single_item_stream(result))
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let result = self.call_with_options(input_variables, options).await?;
+        Ok(single_item_stream(result))
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        vec![self.input_key.clone()]
+    }
+
+    fn get_output_keys(&self) -> Vec<String> {
+        vec![
+            DEFAULT_OUTPUT_KEY.to_string(),
+            DEFAULT_RESULT_KEY.to_string(),
+            MAP_RERANK_DEFAULT_SCORE_KEY.to_string(),
+            DEFAULT_INTERMEDIATE_STEPS_KEY.to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        llm::{FakeLLM, FakeResponse},
+        schemas::Document,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parse_ranked_answer() {
+        assert_eq!(
+            RankedAnswer::parse(" He is 24\nScore: 90\n"),
+            RankedAnswer {
+                answer: "He is 24".to_string(),
+                score: 90.0
+            }
+        );
+        assert_eq!(RankedAnswer::parse("I don't know").score, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_returns_the_best_scored_answer() {
+        let llm = FakeLLM::new()
+            .with_response_when_contains("Nvim", FakeResponse::text("I don't know\nScore: 0"))
+            .with_response_when_contains("24", FakeResponse::text("He is 24\nScore: 90"))
+            .with_response_when_contains("born", FakeResponse::text("Around 24\nScore: 60"));
+        let chain = MapRerankDocumentsBuilder::new()
+            .llm(llm.clone())
+            .build()
+            .unwrap();
+        let documents = [
+            Document::new("Luis uses Nvim"),
+            Document::new("Luis is 24"),
+            Document::new("Luis was born in 2000"),
+        ];
+        let input = chain
+            .qa_prompt_builder()
+            .documents(&documents)
+            .question("How old is Luis?")
+            .build();
+
+        let output = chain.execute(input).await.unwrap();
+
+        assert_eq!(output["output"], "He is 24");
+        assert_eq!(output["score"], 90.0);
+        assert_eq!(
+            output["intermediate_steps"],
+            json!([
+                { "answer": "I don't know", "score": 0.0 },
+                { "answer": "He is 24", "score": 90.0 },
+                { "answer": "Around 24", "score": 60.0 }
+            ])
+        );
+        assert_eq!(
+            output["generate_result"]["tokens"]["completion_tokens"],
+            5 + 5 + 4
+        );
+        assert_eq!(llm.call_count(), 3);
+    }
+}
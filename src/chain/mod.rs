@@ -10,12 +10,29 @@ pub mod llm_chain;
 mod sequential;
 pub use sequential::*;
 
+mod parallel;
+pub use parallel::*;
+
+mod router;
+pub use router::*;
+
 pub mod sql_datbase;
 pub use sql_datbase::*;
 
+mod combine_documents;
+
 mod stuff_documents;
 pub use stuff_documents::*;
 
+mod map_reduce_documents;
+pub use map_reduce_documents::*;
+
+mod refine_documents;
+pub use refine_documents::*;
+
+mod map_rerank_documents;
+pub use map_rerank_documents::*;
+
 mod question_answering;
 pub use question_answering::*;
 
@@ -16,6 +16,9 @@ pub struct ChainCallOptions {
     pub min_length: Option<usize>,
     pub max_length: Option<usize>,
     pub repetition_penalty: Option<f32>,
+    /// Fail with `LLMError::ContextWindowExceeded` before sending a prompt that does not fit
+    /// in the model's context window, see [`LLM::check_context_window`](crate::language_models::llm::LLM::check_context_window).
+    pub check_context_window: Option<bool>,
 }
 
 impl Default for ChainCallOptions {
@@ -37,6 +40,7 @@ impl ChainCallOptions {
             min_length: None,
             max_length: None,
             repetition_penalty: None,
+            check_context_window: None,
         }
     }
 
@@ -130,4 +134,9 @@ impl ChainCallOptions {
         self.repetition_penalty = Some(repetition_penalty);
         self
     }
+
+    pub fn with_check_context_window(mut self, check_context_window: bool) -> Self {
+        self.check_context_window = Some(check_context_window);
+        self
+    }
 }
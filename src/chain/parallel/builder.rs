@@ -0,0 +1,60 @@
+use crate::chain::Chain;
+
+use super::{ParallelChain, ParallelErrorMode};
+
+pub struct ParallelChainBuilder {
+    chains: Vec<(String, Box<dyn Chain>)>,
+    error_mode: ParallelErrorMode,
+}
+
+impl Default for ParallelChainBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParallelChainBuilder {
+    pub fn new() -> Self {
+        Self {
+            chains: Vec::new(),
+            error_mode: ParallelErrorMode::default(),
+        }
+    }
+
+    /// Adds a branch whose output goes under `name`, replacing any branch with that name.
+    pub fn add_chain<S: Into<String>, C: Into<Box<dyn Chain>>>(
+        mut self,
+        name: S,
+        chain: C,
+    ) -> Self {
+        let name = name.into();
+        self.chains.retain(|(branch, _)| *branch != name);
+        self.chains.push((name, chain.into()));
+        self
+    }
+
+    pub fn error_mode(mut self, error_mode: ParallelErrorMode) -> Self {
+        self.error_mode = error_mode;
+        self
+    }
+
+    pub fn build(self) -> ParallelChain {
+        ParallelChain {
+            chains: self.chains,
+            error_mode: self.error_mode,
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! parallel_chain {
+    ( $( $name:expr => $chain:expr ),* $(,)? ) => {
+        {
+            let mut builder = $crate::chain::ParallelChainBuilder::new();
+            $(
+                builder = builder.add_chain($name, $chain);
+            )*
+            builder.build()
+        }
+    };
+}
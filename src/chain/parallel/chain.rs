@@ -0,0 +1,338 @@
+use std::{collections::HashMap, pin::Pin};
+
+use async_trait::async_trait;
+use futures::{
+    future::{join_all, try_join_all},
+    Stream,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    chain::{
+        chain_trait::single_item_stream, Chain, ChainError, DEFAULT_OUTPUT_KEY, DEFAULT_RESULT_KEY,
+    },
+    language_models::{options::CallOptions, GenerateResult, TokenUsage},
+    prompt::PromptArgs,
+    schemas::StreamData,
+};
+
+/// The output key of the errors of the failed branches, in [`ParallelErrorMode::CollectErrors`].
+pub const PARALLEL_ERRORS_KEY: &str = "errors";
+
+/// What a [`ParallelChain`] does when a branch fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParallelErrorMode {
+    /// Fail with the first error, cancelling the branches still running.
+    #[default]
+    FailFast,
+    /// Run every branch and report the errors under `errors`, by branch name, next to the
+    /// outputs of the branches that succeeded.
+    CollectErrors,
+}
+
+/// Runs several named chains concurrently on the same input variables and merges their
+/// outputs.
+///
+/// [`Chain::execute`] returns the output of each branch under its name, all of them as a
+/// JSON object under `output`, and a `generate_result` adding up the token usage of the
+/// branches. Inside a [`SequentialChain`](crate::chain::SequentialChain) the following
+/// chains receive each branch output as an input variable. [`Chain::stream`] yields the
+/// merged output in a single item once every branch is done.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let chain = parallel_chain!(
+///     "summary" => summary_chain,
+///     "entities" => entities_chain,
+///     "sentiment" => sentiment_chain,
+/// );
+///
+/// let output = chain.execute(prompt_args! { "input" => review }).await?;
+/// println!("{}", output["sentiment"]);
+/// ```
+pub struct ParallelChain {
+    pub(crate) chains: Vec<(String, Box<dyn Chain>)>,
+    pub(crate) error_mode: ParallelErrorMode,
+}
+
+impl ParallelChain {
+    /// Runs the branches, returning the output of each one by name, their combined result
+    /// and the errors of the failed ones.
+    async fn run(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<(Map<String, Value>, GenerateResult, Map<String, Value>), ChainError> {
+        let calls = self.chains.iter().map(|(name, chain)| {
+            let input_variables = input_variables.clone();
+            async move {
+                let output = match options {
+                    Some(options) => chain.execute_with_options(input_variables, options).await,
+                    None => chain.execute(input_variables).await,
+                };
+                (name, chain, output)
+            }
+        });
+        let outputs = match self.error_mode {
+            ParallelErrorMode::FailFast => {
+                try_join_all(calls.map(|call| async move {
+                    let (name, chain, output) = call.await;
+                    output.map(|output| (name, chain, Ok(output)))
+                }))
+                .await?
+            }
+            ParallelErrorMode::CollectErrors => join_all(calls).await,
+        };
+
+        let mut branches = Map::new();
+        let mut errors = Map::new();
+        let mut tokens: Option<TokenUsage> = None;
+        for (name, chain, output) in outputs {
+            let output = match output {
+                Ok(output) => output,
+                Err(error) => {
+                    errors.insert(name.clone(), json!(error.to_string()));
+                    continue;
+                }
+            };
+            let result: GenerateResult = match output.get(DEFAULT_RESULT_KEY) {
+                Some(result) => serde_json::from_value(result.clone())?,
+                None => GenerateResult::default(),
+            };
+            if let Some(usage) = &result.tokens {
+                tokens.get_or_insert_with(TokenUsage::default).add(usage);
+            }
+            let chain_output = chain
+                .get_output_keys()
+                .first()
+                .and_then(|output_key| output.get(output_key).cloned())
+                .unwrap_or_else(|| json!(result.generation));
+            branches.insert(name.clone(), chain_output);
+        }
+
+        let result = GenerateResult {
+            generation: Value::Object(branches.clone()).to_string(),
+            tokens,
+            ..Default::default()
+        };
+        Ok((branches, result, errors))
+    }
+
+    async fn execute_branches(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let (branches, result, errors) = self.run(input_variables, options).await?;
+        let mut output: HashMap<String, Value> = branches.clone().into_iter().collect();
+        output.insert(DEFAULT_OUTPUT_KEY.to_string(), Value::Object(branches));
+        output.insert(DEFAULT_RESULT_KEY.to_string(), json!(result));
+        if !errors.is_empty() {
+            output.insert(PARALLEL_ERRORS_KEY.to_string(), Value::Object(errors));
+        }
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl Chain for ParallelChain {
+    /// The generation is the JSON object of the branch outputs.
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        Ok(self.run(input_variables, None).await?.1)
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        Ok(self.run(input_variables, Some(options)).await?.1)
+    }
+
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.execute_branches(input_variables, None).await
+    }
+
+    async fn execute_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.execute_branches(input_variables, Some(options)).await
+    }
+
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let result = self.call(input_variables).await?;
+        Ok(single_item_stream(result))
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let result = self.call_with_options(input_variables, options).await?;
+        Ok(single_item_stream(result))
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .chains
+            .iter()
+            .flat_map(|(_, chain)| chain.get_input_keys())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    fn get_output_keys(&self) -> Vec<String> {
+        let mut keys = vec![DEFAULT_OUTPUT_KEY.to_string()];
+        keys.extend(self.chains.iter().map(|(name, _)| name.clone()));
+        keys.push(DEFAULT_RESULT_KEY.to_string());
+        if self.error_mode == ParallelErrorMode::CollectErrors {
+            keys.push(PARALLEL_ERRORS_KEY.to_string());
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use crate::{
+        chain::{llm_chain::fake_chain, LLMChainBuilder, ParallelChainBuilder},
+        llm::{FakeLLM, FakeResponse},
+        parallel_chain, prompt_args, sequential_chain, template_jinja2,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_merges_branch_outputs() {
+        let chain = parallel_chain!(
+            "summary" => fake_chain(FakeResponse::text("A good phone")),
+            "sentiment" => fake_chain(FakeResponse::text("positive")),
+        );
+
+        let output = chain
+            .execute(prompt_args! { "input" => "I love this phone" })
+            .await
+            .unwrap();
+
+        assert_eq!(output["summary"], "A good phone");
+        assert_eq!(output["sentiment"], "positive");
+        assert_eq!(
+            output["output"],
+            json!({ "summary": "A good phone", "sentiment": "positive" })
+        );
+        assert_eq!(output["generate_result"]["tokens"]["completion_tokens"], 4);
+        assert!(!output.contains_key(PARALLEL_ERRORS_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_error_modes() {
+        let branches = || {
+            ParallelChainBuilder::new()
+                .add_chain("summary", fake_chain(FakeResponse::text("A good phone")))
+                .add_chain("sentiment", fake_chain(FakeResponse::error("rate limited")))
+        };
+
+        let fail_fast = branches().build();
+        assert!(fail_fast
+            .execute(prompt_args! { "input" => "I love this phone" })
+            .await
+            .is_err());
+
+        let collect_errors = branches()
+            .error_mode(ParallelErrorMode::CollectErrors)
+            .build();
+        let output = collect_errors
+            .execute(prompt_args! { "input" => "I love this phone" })
+            .await
+            .unwrap();
+        assert_eq!(output["summary"], "A good phone");
+        assert!(!output.contains_key("sentiment"));
+        assert!(output["errors"]["sentiment"]
+            .as_str()
+            .unwrap()
+            .contains("rate limited"));
+    }
+
+    #[tokio::test]
+    async fn test_streams_with_call_options() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("positive"));
+        let sentiment = LLMChainBuilder::new()
+            .prompt(template_jinja2!("{{input}}", "input"))
+            .llm(llm.clone())
+            .build()
+            .unwrap();
+        let chain = parallel_chain!(
+            "summary" => fake_chain(FakeResponse::text("A good phone")),
+            "sentiment" => sentiment,
+        );
+
+        let stream = chain
+            .stream_with_options(
+                prompt_args! { "input" => "I love this phone" },
+                &CallOptions::new().with_temperature(0.0),
+            )
+            .await
+            .unwrap();
+        let data: Vec<StreamData> = stream.map(|data| data.unwrap()).collect().await;
+
+        assert_eq!(data.len(), 1);
+        let output: Value = serde_json::from_str(&data[0].content).unwrap();
+        assert_eq!(
+            output,
+            json!({ "summary": "A good phone", "sentiment": "positive" })
+        );
+        assert_eq!(data[0].tokens.as_ref().unwrap().completion_tokens, 4);
+        assert_eq!(llm.last_options().unwrap().temperature, Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn test_composes_in_sequential_chain() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("Reply sent"));
+        let reply = LLMChainBuilder::new()
+            .prompt(template_jinja2!(
+                "Reply to a {{sentiment}} review: {{summary}}",
+                "sentiment",
+                "summary"
+            ))
+            .llm(llm.clone())
+            .output_key("reply")
+            .build()
+            .unwrap();
+        let chain = sequential_chain!(
+            parallel_chain!(
+                "summary" => fake_chain(FakeResponse::text("A good phone")),
+                "sentiment" => fake_chain(FakeResponse::text("positive")),
+            ),
+            reply
+        );
+
+        let output = chain
+            .execute(prompt_args! { "input" => "I love this phone" })
+            .await
+            .unwrap();
+
+        assert_eq!(output["reply"], "Reply sent");
+        assert_eq!(output["summary"], "A good phone");
+        assert_eq!(
+            llm.last_request().unwrap()[0].content,
+            "Reply to a positive review: A good phone"
+        );
+        assert_eq!(output["generate_result"]["tokens"]["completion_tokens"], 6);
+    }
+}
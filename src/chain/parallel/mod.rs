@@ -0,0 +1,5 @@
+mod builder;
+mod chain;
+
+pub use builder::*;
+pub use chain::*;
@@ -4,8 +4,13 @@ use async_trait::async_trait;
 use futures::Stream;
 
 use crate::{
-    language_models::{llm::LLM, GenerateResult},
-    prompt::PromptArgs,
+    fmt_template,
+    language_models::{llm::LLM, options::CallOptions, GenerateResult},
+    message_formatter,
+    prompt::{
+        HumanMessagePromptTemplate, MessageFormatterStruct, PromptArgs, PromptTemplate,
+        SystemMessagePromptTemplate,
+    },
     prompt_args,
     schemas::{messages::Message, Document, StreamData},
     template_jinja2,
@@ -81,6 +86,14 @@ impl Chain for CondenseQuestionGeneratorChain {
         self.chain.call(input_variables).await
     }
 
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        self.chain.call_with_options(input_variables, options).await
+    }
+
     async fn stream(
         &self,
         input_variables: PromptArgs,
@@ -88,6 +101,17 @@ impl Chain for CondenseQuestionGeneratorChain {
     {
         self.chain.stream(input_variables).await
     }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.chain
+            .stream_with_options(input_variables, options)
+            .await
+    }
 }
 
 const DEFAULT_STUFF_QA_TEMPLATE: &str = r#"Use the following pieces of context to answer the question at the end. If you don't know the answer, just say that you don't know, don't try to make up an answer.
@@ -98,6 +122,35 @@ Question:{{question}}
 Helpful Answer:
 "#;
 
+const DEFAULT_STUFF_QA_CONTEXT_TEMPLATE: &str = r#"Use the following pieces of context to answer the question at the end. If you don't know the answer, just say that you don't know, don't try to make up an answer.
+
+{{context}}"#;
+
+const DEFAULT_STUFF_QA_QUESTION_TEMPLATE: &str = r#"Question:{{question}}
+Helpful Answer:
+"#;
+
+pub(crate) fn stuff_qa_prompt() -> PromptTemplate {
+    template_jinja2!(DEFAULT_STUFF_QA_TEMPLATE, "context", "question")
+}
+
+/// The default stuff QA prompt with the retrieved context in its own system message, marked
+/// as a cache breakpoint, so follow up questions over the same documents reuse the cached
+/// prefix.
+pub(crate) fn cached_stuff_qa_prompt() -> MessageFormatterStruct {
+    message_formatter![
+        fmt_template!(SystemMessagePromptTemplate::new(template_jinja2!(
+            DEFAULT_STUFF_QA_CONTEXT_TEMPLATE,
+            "context"
+        ))
+        .with_cache_breakpoint()),
+        fmt_template!(HumanMessagePromptTemplate::new(template_jinja2!(
+            DEFAULT_STUFF_QA_QUESTION_TEMPLATE,
+            "question"
+        ))),
+    ]
+}
+
 pub struct StuffQAPromptBuilder<'a> {
     input_documents: Vec<&'a Document>,
     question: String,
@@ -133,11 +186,8 @@ pub(crate) fn load_stuff_qa<L: Into<Box<dyn LLM>>>(
     llm: L,
     options: Option<ChainCallOptions>,
 ) -> StuffDocument {
-    let default_qa_prompt_template =
-        template_jinja2!(DEFAULT_STUFF_QA_TEMPLATE, "context", "question");
-
     let llm_chain_builder = LLMChainBuilder::new()
-        .prompt(default_qa_prompt_template)
+        .prompt(stuff_qa_prompt())
         .options(options.unwrap_or_default())
         .llm(llm)
         .build()
@@ -151,11 +201,58 @@ pub(crate) fn load_stuff_qa<L: Into<Box<dyn LLM>>>(
 #[cfg(test)]
 mod tests {
     use crate::{
-        chain::{Chain, StuffDocument},
-        llm::openai::OpenAI,
-        schemas::Document,
+        chain::{Chain, StuffDocument, StuffDocumentBuilder},
+        llm::{openai::OpenAI, FakeLLM, FakeResponse},
+        schemas::{Document, MessageType},
     };
 
+    #[tokio::test]
+    async fn test_stuff_qa_sends_a_single_message_by_default() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("24"));
+        let chain = StuffDocument::load_stuff_qa(llm.clone());
+        let documents = [Document::new("Luis is 24 years old")];
+        let input = chain
+            .qa_prompt_builder()
+            .documents(&documents)
+            .question("How old is Luis?")
+            .build();
+
+        assert_eq!(chain.invoke(input).await.unwrap(), "24");
+
+        let request = llm.last_request().unwrap();
+        assert_eq!(request.len(), 1);
+        assert_eq!(request[0].message_type, MessageType::HumanMessage);
+        assert!(request[0].content.contains("Luis is 24 years old"));
+        assert!(request[0].content.contains("How old is Luis?"));
+        assert!(request[0].cache_control.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stuff_qa_caches_the_context() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("24"));
+        let chain = StuffDocumentBuilder::new()
+            .llm(llm.clone())
+            .cache_context(true)
+            .build()
+            .unwrap();
+        let documents = [Document::new("Luis is 24 years old")];
+        let input = chain
+            .qa_prompt_builder()
+            .documents(&documents)
+            .question("How old is Luis?")
+            .build();
+
+        assert_eq!(chain.invoke(input).await.unwrap(), "24");
+
+        let request = llm.last_request().unwrap();
+        assert_eq!(request.len(), 2);
+        assert_eq!(request[0].message_type, MessageType::SystemMessage);
+        assert!(request[0].content.contains("Luis is 24 years old"));
+        assert!(request[0].cache_control.is_some());
+        assert!(request[1].content.contains("How old is Luis?"));
+        assert!(request[1].cache_control.is_none());
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_qa() {
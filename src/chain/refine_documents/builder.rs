@@ -0,0 +1,97 @@
+use crate::{
+    chain::{ChainError, LLMChainBuilder},
+    language_models::llm::LLM,
+    prompt::FormatPrompter,
+    template_jinja2,
+};
+
+use super::RefineDocuments;
+
+pub struct RefineDocumentsBuilder {
+    llm: Option<Box<dyn LLM>>,
+    initial_prompt: Option<Box<dyn FormatPrompter>>,
+    refine_prompt: Option<Box<dyn FormatPrompter>>,
+}
+
+impl Default for RefineDocumentsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RefineDocumentsBuilder {
+    pub fn new() -> Self {
+        Self {
+            llm: None,
+            initial_prompt: None,
+            refine_prompt: None,
+        }
+    }
+
+    pub fn llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llm = Some(llm.into());
+        self
+    }
+
+    ///Prompt answering from the first document, with the `context` and `question` variables
+    ///by default.
+    pub fn initial_prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.initial_prompt = Some(prompt.into());
+        self
+    }
+
+    ///Prompt refining the answer with each following document. It also receives the
+    ///`existing_answer` variable.
+    pub fn refine_prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.refine_prompt = Some(prompt.into());
+        self
+    }
+
+    pub fn build(self) -> Result<RefineDocuments, ChainError> {
+        let llm = self
+            .llm
+            .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
+        let initial_prompt = self.initial_prompt.unwrap_or_else(|| {
+            Box::new(template_jinja2!(
+                DEFAULT_REFINE_QA_INITIAL_TEMPLATE,
+                "context",
+                "question"
+            ))
+        });
+        let refine_prompt = self.refine_prompt.unwrap_or_else(|| {
+            Box::new(template_jinja2!(
+                DEFAULT_REFINE_QA_REFINE_TEMPLATE,
+                "context",
+                "question",
+                "existing_answer"
+            ))
+        });
+
+        let initial_chain = LLMChainBuilder::new()
+            .prompt(initial_prompt)
+            .llm(llm.clone_box())
+            .build()?;
+        let refine_chain = LLMChainBuilder::new()
+            .prompt(refine_prompt)
+            .llm(llm)
+            .build()?;
+
+        Ok(RefineDocuments::new(initial_chain, refine_chain))
+    }
+}
+
+const DEFAULT_REFINE_QA_INITIAL_TEMPLATE: &str = r#"Context information is below.
+---------------------
+{{context}}
+---------------------
+Given the context information and not prior knowledge, answer the question: {{question}}
+"#;
+
+const DEFAULT_REFINE_QA_REFINE_TEMPLATE: &str = r#"The original question is as follows: {{question}}
+We have provided an existing answer: {{existing_answer}}
+We have the opportunity to refine the existing answer (only if needed) with some more context below.
+------------
+{{context}}
+------------
+Given the new context, refine the original answer to better answer the question. If the context isn't useful, return the original answer.
+"#;
@@ -0,0 +1,347 @@
+use std::{collections::HashMap, pin::Pin};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+
+use crate::{
+    chain::{
+        combine_documents::{
+            input_documents, COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME,
+            COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY,
+        },
+        Chain, ChainError, LLMChain, StuffQAPromptBuilder, DEFAULT_INTERMEDIATE_STEPS_KEY,
+        DEFAULT_OUTPUT_KEY, DEFAULT_RESULT_KEY,
+    },
+    language_models::{llm::LLM, options::CallOptions, GenerateResult, TokenUsage},
+    prompt::PromptArgs,
+    schemas::StreamData,
+};
+
+use super::RefineDocumentsBuilder;
+
+const REFINE_DOCUMENTS_DEFAULT_EXISTING_ANSWER_VARIABLE_NAME: &str = "existing_answer";
+
+/// Builds an answer from the first document, then refines it with each of the following
+/// documents in turn, one call per document.
+///
+/// The initial chain receives the document as `context`, the refine chain receives it along
+/// with the answer so far as `existing_answer`. [`Chain::execute`] returns the answer after
+/// each document under `intermediate_steps`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let chain = RefineDocuments::load_refine_qa(llm);
+///
+/// let input = chain
+///     .qa_prompt_builder()
+///     .documents(&documents)
+///     .question("How old is luis and whats his favorite text editor")
+///     .build();
+///
+/// let ouput = chain.invoke(input).await.unwrap();
+/// ```
+pub struct RefineDocuments {
+    pub(crate) initial_chain: LLMChain,
+    pub(crate) refine_chain: LLMChain,
+    pub(crate) input_key: String,
+    pub(crate) document_variable_name: String,
+    pub(crate) existing_answer_variable_name: String,
+}
+
+impl RefineDocuments {
+    pub fn new(initial_chain: LLMChain, refine_chain: LLMChain) -> Self {
+        Self {
+            initial_chain,
+            refine_chain,
+            input_key: COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY.to_string(),
+            document_variable_name: COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME.to_string(),
+            existing_answer_variable_name: REFINE_DOCUMENTS_DEFAULT_EXISTING_ANSWER_VARIABLE_NAME
+                .to_string(),
+        }
+    }
+
+    /// load_refine_qa returns an instance of RefineDocuments with prompts designed for
+    /// question answering.
+    pub fn load_refine_qa<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        RefineDocumentsBuilder::new().llm(llm).build().unwrap() // Its safe to unwrap here because the LLM is set.
+    }
+
+    ///Only use this if you use the default prompts
+    pub fn qa_prompt_builder<'a>(&self) -> StuffQAPromptBuilder<'a> {
+        StuffQAPromptBuilder::new()
+    }
+
+    /// The input variables of each step, the first one for the initial chain and the
+    /// others for the refine chain without the existing answer.
+    fn steps(&self, input_variables: &PromptArgs) -> Result<Vec<PromptArgs>, ChainError> {
+        let documents = input_documents(input_variables, &self.input_key)?;
+
+        let steps = documents
+            .into_iter()
+            .map(|document| {
+                let mut step = input_variables.clone();
+                step.insert(
+                    self.document_variable_name.clone(),
+                    Value::String(document.page_content),
+                );
+                step
+            })
+            .collect::<Vec<_>>();
+        if steps.is_empty() {
+            // Answer without context rather than failing on an empty retrieval
+            let mut step = input_variables.clone();
+            step.insert(
+                self.document_variable_name.clone(),
+                Value::String(String::new()),
+            );
+            return Ok(vec![step]);
+        }
+        Ok(steps)
+    }
+
+    fn with_existing_answer(&self, mut step: PromptArgs, existing_answer: &str) -> PromptArgs {
+        step.insert(
+            self.existing_answer_variable_name.clone(),
+            Value::String(existing_answer.to_string()),
+        );
+        step
+    }
+
+    /// Runs every step but the last one, returning the input of the last call, the chain
+    /// making it and the results so far.
+    async fn refine_until_last(
+        &self,
+        input_variables: &PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<(PromptArgs, &LLMChain, Vec<GenerateResult>), ChainError> {
+        let mut steps = self.steps(input_variables)?;
+        let last = steps.pop().unwrap_or_default();
+        let mut results: Vec<GenerateResult> = Vec::new();
+        for step in steps {
+            let (chain, step) = self.next_call(step, &results);
+            let result = match options {
+                Some(options) => chain.call_with_options(step, options).await?,
+                None => chain.call(step).await?,
+            };
+            results.push(result);
+        }
+        let (chain, last) = self.next_call(last, &results);
+        Ok((last, chain, results))
+    }
+
+    fn next_call(&self, step: PromptArgs, results: &[GenerateResult]) -> (&LLMChain, PromptArgs) {
+        match results.last() {
+            Some(result) => (
+                &self.refine_chain,
+                self.with_existing_answer(step, &result.generation),
+            ),
+            None => (&self.initial_chain, step),
+        }
+    }
+
+    async fn refine(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<(GenerateResult, Vec<String>), ChainError> {
+        let (last, chain, mut results) = self.refine_until_last(&input_variables, options).await?;
+        let last = match options {
+            Some(options) => chain.call_with_options(last, options).await?,
+            None => chain.call(last).await?,
+        };
+        results.push(last);
+
+        let steps = results
+            .iter()
+            .map(|result| result.generation.clone())
+            .collect();
+        let tokens = total_tokens(&results);
+        let mut result = results.pop().unwrap_or_default();
+        result.tokens = tokens;
+        Ok((result, steps))
+    }
+
+    /// Streams the last refinement, after making the calls for the previous documents.
+    async fn stream_refine(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let (last, chain, results) = self.refine_until_last(&input_variables, options).await?;
+        let tokens = total_tokens(&results);
+        let stream = match options {
+            Some(options) => chain.stream_with_options(last, options).await?,
+            None => chain.stream(last).await?,
+        };
+        // The usage reported by the last stream covers its own call only
+        Ok(Box::pin(stream.map(move |data| {
+            data.map(|mut data| {
+                if let (Some(total), Some(tokens)) = (data.tokens.as_mut(), tokens.as_ref()) {
+                    total.add(tokens);
+                }
+                data
+            })
+        })))
+    }
+
+    async fn execute_refine(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let (result, steps) = self.refine(input_variables, options).await?;
+        let mut output = HashMap::new();
+        output.insert(DEFAULT_OUTPUT_KEY.to_string(), json!(result.generation));
+        output.insert(DEFAULT_RESULT_KEY.to_string(), json!(result));
+        output.insert(DEFAULT_INTERMEDIATE_STEPS_KEY.to_string(), json!(steps));
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl Chain for RefineDocuments {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        Ok(self.refine(input_variables, None).await?.0)
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        Ok(self.refine(input_variables, Some(options)).await?.0)
+    }
+
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.execute_refine(input_variables, None).await
+    }
+
+    async fn execute_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.execute_refine(input_variables, Some(options)).await
+    }
+
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.stream_refine(input_variables, None).await
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.stream_refine(input_variables, Some(options)).await
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        vec![self.input_key.clone()]
+    }
+
+    fn get_output_keys(&self) -> Vec<String> {
+        vec![
+            DEFAULT_OUTPUT_KEY.to_string(),
+            DEFAULT_RESULT_KEY.to_string(),
+            DEFAULT_INTERMEDIATE_STEPS_KEY.to_string(),
+        ]
+    }
+}
+
+fn total_tokens(results: &[GenerateResult]) -> Option<TokenUsage> {
+    let mut tokens: Option<TokenUsage> = None;
+    for usage in results.iter().filter_map(|result| result.tokens.as_ref()) {
+        tokens.get_or_insert_with(TokenUsage::default).add(usage);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        llm::{FakeLLM, FakeResponse},
+        schemas::Document,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refines_document_by_document() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::text("Luis is 24"),
+            FakeResponse::text("Luis is 24 and uses Nvim"),
+            FakeResponse::text("Luis is 24, uses Nvim and lives in Peru"),
+        ]);
+        let chain = RefineDocuments::load_refine_qa(llm.clone());
+        let documents = [
+            Document::new("Luis is 24"),
+            Document::new("Luis uses Nvim"),
+            Document::new("Luis lives in Peru"),
+        ];
+        let input = chain
+            .qa_prompt_builder()
+            .documents(&documents)
+            .question("Who is Luis?")
+            .build();
+
+        let output = chain.execute(input).await.unwrap();
+
+        assert_eq!(output["output"], "Luis is 24, uses Nvim and lives in Peru");
+        assert_eq!(
+            output["intermediate_steps"],
+            json!([
+                "Luis is 24",
+                "Luis is 24 and uses Nvim",
+                "Luis is 24, uses Nvim and lives in Peru"
+            ])
+        );
+        assert_eq!(
+            output["generate_result"]["tokens"]["completion_tokens"],
+            3 + 6 + 9
+        );
+        let requests = llm.requests();
+        assert!(requests[0][0].content.contains("Luis is 24"));
+        assert!(!requests[0][0].content.contains("existing answer"));
+        let refine_prompt = &requests[2][0].content;
+        assert!(refine_prompt.contains("existing answer: Luis is 24 and uses Nvim"));
+        assert!(refine_prompt.contains("Luis lives in Peru"));
+        assert!(refine_prompt.contains("Who is Luis?"));
+    }
+
+    #[tokio::test]
+    async fn test_streams_the_last_refinement() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::text("Luis is 24"),
+            FakeResponse::chunks(vec!["Luis is 24 ", "and uses Nvim"]),
+        ]);
+        let chain = RefineDocuments::load_refine_qa(llm.clone());
+        let documents = [Document::new("Luis is 24"), Document::new("Luis uses Nvim")];
+        let input = chain
+            .qa_prompt_builder()
+            .documents(&documents)
+            .question("Who is Luis?")
+            .build();
+
+        let stream = chain.stream(input).await.unwrap();
+        let data: Vec<StreamData> = stream.map(|data| data.unwrap()).collect().await;
+
+        let content: String = data.iter().map(|data| data.content.as_str()).collect();
+        assert_eq!(content, "Luis is 24 and uses Nvim");
+        assert_eq!(llm.call_count(), 2);
+        // The usage of the first call is added to the one of the streamed call
+        let tokens = data.iter().find_map(|data| data.tokens.clone()).unwrap();
+        assert_eq!(tokens.completion_tokens, 3 + 6);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use crate::{
+    chain::{Chain, ChainError},
+    language_models::llm::LLM,
+    prompt::PromptArgs,
+    semantic_router::RouteLayer,
+};
+
+use super::{
+    Destination, LLMRouteSelector, PredicateRouteSelector, RouteSelector, RouterChain,
+    SemanticRouteSelector,
+};
+
+pub struct RouterChainBuilder {
+    selector: Option<Box<dyn RouteSelector>>,
+    destinations: Vec<Destination>,
+    chains: HashMap<String, Box<dyn Chain>>,
+    default_chain: Option<Box<dyn Chain>>,
+}
+
+impl Default for RouterChainBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RouterChainBuilder {
+    pub fn new() -> Self {
+        Self {
+            selector: None,
+            destinations: Vec::new(),
+            chains: HashMap::new(),
+            default_chain: None,
+        }
+    }
+
+    /// Adds a destination. The description tells an LLM selector what the chain is good for.
+    pub fn route<S: Into<String>, C: Into<Box<dyn Chain>>>(
+        mut self,
+        name: S,
+        description: S,
+        chain: C,
+    ) -> Self {
+        let name = name.into();
+        self.destinations
+            .retain(|destination| destination.name != name);
+        self.destinations.push(Destination {
+            name: name.clone(),
+            description: description.into(),
+        });
+        self.chains.insert(name, chain.into());
+        self
+    }
+
+    /// The chain running when no destination is selected.
+    pub fn default_chain<C: Into<Box<dyn Chain>>>(mut self, chain: C) -> Self {
+        self.default_chain = Some(chain.into());
+        self
+    }
+
+    pub fn selector<S: RouteSelector + 'static>(mut self, selector: S) -> Self {
+        self.selector = Some(Box::new(selector));
+        self
+    }
+
+    /// Selects with an LLM classifying the `input` variable, see [`LLMRouteSelector`].
+    pub fn llm<L: Into<Box<dyn LLM>>>(self, llm: L) -> Self {
+        self.selector(LLMRouteSelector::new(llm))
+    }
+
+    /// Selects with the routes of `route_layer` matching the `input` variable, see
+    /// [`SemanticRouteSelector`].
+    pub fn route_layer(self, route_layer: RouteLayer) -> Self {
+        self.selector(SemanticRouteSelector::new(route_layer))
+    }
+
+    /// Selects the destination named by `predicate`, or the default chain on `None`.
+    pub fn predicate<F>(self, predicate: F) -> Self
+    where
+        F: Fn(&PromptArgs) -> Option<String> + Send + Sync + 'static,
+    {
+        self.selector(PredicateRouteSelector::new(predicate))
+    }
+
+    pub fn build(self) -> Result<RouterChain, ChainError> {
+        let selector = self
+            .selector
+            .ok_or_else(|| ChainError::MissingObject("Route selector must be set".into()))?;
+        Ok(RouterChain {
+            selector,
+            destinations: self.destinations,
+            chains: self.chains,
+            default_chain: self.default_chain,
+        })
+    }
+}
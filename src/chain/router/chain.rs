@@ -0,0 +1,272 @@
+use std::{collections::HashMap, pin::Pin};
+
+use async_trait::async_trait;
+use futures::Stream;
+use serde_json::{json, Value};
+
+use crate::{
+    chain::{Chain, ChainError},
+    language_models::{options::CallOptions, GenerateResult},
+    prompt::PromptArgs,
+    schemas::StreamData,
+};
+
+use super::{Destination, RouteSelector};
+
+/// The output key of the route taken by [`RouterChain::execute`](Chain::execute).
+pub const ROUTER_ROUTE_KEY: &str = "route";
+/// The route reported when the default chain runs.
+pub const ROUTER_DEFAULT_ROUTE: &str = "default";
+
+/// Sends the input variables to one of several named chains, picked by a
+/// [`RouteSelector`]: an LLM classifying the input, a semantic
+/// [`RouteLayer`](crate::semantic_router::RouteLayer) or a predicate.
+///
+/// When the selector picks no destination, or one that does not exist, the default chain
+/// runs. [`Chain::execute`] adds the route taken under `route`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let chain = RouterChainBuilder::new()
+///     .route("physics", "Good for answering questions about physics", physics_chain)
+///     .route("math", "Good for answering math questions", math_chain)
+///     .default_chain(general_chain)
+///     .llm(llm)
+///     .build()?;
+///
+/// let output = chain.execute(prompt_args! { "input" => "What is black body radiation?" }).await?;
+/// println!("{}: {}", output["route"], output["output"]);
+/// ```
+pub struct RouterChain {
+    pub(crate) selector: Box<dyn RouteSelector>,
+    pub(crate) destinations: Vec<Destination>,
+    pub(crate) chains: HashMap<String, Box<dyn Chain>>,
+    pub(crate) default_chain: Option<Box<dyn Chain>>,
+}
+
+impl RouterChain {
+    /// The route for the input variables and the chain to run.
+    async fn route(&self, input_variables: &PromptArgs) -> Result<(&str, &dyn Chain), ChainError> {
+        let route = self
+            .selector
+            .select(input_variables, &self.destinations)
+            .await?;
+        if let Some((name, chain)) = route.and_then(|route| self.chains.get_key_value(&route)) {
+            return Ok((name.as_str(), chain.as_ref()));
+        }
+        match &self.default_chain {
+            Some(chain) => Ok((ROUTER_DEFAULT_ROUTE, chain.as_ref())),
+            None => Err(ChainError::OtherError(
+                "No route selected and no default chain set".to_string(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl Chain for RouterChain {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        let (route, chain) = self.route(&input_variables).await?;
+        log::debug!("routing to {}", route);
+        chain.call(input_variables).await
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        let (_, chain) = self.route(&input_variables).await?;
+        chain.call_with_options(input_variables, options).await
+    }
+
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let (route, chain) = self.route(&input_variables).await?;
+        let mut output = chain.execute(input_variables).await?;
+        output.insert(ROUTER_ROUTE_KEY.to_string(), json!(route));
+        Ok(output)
+    }
+
+    async fn execute_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let (route, chain) = self.route(&input_variables).await?;
+        let mut output = chain.execute_with_options(input_variables, options).await?;
+        output.insert(ROUTER_ROUTE_KEY.to_string(), json!(route));
+        Ok(output)
+    }
+
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let (_, chain) = self.route(&input_variables).await?;
+        chain.stream(input_variables).await
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let (_, chain) = self.route(&input_variables).await?;
+        chain.stream_with_options(input_variables, options).await
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .chains
+            .values()
+            .chain(self.default_chain.iter())
+            .flat_map(|chain| chain.get_input_keys())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chain::{llm_chain::fake_chain, RouterChainBuilder},
+        embedding::FakeEmbedder,
+        llm::{FakeLLM, FakeResponse},
+        prompt_args,
+        semantic_router::{MemoryIndex, RouteLayerBuilder, Router},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_routes_with_a_predicate() {
+        let chain = RouterChainBuilder::new()
+            .route(
+                "math",
+                "Math questions",
+                fake_chain(FakeResponse::text("4")),
+            )
+            .default_chain(fake_chain(FakeResponse::text("I don't know")))
+            .predicate(|input| {
+                input["input"]
+                    .as_str()
+                    .filter(|input| input.contains('+'))
+                    .map(|_| "math".to_string())
+            })
+            .build()
+            .unwrap();
+
+        let output = chain
+            .execute(prompt_args! { "input" => "2 + 2" })
+            .await
+            .unwrap();
+        assert_eq!(output["route"], "math");
+        assert_eq!(output["output"], "4");
+
+        let output = chain
+            .execute(prompt_args! { "input" => "Who is Luis?" })
+            .await
+            .unwrap();
+        assert_eq!(output["route"], ROUTER_DEFAULT_ROUTE);
+        assert_eq!(output["output"], "I don't know");
+
+        let without_default = RouterChainBuilder::new()
+            .route(
+                "math",
+                "Math questions",
+                fake_chain(FakeResponse::text("4")),
+            )
+            .predicate(|_| None)
+            .build()
+            .unwrap();
+        assert!(without_default
+            .call(prompt_args! { "input" => "Who is Luis?" })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_routes_with_an_llm() {
+        let classifier = FakeLLM::new()
+            .with_response_when_contains("2 + 2", FakeResponse::text(" Math.\n"))
+            .with_default_response(FakeResponse::text("DEFAULT"));
+        let chain = RouterChainBuilder::new()
+            .route(
+                "physics",
+                "Good for physics questions",
+                fake_chain(FakeResponse::text("Heat")),
+            )
+            .route(
+                "math",
+                "Good for math questions",
+                fake_chain(FakeResponse::text("4")),
+            )
+            .default_chain(fake_chain(FakeResponse::text("I don't know")))
+            .llm(classifier.clone())
+            .build()
+            .unwrap();
+
+        let output = chain
+            .execute(prompt_args! { "input" => "2 + 2" })
+            .await
+            .unwrap();
+        assert_eq!(output["route"], "math");
+        assert_eq!(output["output"], "4");
+        let prompt = &classifier.last_request().unwrap()[0].content;
+        assert!(prompt.contains("physics: Good for physics questions"));
+        assert!(prompt.contains("math: Good for math questions"));
+
+        let answer = chain
+            .invoke(prompt_args! { "input" => "Who is Luis?" })
+            .await
+            .unwrap();
+        assert_eq!(answer, "I don't know");
+    }
+
+    #[tokio::test]
+    async fn test_routes_with_a_route_layer() {
+        let route_layer = RouteLayerBuilder::new()
+            .embedder(FakeEmbedder::new())
+            .llm(FakeLLM::new())
+            .index(MemoryIndex::new())
+            .threshold(0.6)
+            .add_route(Router::new(
+                "weather",
+                &["What is the temperature?", "Is it raining?"],
+            ))
+            .build()
+            .await
+            .unwrap();
+        let chain = RouterChainBuilder::new()
+            .route(
+                "weather",
+                "Weather questions",
+                fake_chain(FakeResponse::text("Sunny")),
+            )
+            .default_chain(fake_chain(FakeResponse::text("I don't know")))
+            .route_layer(route_layer)
+            .build()
+            .unwrap();
+
+        let output = chain
+            .execute(prompt_args! { "input" => "What is the temperature in Lima?" })
+            .await
+            .unwrap();
+        assert_eq!(output["route"], "weather");
+        assert_eq!(output["output"], "Sunny");
+
+        let output = chain
+            .execute(prompt_args! { "input" => "Tell me a joke" })
+            .await
+            .unwrap();
+        assert_eq!(output["route"], ROUTER_DEFAULT_ROUTE);
+    }
+}
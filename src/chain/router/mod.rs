@@ -0,0 +1,8 @@
+mod chain;
+pub use chain::*;
+
+mod selector;
+pub use selector::*;
+
+mod builder;
+pub use builder::*;
@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    chain::{Chain, ChainError, LLMChain, LLMChainBuilder},
+    language_models::llm::LLM,
+    prompt::PromptArgs,
+    prompt_args,
+    semantic_router::RouteLayer,
+    template_jinja2,
+};
+
+/// A destination of a [`RouterChain`](super::RouterChain), as shown to the selectors.
+#[derive(Debug, Clone)]
+pub struct Destination {
+    pub name: String,
+    pub description: String,
+}
+
+/// Picks the destination of a [`RouterChain`](super::RouterChain) for a set of input
+/// variables.
+#[async_trait]
+pub trait RouteSelector: Send + Sync {
+    /// Returns the name of one of `destinations`, or `None` to use the default chain.
+    async fn select(
+        &self,
+        input_variables: &PromptArgs,
+        destinations: &[Destination],
+    ) -> Result<Option<String>, ChainError>;
+}
+
+/// The text of `input_key` in the input variables, as the selectors classify it.
+fn input_text(input_variables: &PromptArgs, input_key: &str) -> Result<String, ChainError> {
+    match input_variables.get(input_key) {
+        Some(Value::String(text)) => Ok(text.clone()),
+        Some(value) => Ok(value.to_string()),
+        None => Err(ChainError::MissingInputVariable(input_key.to_string())),
+    }
+}
+
+const DEFAULT_ROUTER_INPUT_KEY: &str = "input";
+
+const DEFAULT_ROUTER_TEMPLATE: &str = r#"Given a raw text input to a language model select the model prompt best suited for the input. You will be given the names of the available prompts and a description of what the prompt is best suited for.
+
+Reply with only the name of the best suited prompt, or DEFAULT if none of them is well suited.
+
+<< CANDIDATE PROMPTS >>
+{{destinations}}
+
+<< INPUT >>
+{{input}}
+
+<< NAME >>
+"#;
+
+/// Asks an LLM to classify the input into one of the destinations by their descriptions.
+/// Replies that name no destination select the default chain.
+pub struct LLMRouteSelector {
+    chain: LLMChain,
+    input_key: String,
+}
+
+impl LLMRouteSelector {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        let chain = LLMChainBuilder::new()
+            .prompt(template_jinja2!(
+                DEFAULT_ROUTER_TEMPLATE,
+                "destinations",
+                "input"
+            ))
+            .llm(llm)
+            .build()
+            .unwrap(); // Its safe to unwrap here because the prompt and the LLM are set.
+        Self {
+            chain,
+            input_key: DEFAULT_ROUTER_INPUT_KEY.to_string(),
+        }
+    }
+
+    /// The input variable to classify, `input` by default.
+    pub fn with_input_key<S: Into<String>>(mut self, input_key: S) -> Self {
+        self.input_key = input_key.into();
+        self
+    }
+}
+
+#[async_trait]
+impl RouteSelector for LLMRouteSelector {
+    async fn select(
+        &self,
+        input_variables: &PromptArgs,
+        destinations: &[Destination],
+    ) -> Result<Option<String>, ChainError> {
+        let descriptions = destinations
+            .iter()
+            .map(|destination| format!("{}: {}", destination.name, destination.description))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let reply = self
+            .chain
+            .invoke(prompt_args! {
+                "destinations" => descriptions,
+                "input" => input_text(input_variables, &self.input_key)?
+            })
+            .await?;
+
+        let reply = reply
+            .trim_matches(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '`' | '.' | '*'));
+        Ok(destinations
+            .iter()
+            .find(|destination| destination.name.eq_ignore_ascii_case(reply))
+            .map(|destination| destination.name.clone()))
+    }
+}
+
+/// Routes by the semantic similarity of the input to the utterances of a [`RouteLayer`],
+/// whose route names must match the destination names. Inputs matching no route select
+/// the default chain.
+pub struct SemanticRouteSelector {
+    route_layer: RouteLayer,
+    input_key: String,
+}
+
+impl SemanticRouteSelector {
+    pub fn new(route_layer: RouteLayer) -> Self {
+        Self {
+            route_layer,
+            input_key: DEFAULT_ROUTER_INPUT_KEY.to_string(),
+        }
+    }
+
+    /// The input variable to match, `input` by default.
+    pub fn with_input_key<S: Into<String>>(mut self, input_key: S) -> Self {
+        self.input_key = input_key.into();
+        self
+    }
+}
+
+#[async_trait]
+impl RouteSelector for SemanticRouteSelector {
+    async fn select(
+        &self,
+        input_variables: &PromptArgs,
+        _destinations: &[Destination],
+    ) -> Result<Option<String>, ChainError> {
+        let query = input_text(input_variables, &self.input_key)?;
+        // Only the route is needed, skip generating the tool input of `RouteLayer::call`
+        let embedding = self
+            .route_layer
+            .embedder
+            .embed_query(&query)
+            .await
+            .map_err(|e| ChainError::OtherError(e.to_string()))?;
+        let choice = self
+            .route_layer
+            .call_embedding(&embedding)
+            .await
+            .map_err(|e| ChainError::OtherError(e.to_string()))?;
+        Ok(choice.map(|choice| choice.route))
+    }
+}
+
+/// Routes with a function of the input variables.
+pub struct PredicateRouteSelector<F>
+where
+    F: Fn(&PromptArgs) -> Option<String> + Send + Sync,
+{
+    predicate: F,
+}
+
+impl<F> PredicateRouteSelector<F>
+where
+    F: Fn(&PromptArgs) -> Option<String> + Send + Sync,
+{
+    pub fn new(predicate: F) -> Self {
+        Self { predicate }
+    }
+}
+
+#[async_trait]
+impl<F> RouteSelector for PredicateRouteSelector<F>
+where
+    F: Fn(&PromptArgs) -> Option<String> + Send + Sync,
+{
+    async fn select(
+        &self,
+        input_variables: &PromptArgs,
+        _destinations: &[Destination],
+    ) -> Result<Option<String>, ChainError> {
+        Ok((self.predicate)(input_variables))
+    }
+}
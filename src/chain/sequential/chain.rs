@@ -5,7 +5,7 @@ use serde_json::{json, Value};
 
 use crate::{
     chain::{Chain, ChainError, DEFAULT_OUTPUT_KEY, DEFAULT_RESULT_KEY},
-    language_models::{GenerateResult, TokenUsage},
+    language_models::{options::CallOptions, GenerateResult, TokenUsage},
     prompt::PromptArgs,
 };
 
@@ -16,36 +16,25 @@ pub struct SequentialChain {
     pub(crate) outputs: HashSet<String>,
 }
 
-#[async_trait]
-impl Chain for SequentialChain {
-    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let output = self.execute(input_variables).await?;
-        let result = output
-            .get(DEFAULT_RESULT_KEY)
-            .ok_or_else(|| ChainError::MissingInputVariable(DEFAULT_RESULT_KEY.to_string()))?
-            .clone();
-        let result: GenerateResult = serde_json::from_value(result)?;
-        Ok(result)
-    }
-    async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
-        self.call(input_variables.clone())
-            .await
-            .map(|result| result.generation)
-    }
-    fn get_input_keys(&self) -> Vec<String> {
-        self.outputs.iter().cloned().collect()
-    }
-
-    async fn execute(
+impl SequentialChain {
+    async fn run(
         &self,
         input_variables: PromptArgs,
+        options: Option<&CallOptions>,
     ) -> Result<HashMap<String, Value>, ChainError> {
         let mut input_variables = input_variables;
         let mut final_token_usage: Option<TokenUsage> = None;
         let mut output_result = HashMap::new();
         let mut final_result = GenerateResult::default();
         for chain in self.chains.iter() {
-            let output = chain.execute(input_variables.clone()).await?;
+            let output = match options {
+                Some(options) => {
+                    chain
+                        .execute_with_options(input_variables.clone(), options)
+                        .await?
+                }
+                None => chain.execute(input_variables.clone()).await?,
+            };
             //Get the oput key for the chain result
             let output_key = chain
                 .get_output_keys()
@@ -59,6 +48,17 @@ impl Chain for SequentialChain {
                 .clone();
             let result: GenerateResult = serde_json::from_value(result)?;
             log::debug!("{}", result.generation);
+            //Pass along the other declared outputs, such as the branches of a ParallelChain
+            for key in chain
+                .get_output_keys()
+                .into_iter()
+                .filter(|key| key != DEFAULT_RESULT_KEY && *key != output_key)
+            {
+                if let Some(value) = output.get(&key) {
+                    output_result.insert(key.clone(), value.clone());
+                    input_variables.insert(key, value.clone());
+                }
+            }
             //Insert the output chain to the final output
             output_result.insert(output_key.clone(), json!(result.generation.clone()));
             input_variables.insert(output_key, json!(result.generation.clone()));
@@ -85,14 +85,130 @@ impl Chain for SequentialChain {
     }
 }
 
+#[async_trait]
+impl Chain for SequentialChain {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        let output = self.execute(input_variables).await?;
+        let result = output
+            .get(DEFAULT_RESULT_KEY)
+            .ok_or_else(|| ChainError::MissingInputVariable(DEFAULT_RESULT_KEY.to_string()))?
+            .clone();
+        let result: GenerateResult = serde_json::from_value(result)?;
+        Ok(result)
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        let output = self.execute_with_options(input_variables, options).await?;
+        let result = output
+            .get(DEFAULT_RESULT_KEY)
+            .ok_or_else(|| ChainError::MissingInputVariable(DEFAULT_RESULT_KEY.to_string()))?
+            .clone();
+        let result: GenerateResult = serde_json::from_value(result)?;
+        Ok(result)
+    }
+    async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
+        self.call(input_variables.clone())
+            .await
+            .map(|result| result.generation)
+    }
+    fn get_input_keys(&self) -> Vec<String> {
+        self.outputs.iter().cloned().collect()
+    }
+
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.run(input_variables, None).await
+    }
+
+    async fn execute_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        self.run(input_variables, Some(options)).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        chain::{Chain, LLMChainBuilder},
-        llm::openai::OpenAI,
+        chain::{llm_chain::fake_chain, Chain, LLMChainBuilder, RouterChainBuilder},
+        language_models::options::CallOptions,
+        llm::{openai::OpenAI, FakeLLM, FakeResponse},
         prompt_args, sequential_chain, template_fstring,
     };
 
+    #[tokio::test]
+    async fn test_forwards_call_options_to_every_chain() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::text("Medias Luis"),
+            FakeResponse::text("Arroz"),
+        ]);
+        let name = LLMChainBuilder::new()
+            .prompt(template_fstring!("Name a shop selling {input}", "input"))
+            .llm(llm.clone())
+            .output_key("name")
+            .build()
+            .unwrap();
+        let slogan = LLMChainBuilder::new()
+            .prompt(template_fstring!("Write a slogan for {name}", "name"))
+            .llm(llm.clone())
+            .output_key("slogan")
+            .build()
+            .unwrap();
+        let chain = sequential_chain!(name, slogan);
+
+        let output = chain
+            .execute_with_options(
+                prompt_args! {"input" => "socks"},
+                &CallOptions::new().with_temperature(0.0),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(output["slogan"], "Arroz");
+        assert_eq!(
+            llm.requests()[1][0].content,
+            "Write a slogan for Medias Luis"
+        );
+        assert_eq!(llm.last_options().unwrap().temperature, Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn test_passes_along_declared_outputs_only() {
+        let router = RouterChainBuilder::new()
+            .route(
+                "greeting",
+                "Greetings",
+                fake_chain(FakeResponse::text("Hola")),
+            )
+            .predicate(|_| Some("greeting".to_string()))
+            .build()
+            .unwrap();
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("Chau"));
+        let reply = LLMChainBuilder::new()
+            .prompt(template_fstring!("Reply to {output}", "output"))
+            .llm(llm.clone())
+            .output_key("reply")
+            .build()
+            .unwrap();
+        let chain = sequential_chain!(router, reply);
+
+        let output = chain.execute(prompt_args! {"input" => "Hi"}).await.unwrap();
+
+        assert_eq!(output["output"], "Hola");
+        assert_eq!(output["reply"], "Chau");
+        assert_eq!(llm.last_request().unwrap()[0].content, "Reply to Hola");
+        // The router does not declare the route it took as an output
+        assert!(!output.contains_key("route"));
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_sequential() {
@@ -6,7 +6,7 @@ use serde_json::Value;
 
 use crate::{
     chain::{chain_trait::Chain, llm_chain::LLMChain, ChainError},
-    language_models::{GenerateResult, TokenUsage},
+    language_models::{options::CallOptions, GenerateResult, TokenUsage},
     prompt::PromptArgs,
     prompt_args,
     schemas::StreamData,
@@ -90,6 +90,7 @@ impl SQLDatabaseChain {
     async fn call_builder_chains(
         &self,
         input_variables: &PromptArgs,
+        options: Option<&CallOptions>,
     ) -> Result<(PromptArgs, Option<TokenUsage>), ChainError> {
         let mut token_usage: Option<TokenUsage> = None;
 
@@ -125,7 +126,14 @@ impl SQLDatabaseChain {
 
         };
 
-        let output = self.llmchain.call(llm_inputs.clone()).await?;
+        let output = match options {
+            Some(options) => {
+                self.llmchain
+                    .call_with_options(llm_inputs.clone(), options)
+                    .await?
+            }
+            None => self.llmchain.call(llm_inputs.clone()).await?,
+        };
         if let Some(tokens) = output.tokens {
             token_usage = Some(tokens);
         }
@@ -147,17 +155,18 @@ impl SQLDatabaseChain {
         );
         Ok((llm_inputs, token_usage))
     }
-}
-
-#[async_trait]
-impl Chain for SQLDatabaseChain {
-    fn get_input_keys(&self) -> Vec<String> {
-        self.llmchain.get_input_keys()
-    }
 
-    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let (llm_inputs, mut token_usage) = self.call_builder_chains(&input_variables).await?;
-        let output = self.llmchain.call(llm_inputs).await?;
+    async fn answer(
+        &self,
+        input_variables: PromptArgs,
+        options: Option<&CallOptions>,
+    ) -> Result<GenerateResult, ChainError> {
+        let (llm_inputs, mut token_usage) =
+            self.call_builder_chains(&input_variables, options).await?;
+        let output = match options {
+            Some(options) => self.llmchain.call_with_options(llm_inputs, options).await?,
+            None => self.llmchain.call(llm_inputs).await?,
+        };
         if let Some(tokens) = output.tokens {
             if let Some(general_result) = token_usage.as_mut() {
                 general_result.completion_tokens += tokens.completion_tokens;
@@ -180,8 +189,28 @@ impl Chain for SQLDatabaseChain {
         Ok(GenerateResult {
             generation: output.to_string(),
             tokens: token_usage,
+            ..Default::default()
         })
     }
+}
+
+#[async_trait]
+impl Chain for SQLDatabaseChain {
+    fn get_input_keys(&self) -> Vec<String> {
+        self.llmchain.get_input_keys()
+    }
+
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        self.answer(input_variables, None).await
+    }
+
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        self.answer(input_variables, Some(options)).await
+    }
 
     async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
         let result = self.call(input_variables).await?;
@@ -193,8 +222,21 @@ impl Chain for SQLDatabaseChain {
         input_variables: PromptArgs,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
-        let (llm_inputs, _) = self.call_builder_chains(&input_variables).await?;
+        let (llm_inputs, _) = self.call_builder_chains(&input_variables, None).await?;
 
         self.llmchain.stream(llm_inputs).await
     }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let (llm_inputs, _) = self
+            .call_builder_chains(&input_variables, Some(options))
+            .await?;
+
+        self.llmchain.stream_with_options(llm_inputs, options).await
+    }
 }
@@ -1,9 +1,12 @@
 use crate::{
-    chain::{options::ChainCallOptions, ChainError, LLMChainBuilder},
+    chain::{
+        options::ChainCallOptions,
+        question_answering::{cached_stuff_qa_prompt, stuff_qa_prompt},
+        ChainError, LLMChainBuilder,
+    },
     language_models::llm::LLM,
     output_parsers::OutputParser,
     prompt::FormatPrompter,
-    template_jinja2,
 };
 
 use super::StuffDocument;
@@ -14,6 +17,7 @@ pub struct StuffDocumentBuilder {
     output_key: Option<String>,
     output_parser: Option<Box<dyn OutputParser>>,
     prompt: Option<Box<dyn FormatPrompter>>,
+    cache_context: bool,
 }
 impl StuffDocumentBuilder {
     pub fn new() -> Self {
@@ -23,6 +27,7 @@ impl StuffDocumentBuilder {
             output_key: None,
             output_parser: None,
             prompt: None,
+            cache_context: false,
         }
     }
 
@@ -47,17 +52,22 @@ impl StuffDocumentBuilder {
         self
     }
 
+    /// Sends the documents of the default prompt in a system message of their own, marked as
+    /// a cache breakpoint, so that questions over the same documents reuse the cached prompt.
+    /// Has no effect with a custom prompt.
+    pub fn cache_context(mut self, cache_context: bool) -> Self {
+        self.cache_context = cache_context;
+        self
+    }
+
     pub fn build(self) -> Result<StuffDocument, ChainError> {
         let llm = self
             .llm
             .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
-        let prompt = match self.prompt {
+        let prompt: Box<dyn FormatPrompter> = match self.prompt {
             Some(prompt) => prompt,
-            None => Box::new(template_jinja2!(
-                DEFAULT_STUFF_QA_TEMPLATE,
-                "context",
-                "question"
-            )),
+            None if self.cache_context => Box::new(cached_stuff_qa_prompt()),
+            None => Box::new(stuff_qa_prompt()),
         };
 
         let llm_chain = {
@@ -75,11 +85,3 @@ impl StuffDocumentBuilder {
         Ok(StuffDocument::new(llm_chain))
     }
 }
-
-const DEFAULT_STUFF_QA_TEMPLATE: &str = r#"Use the following pieces of context to answer the question at the end. If you don't know the answer, just say that you don't know, don't try to make up an answer.
-
-{{context}}
-
-Question:{{question}}
-Helpful Answer:
-"#;
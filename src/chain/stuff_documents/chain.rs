@@ -6,16 +6,20 @@ use serde_json::Value;
 
 use crate::{
     chain::{
-        load_stuff_qa, options::ChainCallOptions, Chain, ChainError, LLMChain, StuffQAPromptBuilder,
+        combine_documents::{
+            input_documents, COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME,
+            COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY,
+        },
+        load_stuff_qa,
+        options::ChainCallOptions,
+        Chain, ChainError, LLMChain, StuffQAPromptBuilder,
     },
-    language_models::{llm::LLM, GenerateResult},
+    language_models::{llm::LLM, options::CallOptions, GenerateResult},
     prompt::PromptArgs,
     schemas::{Document, StreamData},
 };
 
-const COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY: &str = "input_documents";
 const COMBINE_DOCUMENTS_DEFAULT_OUTPUT_KEY: &str = "text";
-const COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME: &str = "context";
 const STUFF_DOCUMENTS_DEFAULT_SEPARATOR: &str = "\n\n";
 
 pub struct StuffDocument {
@@ -35,6 +39,18 @@ impl StuffDocument {
         }
     }
 
+    /// The input variables with the documents joined into the prompt variable.
+    fn stuff_documents(&self, input_variables: PromptArgs) -> Result<PromptArgs, ChainError> {
+        let documents = input_documents(&input_variables, &self.input_key)?;
+
+        let mut input_values = input_variables;
+        input_values.insert(
+            self.document_variable_name.clone(),
+            Value::String(self.join_documents(documents)),
+        );
+        Ok(input_values)
+    }
+
     fn join_documents(&self, docs: Vec<Document>) -> String {
         docs.iter()
             .map(|doc| doc.page_content.clone())
@@ -117,24 +133,19 @@ impl StuffDocument {
 #[async_trait]
 impl Chain for StuffDocument {
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let docs = input_variables
-            .get(&self.input_key)
-            .ok_or_else(|| ChainError::MissingInputVariable(self.input_key.clone()))?;
-
-        let documents: Vec<Document> = serde_json::from_value(docs.clone()).map_err(|e| {
-            ChainError::IncorrectInputVariable {
-                source: e,
-                expected_type: "Vec<Document>".to_string(),
-            }
-        })?;
-
-        let mut input_values = input_variables.clone();
-        input_values.insert(
-            self.document_variable_name.clone(),
-            Value::String(self.join_documents(documents)),
-        );
+        self.llm_chain
+            .call(self.stuff_documents(input_variables)?)
+            .await
+    }
 
-        self.llm_chain.call(input_values).await
+    async fn call_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<GenerateResult, ChainError> {
+        self.llm_chain
+            .call_with_options(self.stuff_documents(input_variables)?, options)
+            .await
     }
 
     async fn stream(
@@ -142,23 +153,20 @@ impl Chain for StuffDocument {
         input_variables: PromptArgs,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
-        let docs = input_variables
-            .get(&self.input_key)
-            .ok_or_else(|| ChainError::MissingInputVariable(self.input_key.clone()))?;
-
-        let documents: Vec<Document> = serde_json::from_value(docs.clone()).map_err(|e| {
-            ChainError::IncorrectInputVariable {
-                source: e,
-                expected_type: "Vec<Document>".to_string(),
-            }
-        })?;
-
-        let mut input_values = input_variables.clone();
-        input_values.insert(
-            self.document_variable_name.clone(),
-            Value::String(self.join_documents(documents)),
-        );
-        self.llm_chain.stream(input_values).await
+        self.llm_chain
+            .stream(self.stuff_documents(input_variables)?)
+            .await
+    }
+
+    async fn stream_with_options(
+        &self,
+        input_variables: PromptArgs,
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        self.llm_chain
+            .stream_with_options(self.stuff_documents(input_variables)?, options)
+            .await
     }
 
     fn get_input_keys(&self) -> Vec<String> {
@@ -0,0 +1,101 @@
+use async_trait::async_trait;
+
+use crate::embedding::{embedder_trait::Embedder, EmbedderError};
+
+/// A deterministic [`Embedder`] for tests, needing no model or network.
+///
+/// Each word is hashed into one of `dimensions` buckets and the counts are normalized, so
+/// the same text always gets the same vector and texts sharing words are close by cosine
+/// similarity, which is enough to exercise retrieval and routing.
+#[derive(Debug, Clone)]
+pub struct FakeEmbedder {
+    dimensions: usize,
+}
+
+impl Default for FakeEmbedder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeEmbedder {
+    pub fn new() -> Self {
+        Self { dimensions: 64 }
+    }
+
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions.max(1);
+        self
+    }
+
+    fn embed(&self, text: &str) -> Vec<f64> {
+        let mut vector = vec![0.0; self.dimensions];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            let hash = fnv1a(&word.to_lowercase());
+            vector[(hash % self.dimensions as u64) as usize] += 1.0;
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+/// FNV-1a, unlike the std hashers stable across Rust versions and platforms.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[async_trait]
+impl Embedder for FakeEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+        Ok(documents
+            .iter()
+            .map(|document| self.embed(document))
+            .collect())
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+        Ok(self.embed(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantic_router::utils::cosine_similarity;
+
+    #[tokio::test]
+    async fn test_fake_embedder_is_deterministic() {
+        let embedder = FakeEmbedder::new().with_dimensions(32);
+        let query = embedder
+            .embed_query("What is the temperature?")
+            .await
+            .unwrap();
+        let documents = embedder
+            .embed_documents(&[
+                "what is the TEMPERATURE".to_string(),
+                "Capital of France is Paris.".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(query.len(), 32);
+        assert_eq!(query, documents[0]);
+        assert!(
+            cosine_similarity(&query, &documents[0]) > cosine_similarity(&query, &documents[1])
+        );
+        assert!(embedder
+            .embed_query("")
+            .await
+            .unwrap()
+            .iter()
+            .all(|x| *x == 0.0));
+    }
+}
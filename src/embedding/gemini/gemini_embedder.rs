@@ -0,0 +1,224 @@
+use crate::embedding::{embedder_trait::Embedder, EmbedderError};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+#[derive(Deserialize)]
+struct ContentEmbedding {
+    values: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct EmbedContentResponse {
+    embedding: ContentEmbedding,
+}
+
+#[derive(Deserialize)]
+struct BatchEmbedContentsResponse {
+    embeddings: Vec<ContentEmbedding>,
+}
+
+/// Embedder for the Gemini API embedding models. Documents are embedded with the
+/// `RETRIEVAL_DOCUMENT` task type and queries with `RETRIEVAL_QUERY`.
+#[derive(Debug, Clone)]
+pub struct GeminiEmbedder {
+    model: String,
+    api_key: String,
+    access_token: Option<String>,
+    base_url: String,
+    dimensions: Option<usize>,
+}
+
+impl Default for GeminiEmbedder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeminiEmbedder {
+    pub fn new() -> Self {
+        Self {
+            model: String::from("text-embedding-004"),
+            api_key: std::env::var("GEMINI_API_KEY")
+                .or_else(|_| std::env::var("GOOGLE_API_KEY"))
+                .unwrap_or_default(),
+            access_token: None,
+            base_url: GEMINI_BASE_URL.to_string(),
+            dimensions: None,
+        }
+    }
+
+    pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = api_key.into();
+        self
+    }
+
+    pub fn with_access_token<S: Into<String>>(mut self, access_token: S) -> Self {
+        self.access_token = Some(access_token.into());
+        self
+    }
+
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Truncates the embeddings to `dimensions`, for models that support it.
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    fn request_body(&self, text: &str, task_type: &str) -> Value {
+        let mut body = json!({
+            "model": format!("models/{}", self.model),
+            "content": { "parts": [{ "text": text }] },
+            "taskType": task_type,
+        });
+        if let Some(dimensions) = self.dimensions {
+            body["outputDimensionality"] = json!(dimensions);
+        }
+        body
+    }
+
+    async fn post<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        body: &Value,
+    ) -> Result<T, EmbedderError> {
+        let request = Client::new()
+            .post(format!(
+                "{}/models/{}:{}",
+                self.base_url, self.model, method
+            ))
+            .json(body);
+        let request = match &self.access_token {
+            Some(access_token) => request.bearer_auth(access_token),
+            None => request.header("x-goog-api-key", &self.api_key),
+        };
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(EmbedderError::HttpError {
+                status_code: response.status(),
+                error_message: response.text().await?,
+            });
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl Embedder for GeminiEmbedder {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+        log::debug!("Embedding documents: {:?}", documents);
+
+        let requests = documents
+            .iter()
+            .map(|document| self.request_body(document, "RETRIEVAL_DOCUMENT"))
+            .collect::<Vec<_>>();
+        let response: BatchEmbedContentsResponse = self
+            .post("batchEmbedContents", &json!({ "requests": requests }))
+            .await?;
+
+        Ok(response
+            .embeddings
+            .into_iter()
+            .map(|embedding| embedding.values)
+            .collect())
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+        log::debug!("Embedding query: {:?}", text);
+
+        let response: EmbedContentResponse = self
+            .post("embedContent", &self.request_body(text, "RETRIEVAL_QUERY"))
+            .await?;
+
+        Ok(response.embedding.values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_gemini_embed_query() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/models/text-embedding-004:embedContent")
+            .match_header("x-goog-api-key", "test-key")
+            .match_body(Matcher::PartialJson(json!({
+                "content": { "parts": [{ "text": "Why is the sky blue?" }] },
+                "taskType": "RETRIEVAL_QUERY",
+                "outputDimensionality": 3
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "embedding": { "values": [0.1, 0.2, 0.3] } }).to_string())
+            .create_async()
+            .await;
+
+        let embedder = GeminiEmbedder::new()
+            .with_api_key("test-key")
+            .with_base_url(server.url())
+            .with_dimensions(3);
+        let embedding = embedder.embed_query("Why is the sky blue?").await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(embedding, vec![0.1, 0.2, 0.3]);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_embed_documents() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/models/text-embedding-004:batchEmbedContents")
+            .match_body(Matcher::PartialJson(json!({
+                "requests": [
+                    { "model": "models/text-embedding-004", "taskType": "RETRIEVAL_DOCUMENT" },
+                    { "content": { "parts": [{ "text": "foo bar" }] } }
+                ]
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(include_str!("../../llm/test_data/gemini_embeddings.json"))
+            .create_async()
+            .await;
+
+        let embedder = GeminiEmbedder::new().with_base_url(server.url());
+        let embeddings = embedder
+            .embed_documents(&["hello world".to_string(), "foo bar".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_embed_http_error() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/models/text-embedding-004:embedContent")
+            .with_status(403)
+            .with_body("permission denied")
+            .create_async()
+            .await;
+
+        let embedder = GeminiEmbedder::new().with_base_url(server.url());
+        let error = embedder.embed_query("hello").await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            EmbedderError::HttpError { status_code, .. } if status_code == 403
+        ));
+    }
+}
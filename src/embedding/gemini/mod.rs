@@ -0,0 +1,2 @@
+pub mod gemini_embedder;
+pub use gemini_embedder::*;
@@ -11,6 +11,9 @@ pub use ollama::*;
 pub mod openai;
 pub use error::*;
 
+pub mod fake;
+pub use fake::*;
+
 #[cfg(feature = "fastembed")]
 mod fastembed;
 #[cfg(feature = "fastembed")]
@@ -20,3 +23,8 @@ pub use fastembed::*;
 pub mod mistralai;
 #[cfg(feature = "mistralai")]
 pub use mistralai::*;
+
+#[cfg(feature = "gemini")]
+pub mod gemini;
+#[cfg(feature = "gemini")]
+pub use gemini::*;
@@ -91,3 +91,38 @@ impl<C: Config + Send + Sync> Embedder for OpenAiEmbedder<C> {
             .collect::<Vec<f64>>())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::CassetteServer;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_embed_from_cassette() {
+        let cassette = CassetteServer::new(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/llm/test_data/cassettes/openai_embeddings.synthetic.json"
+            ),
+            "https://api.openai.com",
+        )
+        .start()
+        .await
+        .unwrap();
+        let embedder = OpenAiEmbedder::new(
+            OpenAIConfig::default().with_api_base(format!("{}/v1", cassette.url())),
+        )
+        .with_model("text-embedding-3-small");
+
+        let documents = embedder
+            .embed_documents(&["Paris is the capital of France".to_string()])
+            .await
+            .unwrap();
+        let query = embedder.embed_query("Capital of France").await.unwrap();
+        assert_eq!(documents.len(), 1);
+        assert!(!query.is_empty());
+        assert_eq!(documents[0].len(), query.len());
+        cassette.assert_replayed().await;
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use super::{Checkpointer, CompiledGraph, GraphError, GraphState, Node};
+
+/// The virtual node the edges to the entry point of a graph start from.
+pub const START: &str = "__start__";
+/// The virtual node ending a graph run.
+pub const END: &str = "__end__";
+
+const DEFAULT_RECURSION_LIMIT: usize = 25;
+
+pub(crate) enum Edge<S> {
+    To(String),
+    Conditional(Arc<dyn Fn(&S) -> String + Send + Sync>),
+}
+
+/// Builds a graph of nodes sharing a state of type `S`, see [`CompiledGraph`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let graph = StateGraph::<State>::new()
+///     .add_node("draft", ChainNode::new(draft_chain))
+///     .add_node("review", ChainNode::new(review_chain))
+///     .add_edge(START, "draft")
+///     .add_edge("draft", "review")
+///     .add_conditional_edges("review", |state: &State| {
+///         if state.approved { END } else { "draft" }
+///     })
+///     .recursion_limit(10)
+///     .compile()?;
+/// ```
+pub struct StateGraph<S: GraphState> {
+    nodes: Vec<(String, Box<dyn Node<S>>)>,
+    edges: Vec<(String, Edge<S>)>,
+    interrupt_before: HashSet<String>,
+    interrupt_after: HashSet<String>,
+    recursion_limit: usize,
+    checkpointer: Option<Arc<dyn Checkpointer>>,
+}
+
+impl<S: GraphState> Default for StateGraph<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: GraphState> StateGraph<S> {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            interrupt_before: HashSet::new(),
+            interrupt_after: HashSet::new(),
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            checkpointer: None,
+        }
+    }
+
+    pub fn add_node<N: Node<S> + 'static>(mut self, name: &str, node: N) -> Self {
+        self.nodes.push((name.to_string(), Box::new(node)));
+        self
+    }
+
+    /// Runs `to` after `from`. Use [`START`] as `from` for the entry point and [`END`] as
+    /// `to` to finish the run.
+    pub fn add_edge(mut self, from: &str, to: &str) -> Self {
+        self.edges
+            .push((from.to_string(), Edge::To(to.to_string())));
+        self
+    }
+
+    /// Runs the node named by `route` with the state after `from`, or finishes the run when it
+    /// returns [`END`].
+    pub fn add_conditional_edges<F, R>(mut self, from: &str, route: F) -> Self
+    where
+        F: Fn(&S) -> R + Send + Sync + 'static,
+        R: Into<String>,
+    {
+        self.edges.push((
+            from.to_string(),
+            Edge::Conditional(Arc::new(move |state| route(state).into())),
+        ));
+        self
+    }
+
+    pub fn set_entry_point(self, node: &str) -> Self {
+        self.add_edge(START, node)
+    }
+
+    pub fn set_finish_point(self, node: &str) -> Self {
+        self.add_edge(node, END)
+    }
+
+    /// Stops the runs before these nodes, until [`CompiledGraph::resume`] is called.
+    /// Requires a checkpointer.
+    pub fn interrupt_before<I, T>(mut self, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.interrupt_before
+            .extend(nodes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Stops the runs after these nodes, until [`CompiledGraph::resume`] is called.
+    /// Requires a checkpointer.
+    pub fn interrupt_after<I, T>(mut self, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.interrupt_after
+            .extend(nodes.into_iter().map(Into::into));
+        self
+    }
+
+    /// The most nodes a run or resume can execute before failing with
+    /// [`GraphError::RecursionLimit`], 25 by default. It guards the loops of the graph.
+    pub fn recursion_limit(mut self, recursion_limit: usize) -> Self {
+        self.recursion_limit = recursion_limit;
+        self
+    }
+
+    /// Where to save the state of the threads after each step, see
+    /// [`CompiledGraph::run_thread`].
+    pub fn checkpointer<C: Checkpointer + 'static>(mut self, checkpointer: C) -> Self {
+        self.checkpointer = Some(Arc::new(checkpointer));
+        self
+    }
+
+    pub fn compile(self) -> Result<CompiledGraph<S>, GraphError> {
+        let mut nodes = HashMap::new();
+        for (name, node) in self.nodes {
+            if name == START || name == END {
+                return Err(GraphError::InvalidGraph(format!(
+                    "{} is a reserved node name",
+                    name
+                )));
+            }
+            if nodes.insert(name.clone(), node).is_some() {
+                return Err(GraphError::InvalidGraph(format!(
+                    "Node {} is added more than once",
+                    name
+                )));
+            }
+        }
+
+        let mut edges = HashMap::new();
+        for (from, edge) in self.edges {
+            if from != START && !nodes.contains_key(&from) {
+                return Err(GraphError::MissingNode(from));
+            }
+            if let Edge::To(to) = &edge {
+                if to != END && !nodes.contains_key(to) {
+                    return Err(GraphError::MissingNode(to.clone()));
+                }
+            }
+            if edges.insert(from.clone(), edge).is_some() {
+                return Err(GraphError::InvalidGraph(format!(
+                    "{} has more than one outgoing edge",
+                    from
+                )));
+            }
+        }
+        if !edges.contains_key(START) {
+            return Err(GraphError::InvalidGraph("No entry point set".to_string()));
+        }
+        if let Some(name) = nodes.keys().find(|name| !edges.contains_key(*name)) {
+            return Err(GraphError::InvalidGraph(format!(
+                "Node {} has no outgoing edge",
+                name
+            )));
+        }
+
+        let interrupts = self.interrupt_before.iter().chain(&self.interrupt_after);
+        if let Some(name) = interrupts.clone().find(|name| !nodes.contains_key(*name)) {
+            return Err(GraphError::MissingNode(name.clone()));
+        }
+        if interrupts.count() > 0 && self.checkpointer.is_none() {
+            return Err(GraphError::InvalidGraph(
+                "Interrupts require a checkpointer".to_string(),
+            ));
+        }
+
+        Ok(CompiledGraph::new(
+            nodes,
+            edges,
+            self.interrupt_before,
+            self.interrupt_after,
+            self.recursion_limit,
+            self.checkpointer,
+        ))
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use super::GraphError;
+
+/// The state of a thread of a [`CompiledGraph`](super::CompiledGraph) after a step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub thread_id: String,
+    /// The number of nodes run in the thread so far.
+    pub step: usize,
+    /// The serialized state.
+    pub state: Value,
+    /// The node to run next, `None` once the thread reached the end of the graph.
+    pub next: Option<String>,
+}
+
+/// Stores the checkpoints of the threads of a graph, so that interrupted or failed runs can
+/// be resumed.
+#[async_trait]
+pub trait Checkpointer: Send + Sync {
+    async fn put(&self, checkpoint: Checkpoint) -> Result<(), GraphError>;
+
+    /// Returns the latest checkpoint of the thread.
+    async fn get(&self, thread_id: &str) -> Result<Option<Checkpoint>, GraphError>;
+
+    /// Returns every checkpoint of the thread, oldest first.
+    async fn list(&self, thread_id: &str) -> Result<Vec<Checkpoint>, GraphError>;
+}
+
+/// Keeps the checkpoints in memory, for the lifetime of the process.
+#[derive(Default)]
+pub struct MemoryCheckpointer {
+    threads: Mutex<HashMap<String, Vec<Checkpoint>>>,
+}
+
+impl MemoryCheckpointer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Checkpointer for MemoryCheckpointer {
+    async fn put(&self, checkpoint: Checkpoint) -> Result<(), GraphError> {
+        self.threads
+            .lock()
+            .await
+            .entry(checkpoint.thread_id.clone())
+            .or_default()
+            .push(checkpoint);
+        Ok(())
+    }
+
+    async fn get(&self, thread_id: &str) -> Result<Option<Checkpoint>, GraphError> {
+        Ok(self
+            .threads
+            .lock()
+            .await
+            .get(thread_id)
+            .and_then(|checkpoints| checkpoints.last().cloned()))
+    }
+
+    async fn list(&self, thread_id: &str) -> Result<Vec<Checkpoint>, GraphError> {
+        Ok(self
+            .threads
+            .lock()
+            .await
+            .get(thread_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Lets several graphs share a store.
+#[async_trait]
+impl<C> Checkpointer for Arc<C>
+where
+    C: Checkpointer + ?Sized,
+{
+    async fn put(&self, checkpoint: Checkpoint) -> Result<(), GraphError> {
+        self.as_ref().put(checkpoint).await
+    }
+
+    async fn get(&self, thread_id: &str) -> Result<Option<Checkpoint>, GraphError> {
+        self.as_ref().get(thread_id).await
+    }
+
+    async fn list(&self, thread_id: &str) -> Result<Vec<Checkpoint>, GraphError> {
+        self.as_ref().list(thread_id).await
+    }
+}
@@ -0,0 +1,614 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use futures::Stream;
+use serde_json::{json, Value};
+
+use crate::{
+    chain::{Chain, ChainError, DEFAULT_OUTPUT_KEY, DEFAULT_RESULT_KEY},
+    language_models::GenerateResult,
+    prompt::PromptArgs,
+    schemas::StreamData,
+};
+
+use super::{
+    args_to_state, builder::Edge, state_to_args, Checkpoint, Checkpointer, GraphError, GraphState,
+    Node, END, START,
+};
+
+/// The result of running a graph until it ends or is interrupted.
+#[derive(Debug, Clone)]
+pub struct GraphRun<S> {
+    pub state: S,
+    /// The node to run on [`CompiledGraph::resume`] when the run was interrupted, `None` when
+    /// it reached the end of the graph.
+    pub next: Option<String>,
+    /// The number of nodes run.
+    pub steps: usize,
+}
+
+impl<S> GraphRun<S> {
+    pub fn is_interrupted(&self) -> bool {
+        self.next.is_some()
+    }
+}
+
+/// An event of [`CompiledGraph::stream`].
+#[derive(Debug, Clone)]
+pub enum GraphEvent<S> {
+    /// A node ran, leaving the state as `state`. `step` counts the nodes run in the thread.
+    Node { node: String, step: usize, state: S },
+    /// The run stopped before `next`, see [`CompiledGraph::resume`].
+    Interrupted { next: String },
+}
+
+/// Where a run is in the graph.
+struct Cursor<S> {
+    thread_id: Option<String>,
+    state: S,
+    next: Option<String>,
+    step: usize,
+    steps: usize,
+    /// Skips the interrupt before the first node of a resumed run.
+    resuming: bool,
+    interrupt_pending: bool,
+    stopped: bool,
+}
+
+struct GraphInner<S: GraphState> {
+    nodes: HashMap<String, Box<dyn Node<S>>>,
+    edges: HashMap<String, Edge<S>>,
+    interrupt_before: HashSet<String>,
+    interrupt_after: HashSet<String>,
+    recursion_limit: usize,
+    checkpointer: Option<Arc<dyn Checkpointer>>,
+}
+
+/// A graph of nodes sharing a state, built with [`StateGraph`](super::StateGraph).
+///
+/// A run starts at the node after [`START`] and follows the edges, conditional ones
+/// included, until it reaches [`END`], so that nodes can loop until a condition holds. Runs
+/// on a thread, see [`CompiledGraph::run_thread`], save a [`Checkpoint`] after each step
+/// and can be resumed after an interrupt or a failure.
+///
+/// The graph is a [`Chain`] taking and returning the state as variables. Its nodes own their
+/// models, so [`Chain::call_with_options`] returns an error rather than run them without
+/// the options.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let graph = StateGraph::<PromptArgs>::new()
+///     .add_node("draft", ChainNode::new(draft_chain))
+///     .add_node("publish", ToolNode::new(publish_tool).with_input_key("draft"))
+///     .add_edge(START, "draft")
+///     .add_edge("draft", "publish")
+///     .add_edge("publish", END)
+///     .interrupt_before(["publish"])
+///     .checkpointer(MemoryCheckpointer::new())
+///     .compile()?;
+///
+/// let run = graph.run_thread("post-1", prompt_args! { "input" => topic }).await?;
+/// assert_eq!(run.next.as_deref(), Some("publish"));
+/// // Once a human approved or edited the draft
+/// let run = graph.resume("post-1", None).await?;
+/// ```
+pub struct CompiledGraph<S: GraphState> {
+    inner: Arc<GraphInner<S>>,
+}
+
+impl<S: GraphState> Clone for CompiledGraph<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: GraphState> CompiledGraph<S> {
+    pub(crate) fn new(
+        nodes: HashMap<String, Box<dyn Node<S>>>,
+        edges: HashMap<String, Edge<S>>,
+        interrupt_before: HashSet<String>,
+        interrupt_after: HashSet<String>,
+        recursion_limit: usize,
+        checkpointer: Option<Arc<dyn Checkpointer>>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(GraphInner {
+                nodes,
+                edges,
+                interrupt_before,
+                interrupt_after,
+                recursion_limit,
+                checkpointer,
+            }),
+        }
+    }
+
+    /// Runs the graph from the start, without saving checkpoints.
+    pub async fn run(&self, state: S) -> Result<GraphRun<S>, GraphError> {
+        let cursor = self.start(None, state).await?;
+        self.run_cursor(cursor).await
+    }
+
+    /// Runs the graph from the start on the thread `thread_id`, saving a checkpoint after
+    /// each step.
+    pub async fn run_thread(&self, thread_id: &str, state: S) -> Result<GraphRun<S>, GraphError> {
+        let cursor = self.start(Some(thread_id.to_string()), state).await?;
+        self.run_cursor(cursor).await
+    }
+
+    /// Continues the thread from its latest checkpoint, with `state` replacing the saved
+    /// state when it is set, for instance with the edits of a human reviewer.
+    pub async fn resume(
+        &self,
+        thread_id: &str,
+        state: Option<S>,
+    ) -> Result<GraphRun<S>, GraphError> {
+        let cursor = self.load(thread_id.to_string(), state).await?;
+        self.run_cursor(cursor).await
+    }
+
+    /// Like [`CompiledGraph::run`], returning an event for each step.
+    pub fn stream(
+        &self,
+        state: S,
+    ) -> Pin<Box<dyn Stream<Item = Result<GraphEvent<S>, GraphError>> + Send>> {
+        let graph = self.clone();
+        self.stream_cursor(async move { graph.start(None, state).await })
+    }
+
+    /// Like [`CompiledGraph::run_thread`], returning an event for each step.
+    pub fn stream_thread(
+        &self,
+        thread_id: &str,
+        state: S,
+    ) -> Pin<Box<dyn Stream<Item = Result<GraphEvent<S>, GraphError>> + Send>> {
+        let graph = self.clone();
+        let thread_id = thread_id.to_string();
+        self.stream_cursor(async move { graph.start(Some(thread_id), state).await })
+    }
+
+    /// Like [`CompiledGraph::resume`], returning an event for each step.
+    pub fn stream_resume(
+        &self,
+        thread_id: &str,
+        state: Option<S>,
+    ) -> Pin<Box<dyn Stream<Item = Result<GraphEvent<S>, GraphError>> + Send>> {
+        let graph = self.clone();
+        let thread_id = thread_id.to_string();
+        self.stream_cursor(async move { graph.load(thread_id, state).await })
+    }
+
+    /// The state of the thread at its latest checkpoint.
+    pub async fn get_state(&self, thread_id: &str) -> Result<Option<S>, GraphError> {
+        match self.checkpointer()?.get(thread_id).await? {
+            Some(checkpoint) => Ok(Some(serde_json::from_value(checkpoint.state)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Every checkpoint of the thread, oldest first.
+    pub async fn history(&self, thread_id: &str) -> Result<Vec<Checkpoint>, GraphError> {
+        self.checkpointer()?.list(thread_id).await
+    }
+
+    fn checkpointer(&self) -> Result<&dyn Checkpointer, GraphError> {
+        self.inner
+            .checkpointer
+            .as_deref()
+            .ok_or_else(|| GraphError::InvalidGraph("No checkpointer set".to_string()))
+    }
+
+    /// The node to run after `from`, `None` at the end of the graph.
+    fn next_node(&self, from: &str, state: &S) -> Result<Option<String>, GraphError> {
+        let next = match self.inner.edges.get(from) {
+            Some(Edge::To(to)) => to.clone(),
+            Some(Edge::Conditional(route)) => route(state),
+            None => return Err(GraphError::MissingNode(from.to_string())),
+        };
+        if next == END {
+            return Ok(None);
+        }
+        if !self.inner.nodes.contains_key(&next) {
+            return Err(GraphError::MissingNode(next));
+        }
+        Ok(Some(next))
+    }
+
+    async fn start(&self, thread_id: Option<String>, state: S) -> Result<Cursor<S>, GraphError> {
+        let cursor = Cursor {
+            thread_id,
+            next: self.next_node(START, &state)?,
+            state,
+            step: 0,
+            steps: 0,
+            resuming: false,
+            interrupt_pending: false,
+            stopped: false,
+        };
+        self.save(&cursor).await?;
+        Ok(cursor)
+    }
+
+    async fn load(&self, thread_id: String, state: Option<S>) -> Result<Cursor<S>, GraphError> {
+        let checkpoint = self.checkpointer()?.get(&thread_id).await?.ok_or_else(|| {
+            GraphError::CheckpointError(format!("No checkpoint for thread {}", thread_id))
+        })?;
+        let updated = state.is_some();
+        let cursor = Cursor {
+            thread_id: Some(thread_id),
+            state: match state {
+                Some(state) => state,
+                None => serde_json::from_value(checkpoint.state)?,
+            },
+            next: checkpoint.next,
+            step: checkpoint.step,
+            steps: 0,
+            resuming: true,
+            interrupt_pending: false,
+            stopped: false,
+        };
+        if updated {
+            self.save(&cursor).await?;
+        }
+        Ok(cursor)
+    }
+
+    async fn save(&self, cursor: &Cursor<S>) -> Result<(), GraphError> {
+        if let (Some(checkpointer), Some(thread_id)) = (&self.inner.checkpointer, &cursor.thread_id)
+        {
+            checkpointer
+                .put(Checkpoint {
+                    thread_id: thread_id.clone(),
+                    step: cursor.step,
+                    state: serde_json::to_value(&cursor.state)?,
+                    next: cursor.next.clone(),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Runs the next node, returning `None` once the run ended or was interrupted.
+    async fn step(&self, cursor: &mut Cursor<S>) -> Result<Option<GraphEvent<S>>, GraphError> {
+        if cursor.stopped {
+            return Ok(None);
+        }
+        let Some(node_name) = cursor.next.clone() else {
+            return Ok(None);
+        };
+        let resuming = std::mem::take(&mut cursor.resuming);
+        if cursor.interrupt_pending
+            || (!resuming && self.inner.interrupt_before.contains(&node_name))
+        {
+            cursor.stopped = true;
+            return Ok(Some(GraphEvent::Interrupted { next: node_name }));
+        }
+        if cursor.steps >= self.inner.recursion_limit {
+            return Err(GraphError::RecursionLimit(self.inner.recursion_limit));
+        }
+
+        let node = self
+            .inner
+            .nodes
+            .get(&node_name)
+            .ok_or_else(|| GraphError::MissingNode(node_name.clone()))?;
+        log::debug!("running node {}", node_name);
+        let state = node.run(cursor.state.clone()).await?;
+        cursor.next = self.next_node(&node_name, &state)?;
+        cursor.state = state;
+        cursor.step += 1;
+        cursor.steps += 1;
+        self.save(cursor).await?;
+        cursor.interrupt_pending = self.inner.interrupt_after.contains(&node_name);
+
+        Ok(Some(GraphEvent::Node {
+            node: node_name,
+            step: cursor.step,
+            state: cursor.state.clone(),
+        }))
+    }
+
+    async fn run_cursor(&self, mut cursor: Cursor<S>) -> Result<GraphRun<S>, GraphError> {
+        while self.step(&mut cursor).await?.is_some() {}
+        Ok(GraphRun {
+            state: cursor.state,
+            next: cursor.next,
+            steps: cursor.steps,
+        })
+    }
+
+    fn stream_cursor<F>(
+        &self,
+        cursor: F,
+    ) -> Pin<Box<dyn Stream<Item = Result<GraphEvent<S>, GraphError>> + Send>>
+    where
+        F: Future<Output = Result<Cursor<S>, GraphError>> + Send + 'static,
+    {
+        let graph = self.clone();
+        Box::pin(async_stream::try_stream! {
+            let mut cursor = cursor.await?;
+            while let Some(event) = graph.step(&mut cursor).await? {
+                yield event;
+            }
+        })
+    }
+
+    /// Runs the graph on the state in `input_variables`, failing when it is interrupted as
+    /// there is no thread to resume.
+    async fn run_variables(&self, input_variables: PromptArgs) -> Result<PromptArgs, ChainError> {
+        let run = self.run(args_to_state(input_variables)?).await?;
+        if let Some(next) = run.next {
+            return Err(interrupted(&next));
+        }
+        Ok(state_to_args(&run.state)?)
+    }
+}
+
+fn interrupted(next: &str) -> ChainError {
+    ChainError::GraphError(format!(
+        "Interrupted before {}, use run_thread to resume the run",
+        next
+    ))
+}
+
+/// The generation is the `output` of the final state, or the whole state as JSON when it
+/// has none.
+fn generation(state: &PromptArgs) -> String {
+    match state.get(DEFAULT_OUTPUT_KEY) {
+        Some(Value::String(output)) => output.clone(),
+        _ => json!(state).to_string(),
+    }
+}
+
+#[async_trait]
+impl<S: GraphState> Chain for CompiledGraph<S> {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        let state = self.run_variables(input_variables).await?;
+        Ok(GenerateResult {
+            generation: generation(&state),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the final state, with the generation under `generate_result`.
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let mut output = self.run_variables(input_variables).await?;
+        let result = GenerateResult {
+            generation: generation(&output),
+            ..Default::default()
+        };
+        output.insert(DEFAULT_RESULT_KEY.to_string(), json!(result));
+        Ok(output)
+    }
+
+    /// Yields an item for each node run, with the node, the step and the state after it
+    /// as value.
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let events = CompiledGraph::stream(self, args_to_state(input_variables)?);
+        Ok(Box::pin(futures::StreamExt::map(
+            events,
+            |event| match event? {
+                GraphEvent::Node { node, step, state } => Ok(StreamData::new(
+                    json!({ "node": node, "step": step, "state": state }),
+                    None,
+                    "",
+                )),
+                GraphEvent::Interrupted { next } => Err(interrupted(&next)),
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        chain::LLMChainBuilder,
+        graph::{ChainNode, FnNode, MemoryCheckpointer, StateGraph},
+        llm::{FakeLLM, FakeResponse},
+        prompt_args, template_jinja2,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    struct Counter {
+        count: u32,
+    }
+
+    fn counter_graph(limit: u32) -> StateGraph<Counter> {
+        StateGraph::new()
+            .add_node(
+                "increment",
+                FnNode::new(|mut state: Counter| async move {
+                    state.count += 1;
+                    Ok(state)
+                }),
+            )
+            .set_entry_point("increment")
+            .add_conditional_edges("increment", move |state: &Counter| {
+                if state.count < limit {
+                    "increment"
+                } else {
+                    END
+                }
+            })
+    }
+
+    #[tokio::test]
+    async fn test_loops_until_the_condition_holds() {
+        let graph = counter_graph(3).compile().unwrap();
+
+        let run = graph.run(Counter::default()).await.unwrap();
+
+        assert_eq!(run.state.count, 3);
+        assert_eq!(run.steps, 3);
+        assert!(!run.is_interrupted());
+    }
+
+    #[tokio::test]
+    async fn test_recursion_limit() {
+        let graph = counter_graph(100).recursion_limit(5).compile().unwrap();
+
+        let error = graph.run(Counter::default()).await.unwrap_err();
+
+        assert!(matches!(error, GraphError::RecursionLimit(5)));
+    }
+
+    #[test]
+    fn test_compile_validates_the_graph() {
+        let no_entry_point = StateGraph::<Counter>::new()
+            .add_node("a", FnNode::new(|state: Counter| async move { Ok(state) }))
+            .set_finish_point("a");
+        assert!(matches!(
+            no_entry_point.compile(),
+            Err(GraphError::InvalidGraph(_))
+        ));
+
+        let missing_node = counter_graph(1).add_edge("increment_twice", END);
+        assert!(matches!(
+            missing_node.compile(),
+            Err(GraphError::MissingNode(name)) if name == "increment_twice"
+        ));
+
+        let no_checkpointer = counter_graph(1).interrupt_before(["increment"]);
+        assert!(matches!(
+            no_checkpointer.compile(),
+            Err(GraphError::InvalidGraph(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_interrupts_and_resumes_a_thread() {
+        let llm = FakeLLM::new().with_default_response(FakeResponse::text("Rust is fast"));
+        let draft = LLMChainBuilder::new()
+            .prompt(template_jinja2!("Write about {{topic}}", "topic"))
+            .llm(llm)
+            .output_key("draft")
+            .build()
+            .unwrap();
+        let graph = StateGraph::<PromptArgs>::new()
+            .add_node("draft", ChainNode::new(draft))
+            .add_node(
+                "publish",
+                FnNode::new(|mut state: PromptArgs| async move {
+                    let draft = state["draft"].clone();
+                    state.insert("published".to_string(), draft);
+                    Ok(state)
+                }),
+            )
+            .add_edge(START, "draft")
+            .add_edge("draft", "publish")
+            .add_edge("publish", END)
+            .interrupt_before(["publish"])
+            .checkpointer(MemoryCheckpointer::new())
+            .compile()
+            .unwrap();
+
+        let run = graph
+            .run_thread("post", prompt_args! { "topic" => "Rust" })
+            .await
+            .unwrap();
+        assert_eq!(run.next.as_deref(), Some("publish"));
+        assert!(!run.state.contains_key("published"));
+
+        let mut state = graph.get_state("post").await.unwrap().unwrap();
+        assert_eq!(state["draft"], "Rust is fast");
+        state.insert("draft".to_string(), json!("Rust is fast and safe"));
+        let run = graph.resume("post", Some(state)).await.unwrap();
+
+        assert_eq!(run.next, None);
+        assert_eq!(run.steps, 1);
+        assert_eq!(run.state["published"], "Rust is fast and safe");
+        let history = graph.history("post").await.unwrap();
+        let steps: Vec<(usize, Option<&str>)> = history
+            .iter()
+            .map(|checkpoint| (checkpoint.step, checkpoint.next.as_deref()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (0, Some("draft")),
+                (1, Some("publish")),
+                (1, Some("publish")),
+                (2, None)
+            ]
+        );
+
+        assert!(graph
+            .call(prompt_args! { "topic" => "Rust" })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_streams_node_events() {
+        let graph = counter_graph(2)
+            .interrupt_after(["increment"])
+            .checkpointer(MemoryCheckpointer::new())
+            .compile()
+            .unwrap();
+
+        let events: Vec<GraphEvent<Counter>> = graph
+            .stream_thread("counter", Counter::default())
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+        assert!(matches!(
+            &events[..],
+            [
+                GraphEvent::Node { step: 1, state, .. },
+                GraphEvent::Interrupted { next },
+            ] if state.count == 1 && next == "increment"
+        ));
+
+        let events: Vec<GraphEvent<Counter>> = graph
+            .stream_resume("counter", None)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+        assert!(matches!(
+            &events[..],
+            [GraphEvent::Node { node, step: 2, state }] if node == "increment" && state.count == 2
+        ));
+        assert_eq!(graph.get_state("counter").await.unwrap().unwrap().count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_runs_as_a_chain() {
+        let graph = counter_graph(2).compile().unwrap();
+
+        let output = graph.execute(prompt_args! { "count" => 0 }).await.unwrap();
+        assert_eq!(output["count"], 2);
+        assert_eq!(output["generate_result"]["generation"], r#"{"count":2}"#);
+
+        let items: Vec<StreamData> = Chain::stream(&graph, prompt_args! { "count" => 0 })
+            .await
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[1].value,
+            json!({ "node": "increment", "step": 2, "state": { "count": 2 } })
+        );
+    }
+}
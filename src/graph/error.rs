@@ -0,0 +1,42 @@
+use thiserror::Error;
+
+use crate::{agent::AgentError, chain::ChainError};
+
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("Chain error: {0}")]
+    ChainError(#[from] ChainError),
+
+    #[error("Agent error: {0}")]
+    AgentError(#[from] AgentError),
+
+    #[error("Tool error: {0}")]
+    ToolError(String),
+
+    #[error("Node not found: {0}")]
+    MissingNode(String),
+
+    #[error("Invalid graph: {0}")]
+    InvalidGraph(String),
+
+    #[error("Recursion limit of {0} steps reached")]
+    RecursionLimit(usize),
+
+    #[error("Checkpoint error: {0}")]
+    CheckpointError(String),
+
+    #[error("Serde json error: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error("Error: {0}")]
+    OtherError(String),
+}
+
+impl From<GraphError> for ChainError {
+    fn from(error: GraphError) -> Self {
+        match error {
+            GraphError::ChainError(error) => error,
+            error => ChainError::GraphError(error.to_string()),
+        }
+    }
+}
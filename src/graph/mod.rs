@@ -0,0 +1,11 @@
+mod builder;
+mod checkpoint;
+mod compiled;
+mod error;
+mod node;
+
+pub use builder::*;
+pub use checkpoint::*;
+pub use compiled::*;
+pub use error::*;
+pub use node::*;
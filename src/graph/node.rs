@@ -0,0 +1,299 @@
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    agent::{Agent, AgentError},
+    chain::{
+        Chain, ChainError, DEFAULT_INTERMEDIATE_STEPS_KEY, DEFAULT_OUTPUT_KEY, DEFAULT_RESULT_KEY,
+    },
+    prompt::PromptArgs,
+    schemas::agent::{AgentAction, AgentEvent},
+    tools::Tool,
+};
+
+use super::GraphError;
+
+/// The state shared by the nodes of a graph. Any type that serializes to a JSON object
+/// works, from `PromptArgs` to a struct with a field for each variable.
+pub trait GraphState: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {}
+
+impl<T> GraphState for T where T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {}
+
+pub(crate) fn state_to_args<S: GraphState>(state: &S) -> Result<PromptArgs, GraphError> {
+    match serde_json::to_value(state)? {
+        Value::Object(variables) => Ok(variables.into_iter().collect()),
+        _ => Err(GraphError::OtherError(
+            "The state must serialize to a JSON object".to_string(),
+        )),
+    }
+}
+
+pub(crate) fn args_to_state<S: GraphState>(variables: PromptArgs) -> Result<S, GraphError> {
+    Ok(serde_json::from_value(Value::Object(
+        variables.into_iter().collect(),
+    ))?)
+}
+
+/// A step of a graph, which receives the current state and returns the updated one.
+#[async_trait]
+pub trait Node<S: GraphState>: Send + Sync {
+    async fn run(&self, state: S) -> Result<S, GraphError>;
+}
+
+/// A node running an async closure.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let node = FnNode::new(|mut state: State| async move {
+///     state.attempts += 1;
+///     Ok(state)
+/// });
+/// ```
+pub struct FnNode<F> {
+    f: F,
+}
+
+impl<F> FnNode<F> {
+    pub fn new(f: F) -> Self {
+        Self { f }
+    }
+}
+
+#[async_trait]
+impl<S, F, Fut> Node<S> for FnNode<F>
+where
+    S: GraphState,
+    F: Fn(S) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<S, GraphError>> + Send,
+{
+    async fn run(&self, state: S) -> Result<S, GraphError> {
+        (self.f)(state).await
+    }
+}
+
+/// A node executing a chain with the state as input variables. The outputs of the chain,
+/// except `generate_result`, are written back to the state under their keys.
+pub struct ChainNode {
+    chain: Box<dyn Chain>,
+}
+
+impl ChainNode {
+    pub fn new<C: Into<Box<dyn Chain>>>(chain: C) -> Self {
+        Self {
+            chain: chain.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: GraphState> Node<S> for ChainNode {
+    async fn run(&self, state: S) -> Result<S, GraphError> {
+        let mut variables = state_to_args(&state)?;
+        let output = self.chain.execute(variables.clone()).await?;
+        for (key, value) in output {
+            if key != DEFAULT_RESULT_KEY {
+                variables.insert(key, value);
+            }
+        }
+        args_to_state(variables)
+    }
+}
+
+/// A node calling a tool with a variable of the state, `input` by default, and writing the
+/// result to another one, `output` by default.
+pub struct ToolNode {
+    tool: Arc<dyn Tool>,
+    input_key: String,
+    output_key: String,
+}
+
+impl ToolNode {
+    pub fn new(tool: Arc<dyn Tool>) -> Self {
+        Self {
+            tool,
+            input_key: "input".to_string(),
+            output_key: DEFAULT_OUTPUT_KEY.to_string(),
+        }
+    }
+
+    pub fn with_input_key<S: Into<String>>(mut self, input_key: S) -> Self {
+        self.input_key = input_key.into();
+        self
+    }
+
+    pub fn with_output_key<S: Into<String>>(mut self, output_key: S) -> Self {
+        self.output_key = output_key.into();
+        self
+    }
+}
+
+#[async_trait]
+impl<S: GraphState> Node<S> for ToolNode {
+    async fn run(&self, state: S) -> Result<S, GraphError> {
+        let mut variables = state_to_args(&state)?;
+        let input = match variables.get(&self.input_key) {
+            Some(Value::String(input)) => input.clone(),
+            Some(input) => input.to_string(),
+            None => return Err(ChainError::MissingInputVariable(self.input_key.clone()).into()),
+        };
+        let output = self
+            .tool
+            .call(&input)
+            .await
+            .map_err(|e| GraphError::ToolError(e.to_string()))?;
+        variables.insert(self.output_key.clone(), json!(output));
+        args_to_state(variables)
+    }
+}
+
+/// A node running one iteration of an agent: it plans with the `intermediate_steps` of the
+/// state and either runs the tools it picked, appending the observations to
+/// `intermediate_steps`, or writes its final answer to `output`.
+///
+/// Unlike [`AgentExecutor`](crate::agent::AgentExecutor), the loop is up to the graph, which
+/// can add other nodes to it, interrupt it for approval or bound it with its recursion limit.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let graph = StateGraph::<PromptArgs>::new()
+///     .add_node("agent", AgentNode::new(agent))
+///     .add_edge(START, "agent")
+///     .add_conditional_edges("agent", |state: &PromptArgs| {
+///         if state.contains_key("output") { END } else { "agent" }
+///     })
+///     .compile()?;
+/// ```
+pub struct AgentNode<A: Agent> {
+    agent: A,
+    tools: HashMap<String, Arc<dyn Tool>>,
+}
+
+impl<A: Agent> AgentNode<A> {
+    pub fn new(agent: A) -> Self {
+        let tools = agent
+            .get_tools()
+            .into_iter()
+            .map(|tool| (tool.name().trim().replace(" ", "_"), tool))
+            .collect();
+        Self { agent, tools }
+    }
+}
+
+#[async_trait]
+impl<S: GraphState, A: Agent> Node<S> for AgentNode<A> {
+    async fn run(&self, state: S) -> Result<S, GraphError> {
+        let mut variables = state_to_args(&state)?;
+        let mut steps: Vec<(AgentAction, String)> =
+            match variables.get(DEFAULT_INTERMEDIATE_STEPS_KEY) {
+                Some(Value::Null) | None => Vec::new(),
+                Some(steps) => serde_json::from_value(steps.clone())?,
+            };
+        let mut inputs = variables.clone();
+        inputs
+            .entry("chat_history".to_string())
+            .or_insert_with(|| json!([]));
+
+        match self.agent.plan(&steps, inputs).await? {
+            AgentEvent::Action(actions) => {
+                for action in actions {
+                    let tool = self
+                        .tools
+                        .get(&action.tool.trim().replace(" ", "_"))
+                        .ok_or_else(|| {
+                            AgentError::ToolError(format!("Tool {} not found", action.tool))
+                        })?;
+                    let observation = match tool.call(&action.tool_input).await {
+                        Ok(observation) => observation,
+                        Err(err) => format!("The tool return the following error: {}", err),
+                    };
+                    steps.push((action, observation));
+                }
+            }
+            AgentEvent::Finish(finish) => {
+                variables.insert(DEFAULT_OUTPUT_KEY.to_string(), json!(finish.output));
+            }
+        }
+        variables.insert(DEFAULT_INTERMEDIATE_STEPS_KEY.to_string(), json!(steps));
+        args_to_state(variables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::{
+        agent::OpenAiToolAgentBuilder,
+        graph::{StateGraph, END, START},
+        llm::{FakeLLM, FakeResponse},
+        prompt_args,
+    };
+
+    use super::*;
+
+    struct Calc {}
+
+    #[async_trait]
+    impl Tool for Calc {
+        fn name(&self) -> String {
+            "Calculator".to_string()
+        }
+        fn description(&self) -> String {
+            "Usefull to make calculations".to_string()
+        }
+        async fn run(&self, _input: Value) -> Result<String, Box<dyn Error>> {
+            Ok("25".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_node() {
+        let node = ToolNode::new(Arc::new(Calc {})).with_output_key("result");
+
+        let state = node
+            .run(prompt_args! { "input" => "20 + 5" })
+            .await
+            .unwrap();
+
+        assert_eq!(state["result"], "25");
+    }
+
+    #[tokio::test]
+    async fn test_agent_node_loops_in_a_graph() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::tool_call("Calculator", json!({"input": "20 + 5"})),
+            FakeResponse::text("The result is 25"),
+        ]);
+        let agent = OpenAiToolAgentBuilder::new()
+            .tools(&[Arc::new(Calc {})])
+            .build(llm.clone())
+            .unwrap();
+        let graph = StateGraph::<PromptArgs>::new()
+            .add_node("agent", AgentNode::new(agent))
+            .add_edge(START, "agent")
+            .add_conditional_edges("agent", |state: &PromptArgs| {
+                if state.contains_key("output") {
+                    END
+                } else {
+                    "agent"
+                }
+            })
+            .compile()
+            .unwrap();
+
+        let run = graph
+            .run(prompt_args! { "input" => "How much is 20 + 5?" })
+            .await
+            .unwrap();
+
+        assert_eq!(run.steps, 2);
+        assert_eq!(run.state["output"], "The result is 25");
+        assert_eq!(run.state["intermediate_steps"][0][1], "25");
+        assert_eq!(llm.requests()[1].last().unwrap().content, "25");
+    }
+}
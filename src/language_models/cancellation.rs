@@ -0,0 +1,114 @@
+use std::{future::Future, pin::Pin};
+
+use futures::{Stream, StreamExt};
+pub use tokio_util::sync::CancellationToken;
+
+use crate::schemas::StreamData;
+
+use super::{GenerateResult, LLMError};
+
+/// Resolves once `token` is cancelled, never when there is no token.
+pub(crate) async fn cancelled(token: Option<&CancellationToken>) {
+    match token {
+        Some(token) => token.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Runs a request unless `token` is cancelled first, in which case the request is dropped and
+/// `LLMError::Cancelled` is returned with an empty partial result.
+pub(crate) async fn cancellable<T, F>(
+    token: Option<&CancellationToken>,
+    future: F,
+) -> Result<T, LLMError>
+where
+    F: Future<Output = Result<T, LLMError>>,
+{
+    tokio::select! {
+        biased;
+        _ = cancelled(token) => Err(LLMError::cancelled(GenerateResult::default())),
+        result = future => result,
+    }
+}
+
+/// Stops `stream` as soon as `token` is cancelled. The last item is then an
+/// `LLMError::Cancelled` holding everything streamed so far.
+pub(crate) fn cancellable_stream(
+    stream: Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>,
+    token: Option<CancellationToken>,
+) -> Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>> {
+    let Some(token) = token else {
+        return stream;
+    };
+
+    Box::pin(async_stream::stream! {
+        let mut stream = stream;
+        let mut partial = GenerateResult::default();
+        loop {
+            let item = tokio::select! {
+                biased;
+                _ = token.cancelled() => None,
+                item = stream.next() => Some(item),
+            };
+            match item {
+                None => {
+                    yield Err(LLMError::cancelled(partial));
+                    break;
+                }
+                Some(None) => break,
+                Some(Some(Ok(data))) => {
+                    partial.merge_stream_data(&data);
+                    yield Ok(data);
+                }
+                Some(Some(Err(e))) => yield Err(e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::language_models::TokenUsage;
+
+    #[tokio::test]
+    async fn test_cancellable_drops_request() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let result = cancellable(Some(&token), async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        })
+        .await;
+
+        assert!(matches!(result, Err(LLMError::Cancelled { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_cancellable_stream_returns_partial() {
+        let token = CancellationToken::new();
+        let chunks = futures::stream::iter(vec![
+            Ok(StreamData::new(serde_json::Value::Null, None, "Hello")),
+            Ok(StreamData::new(
+                serde_json::Value::Null,
+                Some(TokenUsage::new(5, 1)),
+                " world",
+            )),
+        ])
+        .chain(futures::stream::pending());
+        let mut stream = cancellable_stream(Box::pin(chunks), Some(token.clone()));
+
+        assert_eq!(stream.next().await.unwrap().unwrap().content, "Hello");
+        assert_eq!(stream.next().await.unwrap().unwrap().content, " world");
+        token.cancel();
+
+        let error = stream.next().await.unwrap().unwrap_err();
+        let partial = error.partial_result().unwrap();
+        assert_eq!(partial.generation, "Hello world");
+        assert_eq!(partial.tokens.as_ref().unwrap().total_tokens, 6);
+        assert!(stream.next().await.is_none());
+    }
+}
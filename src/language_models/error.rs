@@ -1,12 +1,20 @@
+use std::time::Duration;
+
 use async_openai::error::OpenAIError;
 #[cfg(feature = "ollama")]
 use ollama_rs::error::OllamaError;
-use reqwest::Error as ReqwestError;
+use reqwest::{header::HeaderMap, Error as ReqwestError};
 use serde_json::Error as SerdeJsonError;
 use thiserror::Error;
 use tokio::time::error::Elapsed;
 
-use crate::llm::{AnthropicError, DeepseekError, QwenError};
+#[cfg(feature = "gemini")]
+use crate::llm::GeminiError;
+#[cfg(feature = "gguf")]
+use crate::llm::GgufError;
+use crate::llm::{AnthropicError, DeepseekError, OpenAICompatibleError, QwenError};
+
+use super::GenerateResult;
 
 #[derive(Error, Debug)]
 pub enum LLMError {
@@ -22,6 +30,17 @@ pub enum LLMError {
     #[error("Deepseek error: {0}")]
     DeepseekError(#[from] DeepseekError),
 
+    #[error("OpenAI compatible API error: {0}")]
+    OpenAICompatibleError(#[from] OpenAICompatibleError),
+
+    #[cfg(feature = "gemini")]
+    #[error("Gemini error: {0}")]
+    GeminiError(#[from] GeminiError),
+
+    #[cfg(feature = "gguf")]
+    #[error("GGUF error: {0}")]
+    GgufError(#[from] GgufError),
+
     #[cfg(feature = "ollama")]
     #[error("Ollama error: {0}")]
     OllamaError(#[from] OllamaError),
@@ -47,6 +66,159 @@ pub enum LLMError {
     #[error("Parsing error: {0}")]
     ParsingError(String),
 
+    #[error("Prompt of {prompt_tokens} tokens plus {max_output_tokens} output tokens exceeds the context window of {context_window} tokens")]
+    ContextWindowExceeded {
+        prompt_tokens: usize,
+        max_output_tokens: usize,
+        context_window: usize,
+    },
+
+    #[error("Unsupported content: {0}")]
+    UnsupportedContent(String),
+
     #[error("Error: {0}")]
     OtherError(String),
+
+    #[error("Generation cancelled")]
+    Cancelled { partial: Box<GenerateResult> },
+}
+
+/// The `Retry-After` field of the rate limit and overload errors that carry one, borrowed
+/// like `error` is. The one list of these variants, for reading and for setting the field.
+macro_rules! retry_after_field {
+    ($error:expr) => {
+        match $error {
+            LLMError::AnthropicError(
+                AnthropicError::RateLimitError(_, retry_after)
+                | AnthropicError::OverloadedError(_, retry_after),
+            )
+            | LLMError::DeepseekError(
+                DeepseekError::RateLimitError(_, retry_after)
+                | DeepseekError::ServerOverloadedError(_, retry_after),
+            )
+            | LLMError::QwenError(
+                QwenError::ModelServingError(_, retry_after)
+                | QwenError::ModelUnavailableError(_, retry_after),
+            )
+            | LLMError::OpenAICompatibleError(
+                OpenAICompatibleError::RateLimitError(_, retry_after)
+                | OpenAICompatibleError::ServerError(_, retry_after),
+            ) => Some(retry_after),
+            #[cfg(feature = "gemini")]
+            LLMError::GeminiError(
+                GeminiError::ResourceExhaustedError(_, retry_after)
+                | GeminiError::UnavailableError(_, retry_after),
+            ) => Some(retry_after),
+            _ => None,
+        }
+    };
+}
+
+impl LLMError {
+    /// Attaches the delay requested by the provider in a `Retry-After` header, if any, to the
+    /// rate limit and overload errors that carry it.
+    pub(crate) fn with_retry_after(mut self, headers: &HeaderMap) -> Self {
+        let retry_after = headers
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+            .map(Duration::from_secs_f64);
+        if let (Some(retry_after), Some(slot)) = (retry_after, self.retry_after_slot()) {
+            *slot = Some(retry_after);
+        }
+        self
+    }
+
+    fn retry_after_slot(&mut self) -> Option<&mut Option<Duration>> {
+        retry_after_field!(self)
+    }
+
+    pub(crate) fn cancelled(partial: GenerateResult) -> Self {
+        LLMError::Cancelled {
+            partial: Box::new(partial),
+        }
+    }
+
+    /// Whether the call was stopped through its `CancellationToken`.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, LLMError::Cancelled { .. })
+    }
+
+    /// What was generated before the call was cancelled, including the token usage reported
+    /// up to that point.
+    pub fn partial_result(&self) -> Option<&GenerateResult> {
+        match self {
+            LLMError::Cancelled { partial } => Some(partial),
+            _ => None,
+        }
+    }
+
+    /// The delay the provider asked for before retrying, if it sent one.
+    pub fn retry_after(&self) -> Option<Duration> {
+        retry_after_field!(self).copied().flatten()
+    }
+
+    /// Whether the error is transient (rate limits, overloaded or failing servers, timeouts)
+    /// and the same request may succeed if sent again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LLMError::Timeout(_) => true,
+            LLMError::RequestError(e) => is_retryable_reqwest(e),
+            LLMError::OpenAIError(OpenAIError::Reqwest(e)) => is_retryable_reqwest(e),
+            LLMError::OpenAIError(OpenAIError::ApiError(e)) => {
+                let kind = e
+                    .r#type
+                    .as_deref()
+                    .or(e.code.as_deref())
+                    .unwrap_or_default();
+                kind.contains("rate_limit") || kind == "server_error" || kind == "overloaded"
+            }
+            LLMError::AnthropicError(e) => matches!(
+                e,
+                AnthropicError::RateLimitError(..)
+                    | AnthropicError::ApiError(_)
+                    | AnthropicError::OverloadedError(..)
+            ),
+            LLMError::DeepseekError(e) => matches!(
+                e,
+                DeepseekError::RateLimitError(..)
+                    | DeepseekError::ServerError(_)
+                    | DeepseekError::ServerOverloadedError(..)
+            ),
+            LLMError::QwenError(e) => matches!(
+                e,
+                QwenError::ModelServingError(..)
+                    | QwenError::NetworkError(_)
+                    | QwenError::APIConnectionError(_)
+                    | QwenError::InternalError(_)
+                    | QwenError::SystemError(_)
+                    | QwenError::TimeoutError(_)
+                    | QwenError::ModelUnavailableError(..)
+            ),
+            LLMError::OpenAICompatibleError(e) => matches!(
+                e,
+                OpenAICompatibleError::RateLimitError(..) | OpenAICompatibleError::ServerError(..)
+            ),
+            #[cfg(feature = "gemini")]
+            LLMError::GeminiError(e) => matches!(
+                e,
+                GeminiError::ResourceExhaustedError(..)
+                    | GeminiError::InternalError(_)
+                    | GeminiError::UnavailableError(..)
+                    | GeminiError::DeadlineExceededError(_)
+            ),
+            _ => false,
+        }
+    }
+}
+
+fn is_retryable_reqwest(error: &ReqwestError) -> bool {
+    if error.is_timeout() || error.is_connect() {
+        return true;
+    }
+    error
+        .status()
+        .map(|status| status.as_u16() == 429 || status.is_server_error())
+        .unwrap_or(false)
 }
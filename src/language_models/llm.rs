@@ -1,11 +1,18 @@
 use std::pin::Pin;
 
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{stream, Stream, StreamExt};
 
-use crate::schemas::{Message, StreamData};
+use crate::schemas::{Message, StreamData, StreamEvent};
 
-use super::{options::CallOptions, GenerateResult, LLMError};
+use super::{
+    options::CallOptions,
+    tokens::{count_message_tokens, Tokenizer},
+    GenerateResult, LLMError,
+};
+
+/// The number of calls [`LLM::generate_batch`] runs at a time by default.
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
 
 #[async_trait]
 pub trait LLM: Sync + Send + LLMClone {
@@ -20,11 +27,69 @@ pub trait LLM: Sync + Send + LLMClone {
         _messages: &[Message],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError>;
 
+    /// Like [`LLM::generate`], with `options` overriding the model's own options for this
+    /// call only, so a shared model can be called with a different temperature, stop words or
+    /// tools per request.
+    ///
+    /// Defaults to calling a copy of the model given the options through
+    /// [`LLM::override_options`].
+    async fn generate_with_options(
+        &self,
+        messages: &[Message],
+        options: &CallOptions,
+    ) -> Result<GenerateResult, LLMError> {
+        let mut llm = self.clone_box();
+        llm.override_options(options.clone());
+        llm.generate(messages).await
+    }
+
+    /// Like [`LLM::stream`], with `options` overriding the model's own options for this call
+    /// only, see [`LLM::generate_with_options`].
+    async fn stream_with_options(
+        &self,
+        messages: &[Message],
+        options: &CallOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let mut llm = self.clone_box();
+        llm.override_options(options.clone());
+        llm.stream(messages).await
+    }
+
+    /// Generates the answers to many independent conversations, in order. Each conversation
+    /// gets its own result so that one failure does not lose the others, the outer error is
+    /// for failures of the whole batch.
+    ///
+    /// Runs [`DEFAULT_BATCH_CONCURRENCY`] calls to [`LLM::generate`] at a time, see
+    /// [`generate_concurrently`] for another bound. Providers with a batch API may override it.
+    async fn generate_batch(
+        &self,
+        batch: &[Vec<Message>],
+    ) -> Result<Vec<Result<GenerateResult, LLMError>>, LLMError> {
+        Ok(generate_concurrently(self, batch, DEFAULT_BATCH_CONCURRENCY).await)
+    }
+
+    /// Like [`LLM::stream`], flattened into provider independent [`StreamEvent`]s.
+    async fn stream_events(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LLMError>> + Send>>, LLMError> {
+        Ok(Box::pin(stream_events(self.stream(messages).await?)))
+    }
+
     /// This is usefull when you want to create a chain and override
     /// LLM options
     fn add_options(&mut self, _options: CallOptions) {
         // No action taken
     }
+
+    /// Like [`LLM::add_options`], except that the options set in `options` replace the
+    /// model's own, stop words and functions included, instead of adding to them. Used to
+    /// apply the per-call options of [`LLM::generate_with_options`].
+    ///
+    /// Defaults to [`LLM::add_options`].
+    fn override_options(&mut self, options: CallOptions) {
+        self.add_options(options)
+    }
     //This is usefull when using non chat models
     fn messages_to_string(&self, messages: &[Message]) -> String {
         messages
@@ -33,6 +98,72 @@ pub trait LLM: Sync + Send + LLMClone {
             .collect::<Vec<String>>()
             .join("\n")
     }
+
+    /// Estimates the number of prompt tokens `messages` take. Defaults to the `cl100k_base`
+    /// encoding, providers override it with the encoding of their model when known.
+    fn count_tokens(&self, messages: &[Message]) -> usize {
+        count_message_tokens(Tokenizer::Cl100kBase, messages)
+    }
+
+    /// The model's context window in tokens, prompt and completion included, if known.
+    fn context_window(&self) -> Option<usize> {
+        None
+    }
+
+    /// The number of tokens the model may generate per call as configured, which the
+    /// provider reserves out of the context window.
+    fn max_output_tokens(&self) -> Option<usize> {
+        None
+    }
+
+    /// Checks that `messages` plus the output tokens fit in the context window before
+    /// sending them, returning the estimated prompt tokens. Passes when the window is unknown.
+    fn check_context_window(&self, messages: &[Message]) -> Result<usize, LLMError> {
+        let prompt_tokens = self.count_tokens(messages);
+        if let Some(context_window) = self.context_window() {
+            let max_output_tokens = self.max_output_tokens().unwrap_or_default();
+            if prompt_tokens + max_output_tokens > context_window {
+                return Err(LLMError::ContextWindowExceeded {
+                    prompt_tokens,
+                    max_output_tokens,
+                    context_window,
+                });
+            }
+        }
+        Ok(prompt_tokens)
+    }
+}
+
+/// Calls [`LLM::generate`] for each conversation of `batch`, at most `concurrency` at a time,
+/// and returns the results in the order of `batch`.
+pub async fn generate_concurrently<L: LLM + ?Sized>(
+    llm: &L,
+    batch: &[Vec<Message>],
+    concurrency: usize,
+) -> Vec<Result<GenerateResult, LLMError>> {
+    // Creating the futures up front keeps their lifetimes concrete, which the `Send` bound of
+    // the async trait methods needs; they only start once polled
+    let calls: Vec<_> = batch
+        .iter()
+        .map(|messages| llm.generate(messages))
+        .collect();
+    stream::iter(calls)
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Flattens a stream of chunks into their events, keeping errors in place.
+pub(crate) fn stream_events<E: Send + 'static>(
+    stream: Pin<Box<dyn Stream<Item = Result<StreamData, E>> + Send>>,
+) -> impl Stream<Item = Result<StreamEvent, E>> + Send {
+    stream.flat_map(|data| {
+        let events = match data {
+            Ok(data) => data.events().into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        };
+        stream::iter(events)
+    })
 }
 
 pub trait LLMClone {
@@ -56,3 +187,111 @@ where
         Box::new(llm)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        language_models::cancellation::CancellationToken,
+        llm::{FakeLLM, FakeResponse},
+        schemas::FunctionDefinition,
+    };
+
+    #[tokio::test]
+    async fn test_generate_concurrently_keeps_order_and_bound() {
+        let mut llm = FakeLLM::new().with_delay(Duration::from_millis(20));
+        for n in ["1", "2", "4", "5", "6"] {
+            llm = llm.with_response_when_contains(n, FakeResponse::text(n));
+        }
+        let llm = llm.with_response_when_contains("3", FakeResponse::error("three"));
+        let batch: Vec<Vec<Message>> = (1..=6)
+            .map(|n| vec![Message::new_human_message(n.to_string())])
+            .collect();
+
+        let results = generate_concurrently(&llm, &batch, 2).await;
+        let generations: Vec<String> = results
+            .iter()
+            .map(|result| match result {
+                Ok(result) => result.generation.clone(),
+                Err(_) => "error".to_string(),
+            })
+            .collect();
+        assert_eq!(generations, vec!["1", "2", "error", "4", "5", "6"]);
+        assert_eq!(llm.max_concurrent_calls(), 2);
+
+        let results = llm.generate_batch(&batch).await.unwrap();
+        assert_eq!(results.len(), 6);
+        assert_eq!(llm.max_concurrent_calls(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_per_call_options_override_the_model_options() {
+        let llm = FakeLLM::new()
+            .with_default_response(FakeResponse::text("ok"))
+            .with_options(
+                CallOptions::new()
+                    .with_functions(vec![FunctionDefinition::new(
+                        "baked_in",
+                        "Set on the model",
+                        json!({}),
+                    )])
+                    .with_stop_words(vec!["STOP".to_string()])
+                    .with_temperature(0.2),
+            );
+
+        let options = CallOptions::new()
+            .with_functions(vec![FunctionDefinition::new(
+                "per_call",
+                "Given for this call",
+                json!({}),
+            )])
+            .with_stop_words(vec!["END".to_string()]);
+        llm.generate_with_options(&[Message::new_human_message("hi")], &options)
+            .await
+            .unwrap();
+
+        let used = llm.last_options().unwrap();
+        let functions: Vec<String> = used
+            .functions
+            .unwrap()
+            .into_iter()
+            .map(|function| function.name)
+            .collect();
+        assert_eq!(functions, vec!["per_call"]);
+        assert_eq!(used.stop_words, Some(vec!["END".to_string()]));
+        assert_eq!(used.temperature, Some(0.2));
+
+        // The model itself keeps its own options
+        llm.generate(&[Message::new_human_message("hi")])
+            .await
+            .unwrap();
+        let used = llm.last_options().unwrap();
+        assert_eq!(used.functions.unwrap()[0].name, "baked_in");
+        assert_eq!(used.stop_words, Some(vec!["STOP".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_token_only_applies_per_call() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let options = CallOptions::new().with_cancellation_token(token);
+
+        let mut llm = FakeLLM::new().with_default_response(FakeResponse::text("ok"));
+        let error = llm
+            .generate_with_options(&[Message::new_human_message("hi")], &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, LLMError::Cancelled { .. }));
+        assert!(llm.last_options().unwrap().cancellation_token.is_some());
+
+        llm.add_options(options);
+        assert!(llm.options().cancellation_token.is_none());
+        llm.generate(&[Message::new_human_message("hi")])
+            .await
+            .unwrap();
+    }
+}
@@ -1,19 +1,42 @@
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+use crate::schemas::{
+    merge_tool_call_delta, FunctionCallResponse, MessageReasoning, ReasoningBlock,
+    ReasoningRetention, StreamData,
+};
+
+pub mod cancellation;
 pub mod llm;
 pub mod options;
+pub mod structured;
+pub mod tokens;
 
 mod error;
 pub use error::*;
 
-//TODO: check if its this should have a data:serde::Value to save all other things, like OpenAI
-//function responses
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct GenerateResult {
     pub tokens: Option<TokenUsage>,
     pub generation: String,
+    /// Tool calls requested by the model. When this is not empty `generation` only holds
+    /// the text the model produced alongside the calls, if any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<FunctionCallResponse>,
+    pub finish_reason: Option<FinishReason>,
+    /// The model that served the request, as reported by the provider.
+    pub model: Option<String>,
+    /// Reasoning ("thinking") text for models that return it separately from the answer.
+    pub reasoning_content: Option<String>,
+    /// The blocks `reasoning_content` is made of, for providers signing their reasoning. They
+    /// are needed to send the reasoning back to Claude, see [`MessageReasoning`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasoning_blocks: Vec<ReasoningBlock>,
+    pub logprobs: Option<Value>,
+    /// The unmodified provider response, for anything not covered by the typed fields.
+    pub raw_response: Option<Value>,
 }
 
 impl GenerateResult {
@@ -38,13 +61,126 @@ impl GenerateResult {
 
         map
     }
+
+    /// The reasoning to keep with the AI message of this result, see [`ReasoningRetention`].
+    pub fn reasoning(&self, retention: ReasoningRetention) -> Option<MessageReasoning> {
+        retention.reasoning(self.reasoning_content.as_deref(), &self.reasoning_blocks)
+    }
+
+    /// The chunks streaming the reasoning of this result, one per block when it has some.
+    pub(crate) fn reasoning_chunks(&self) -> Vec<StreamData> {
+        if self.reasoning_blocks.is_empty() {
+            return self
+                .reasoning_content
+                .iter()
+                .map(|reasoning| {
+                    StreamData::new(Value::Null, None, "")
+                        .with_reasoning_content(Some(reasoning.clone()))
+                })
+                .collect();
+        }
+        self.reasoning_blocks
+            .iter()
+            .map(|block| match block {
+                ReasoningBlock::Signed { content, signature } => {
+                    StreamData::new(Value::Null, None, "")
+                        .with_reasoning_content(Some(content.clone()))
+                        .with_reasoning_signature(Some(signature.clone()))
+                }
+                ReasoningBlock::Redacted { data } => StreamData::new(Value::Null, None, "")
+                    .with_reasoning_redacted(Some(data.clone())),
+            })
+            .collect()
+    }
+
+    /// Folds a streamed chunk into the result, reassembling tool calls from their deltas.
+    /// Collecting a whole stream this way gives the same result as `generate`.
+    pub fn merge_stream_data(&mut self, data: &StreamData) {
+        self.generation.push_str(&data.content);
+        if let Some(reasoning) = &data.reasoning_content {
+            self.reasoning_content
+                .get_or_insert_with(String::new)
+                .push_str(reasoning);
+        }
+        if let Some(signature) = &data.reasoning_signature {
+            // The signature closes the block made of the reasoning since the previous one
+            let signed: usize = self
+                .reasoning_blocks
+                .iter()
+                .map(|block| match block {
+                    ReasoningBlock::Signed { content, .. } => content.len(),
+                    ReasoningBlock::Redacted { .. } => 0,
+                })
+                .sum();
+            let content = self
+                .reasoning_content
+                .as_deref()
+                .and_then(|reasoning| reasoning.get(signed..))
+                .unwrap_or_default();
+            self.reasoning_blocks.push(ReasoningBlock::Signed {
+                content: content.to_string(),
+                signature: signature.clone(),
+            });
+        }
+        if let Some(redacted) = &data.reasoning_redacted {
+            self.reasoning_blocks.push(ReasoningBlock::Redacted {
+                data: redacted.clone(),
+            });
+        }
+        for delta in &data.tool_call_deltas {
+            merge_tool_call_delta(&mut self.tool_calls, delta);
+        }
+        if data.tokens.is_some() {
+            self.tokens = data.tokens.clone();
+        }
+        if data.finish_reason.is_some() {
+            self.finish_reason = data.finish_reason.clone();
+        }
+        if data.model.is_some() {
+            self.model = data.model.clone();
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+/// Why the model stopped generating, normalised across providers.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished naturally or hit a stop sequence.
+    Stop,
+    /// The output reached the maximum number of tokens.
+    Length,
+    /// The model stopped to call one or more tools.
+    ToolCalls,
+    /// The output was withheld by the provider's content filter.
+    ContentFilter,
+    /// Any provider specific reason not covered above.
+    Other(String),
+}
+
+impl From<&str> for FinishReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "stop" | "end_turn" | "stop_sequence" => FinishReason::Stop,
+            "length" | "max_tokens" => FinishReason::Length,
+            "tool_calls" | "tool_use" | "function_call" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// The prompt tokens read from the provider's prompt cache, counted in `prompt_tokens`.
+    #[serde(default)]
+    pub cache_read_tokens: u32,
+    /// The prompt tokens written to the provider's prompt cache, counted in `prompt_tokens`.
+    #[serde(default)]
+    pub cache_creation_tokens: u32,
 }
 
 impl TokenUsage {
@@ -53,6 +189,8 @@ impl TokenUsage {
             prompt_tokens: self.prompt_tokens + other.prompt_tokens,
             completion_tokens: self.completion_tokens + other.completion_tokens,
             total_tokens: self.total_tokens + other.total_tokens,
+            cache_read_tokens: self.cache_read_tokens + other.cache_read_tokens,
+            cache_creation_tokens: self.cache_creation_tokens + other.cache_creation_tokens,
         }
     }
 
@@ -60,6 +198,8 @@ impl TokenUsage {
         self.prompt_tokens += other.prompt_tokens;
         self.completion_tokens += other.completion_tokens;
         self.total_tokens += other.total_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cache_creation_tokens += other.cache_creation_tokens;
     }
 }
 
@@ -69,6 +209,13 @@ impl TokenUsage {
             prompt_tokens,
             completion_tokens,
             total_tokens: prompt_tokens + completion_tokens,
+            ..Default::default()
         }
     }
+
+    pub fn with_cache_tokens(mut self, cache_read_tokens: u32, cache_creation_tokens: u32) -> Self {
+        self.cache_read_tokens = cache_read_tokens;
+        self.cache_creation_tokens = cache_creation_tokens;
+        self
+    }
 }
@@ -1,9 +1,12 @@
 use futures::Future;
+use serde::{Deserialize, Serialize};
 use std::{pin::Pin, sync::Arc};
 use tokio::sync::Mutex;
 
 use crate::schemas::{FunctionCallBehavior, FunctionDefinition, ResponseFormat};
 
+use super::cancellation::CancellationToken;
+
 #[derive(Clone)]
 pub struct CallOptions {
     pub candidate_count: Option<usize>,
@@ -28,6 +31,19 @@ pub struct CallOptions {
     pub function_call_behavior: Option<FunctionCallBehavior>,
     pub response_format: Option<ResponseFormat>,
     pub stream_usage: Option<bool>,
+    /// Cancels the call, or the stream, while it is in flight. The call then fails with
+    /// `LLMError::Cancelled`, which carries what was generated so far.
+    ///
+    /// Only applies to the call it is given for, through `LLM::generate_with_options` or
+    /// `LLM::stream_with_options`: [`CallOptions::merge_options`] leaves it out, so that a
+    /// cancelled token does not fail every later call of the model.
+    pub cancellation_token: Option<CancellationToken>,
+    /// How hard thinking models should reason before answering. Providers taking a token
+    /// budget instead get [`ReasoningEffort::budget_tokens`].
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// The number of tokens thinking models may spend reasoning, `0` disables reasoning where
+    /// the provider allows it. Takes precedence over `reasoning_effort`.
+    pub reasoning_budget: Option<u32>,
 }
 
 impl Default for CallOptions {
@@ -56,6 +72,9 @@ impl CallOptions {
             function_call_behavior: None,
             response_format: None,
             stream_usage: None,
+            cancellation_token: None,
+            reasoning_effort: None,
+            reasoning_budget: None,
         }
     }
 
@@ -161,6 +180,55 @@ impl CallOptions {
         self
     }
 
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    pub fn with_reasoning_effort(mut self, reasoning_effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(reasoning_effort);
+        self
+    }
+
+    pub fn with_reasoning_budget(mut self, reasoning_budget: u32) -> Self {
+        self.reasoning_budget = Some(reasoning_budget);
+        self
+    }
+
+    /// The reasoning budget asked for, either directly or through the effort.
+    pub fn reasoning_budget_tokens(&self) -> Option<u32> {
+        self.reasoning_budget
+            .or_else(|| self.reasoning_effort.map(|effort| effort.budget_tokens()))
+    }
+
+    /// The reasoning effort asked for, either directly or derived from the budget. `None` when
+    /// no reasoning was asked for or the budget disables it.
+    pub fn reasoning_effort_level(&self) -> Option<ReasoningEffort> {
+        match self.reasoning_budget {
+            Some(0) => None,
+            Some(budget) => Some(ReasoningEffort::from_budget_tokens(budget)),
+            None => self.reasoning_effort,
+        }
+    }
+
+    /// Like [`CallOptions::merge_options`], except that incoming stop words and functions
+    /// replace the existing ones instead of being appended to them, and that the cancellation
+    /// token is taken too.
+    pub fn override_options(&mut self, mut incoming_options: CallOptions) {
+        let stop_words = incoming_options.stop_words.take();
+        let functions = incoming_options.functions.take();
+        if incoming_options.cancellation_token.is_some() {
+            self.cancellation_token = incoming_options.cancellation_token.take();
+        }
+        self.merge_options(incoming_options);
+        if stop_words.is_some() {
+            self.stop_words = stop_words;
+        }
+        if functions.is_some() {
+            self.functions = functions;
+        }
+    }
+
     pub fn merge_options(&mut self, incoming_options: CallOptions) {
         // For simple scalar types wrapped in Option, prefer incoming option if it is Some
         self.candidate_count = incoming_options.candidate_count.or(self.candidate_count);
@@ -186,6 +254,8 @@ impl CallOptions {
             .response_format
             .or(self.response_format.clone());
         self.stream_usage = incoming_options.stream_usage.or(self.stream_usage);
+        self.reasoning_effort = incoming_options.reasoning_effort.or(self.reasoning_effort);
+        self.reasoning_budget = incoming_options.reasoning_budget.or(self.reasoning_budget);
 
         // For `Vec<String>`, merge if both are Some; prefer incoming if only incoming is Some
         if let Some(mut new_stop_words) = incoming_options.stop_words {
@@ -212,3 +282,40 @@ impl CallOptions {
             .or_else(|| self.streaming_func.clone());
     }
 }
+
+/// How much thinking models reason before answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    /// The token budget used for providers that take one instead of an effort level.
+    pub fn budget_tokens(&self) -> u32 {
+        match self {
+            ReasoningEffort::Low => 1024,
+            ReasoningEffort::Medium => 4096,
+            ReasoningEffort::High => 16384,
+        }
+    }
+
+    /// The effort level closest to a token budget.
+    pub fn from_budget_tokens(budget: u32) -> Self {
+        match budget {
+            0..=2048 => ReasoningEffort::Low,
+            2049..=8192 => ReasoningEffort::Medium,
+            _ => ReasoningEffort::High,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+
+use crate::schemas::{Message, ResponseFormat};
+
+use super::{llm::LLM, options::CallOptions, LLMError};
+
+/// Generates responses in a [`ResponseFormat`] and deserializes them, with any [`LLM`].
+///
+/// Providers use their native structured output mode when they have one. Claude is forced to
+/// call a tool taking the schema as input, and the others are asked for JSON in the prompt,
+/// with the response checked against the schema.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, JsonSchema)]
+/// struct Movie {
+///     title: String,
+///     year: u32,
+/// }
+///
+/// let movie: Movie = llm
+///     .generate_typed(&[Message::new_human_message("Which movie has Pacino and De Niro?")])
+///     .await?;
+/// ```
+#[async_trait]
+pub trait StructuredOutput {
+    async fn generate_structured<T: DeserializeOwned + Send>(
+        &self,
+        messages: &[Message],
+        response_format: ResponseFormat,
+    ) -> Result<T, LLMError>;
+
+    /// Like [`StructuredOutput::generate_structured`], with the schema derived from `T`.
+    #[cfg(feature = "schemars")]
+    async fn generate_typed<T: DeserializeOwned + schemars::JsonSchema + Send>(
+        &self,
+        messages: &[Message],
+    ) -> Result<T, LLMError> {
+        self.generate_structured(messages, ResponseFormat::from_type::<T>())
+            .await
+    }
+}
+
+#[async_trait]
+impl<L: LLM + ?Sized> StructuredOutput for L {
+    async fn generate_structured<T: DeserializeOwned + Send>(
+        &self,
+        messages: &[Message],
+        response_format: ResponseFormat,
+    ) -> Result<T, LLMError> {
+        let mut llm = self.clone_box();
+        llm.add_options(CallOptions::new().with_response_format(response_format.clone()));
+        let result = llm.generate(messages).await?;
+        response_format.parse(&result.generation)
+    }
+}
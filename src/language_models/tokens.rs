@@ -0,0 +1,118 @@
+pub use tiktoken_rs::tokenizer::Tokenizer;
+use tiktoken_rs::{
+    cl100k_base_singleton, o200k_base_singleton, p50k_base_singleton, p50k_edit_singleton,
+    r50k_base_singleton, tokenizer::get_tokenizer, CoreBPE,
+};
+
+use crate::schemas::Message;
+
+/// Tokens every chat message adds on top of its content (role and separators), following
+/// OpenAI's accounting for its chat models.
+const TOKENS_PER_MESSAGE: usize = 4;
+/// Tokens the reply is primed with.
+const TOKENS_PER_REPLY: usize = 3;
+
+/// The `tiktoken` encoding used for `model`, falling back to `cl100k_base` (the same default
+/// as `SplitterOptions`) for models it does not know, including non OpenAI ones.
+pub fn tokenizer_for_model(model: &str) -> Tokenizer {
+    get_tokenizer(model).unwrap_or(Tokenizer::Cl100kBase)
+}
+
+/// Counts the tokens `messages` take in a chat prompt with the given encoding.
+///
+/// Only text is counted: message content, tool calls and text parts. Images, audio and
+/// files are billed differently by each provider and are not included.
+pub fn count_message_tokens(tokenizer: Tokenizer, messages: &[Message]) -> usize {
+    let tokens = with_bpe(tokenizer, |bpe| {
+        messages
+            .iter()
+            .map(|message| {
+                let tool_calls = message
+                    .tool_calls
+                    .as_ref()
+                    .map(|tool_calls| bpe.encode_ordinary(&tool_calls.to_string()).len())
+                    .unwrap_or_default();
+                TOKENS_PER_MESSAGE + bpe.encode_ordinary(&message.content).len() + tool_calls
+            })
+            .sum::<usize>()
+    });
+    tokens + TOKENS_PER_REPLY
+}
+
+/// Counts the tokens of plain `text` with the given encoding.
+pub fn count_text_tokens(tokenizer: Tokenizer, text: &str) -> usize {
+    with_bpe(tokenizer, |bpe| bpe.encode_ordinary(text).len())
+}
+
+fn with_bpe<T>(tokenizer: Tokenizer, f: impl FnOnce(&CoreBPE) -> T) -> T {
+    let bpe = match tokenizer {
+        Tokenizer::O200kBase => o200k_base_singleton(),
+        Tokenizer::Cl100kBase => cl100k_base_singleton(),
+        Tokenizer::P50kBase => p50k_base_singleton(),
+        Tokenizer::P50kEdit => p50k_edit_singleton(),
+        Tokenizer::R50kBase | Tokenizer::Gpt2 => r50k_base_singleton(),
+    };
+    let bpe = bpe.lock();
+    f(&bpe)
+}
+
+/// The context window, in tokens, of well known models. Matches on the model name prefix
+/// so that dated snapshots (`gpt-4o-2024-08-06`, ...) are covered.
+pub fn context_window_for_model(model: &str) -> Option<usize> {
+    const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+        ("gpt-4.1", 1_047_576),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4-1106", 128_000),
+        ("gpt-4-0125", 128_000),
+        ("gpt-4-32k", 32_768),
+        ("gpt-4", 8_192),
+        ("gpt-3.5-turbo-instruct", 4_096),
+        ("gpt-3.5-turbo", 16_385),
+        ("o1-mini", 128_000),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("o4", 200_000),
+        ("claude", 200_000),
+        ("deepseek", 64_000),
+        ("gemini-1.5-pro", 2_097_152),
+        ("gemini", 1_048_576),
+        ("qwen-max", 32_768),
+        ("qwen-plus", 131_072),
+        ("qwen-turbo", 1_000_000),
+    ];
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_message_tokens() {
+        let messages = [
+            Message::new_system_message("You are a helpful assistant."),
+            Message::new_human_message("Hello world"),
+        ];
+
+        // 6 + 2 content tokens, 4 per message and 3 for the reply
+        assert_eq!(count_message_tokens(Tokenizer::Cl100kBase, &messages), 19);
+        assert_eq!(count_message_tokens(Tokenizer::Cl100kBase, &[]), 3);
+    }
+
+    #[test]
+    fn test_context_window_for_model() {
+        assert_eq!(context_window_for_model("gpt-4o-2024-08-06"), Some(128_000));
+        assert_eq!(context_window_for_model("gpt-4-0613"), Some(8_192));
+        assert_eq!(
+            context_window_for_model("claude-3-5-sonnet-20240620"),
+            Some(200_000)
+        );
+        assert_eq!(context_window_for_model("llama3"), None);
+        assert_eq!(tokenizer_for_model("gpt-4o"), Tokenizer::O200kBase);
+        assert_eq!(tokenizer_for_model("llama3"), Tokenizer::Cl100kBase);
+    }
+}
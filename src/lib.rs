@@ -3,6 +3,7 @@ pub mod agent;
 pub mod chain;
 pub mod document_loaders;
 pub mod embedding;
+pub mod graph;
 pub mod language_models;
 pub mod llm;
 pub mod memory;
@@ -10,6 +11,8 @@ pub mod output_parsers;
 pub mod prompt;
 pub mod schemas;
 pub mod semantic_router;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod text_splitter;
 pub mod tools;
 pub mod vectorstore;
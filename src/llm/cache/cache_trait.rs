@@ -0,0 +1,12 @@
+use async_trait::async_trait;
+
+use crate::language_models::GenerateResult;
+
+use super::CacheError;
+
+/// Storage for [`CachedLLM`](super::CachedLLM) responses, keyed by the hash of a request.
+#[async_trait]
+pub trait LLMCache: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<GenerateResult>, CacheError>;
+    async fn set(&self, key: &str, value: &GenerateResult) -> Result<(), CacheError>;
+}
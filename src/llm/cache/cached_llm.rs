@@ -0,0 +1,494 @@
+use std::{pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
+use serde_json::{json, Value};
+
+use crate::{
+    embedding::Embedder,
+    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError},
+    schemas::{Message, StreamData, ToolCallDelta},
+};
+
+use super::{semantic::SemanticIndex, LLMCache};
+
+/// Caches the responses of any [`LLM`].
+///
+/// Requests are keyed on a stable hash of the messages and the call options that affect the
+/// output (temperature, max tokens, tools, ...). Only options passed through
+/// [`LLM::add_options`] on this wrapper are seen, so when wrapping differently configured
+/// models over the same cache give each one its own namespace with [`CachedLLM::with_namespace`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let llm = CachedLLM::new(OpenAI::default(), FileCache::new(".llm_cache"))
+///     .with_namespace("gpt-4o-mini");
+/// let first = llm.invoke("What is Rust?").await?; // calls the API
+/// let second = llm.invoke("What is Rust?").await?; // served from disk
+/// ```
+pub struct CachedLLM {
+    llm: Box<dyn LLM>,
+    cache: Arc<dyn LLMCache>,
+    options: CallOptions,
+    namespace: Option<String>,
+    semantic: Option<Arc<SemanticIndex>>,
+}
+
+impl Clone for CachedLLM {
+    fn clone(&self) -> Self {
+        Self {
+            llm: self.llm.clone_box(),
+            cache: self.cache.clone(),
+            options: self.options.clone(),
+            namespace: self.namespace.clone(),
+            semantic: self.semantic.clone(),
+        }
+    }
+}
+
+impl CachedLLM {
+    pub fn new<L: Into<Box<dyn LLM>>, C: LLMCache + 'static>(llm: L, cache: C) -> Self {
+        Self::with_shared_cache(llm, Arc::new(cache))
+    }
+
+    /// Like [`CachedLLM::new`] but with a cache shared with other wrappers.
+    pub fn with_shared_cache<L: Into<Box<dyn LLM>>>(llm: L, cache: Arc<dyn LLMCache>) -> Self {
+        Self {
+            llm: llm.into(),
+            cache,
+            options: CallOptions::default(),
+            namespace: None,
+            semantic: None,
+        }
+    }
+
+    /// Separates the entries of this wrapper from others stored in the same cache.
+    pub fn with_namespace<S: Into<String>>(mut self, namespace: S) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Also serves requests whose prompt embedding has a cosine similarity of at least
+    /// `threshold` with an earlier prompt sent with the same options.
+    ///
+    /// The embeddings are kept in memory, so semantic matches only cover requests made
+    /// through this wrapper (or its clones) since it was created.
+    pub fn with_semantic_cache<E: Embedder + 'static>(
+        mut self,
+        embedder: E,
+        threshold: f64,
+    ) -> Self {
+        self.semantic = Some(Arc::new(SemanticIndex::new(Arc::new(embedder), threshold)));
+        self
+    }
+
+    /// The cache key of a request with these messages and the current options.
+    pub fn cache_key(&self, messages: &[Message]) -> String {
+        stable_hash(&json!({
+            "scope": self.scope(),
+            "messages": messages,
+        }))
+    }
+
+    fn scope(&self) -> String {
+        let options = &self.options;
+        stable_hash(&json!({
+            "namespace": self.namespace,
+            "candidate_count": options.candidate_count,
+            "max_tokens": options.max_tokens,
+            "temperature": options.temperature,
+            "stop_words": options.stop_words,
+            "top_k": options.top_k,
+            "top_p": options.top_p,
+            "seed": options.seed,
+            "min_length": options.min_length,
+            "max_length": options.max_length,
+            "n": options.n,
+            "repetition_penalty": options.repetition_penalty,
+            "frequency_penalty": options.frequency_penalty,
+            "presence_penalty": options.presence_penalty,
+            "reasoning_effort": options.reasoning_effort,
+            "reasoning_budget": options.reasoning_budget,
+            "functions": options.functions.as_ref().map(|functions| {
+                functions
+                    .iter()
+                    .map(|f| json!([f.name, f.description, f.parameters]))
+                    .collect::<Vec<_>>()
+            }),
+            "function_call_behavior": options
+                .function_call_behavior
+                .as_ref()
+                .map(|behavior| format!("{:?}", behavior)),
+            "response_format": options
+                .response_format
+                .as_ref()
+                .map(|format| format!("{:?}", format)),
+        }))
+    }
+
+    /// Looks the request up, returning the prompt embedding on a miss so that it can be
+    /// stored along with the response.
+    async fn lookup(
+        &self,
+        key: &str,
+        messages: &[Message],
+    ) -> (Option<GenerateResult>, Option<Vec<f64>>) {
+        match self.cache.get(key).await {
+            Ok(Some(hit)) => return (Some(hit), None),
+            Ok(None) => {}
+            Err(e) => log::warn!("LLM cache lookup failed: {}", e),
+        }
+
+        let Some(semantic) = &self.semantic else {
+            return (None, None);
+        };
+        let embedding = match semantic.embed(&self.llm.messages_to_string(messages)).await {
+            Ok(embedding) => embedding,
+            Err(e) => {
+                log::warn!("LLM semantic cache embedding failed: {}", e);
+                return (None, None);
+            }
+        };
+        let Some(similar) = semantic.find(&self.scope(), &embedding) else {
+            return (None, Some(embedding));
+        };
+        match self.cache.get(&similar).await {
+            Ok(Some(hit)) => (Some(hit), None),
+            Ok(None) => (None, Some(embedding)),
+            Err(e) => {
+                log::warn!("LLM cache lookup failed: {}", e);
+                (None, Some(embedding))
+            }
+        }
+    }
+
+    fn writer(&self, key: String, embedding: Option<Vec<f64>>) -> CacheWriter {
+        CacheWriter {
+            cache: self.cache.clone(),
+            semantic: self.semantic.clone(),
+            scope: self.scope(),
+            key,
+            embedding,
+        }
+    }
+}
+
+/// Everything needed to store a response once it is complete, detached from the wrapper so
+/// that it can move into a stream.
+struct CacheWriter {
+    cache: Arc<dyn LLMCache>,
+    semantic: Option<Arc<SemanticIndex>>,
+    scope: String,
+    key: String,
+    embedding: Option<Vec<f64>>,
+}
+
+impl CacheWriter {
+    async fn store(self, result: &GenerateResult) {
+        if let Err(e) = self.cache.set(&self.key, result).await {
+            log::warn!("LLM cache store failed: {}", e);
+            return;
+        }
+        if let (Some(semantic), Some(embedding)) = (self.semantic, self.embedding) {
+            semantic.insert(&self.scope, embedding, &self.key);
+        }
+    }
+}
+
+#[async_trait]
+impl LLM for CachedLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let key = self.cache_key(messages);
+        let (hit, embedding) = self.lookup(&key, messages).await;
+        if let Some(hit) = hit {
+            if let Some(streaming_func) = &self.options.streaming_func {
+                let mut func = streaming_func.lock().await;
+                let _ = func(hit.generation.clone()).await;
+            }
+            return Ok(hit);
+        }
+
+        let result = self.llm.generate(messages).await?;
+        self.writer(key, embedding).store(&result).await;
+        Ok(result)
+    }
+
+    /// Replays a cached response as a chunk, after one per block of reasoning. On a miss the provider stream is passed
+    /// through and cached once it completes without errors.
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let key = self.cache_key(messages);
+        let (hit, embedding) = self.lookup(&key, messages).await;
+        if let Some(hit) = hit {
+            let mut chunks = hit.reasoning_chunks();
+            let data = StreamData::new(Value::Null, hit.tokens, hit.generation)
+                .with_finish_reason(hit.finish_reason)
+                .with_model(hit.model)
+                .with_logprobs(hit.logprobs)
+                .with_tool_call_deltas(
+                    hit.tool_calls
+                        .iter()
+                        .enumerate()
+                        .map(|(index, tool_call)| ToolCallDelta::from_tool_call(index, tool_call))
+                        .collect(),
+                );
+            chunks.push(data);
+            return Ok(Box::pin(stream::iter(chunks.into_iter().map(Ok))));
+        }
+
+        let mut inner = self.llm.stream(messages).await?;
+        let writer = self.writer(key, embedding);
+        let stream = async_stream::stream! {
+            let mut result = GenerateResult::default();
+            while let Some(item) = inner.next().await {
+                match item {
+                    Ok(data) => {
+                        result.merge_stream_data(&data);
+                        yield Ok(data);
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+            writer.store(&result).await;
+        };
+        Ok(Box::pin(stream))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.options.merge_options(options.clone());
+        self.llm.add_options(options);
+    }
+
+    fn override_options(&mut self, options: CallOptions) {
+        self.options.override_options(options.clone());
+        self.llm.override_options(options);
+    }
+
+    fn messages_to_string(&self, messages: &[Message]) -> String {
+        self.llm.messages_to_string(messages)
+    }
+
+    fn count_tokens(&self, messages: &[Message]) -> usize {
+        self.llm.count_tokens(messages)
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.llm.context_window()
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.llm.max_output_tokens()
+    }
+}
+
+/// FNV-1a (128 bit) of the JSON encoding, unlike `DefaultHasher` it is guaranteed not to
+/// change between Rust versions, which matters for caches that outlive the process.
+fn stable_hash(value: &Value) -> String {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    let hash = value.to_string().bytes().fold(OFFSET, |hash, byte| {
+        (hash ^ byte as u128).wrapping_mul(PRIME)
+    });
+    format!("{:032x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        embedding::EmbedderError,
+        language_models::options::ReasoningEffort,
+        llm::{FakeLLM, FakeResponse, InMemoryCache},
+        schemas::ReasoningBlock,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Default)]
+    struct CountingLLM {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LLM for CountingLLM {
+        async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(GenerateResult {
+                generation: format!("{} #{}", messages[0].content, calls),
+                ..Default::default()
+            })
+        }
+
+        async fn stream(
+            &self,
+            messages: &[Message],
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError>
+        {
+            let result = self.generate(messages).await?;
+            let chunks = result
+                .generation
+                .split_inclusive(' ')
+                .map(|chunk| Ok(StreamData::new(Value::Null, None, chunk)))
+                .collect::<Vec<_>>();
+            Ok(Box::pin(stream::iter(chunks)))
+        }
+    }
+
+    /// Embeds a text as its letter counts, so texts that differ only in casing or word
+    /// order are identical.
+    struct LetterEmbedder;
+
+    #[async_trait]
+    impl Embedder for LetterEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            let mut embeddings = Vec::new();
+            for document in documents {
+                embeddings.push(self.embed_query(document).await?);
+            }
+            Ok(embeddings)
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+            let mut counts = vec![0.0; 26];
+            for c in text
+                .to_lowercase()
+                .chars()
+                .filter(|c| c.is_ascii_lowercase())
+            {
+                counts[(c as u8 - b'a') as usize] += 1.0;
+            }
+            Ok(counts)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_is_cached() {
+        let inner = CountingLLM::default();
+        let llm = CachedLLM::new(inner.clone(), InMemoryCache::default());
+
+        let first = llm.invoke("hello").await.unwrap();
+        let second = llm.invoke("hello").await.unwrap();
+        let other = llm.invoke("bye").await.unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_options_are_part_of_the_key() {
+        let llm = CachedLLM::new(CountingLLM::default(), InMemoryCache::default());
+        let messages = [Message::new_human_message("hello")];
+        let default_key = llm.cache_key(&messages);
+
+        let mut hot = llm.clone();
+        hot.add_options(CallOptions::new().with_temperature(1.0));
+
+        assert_eq!(default_key, llm.cache_key(&messages));
+        assert_ne!(default_key, hot.cache_key(&messages));
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_options_are_part_of_the_key() {
+        let inner = CountingLLM::default();
+        let cache: Arc<dyn LLMCache> = Arc::new(InMemoryCache::default());
+        let mut low = CachedLLM::with_shared_cache(inner.clone(), cache.clone());
+        low.add_options(CallOptions::new().with_reasoning_effort(ReasoningEffort::Low));
+        let mut high = CachedLLM::with_shared_cache(inner.clone(), cache.clone());
+        high.add_options(CallOptions::new().with_reasoning_effort(ReasoningEffort::High));
+        let mut budget = CachedLLM::with_shared_cache(inner.clone(), cache);
+        budget.add_options(CallOptions::new().with_reasoning_budget(2048));
+
+        low.invoke("hello").await.unwrap();
+        high.invoke("hello").await.unwrap();
+        budget.invoke("hello").await.unwrap();
+
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_replays_cached_reasoning() {
+        let generated = GenerateResult {
+            generation: "Lima".to_string(),
+            reasoning_content: Some("Peru. Lima.".to_string()),
+            reasoning_blocks: vec![
+                ReasoningBlock::Signed {
+                    content: "Peru.".to_string(),
+                    signature: "sig_01".to_string(),
+                },
+                ReasoningBlock::Redacted {
+                    data: "abc".to_string(),
+                },
+                ReasoningBlock::Signed {
+                    content: " Lima.".to_string(),
+                    signature: "sig_02".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+        let fake =
+            FakeLLM::new().with_response(FakeResponse::Generation(Box::new(generated.clone())));
+        let llm = CachedLLM::new(fake, InMemoryCache::default());
+        let messages = [Message::new_human_message("Capital of Peru?")];
+        llm.generate(&messages).await.unwrap();
+
+        let mut replayed = GenerateResult::default();
+        let mut stream = llm.stream(&messages).await.unwrap();
+        while let Some(data) = stream.next().await {
+            replayed.merge_stream_data(&data.unwrap());
+        }
+
+        assert_eq!(replayed.generation, generated.generation);
+        assert_eq!(replayed.reasoning_content, generated.reasoning_content);
+        assert_eq!(replayed.reasoning_blocks, generated.reasoning_blocks);
+    }
+
+    #[tokio::test]
+    async fn test_stream_replays_cached_content() {
+        let inner = CountingLLM::default();
+        let llm = CachedLLM::new(inner.clone(), InMemoryCache::default());
+        let messages = [Message::new_human_message("hello there")];
+
+        let mut first = String::new();
+        let mut stream = llm.stream(&messages).await.unwrap();
+        while let Some(data) = stream.next().await {
+            first.push_str(&data.unwrap().content);
+        }
+
+        let replayed: Vec<_> = llm.stream(&messages).await.unwrap().collect().await;
+
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].as_ref().unwrap().content, first);
+        assert_eq!(llm.invoke("hello there").await.unwrap(), first);
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache() {
+        let inner = CountingLLM::default();
+        let llm = CachedLLM::new(inner.clone(), InMemoryCache::default())
+            .with_semantic_cache(LetterEmbedder, 0.99);
+
+        let first = llm.invoke("Hello World").await.unwrap();
+        let similar = llm.invoke("world hello").await.unwrap();
+        let different = llm.invoke("Something else").await.unwrap();
+
+        assert_eq!(first, similar);
+        assert_ne!(first, different);
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_stable_hash() {
+        assert_eq!(stable_hash(&json!({"a": 1})), stable_hash(&json!({"a": 1})));
+        assert_ne!(stable_hash(&json!({"a": 1})), stable_hash(&json!({"a": 2})));
+    }
+}
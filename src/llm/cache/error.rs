@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+use crate::embedding::EmbedderError;
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("JSON serialization/deserialization error: {0}")]
+    SerdeError(#[from] serde_json::Error),
+
+    #[error("Embedder error: {0}")]
+    EmbedderError(#[from] EmbedderError),
+
+    #[cfg(feature = "sqlite-cache")]
+    #[error("SQLx error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+}
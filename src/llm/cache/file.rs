@@ -0,0 +1,46 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use async_trait::async_trait;
+use tokio::fs;
+
+use crate::language_models::GenerateResult;
+
+use super::{CacheError, LLMCache};
+
+/// Stores each cached response as a JSON file named after its key inside `directory`.
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    directory: PathBuf,
+}
+
+impl FileCache {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+}
+
+#[async_trait]
+impl LLMCache for FileCache {
+    async fn get(&self, key: &str) -> Result<Option<GenerateResult>, CacheError> {
+        match fs::read(self.path(key)).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn set(&self, key: &str, value: &GenerateResult) -> Result<(), CacheError> {
+        fs::create_dir_all(&self.directory).await?;
+        // Write then rename, so concurrent readers never see a partial file.
+        let tmp = self.directory.join(format!("{}.json.tmp", key));
+        fs::write(&tmp, serde_json::to_vec(value)?).await?;
+        fs::rename(&tmp, self.path(key)).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+
+use crate::language_models::GenerateResult;
+
+use super::{CacheError, LLMCache};
+
+/// In-memory cache that evicts the least recently used entry once `capacity` is reached.
+pub struct InMemoryCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    values: HashMap<String, (GenerateResult, u64)>,
+    clock: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+impl InMemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().values.clear();
+    }
+}
+
+impl Default for InMemoryCache {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+#[async_trait]
+impl LLMCache for InMemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<GenerateResult>, CacheError> {
+        let mut entries = self.entries.lock().unwrap();
+        let tick = entries.tick();
+        Ok(entries.values.get_mut(key).map(|(value, last_used)| {
+            *last_used = tick;
+            value.clone()
+        }))
+    }
+
+    async fn set(&self, key: &str, value: &GenerateResult) -> Result<(), CacheError> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let tick = entries.tick();
+        entries
+            .values
+            .insert(key.to_string(), (value.clone(), tick));
+
+        if entries.values.len() > self.capacity {
+            let oldest = entries
+                .values
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.values.remove(&oldest);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(generation: &str) -> GenerateResult {
+        GenerateResult {
+            generation: generation.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let cache = InMemoryCache::new(2);
+        cache.set("a", &result("a")).await.unwrap();
+        cache.set("b", &result("b")).await.unwrap();
+        cache.get("a").await.unwrap();
+        cache.set("c", &result("c")).await.unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").await.unwrap().is_none());
+        assert_eq!(cache.get("a").await.unwrap().unwrap().generation, "a");
+        assert_eq!(cache.get("c").await.unwrap().unwrap().generation, "c");
+    }
+}
@@ -0,0 +1,21 @@
+mod error;
+pub use error::*;
+
+mod cache_trait;
+pub use cache_trait::*;
+
+mod memory;
+pub use memory::*;
+
+mod file;
+pub use file::*;
+
+#[cfg(feature = "sqlite-cache")]
+mod sqlite;
+#[cfg(feature = "sqlite-cache")]
+pub use sqlite::*;
+
+mod semantic;
+
+mod cached_llm;
+pub use cached_llm::*;
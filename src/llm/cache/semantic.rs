@@ -0,0 +1,54 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{embedding::Embedder, semantic_router::utils::cosine_similarity};
+
+use super::CacheError;
+
+struct Entry {
+    scope: String,
+    embedding: Vec<f64>,
+    key: String,
+}
+
+/// Maps prompt embeddings to the cache keys of their responses, so that a prompt close
+/// enough to an earlier one can reuse its response.
+pub(super) struct SemanticIndex {
+    embedder: Arc<dyn Embedder>,
+    threshold: f64,
+    entries: RwLock<Vec<Entry>>,
+}
+
+impl SemanticIndex {
+    pub fn new(embedder: Arc<dyn Embedder>, threshold: f64) -> Self {
+        Self {
+            embedder,
+            threshold,
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f64>, CacheError> {
+        Ok(self.embedder.embed_query(text).await?)
+    }
+
+    /// The key of the most similar prompt within `scope`, if it reaches the threshold.
+    pub fn find(&self, scope: &str, embedding: &[f64]) -> Option<String> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.scope == scope)
+            .map(|entry| (cosine_similarity(&entry.embedding, embedding), entry))
+            .filter(|(similarity, _)| *similarity >= self.threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, entry)| entry.key.clone())
+    }
+
+    pub fn insert(&self, scope: &str, embedding: Vec<f64>, key: &str) {
+        self.entries.write().unwrap().push(Entry {
+            scope: scope.to_string(),
+            embedding,
+            key: key.to_string(),
+        });
+    }
+}
@@ -0,0 +1,102 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Row, Sqlite,
+};
+
+use crate::language_models::GenerateResult;
+
+use super::{CacheError, LLMCache};
+
+/// Caches responses in a SQLite table with `key` and `value` columns.
+#[derive(Debug, Clone)]
+pub struct SqliteCache {
+    pool: Pool<Sqlite>,
+    table: String,
+}
+
+impl SqliteCache {
+    /// Uses an existing pool, creating `table` if it does not exist yet.
+    pub async fn new<S: Into<String>>(pool: Pool<Sqlite>, table: S) -> Result<Self, CacheError> {
+        let cache = Self {
+            pool,
+            table: table.into(),
+        };
+        sqlx::query(&format!(
+            r#"CREATE TABLE IF NOT EXISTS {} (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )"#,
+            cache.table
+        ))
+        .execute(&cache.pool)
+        .await?;
+        Ok(cache)
+    }
+
+    /// Opens (or creates) the database at `connection_url` and uses the `llm_cache` table.
+    pub async fn connect(connection_url: &str) -> Result<Self, CacheError> {
+        let options = SqliteConnectOptions::from_str(connection_url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        Self::new(pool, "llm_cache").await
+    }
+}
+
+#[async_trait]
+impl LLMCache for SqliteCache {
+    async fn get(&self, key: &str) -> Result<Option<GenerateResult>, CacheError> {
+        let row = sqlx::query(&format!("SELECT value FROM {} WHERE key = ?", self.table))
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => {
+                let value: String = row.try_get("value")?;
+                Ok(Some(serde_json::from_str(&value)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: &GenerateResult) -> Result<(), CacheError> {
+        sqlx::query(&format!(
+            "INSERT OR REPLACE INTO {} (key, value) VALUES (?, ?)",
+            self.table
+        ))
+        .bind(key)
+        .bind(serde_json::to_string(value)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_cache_roundtrip() {
+        // An in-memory database only lives as long as its connection, so keep a single one.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let cache = SqliteCache::new(pool, "llm_cache").await.unwrap();
+        let value = GenerateResult {
+            generation: "cached".to_string(),
+            ..Default::default()
+        };
+
+        assert!(cache.get("key").await.unwrap().is_none());
+        cache.set("key", &value).await.unwrap();
+        assert_eq!(
+            cache.get("key").await.unwrap().unwrap().generation,
+            "cached"
+        );
+    }
+}
@@ -0,0 +1,626 @@
+use std::{pin::Pin, time::SystemTime};
+
+use async_trait::async_trait;
+use aws_credential_types::Credentials;
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SigningParams, SigningSettings},
+    sign::v4,
+};
+use base64::prelude::*;
+use futures::{Stream, StreamExt};
+use reqwest::{header::HeaderValue, Client, Request, Response};
+use serde_json::Value;
+
+use crate::{language_models::LLMError, llm::AnthropicError};
+
+use super::transport::{into_envelope, ClaudeTransport};
+
+/// AWS access keys, as used to sign Bedrock requests.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn new<S: Into<String>>(access_key_id: S, secret_access_key: S) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    /// The session token of temporary credentials.
+    pub fn with_session_token<S: Into<String>>(mut self, session_token: S) -> Self {
+        self.session_token = Some(session_token.into());
+        self
+    }
+
+    /// Reads `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+    pub fn from_env() -> Result<Self, LLMError> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| -> LLMError {
+                AnthropicError::AuthenticationError(format!("{} is not set", name)).into()
+            })
+        };
+        let credentials = Self::new(var("AWS_ACCESS_KEY_ID")?, var("AWS_SECRET_ACCESS_KEY")?);
+        Ok(match std::env::var("AWS_SESSION_TOKEN") {
+            Ok(session_token) => credentials.with_session_token(session_token),
+            Err(_) => credentials,
+        })
+    }
+}
+
+impl From<AwsCredentials> for Credentials {
+    fn from(credentials: AwsCredentials) -> Self {
+        Credentials::new(
+            credentials.access_key_id,
+            credentials.secret_access_key,
+            credentials.session_token,
+            None,
+            "langchain-rust",
+        )
+    }
+}
+
+#[derive(Clone)]
+enum CredentialsSource {
+    Static(AwsCredentials),
+    Provider(aws_credential_types::provider::SharedCredentialsProvider),
+}
+
+/// Claude on [Amazon Bedrock](https://docs.aws.amazon.com/bedrock/latest/userguide/model-parameters-anthropic-claude-messages.html),
+/// with requests signed by [Signature Version 4](https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_sigv.html).
+///
+/// Models are named by their Bedrock id or inference profile, for example
+/// `anthropic.claude-3-5-sonnet-20240620-v1:0` or `us.anthropic.claude-3-5-haiku-20241022-v1:0`.
+/// [`BedrockTransport::from_aws_config`] takes the region and the credentials from the usual
+/// AWS configuration, profiles and roles included. Requires the `bedrock` feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let claude = Claude::new()
+///     .with_model("anthropic.claude-3-5-sonnet-20240620-v1:0")
+///     .with_transport(BedrockTransport::from_env()?);
+/// ```
+#[derive(Clone)]
+pub struct BedrockTransport {
+    region: String,
+    credentials: CredentialsSource,
+    endpoint: Option<String>,
+}
+
+impl BedrockTransport {
+    pub const ANTHROPIC_VERSION: &'static str = "bedrock-2023-05-31";
+
+    pub fn new<S: Into<String>>(region: S, credentials: AwsCredentials) -> Self {
+        Self {
+            region: region.into(),
+            credentials: CredentialsSource::Static(credentials),
+            endpoint: None,
+        }
+    }
+
+    /// Reads the region from `AWS_REGION` or `AWS_DEFAULT_REGION`, and the credentials as
+    /// [`AwsCredentials::from_env`] does.
+    pub fn from_env() -> Result<Self, LLMError> {
+        let region = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .map_err(|_| LLMError::OtherError("AWS_REGION is not set".to_string()))?;
+        Ok(Self::new(region, AwsCredentials::from_env()?))
+    }
+
+    /// Takes the region and the credentials from the AWS configuration of the environment,
+    /// refreshing temporary credentials as they expire.
+    pub async fn from_aws_config() -> Result<Self, LLMError> {
+        let config = aws_config::load_from_env().await;
+        let region = config
+            .region()
+            .map(|region| region.to_string())
+            .ok_or_else(|| LLMError::OtherError("No AWS region is configured".to_string()))?;
+        let provider = config.credentials_provider().ok_or_else(|| {
+            AnthropicError::AuthenticationError("No AWS credentials are configured".to_string())
+        })?;
+        Ok(Self {
+            region,
+            credentials: CredentialsSource::Provider(provider),
+            endpoint: None,
+        })
+    }
+
+    /// Sets the API URL, such as a VPC endpoint or a proxy. Defaults to
+    /// `https://bedrock-runtime.{region}.amazonaws.com`.
+    pub fn with_endpoint<S: Into<String>>(mut self, endpoint: S) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    fn endpoint(&self) -> String {
+        self.endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://bedrock-runtime.{}.amazonaws.com", self.region))
+    }
+
+    async fn credentials(&self) -> Result<Credentials, LLMError> {
+        match &self.credentials {
+            CredentialsSource::Static(credentials) => Ok(credentials.clone().into()),
+            CredentialsSource::Provider(provider) => {
+                use aws_credential_types::provider::ProvideCredentials;
+
+                Ok(provider
+                    .provide_credentials()
+                    .await
+                    .map_err(|e| AnthropicError::AuthenticationError(e.to_string()))?)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ClaudeTransport for BedrockTransport {
+    async fn build_request(&self, client: &Client, payload: Value) -> Result<Request, LLMError> {
+        let (model, stream, body) = into_envelope(payload, Self::ANTHROPIC_VERSION);
+        let (action, accept) = match stream {
+            true => (
+                "invoke-with-response-stream",
+                "application/vnd.amazon.eventstream",
+            ),
+            false => ("invoke", "application/json"),
+        };
+        let mut request = client
+            .post(format!(
+                "{}/model/{}/{}",
+                self.endpoint(),
+                uri_encode(&model),
+                action
+            ))
+            .header("accept", accept)
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(&body)?)
+            .build()?;
+        sign_request(
+            &mut request,
+            self.credentials().await?,
+            &self.region,
+            "bedrock",
+            SystemTime::now(),
+        )?;
+        Ok(request)
+    }
+
+    fn decode_stream(
+        &self,
+        response: Response,
+    ) -> Pin<Box<dyn Stream<Item = Result<String, LLMError>> + Send>> {
+        Box::pin(event_stream_chunks(response.bytes_stream()))
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters, so that model ids such as
+/// `anthropic.claude-3-haiku-20240307-v1:0` fit in a path segment.
+fn uri_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Signs `request` with AWS Signature Version 4, adding the `x-amz-date`, the session token
+/// if any, and the `authorization` headers. Every header set so far is signed.
+fn sign_request(
+    request: &mut Request,
+    credentials: Credentials,
+    region: &str,
+    service: &str,
+    time: SystemTime,
+) -> Result<(), LLMError> {
+    let signing_error = |e: &dyn std::fmt::Display| {
+        LLMError::OtherError(format!("Could not sign the Bedrock request: {}", e))
+    };
+    let identity = credentials.into();
+    let params: SigningParams = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(region)
+        .name(service)
+        .time(time)
+        .settings(SigningSettings::default())
+        .build()
+        .map_err(|e| signing_error(&e))?
+        .into();
+
+    let instructions = {
+        let headers = request
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)));
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .unwrap_or_default();
+        let signable = SignableRequest::new(
+            request.method().as_str(),
+            request.url().as_str(),
+            headers,
+            SignableBody::Bytes(body),
+        )
+        .map_err(|e| signing_error(&e))?;
+        sign(signable, &params)
+            .map_err(|e| signing_error(&e))?
+            .into_parts()
+            .0
+    };
+
+    let (headers, _) = instructions.into_parts();
+    for header in headers {
+        let mut value = HeaderValue::from_str(header.value())
+            .map_err(|_| LLMError::OtherError("Invalid AWS credentials".to_string()))?;
+        value.set_sensitive(header.sensitive());
+        request.headers_mut().insert(header.name(), value);
+    }
+    Ok(())
+}
+
+/// A message of the [AWS event stream](https://docs.aws.amazon.com/transcribe/latest/dg/event-stream.html)
+/// encoding Bedrock streams responses in.
+struct EventMessage {
+    headers: Vec<(String, String)>,
+    payload: Vec<u8>,
+}
+
+impl EventMessage {
+    /// Takes the first message out of `buffer` once it is complete. Messages are framed as
+    /// the total length, the headers length and a CRC32 of both, then the headers, the
+    /// payload and a CRC32 of the whole message.
+    fn decode(buffer: &mut Vec<u8>) -> Result<Option<Self>, LLMError> {
+        if buffer.len() < 12 {
+            return Ok(None);
+        }
+        if crc32fast::hash(&buffer[..8]) != read_u32(&buffer[8..12]) {
+            return Err(LLMError::OtherError(
+                "Invalid event stream message: the prelude checksum does not match".to_string(),
+            ));
+        }
+        let total_length = read_u32(&buffer[..4]) as usize;
+        let headers_length = read_u32(&buffer[4..8]) as usize;
+        if total_length < 16 + headers_length {
+            return Err(LLMError::OtherError(
+                "Invalid event stream message".to_string(),
+            ));
+        }
+        if buffer.len() < total_length {
+            return Ok(None);
+        }
+        let message: Vec<u8> = buffer.drain(..total_length).collect();
+        if crc32fast::hash(&message[..total_length - 4]) != read_u32(&message[total_length - 4..]) {
+            return Err(LLMError::OtherError(
+                "Invalid event stream message: the message checksum does not match".to_string(),
+            ));
+        }
+        let headers = decode_headers(&message[12..12 + headers_length])?;
+        let payload = message[12 + headers_length..total_length - 4].to_vec();
+        Ok(Some(Self { headers, payload }))
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Decodes the headers of an event message, keeping the string ones.
+fn decode_headers(mut bytes: &[u8]) -> Result<Vec<(String, String)>, LLMError> {
+    let invalid = || LLMError::OtherError("Invalid event stream headers".to_string());
+    let mut headers = Vec::new();
+    while !bytes.is_empty() {
+        let name_length = bytes[0] as usize;
+        let name = bytes.get(1..1 + name_length).ok_or_else(invalid)?;
+        let name = String::from_utf8_lossy(name).to_string();
+        let value_type = *bytes.get(1 + name_length).ok_or_else(invalid)?;
+        bytes = &bytes[2 + name_length..];
+        let value_length = match value_type {
+            // Booleans are stored in the type
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            // Byte arrays and strings are prefixed with their length
+            6 | 7 => {
+                let length = bytes.get(..2).ok_or_else(invalid)?;
+                bytes = &bytes[2..];
+                u16::from_be_bytes([length[0], length[1]]) as usize
+            }
+            _ => return Err(invalid()),
+        };
+        let value = bytes.get(..value_length).ok_or_else(invalid)?;
+        if value_type == 7 {
+            headers.push((name, String::from_utf8_lossy(value).to_string()));
+        }
+        bytes = &bytes[value_length..];
+    }
+    Ok(headers)
+}
+
+/// Turns a Bedrock response stream into the Messages API events it wraps, each of them the
+/// base64 `bytes` of a `chunk` event.
+fn event_stream_chunks<S, B>(bytes: S) -> impl Stream<Item = Result<String, LLMError>> + Send
+where
+    S: Stream<Item = Result<B, reqwest::Error>> + Send + 'static,
+    B: AsRef<[u8]> + Send,
+{
+    async_stream::stream! {
+        let mut bytes = Box::pin(bytes);
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = bytes.next().await {
+            match chunk {
+                Ok(chunk) => buffer.extend_from_slice(chunk.as_ref()),
+                Err(e) => {
+                    yield Err(LLMError::RequestError(e));
+                    return;
+                }
+            }
+            loop {
+                let message = match EventMessage::decode(&mut buffer) {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+                match message.header(":message-type") {
+                    Some("event") if message.header(":event-type") == Some("chunk") => {
+                        yield chunk_event(&message.payload);
+                    }
+                    Some("exception") | Some("error") => {
+                        yield Err(exception_error(&message));
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn chunk_event(payload: &[u8]) -> Result<String, LLMError> {
+    let chunk: Value = serde_json::from_slice(payload)?;
+    let bytes = BASE64_STANDARD
+        .decode(chunk["bytes"].as_str().unwrap_or_default())
+        .map_err(|e| LLMError::OtherError(format!("Invalid Bedrock chunk: {}", e)))?;
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn exception_error(message: &EventMessage) -> LLMError {
+    let exception = message
+        .header(":exception-type")
+        .or_else(|| message.header(":error-code"))
+        .unwrap_or("unknown")
+        .to_string();
+    let text = serde_json::from_slice::<Value>(&message.payload)
+        .ok()
+        .and_then(|payload| payload["message"].as_str().map(String::from))
+        .unwrap_or_else(|| String::from_utf8_lossy(&message.payload).to_string());
+    match exception.as_str() {
+        "throttlingException" => AnthropicError::RateLimitError(text, None).into(),
+        "validationException" => AnthropicError::InvalidRequestError(text).into(),
+        "serviceUnavailableException" => AnthropicError::OverloadedError(text, None).into(),
+        _ => AnthropicError::ApiError(format!("{}: {}", exception, text)).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use futures::stream;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        language_models::{llm::LLM, FinishReason, GenerateResult},
+        llm::Claude,
+        schemas::Message,
+    };
+
+    /// Encodes an event message with its checksums.
+    fn encode_event(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+        let mut encoded_headers = Vec::new();
+        for (name, value) in headers {
+            encoded_headers.push(name.len() as u8);
+            encoded_headers.extend_from_slice(name.as_bytes());
+            encoded_headers.push(7);
+            encoded_headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            encoded_headers.extend_from_slice(value.as_bytes());
+        }
+        let total_length = 16 + encoded_headers.len() + payload.len();
+        let mut message = Vec::new();
+        message.extend_from_slice(&(total_length as u32).to_be_bytes());
+        message.extend_from_slice(&(encoded_headers.len() as u32).to_be_bytes());
+        message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+        message.extend_from_slice(&encoded_headers);
+        message.extend_from_slice(payload);
+        message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+        message
+    }
+
+    fn chunk(event: Value) -> Vec<u8> {
+        let payload = json!({ "bytes": BASE64_STANDARD.encode(event.to_string()) });
+        encode_event(
+            &[
+                (":event-type", "chunk"),
+                (":content-type", "application/json"),
+                (":message-type", "event"),
+            ],
+            payload.to_string().as_bytes(),
+        )
+    }
+
+    #[test]
+    fn test_sign_request_matches_aws_example() {
+        // The example of the AWS Signature Version 4 documentation
+        let mut request = Client::new()
+            .get("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")
+            .header(
+                "content-type",
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )
+            .build()
+            .unwrap();
+        let credentials =
+            AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+        // 2015-08-30T12:36:00Z
+        let now = UNIX_EPOCH + Duration::from_secs(1_440_938_160);
+
+        sign_request(&mut request, credentials.into(), "us-east-1", "iam", now).unwrap();
+
+        assert_eq!(
+            request.headers()["authorization"],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+        assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+    }
+
+    #[tokio::test]
+    async fn test_event_stream_split_across_chunks() {
+        let mut bytes = chunk(json!({"type": "message_start"}));
+        bytes.extend(encode_event(
+            &[(":message-type", "event"), (":event-type", "metadata")],
+            b"{}",
+        ));
+        bytes.extend(chunk(json!({"type": "message_stop"})));
+        bytes.extend(encode_event(
+            &[
+                (":message-type", "exception"),
+                (":exception-type", "throttlingException"),
+            ],
+            br#"{"message":"Too many requests"}"#,
+        ));
+        // Network chunks do not follow message boundaries
+        let pieces: Vec<Result<Vec<u8>, reqwest::Error>> =
+            bytes.chunks(7).map(|piece| Ok(piece.to_vec())).collect();
+
+        let events: Vec<Result<String, LLMError>> =
+            event_stream_chunks(stream::iter(pieces)).collect().await;
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].as_ref().unwrap(), r#"{"type":"message_start"}"#);
+        assert_eq!(events[1].as_ref().unwrap(), r#"{"type":"message_stop"}"#);
+        assert!(matches!(
+            &events[2],
+            Err(LLMError::AnthropicError(AnthropicError::RateLimitError(message, _)))
+                if message == "Too many requests"
+        ));
+    }
+
+    #[test]
+    fn test_event_stream_checksums_are_verified() {
+        let message = chunk(json!({"type": "message_start"}));
+
+        let mut corrupted_prelude = message.clone();
+        corrupted_prelude[9] ^= 1;
+        assert!(EventMessage::decode(&mut corrupted_prelude).is_err());
+
+        let mut corrupted_payload = message.clone();
+        let middle = corrupted_payload.len() / 2;
+        corrupted_payload[middle] ^= 1;
+        assert!(EventMessage::decode(&mut corrupted_payload).is_err());
+
+        let mut intact = message;
+        assert!(EventMessage::decode(&mut intact).unwrap().is_some());
+        assert!(intact.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_claude_through_bedrock() {
+        let mut server = mockito::Server::new_async().await;
+        let model = "anthropic.claude-3-haiku-20240307-v1:0";
+        let authorization = mockito::Matcher::Regex(
+            r"^AWS4-HMAC-SHA256 Credential=AKID/\d{8}/us-east-1/bedrock/aws4_request, SignedHeaders=accept;content-type;host;x-amz-date;x-amz-security-token, Signature=[0-9a-f]{64}$".to_string(),
+        );
+        let invoke = server
+            .mock(
+                "POST",
+                "/model/anthropic.claude-3-haiku-20240307-v1%3A0/invoke",
+            )
+            .match_header("authorization", authorization.clone())
+            .match_header("x-amz-security-token", "session")
+            .match_body(mockito::Matcher::Json(json!({
+                "anthropic_version": "bedrock-2023-05-31",
+                "max_tokens": 1024,
+                "messages": [{"role": "user", "content": [{"type": "text", "text": "Hi"}]}]
+            })))
+            .with_body(
+                json!({
+                    "id": "msg_1", "type": "message", "role": "assistant", "model": model,
+                    "content": [{"type": "text", "text": "Hello!"}],
+                    "stop_reason": "end_turn",
+                    "usage": {"input_tokens": 8, "output_tokens": 3}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let mut events = Vec::new();
+        for event in [
+            json!({"type": "message_start", "message": {"model": model, "usage": {"input_tokens": 8}}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo!"}}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 3}}),
+        ] {
+            events.extend(chunk(event));
+        }
+        let stream = server
+            .mock(
+                "POST",
+                "/model/anthropic.claude-3-haiku-20240307-v1%3A0/invoke-with-response-stream",
+            )
+            .match_header("authorization", authorization)
+            .with_header("content-type", "application/vnd.amazon.eventstream")
+            .with_body(events)
+            .create_async()
+            .await;
+
+        let claude = Claude::new().with_model(model).with_transport(
+            BedrockTransport::new(
+                "us-east-1",
+                AwsCredentials::new("AKID", "secret").with_session_token("session"),
+            )
+            .with_endpoint(server.url()),
+        );
+        let messages = [Message::new_human_message("Hi")];
+
+        let result = claude.generate(&messages).await.unwrap();
+        assert_eq!(result.generation, "Hello!");
+        assert_eq!(result.tokens.unwrap().total_tokens, 11);
+
+        let mut streamed = GenerateResult::default();
+        let mut chunks = claude.stream(&messages).await.unwrap();
+        while let Some(data) = chunks.next().await {
+            streamed.merge_stream_data(&data.unwrap());
+        }
+        assert_eq!(streamed.generation, "Hello!");
+        assert_eq!(streamed.finish_reason, Some(FinishReason::Stop));
+        assert_eq!(claude.context_window(), Some(200_000));
+        invoke.assert_async().await;
+        stream.assert_async().await;
+    }
+}
@@ -1,15 +1,35 @@
 use crate::{
-    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError, TokenUsage},
+    language_models::{
+        cancellation::{cancellable, cancellable_stream},
+        llm::LLM,
+        options::CallOptions,
+        tokens::context_window_for_model,
+        FinishReason, GenerateResult, LLMError, TokenUsage,
+    },
     llm::AnthropicError,
-    schemas::{Message, MessageType, StreamData},
+    schemas::{Message, MessageType, ResponseFormat, StreamData, ToolCallDelta},
 };
 use async_trait::async_trait;
 use futures::{Stream, StreamExt};
 use reqwest::Client;
-use serde_json::Value;
-use std::{collections::HashMap, pin::Pin};
+use serde_json::{json, Value};
+use std::{pin::Pin, sync::Arc};
 
-use super::models::{ApiResponse, ClaudeMessage, Payload};
+use super::{
+    models::{
+        ApiResponse, ClaudeMessage, ClaudeTool, Payload, SystemPrompt, Thinking, ToolChoice, Usage,
+    },
+    AnthropicTransport, ClaudeTransport,
+};
+
+/// Anthropic requires `max_tokens`, this is sent when the options do not set it.
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// The tool Claude is forced to call for JSON object responses.
+const OUTPUT_TOOL_NAME: &str = "json_output";
+
+/// The smallest thinking budget Anthropic accepts.
+const MIN_THINKING_BUDGET: u32 = 1024;
 
 pub enum ClaudeModel {
     Claude3pus20240229,
@@ -35,6 +55,8 @@ pub struct Claude {
     options: CallOptions,
     api_key: String,
     anthropic_version: String,
+    base_url: String,
+    transport: Option<Arc<dyn ClaudeTransport>>,
 }
 
 impl Default for Claude {
@@ -50,6 +72,8 @@ impl Claude {
             options: CallOptions::default(),
             api_key: std::env::var("CLAUDE_API_KEY").unwrap_or_default(),
             anthropic_version: "2023-06-01".to_string(),
+            base_url: "https://api.anthropic.com".to_string(),
+            transport: None,
         }
     }
 
@@ -73,19 +97,41 @@ impl Claude {
         self
     }
 
+    /// Sets the API URL, without the `/v1/messages` path. Defaults to
+    /// `https://api.anthropic.com`.
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Reaches Claude through `transport`, such as `BedrockTransport` with the `bedrock`
+    /// feature or [`VertexTransport`](super::VertexTransport), instead of the Anthropic API.
+    /// The API key, version and base URL are then unused.
+    pub fn with_transport<T: ClaudeTransport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    fn transport(&self) -> Arc<dyn ClaudeTransport> {
+        self.transport.clone().unwrap_or_else(|| {
+            Arc::new(AnthropicTransport::new(
+                self.base_url.as_str(),
+                self.api_key.as_str(),
+                self.anthropic_version.as_str(),
+            ))
+        })
+    }
+
     async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
         let client = Client::new();
         let is_stream = self.options.streaming_func.is_some();
 
-        let payload = self.build_payload(messages, is_stream);
-        let res = client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", self.anthropic_version.clone())
-            .header("content-type", "application/json; charset=utf-8")
-            .json(&payload)
-            .send()
+        let payload = self.build_payload(messages, is_stream)?;
+        let request = self
+            .transport()
+            .build_request(&client, serde_json::to_value(&payload)?)
             .await?;
+        let res = client.execute(request).await?;
         let res = match res.status().as_u16() {
             401 => Err(LLMError::AnthropicError(
                 AnthropicError::AuthenticationError("Invalid API Key".to_string()),
@@ -98,50 +144,198 @@ impl Claude {
             ))),
             429 => Err(LLMError::AnthropicError(AnthropicError::RateLimitError(
                 "Rate Limit Exceeded".to_string(),
-            ))),
+                None,
+            ))
+            .with_retry_after(res.headers())),
             503 => Err(LLMError::AnthropicError(AnthropicError::OverloadedError(
                 "Service Unavailable".to_string(),
-            ))),
-            _ => Ok(res.json::<ApiResponse>().await?),
+                None,
+            ))
+            .with_retry_after(res.headers())),
+            _ if !res.status().is_success() => {
+                let headers = res.headers().clone();
+                let body = res.json::<Value>().await.unwrap_or_default();
+                Err(parse_error(&body).with_retry_after(&headers))
+            }
+            _ => Ok(res.json::<Value>().await?),
         }?;
+        let api_response: ApiResponse = serde_json::from_value(res.clone())?;
 
-        let generation = res
-            .content
-            .first()
-            .map(|c| c.text.clone())
-            .unwrap_or_default();
+        let tokens = Some(TokenUsage::from(&api_response.usage));
 
-        let tokens = Some(TokenUsage {
-            prompt_tokens: res.usage.input_tokens,
-            completion_tokens: res.usage.output_tokens,
-            total_tokens: res.usage.input_tokens + res.usage.output_tokens,
-        });
+        let mut generation = api_response.text();
+        let mut tool_calls = api_response.tool_calls();
+        let mut finish_reason = api_response.stop_reason.as_deref().map(FinishReason::from);
+        if let Some(output_tool) = self.output_tool() {
+            // The forced call of the output tool is the response
+            if let Some(position) = tool_calls
+                .iter()
+                .position(|tool_call| tool_call.function.name == output_tool.name)
+            {
+                generation = tool_calls.remove(position).function.arguments;
+                finish_reason = Some(FinishReason::Stop);
+            }
+        } else if let Some(response_format) = self.prompted_format() {
+            generation = response_format.validate(&generation)?;
+        }
 
-        Ok(GenerateResult { tokens, generation })
+        Ok(GenerateResult {
+            tokens,
+            generation,
+            tool_calls,
+            finish_reason,
+            reasoning_content: api_response.reasoning(),
+            reasoning_blocks: api_response.reasoning_blocks(),
+            model: Some(api_response.model),
+            raw_response: Some(res),
+            ..Default::default()
+        })
     }
 
-    fn build_payload(&self, messages: &[Message], stream: bool) -> Payload {
+    /// The thinking budget asked for in the options, if any.
+    fn thinking_budget(&self) -> Option<u32> {
+        self.options
+            .reasoning_budget_tokens()
+            .filter(|budget| *budget > 0)
+            .map(|budget| budget.max(MIN_THINKING_BUDGET))
+    }
+
+    /// Claude has no JSON mode, JSON responses are the input of a tool it is forced to call.
+    fn output_tool(&self) -> Option<ClaudeTool> {
+        let response_format = self.options.response_format.as_ref()?;
+        // Forcing a tool is not allowed while thinking, see `prompted_format`
+        if self.thinking_budget().is_some() {
+            return None;
+        }
+        match response_format {
+            ResponseFormat::Text => None,
+            ResponseFormat::JsonObject => Some(ClaudeTool {
+                name: OUTPUT_TOOL_NAME.to_string(),
+                description: "Respond with a JSON object.".to_string(),
+                input_schema: json!({ "type": "object" }),
+            }),
+            ResponseFormat::JsonSchema {
+                name,
+                description,
+                schema,
+                ..
+            } => Some(ClaudeTool {
+                name: name.clone(),
+                description: description
+                    .clone()
+                    .unwrap_or_else(|| "Respond with the input of this tool.".to_string()),
+                input_schema: schema
+                    .clone()
+                    .unwrap_or_else(|| json!({ "type": "object" })),
+            }),
+        }
+    }
+
+    /// The response format to ask for in the prompt, when thinking rules out the output tool.
+    fn prompted_format(&self) -> Option<&ResponseFormat> {
+        self.options
+            .response_format
+            .as_ref()
+            .filter(|response_format| response_format.is_json() && self.thinking_budget().is_some())
+    }
+
+    fn build_payload(&self, messages: &[Message], stream: bool) -> Result<Payload, LLMError> {
+        let messages = match self.prompted_format() {
+            Some(response_format) => response_format.with_instructions(messages),
+            None => messages.to_vec(),
+        };
         let (system_message, other_messages): (Vec<_>, Vec<_>) = messages
-            .into_iter()
+            .iter()
             .partition(|m| m.message_type == MessageType::SystemMessage);
+
+        let mut claude_messages: Vec<ClaudeMessage> = Vec::new();
+        for message in other_messages {
+            let message = ClaudeMessage::from_message(message)?;
+            // Consecutive tool results must be sent back together in a single user turn
+            match claude_messages.last_mut() {
+                Some(last) if last.is_tool_result() && message.is_tool_result() => {
+                    last.append(message)
+                }
+                _ => claude_messages.push(message),
+            }
+        }
+
+        // Thinking counts towards `max_tokens`, which must leave room for the answer
+        let thinking_budget = self.thinking_budget();
+        let max_tokens = match (self.options.max_tokens, thinking_budget) {
+            (Some(max_tokens), Some(budget)) if max_tokens <= budget => budget + max_tokens,
+            (Some(max_tokens), _) => max_tokens,
+            (None, Some(budget)) => budget + DEFAULT_MAX_TOKENS,
+            (None, None) => DEFAULT_MAX_TOKENS,
+        };
+
         let mut payload = Payload {
             model: self.model.clone(),
-            system: system_message.get(0).map(|m| m.content.clone()),
-            messages: other_messages
-                .into_iter()
-                .map(ClaudeMessage::from_message)
-                .collect::<Vec<_>>(),
-            max_tokens: self.options.max_tokens.unwrap_or(1024),
+            system: system_message
+                .first()
+                .map(|m| SystemPrompt::from_message(m)),
+            messages: claude_messages,
+            max_tokens,
             stream: None,
             stop_sequences: self.options.stop_words.clone(),
             temperature: self.options.temperature,
             top_p: self.options.top_p,
             top_k: self.options.top_k,
+            tools: self
+                .options
+                .functions
+                .as_ref()
+                .map(|functions| functions.iter().map(ClaudeTool::from).collect()),
+            tool_choice: self
+                .options
+                .function_call_behavior
+                .as_ref()
+                .map(ToolChoice::from),
+            thinking: thinking_budget.map(|budget_tokens| Thinking::Enabled { budget_tokens }),
         };
+        if let Some(output_tool) = self.output_tool() {
+            payload.tool_choice = Some(ToolChoice::Tool {
+                name: output_tool.name.clone(),
+            });
+            payload.tools.get_or_insert_with(Vec::new).push(output_tool);
+        }
         if stream {
             payload.stream = Some(true);
         }
-        payload
+        limit_cache_breakpoints(&mut payload);
+        Ok(payload)
+    }
+}
+
+/// Anthropic takes at most this many cache breakpoints per request.
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// Keeps the last breakpoints within the limit, as they cover the longest prefixes.
+fn limit_cache_breakpoints(payload: &mut Payload) {
+    let cached_blocks: Vec<(usize, usize)> = payload
+        .messages
+        .iter()
+        .enumerate()
+        .flat_map(|(i, message)| {
+            message
+                .cache_controls
+                .iter()
+                .enumerate()
+                .filter(|(_, cache_control)| cache_control.is_some())
+                .map(move |(j, _)| (i, j))
+        })
+        .collect();
+    let system_cached = payload.system.as_ref().is_some_and(SystemPrompt::is_cached);
+    let mut excess =
+        (cached_blocks.len() + system_cached as usize).saturating_sub(MAX_CACHE_BREAKPOINTS);
+    if excess > 0 && system_cached {
+        if let Some(system) = payload.system.as_mut() {
+            system.uncache();
+        }
+        excess -= 1;
+    }
+    for (i, j) in cached_blocks.into_iter().take(excess) {
+        payload.messages[i].cache_controls[j] = None;
     }
 }
 
@@ -150,23 +344,29 @@ impl LLM for Claude {
     async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
         match &self.options.streaming_func {
             Some(func) => {
-                let mut complete_response = String::new();
+                let mut generate_result = GenerateResult::default();
                 let mut stream = self.stream(messages).await?;
                 while let Some(data) = stream.next().await {
                     match data {
                         Ok(value) => {
-                            let mut func = func.lock().await;
-                            complete_response.push_str(&value.content);
-                            let _ = func(value.content).await;
+                            generate_result.merge_stream_data(&value);
+                            if !value.content.is_empty() {
+                                let mut func = func.lock().await;
+                                let _ = func(value.content).await;
+                            }
                         }
                         Err(e) => return Err(e),
                     }
                 }
-                let mut generate_result = GenerateResult::default();
-                generate_result.generation = complete_response;
                 Ok(generate_result)
             }
-            None => self.generate(messages).await,
+            None => {
+                cancellable(
+                    self.options.cancellation_token.as_ref(),
+                    self.generate(messages),
+                )
+                .await
+            }
         }
     }
     async fn stream(
@@ -174,91 +374,680 @@ impl LLM for Claude {
         messages: &[Message],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
         let client = Client::new();
-        let payload = self.build_payload(messages, true);
-        let request = client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", &self.anthropic_version)
-            .header("content-type", "application/json; charset=utf-8")
-            .json(&payload)
-            .build()?;
-
-        // Instead of sending the request directly, return a stream wrapper
-        let stream = client.execute(request).await?;
-        let stream = stream.bytes_stream();
-        // Process each chunk as it arrives
-        let processed_stream = stream.then(move |result| {
-            async move {
-                match result {
-                    Ok(bytes) => {
-                        let value: Value = parse_sse_to_json(&String::from_utf8_lossy(&bytes))?;
-                        if value["type"].as_str().unwrap_or("") == "content_block_delta" {
-                            let content = value["delta"]["text"].clone();
-                            // Return StreamData based on the parsed content
-                            // TODO get tokens from the response
-                            Ok(StreamData::new(value, None, content.as_str().unwrap_or("")))
-                        } else {
-                            Ok(StreamData::new(value, None, ""))
-                        }
-                    }
-                    Err(e) => Err(LLMError::RequestError(e)),
-                }
-            }
+        let payload = self.build_payload(messages, true)?;
+        let transport = self.transport();
+        let request = transport
+            .build_request(&client, serde_json::to_value(&payload)?)
+            .await?;
+
+        let token = self.options.cancellation_token.clone();
+        let res = cancellable(token.as_ref(), async { Ok(client.execute(request).await?) }).await?;
+        if !res.status().is_success() {
+            let headers = res.headers().clone();
+            let body = res.json::<Value>().await.unwrap_or_default();
+            return Err(parse_error(&body).with_retry_after(&headers));
+        }
+
+        let mut state = StreamState {
+            output_tool: self.output_tool().map(|tool| tool.name),
+            ..Default::default()
+        };
+        let processed_stream = transport.decode_stream(res).filter_map(move |data| {
+            let data = data
+                .and_then(|data| Ok(serde_json::from_str::<Value>(&data)?))
+                .and_then(|value| state.parse_event(value))
+                .transpose();
+            async move { data }
         });
 
-        Ok(Box::pin(processed_stream))
+        Ok(cancellable_stream(Box::pin(processed_stream), token))
     }
 
     fn add_options(&mut self, options: CallOptions) {
         self.options.merge_options(options)
     }
-}
 
-fn parse_sse_to_json(sse_data: &str) -> Result<Value, LLMError> {
-    if let Ok(json) = serde_json::from_str::<Value>(sse_data) {
-        return parse_error(&json);
+    fn override_options(&mut self, options: CallOptions) {
+        self.options.override_options(options)
     }
 
-    let lines: Vec<&str> = sse_data.trim().split('\n').collect();
-    let mut event_data: HashMap<&str, String> = HashMap::new();
+    fn context_window(&self) -> Option<usize> {
+        // Bedrock ids are prefixed, as in `us.anthropic.claude-3-5-haiku-20241022-v1:0`
+        let model = self
+            .model
+            .find("claude")
+            .map_or(self.model.as_str(), |start| &self.model[start..]);
+        context_window_for_model(model)
+    }
 
-    for line in lines {
-        if let Some((key, value)) = line.split_once(": ") {
-            event_data.insert(key, value.to_string());
-        }
+    fn max_output_tokens(&self) -> Option<usize> {
+        Some(self.options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) as usize)
     }
+}
+
+/// What the events of a message stream refer to, which Claude only sends once per message
+/// or content block.
+#[derive(Default)]
+struct StreamState {
+    /// The prompt usage, only reported at the start.
+    prompt_usage: TokenUsage,
+    /// The content block index of each tool call, and whether its arguments started.
+    tool_blocks: Vec<(usize, bool)>,
+    /// The tool producing JSON responses, whose input is streamed as content.
+    output_tool: Option<String>,
+    output_block: Option<usize>,
+}
 
-    if let Some(data) = event_data.get("data") {
-        let data: Value = serde_json::from_str(data)?;
-        return match data["type"].as_str() {
-            Some("error") => parse_error(&data),
-            _ => Ok(data),
+impl StreamState {
+    fn tool_position(&self, block: &Value) -> Option<usize> {
+        let index = block["index"].as_u64()? as usize;
+        self.tool_blocks.iter().position(|(i, _)| *i == index)
+    }
+
+    /// Turns an event into a chunk, or `None` for events carrying nothing to report.
+    fn parse_event(&mut self, value: Value) -> Result<Option<StreamData>, LLMError> {
+        let data = match value["type"].as_str().unwrap_or_default() {
+            "message_start" => {
+                if let Ok(usage) =
+                    serde_json::from_value::<Usage>(value["message"]["usage"].clone())
+                {
+                    self.prompt_usage = TokenUsage::from(&usage);
+                }
+                let model = value["message"]["model"].as_str().map(String::from);
+                StreamData::new(value, None, "").with_model(model)
+            }
+            "content_block_start" if value["content_block"]["type"] == "tool_use" => {
+                let index = value["index"].as_u64().unwrap_or_default() as usize;
+                if self.output_tool.is_some()
+                    && value["content_block"]["name"].as_str() == self.output_tool.as_deref()
+                {
+                    self.output_block = Some(index);
+                    return Ok(None);
+                }
+                self.tool_blocks.push((index, false));
+                let block = &value["content_block"];
+                let delta = ToolCallDelta::new(self.tool_blocks.len() - 1)
+                    .with_id(block["id"].as_str().unwrap_or_default())
+                    .with_name(block["name"].as_str().unwrap_or_default());
+                StreamData::new(value, None, "").with_tool_call_deltas(vec![delta])
+            }
+            "content_block_start" if value["content_block"]["type"] == "redacted_thinking" => {
+                let data = value["content_block"]["data"].as_str().map(String::from);
+                StreamData::new(value, None, "").with_reasoning_redacted(data)
+            }
+            "content_block_delta" => match value["delta"]["type"].as_str() {
+                Some("thinking_delta") => {
+                    let thinking = value["delta"]["thinking"].as_str().map(String::from);
+                    StreamData::new(value, None, "").with_reasoning_content(thinking)
+                }
+                Some("signature_delta") => {
+                    let signature = value["delta"]["signature"].as_str().map(String::from);
+                    StreamData::new(value, None, "").with_reasoning_signature(signature)
+                }
+                Some("input_json_delta")
+                    if self.output_block.is_some()
+                        && value["index"].as_u64() == self.output_block.map(|i| i as u64) =>
+                {
+                    let content = value["delta"]["partial_json"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string();
+                    StreamData::new(value, None, content)
+                }
+                Some("input_json_delta") => {
+                    let Some(position) = self.tool_position(&value) else {
+                        return Ok(None);
+                    };
+                    self.tool_blocks[position].1 = true;
+                    let delta = ToolCallDelta::new(position).with_arguments(
+                        value["delta"]["partial_json"].as_str().unwrap_or_default(),
+                    );
+                    StreamData::new(value, None, "").with_tool_call_deltas(vec![delta])
+                }
+                _ => {
+                    let content = value["delta"]["text"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string();
+                    StreamData::new(value, None, content)
+                }
+            },
+            // Tools without parameters get no input deltas, their arguments are an empty object
+            "content_block_stop" => match self.tool_position(&value) {
+                Some(position) if !self.tool_blocks[position].1 => {
+                    let delta = ToolCallDelta::new(position).with_arguments("{}");
+                    StreamData::new(value, None, "").with_tool_call_deltas(vec![delta])
+                }
+                _ => return Ok(None),
+            },
+            "message_delta" => {
+                let finish_reason = value["delta"]["stop_reason"]
+                    .as_str()
+                    .map(FinishReason::from)
+                    .map(|finish_reason| match finish_reason {
+                        FinishReason::ToolCalls if self.output_block.is_some() => {
+                            FinishReason::Stop
+                        }
+                        finish_reason => finish_reason,
+                    });
+                let tokens = value["usage"]["output_tokens"]
+                    .as_u64()
+                    .map(|output_tokens| {
+                        TokenUsage::new(self.prompt_usage.prompt_tokens, output_tokens as u32)
+                            .with_cache_tokens(
+                                self.prompt_usage.cache_read_tokens,
+                                self.prompt_usage.cache_creation_tokens,
+                            )
+                    });
+                StreamData::new(value, tokens, "").with_finish_reason(finish_reason)
+            }
+            "error" => return Err(parse_error(&value)),
+            _ => return Ok(None),
         };
+        Ok(Some(data))
     }
-    log::error!("No data field in the SSE event");
-    Err(LLMError::ContentNotFound("data".to_string()))
 }
 
-fn parse_error(json: &Value) -> Result<Value, LLMError> {
+fn parse_error(json: &Value) -> LLMError {
     let error_type = json["error"]["type"].as_str().unwrap_or("");
     let message = json["error"]["message"].as_str().unwrap_or("").to_string();
     match error_type {
-        "invalid_request_error" => Err(AnthropicError::InvalidRequestError(message))?,
-        "authentication_error" => Err(AnthropicError::AuthenticationError(message))?,
-        "permission_error" => Err(AnthropicError::PermissionError(message))?,
-        "not_found_error" => Err(AnthropicError::NotFoundError(message))?,
-        "rate_limit_error" => Err(AnthropicError::RateLimitError(message))?,
-        "api_error" => Err(AnthropicError::ApiError(message))?,
-        "overloaded_error" => Err(AnthropicError::OverloadedError(message))?,
-        _ => Err(LLMError::OtherError("Unknown error".to_string())),
+        "invalid_request_error" => AnthropicError::InvalidRequestError(message).into(),
+        "authentication_error" => AnthropicError::AuthenticationError(message).into(),
+        "permission_error" => AnthropicError::PermissionError(message).into(),
+        "not_found_error" => AnthropicError::NotFoundError(message).into(),
+        "rate_limit_error" => AnthropicError::RateLimitError(message, None).into(),
+        "api_error" => AnthropicError::ApiError(message).into(),
+        "overloaded_error" => AnthropicError::OverloadedError(message, None).into(),
+        // Cloud providers answer their own errors with a bare message
+        _ => match json["message"].as_str() {
+            Some(message) => AnthropicError::ApiError(message.to_string()).into(),
+            None => LLMError::OtherError("Unknown error".to_string()),
+        },
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::language_models::options::ReasoningEffort;
+    use crate::schemas::{
+        CacheControl, CacheTtl, ContentPart, FunctionCallBehavior, FunctionDefinition,
+        ReasoningBlock, ReasoningRetention,
+    };
+    use crate::testing::CassetteServer;
+    use serde_json::json;
     use tokio::test;
 
+    #[test]
+    async fn test_build_payload_with_tools() {
+        let claude = Claude::new().with_options(
+            CallOptions::new()
+                .with_functions(vec![FunctionDefinition::new(
+                    "get_weather",
+                    "Get the weather for a city",
+                    json!({
+                        "type": "object",
+                        "properties": { "city": { "type": "string" } },
+                        "required": ["city"]
+                    }),
+                )])
+                .with_function_call_behavior(FunctionCallBehavior::Named(
+                    "get_weather".to_string(),
+                )),
+        );
+
+        let tool_calls = json!([{
+            "id": "toolu_01",
+            "type": "function",
+            "function": { "name": "get_weather", "arguments": "{\"city\":\"Lima\"}" }
+        }, {
+            "id": "toolu_02",
+            "type": "function",
+            "function": { "name": "get_weather", "arguments": "{\"city\":\"Cusco\"}" }
+        }]);
+        let messages = vec![
+            Message::new_system_message("You are a weather bot"),
+            Message::new_human_message("Weather in Lima and Cusco?"),
+            Message::new_ai_message("").with_tool_calls(tool_calls),
+            Message::new_tool_message("Sunny", "toolu_01"),
+            Message::new_tool_message("Rainy", "toolu_02"),
+        ];
+
+        let payload =
+            serde_json::to_value(claude.build_payload(&messages, false).unwrap()).unwrap();
+
+        assert_eq!(payload["system"], "You are a weather bot");
+        assert_eq!(payload["tools"][0]["name"], "get_weather");
+        assert_eq!(payload["tools"][0]["input_schema"]["required"][0], "city");
+        assert_eq!(
+            payload["tool_choice"],
+            json!({ "type": "tool", "name": "get_weather" })
+        );
+        assert_eq!(
+            payload["messages"][1],
+            json!({
+                "role": "assistant",
+                "content": [
+                    { "type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": { "city": "Lima" } },
+                    { "type": "tool_use", "id": "toolu_02", "name": "get_weather", "input": { "city": "Cusco" } }
+                ]
+            })
+        );
+        assert_eq!(
+            payload["messages"][2],
+            json!({
+                "role": "user",
+                "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_01", "content": "Sunny" },
+                    { "type": "tool_result", "tool_use_id": "toolu_02", "content": "Rainy" }
+                ]
+            })
+        );
+        assert_eq!(payload["messages"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    async fn test_build_payload_with_content_parts() {
+        let messages = vec![Message::new_human_message_with_parts(vec![
+            ContentPart::text("Compare these"),
+            ContentPart::image_url("https://example.com/cat.png"),
+            ContentPart::image_url("data:image/png;base64,iVBORw=="),
+            ContentPart::file_bytes("application/pdf", b"%PDF", None),
+        ])];
+
+        let payload =
+            serde_json::to_value(Claude::new().build_payload(&messages, false).unwrap()).unwrap();
+
+        assert_eq!(
+            payload["messages"][0]["content"],
+            json!([
+                { "type": "text", "text": "Compare these" },
+                { "type": "image", "source": { "type": "url", "url": "https://example.com/cat.png" } },
+                { "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "iVBORw==" } },
+                { "type": "document", "source": { "type": "base64", "media_type": "application/pdf", "data": "JVBERg==" } }
+            ])
+        );
+
+        let audio = vec![Message::new_human_message_with_parts(vec![
+            ContentPart::audio_bytes("audio/wav", b"RIFF"),
+        ])];
+        assert!(matches!(
+            Claude::new().build_payload(&audio, false),
+            Err(LLMError::UnsupportedContent(_))
+        ));
+    }
+
+    #[test]
+    async fn test_build_payload_with_cache_breakpoints() {
+        let mut messages = vec![
+            Message::new_system_message("You are a weather bot").with_cache_breakpoint(),
+            Message::new_human_message("Weather in Lima?"),
+            Message::new_ai_message("").with_tool_calls(json!([{
+                "id": "toolu_01",
+                "type": "function",
+                "function": { "name": "get_weather", "arguments": "{}" }
+            }])),
+            Message::new_tool_message("Sunny", "toolu_01")
+                .with_cache_control(CacheControl::new(CacheTtl::OneHour)),
+        ];
+
+        let payload =
+            serde_json::to_value(Claude::new().build_payload(&messages, false).unwrap()).unwrap();
+
+        assert_eq!(
+            payload["system"],
+            json!([{ "type": "text", "text": "You are a weather bot", "cache_control": { "type": "ephemeral" } }])
+        );
+        assert_eq!(
+            payload["messages"][0]["content"][0].get("cache_control"),
+            None
+        );
+        assert_eq!(
+            payload["messages"][2]["content"],
+            json!([{
+                "type": "tool_result",
+                "tool_use_id": "toolu_01",
+                "content": "Sunny",
+                "cache_control": { "type": "ephemeral", "ttl": "1h" }
+            }])
+        );
+
+        // Past the limit, the earliest breakpoints go first
+        messages.extend([
+            Message::new_ai_message("Sunny in Lima").with_cache_breakpoint(),
+            Message::new_human_message("And Cusco?").with_cache_breakpoint(),
+            Message::new_ai_message("Rainy in Cusco").with_cache_breakpoint(),
+        ]);
+        let payload =
+            serde_json::to_value(Claude::new().build_payload(&messages, false).unwrap()).unwrap();
+
+        assert_eq!(payload["system"][0].get("cache_control"), None);
+        let cached = payload["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|message| message.to_string().contains("cache_control"))
+            .count();
+        assert_eq!(cached, MAX_CACHE_BREAKPOINTS);
+    }
+
+    #[test]
+    async fn test_build_payload_caches_content_parts() {
+        let messages = vec![Message::new_human_message_with_parts(vec![
+            ContentPart::file_bytes("application/pdf", b"%PDF", None).with_cache_breakpoint(),
+            ContentPart::text("Summarize this report"),
+        ])];
+
+        let payload =
+            serde_json::to_value(Claude::new().build_payload(&messages, false).unwrap()).unwrap();
+
+        assert_eq!(
+            payload["messages"][0]["content"],
+            json!([
+                {
+                    "type": "document",
+                    "source": { "type": "base64", "media_type": "application/pdf", "data": "JVBERg==" },
+                    "cache_control": { "type": "ephemeral" }
+                },
+                { "type": "text", "text": "Summarize this report" }
+            ])
+        );
+    }
+
+    #[test]
+    async fn test_tool_use_response_to_function_calls() {
+        let response: ApiResponse = serde_json::from_value(json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-3-5-sonnet-20240620",
+            "content": [
+                { "type": "text", "text": "Let me check." },
+                { "type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": { "city": "Lima" } }
+            ],
+            "stop_reason": "tool_use",
+            "stop_sequence": null,
+            "usage": { "input_tokens": 10, "output_tokens": 5 }
+        }))
+        .unwrap();
+
+        let tool_calls = response.tool_calls();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id, "toolu_01");
+        assert_eq!(tool_calls[0].type_field, "function");
+        assert_eq!(tool_calls[0].function.name, "get_weather");
+        assert_eq!(
+            serde_json::from_str::<Value>(&tool_calls[0].function.arguments).unwrap(),
+            json!({ "city": "Lima" })
+        );
+        assert_eq!(response.text(), "Let me check.");
+    }
+
+    #[test]
+    async fn test_stream_events_with_tool_use() {
+        let events = [
+            json!({ "type": "message_start", "message": { "model": "claude-3-5-sonnet-20240620", "usage": { "input_tokens": 25, "output_tokens": 1 } } }),
+            json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "text", "text": "" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "Let me check." } }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "tool_use", "id": "toolu_01", "name": "get_weather", "input": {} } }),
+            json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "input_json_delta", "partial_json": "{\"city\":" } }),
+            json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "input_json_delta", "partial_json": " \"Lima\"}" } }),
+            json!({ "type": "content_block_stop", "index": 1 }),
+            json!({ "type": "content_block_start", "index": 2, "content_block": { "type": "tool_use", "id": "toolu_02", "name": "get_time", "input": {} } }),
+            json!({ "type": "content_block_stop", "index": 2 }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "tool_use" }, "usage": { "output_tokens": 40 } }),
+            json!({ "type": "message_stop" }),
+        ];
+
+        let mut state = StreamState::default();
+        let mut result = GenerateResult::default();
+        for event in events {
+            if let Some(data) = state.parse_event(event).unwrap() {
+                result.merge_stream_data(&data);
+            }
+        }
+
+        assert_eq!(result.generation, "Let me check.");
+        assert_eq!(result.model.as_deref(), Some("claude-3-5-sonnet-20240620"));
+        assert_eq!(result.tool_calls.len(), 2);
+        assert_eq!(result.tool_calls[0].id, "toolu_01");
+        assert_eq!(
+            result.tool_calls[0].function.arguments,
+            r#"{"city": "Lima"}"#
+        );
+        assert_eq!(result.tool_calls[1].function.name, "get_time");
+        assert_eq!(result.tool_calls[1].function.arguments, "{}");
+        assert_eq!(result.finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(result.tokens, Some(TokenUsage::new(25, 40)));
+
+        let mut state = StreamState::default();
+        let mut result = GenerateResult::default();
+        let events = [
+            json!({ "type": "message_start", "message": { "model": "claude-3-5-sonnet-20240620", "usage": { "input_tokens": 5, "cache_creation_input_tokens": 20, "cache_read_input_tokens": 100, "output_tokens": 1 } } }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "end_turn" }, "usage": { "output_tokens": 10 } }),
+        ];
+        for event in events {
+            if let Some(data) = state.parse_event(event).unwrap() {
+                result.merge_stream_data(&data);
+            }
+        }
+        assert_eq!(
+            result.tokens,
+            Some(TokenUsage::new(125, 10).with_cache_tokens(100, 20))
+        );
+
+        let error = json!({ "type": "error", "error": { "type": "overloaded_error", "message": "Overloaded" } });
+        assert!(state.parse_event(error).unwrap_err().is_retryable());
+    }
+
+    #[test]
+    async fn test_thinking_payload_and_response() {
+        let claude = Claude::new().with_options(
+            CallOptions::new()
+                .with_reasoning_effort(ReasoningEffort::Medium)
+                .with_max_tokens(2000),
+        );
+        let blocks = [
+            ReasoningBlock::Signed {
+                content: "2 + 2 is 4".to_string(),
+                signature: "sig_01".to_string(),
+            },
+            ReasoningBlock::Redacted {
+                data: "xyz".to_string(),
+            },
+        ];
+        let reasoning = ReasoningRetention::Resend.reasoning(Some("2 + 2 is 4"), &blocks);
+        let messages = vec![
+            Message::new_human_message("What is 2 + 2?"),
+            Message::new_ai_message("4").with_reasoning(reasoning),
+            Message::new_human_message("And times 3?"),
+        ];
+
+        let payload =
+            serde_json::to_value(claude.build_payload(&messages, false).unwrap()).unwrap();
+        assert_eq!(
+            payload["thinking"],
+            json!({ "type": "enabled", "budget_tokens": 4096 })
+        );
+        assert_eq!(payload["max_tokens"], 6096);
+        assert_eq!(
+            payload["messages"][1]["content"],
+            json!([
+                { "type": "thinking", "thinking": "2 + 2 is 4", "signature": "sig_01" },
+                { "type": "redacted_thinking", "data": "xyz" },
+                { "type": "text", "text": "4" }
+            ])
+        );
+
+        let persisted = ReasoningRetention::Persist.reasoning(Some("2 + 2 is 4"), &blocks);
+        let messages = vec![Message::new_ai_message("4").with_reasoning(persisted)];
+        let payload =
+            serde_json::to_value(Claude::new().build_payload(&messages, false).unwrap()).unwrap();
+        assert!(payload.get("thinking").is_none());
+        assert_eq!(
+            payload["messages"][0]["content"].as_array().unwrap().len(),
+            1
+        );
+
+        let response: ApiResponse = serde_json::from_value(json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-3-7-sonnet-20250219",
+            "content": [
+                { "type": "thinking", "thinking": "12 times 3 is 36", "signature": "sig_02" },
+                { "type": "redacted_thinking", "data": "abc" },
+                { "type": "text", "text": "36" }
+            ],
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": { "input_tokens": 10, "output_tokens": 5 }
+        }))
+        .unwrap();
+        assert_eq!(response.text(), "36");
+        assert_eq!(response.reasoning().as_deref(), Some("12 times 3 is 36"));
+        assert_eq!(
+            response.reasoning_blocks(),
+            vec![
+                ReasoningBlock::Signed {
+                    content: "12 times 3 is 36".to_string(),
+                    signature: "sig_02".to_string(),
+                },
+                ReasoningBlock::Redacted {
+                    data: "abc".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    async fn test_stream_keeps_thinking_blocks_apart() {
+        let response: ApiResponse = serde_json::from_value(json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-3-7-sonnet-20250219",
+            "content": [
+                { "type": "thinking", "thinking": "Lima is in Peru.", "signature": "sig_01" },
+                { "type": "redacted_thinking", "data": "abc" },
+                { "type": "thinking", "thinking": " Its time zone is UTC-5.", "signature": "sig_02" },
+                { "type": "text", "text": "It is noon in Lima." }
+            ],
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": { "input_tokens": 10, "output_tokens": 5 }
+        }))
+        .unwrap();
+        let events = [
+            json!({ "type": "message_start", "message": { "model": "claude-3-7-sonnet-20250219", "usage": { "input_tokens": 10, "output_tokens": 1 } } }),
+            json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "thinking", "thinking": "" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "thinking_delta", "thinking": "Lima is" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "thinking_delta", "thinking": " in Peru." } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "signature_delta", "signature": "sig_01" } }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "redacted_thinking", "data": "abc" } }),
+            json!({ "type": "content_block_stop", "index": 1 }),
+            json!({ "type": "content_block_start", "index": 2, "content_block": { "type": "thinking", "thinking": "" } }),
+            json!({ "type": "content_block_delta", "index": 2, "delta": { "type": "thinking_delta", "thinking": " Its time zone is UTC-5." } }),
+            json!({ "type": "content_block_delta", "index": 2, "delta": { "type": "signature_delta", "signature": "sig_02" } }),
+            json!({ "type": "content_block_stop", "index": 2 }),
+            json!({ "type": "content_block_start", "index": 3, "content_block": { "type": "text", "text": "" } }),
+            json!({ "type": "content_block_delta", "index": 3, "delta": { "type": "text_delta", "text": "It is noon in Lima." } }),
+            json!({ "type": "content_block_stop", "index": 3 }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "end_turn" }, "usage": { "output_tokens": 5 } }),
+            json!({ "type": "message_stop" }),
+        ];
+
+        let mut state = StreamState::default();
+        let mut result = GenerateResult::default();
+        for event in events {
+            if let Some(data) = state.parse_event(event).unwrap() {
+                result.merge_stream_data(&data);
+            }
+        }
+
+        assert_eq!(result.generation, response.text());
+        assert_eq!(result.reasoning_content, response.reasoning());
+        assert_eq!(result.reasoning_blocks, response.reasoning_blocks());
+
+        // The blocks are sent back as they came
+        let reasoning = result.reasoning(ReasoningRetention::Resend);
+        let messages =
+            vec![Message::new_ai_message("It is noon in Lima.").with_reasoning(reasoning)];
+        let payload =
+            serde_json::to_value(Claude::new().build_payload(&messages, false).unwrap()).unwrap();
+        assert_eq!(
+            payload["messages"][0]["content"],
+            json!([
+                { "type": "thinking", "thinking": "Lima is in Peru.", "signature": "sig_01" },
+                { "type": "redacted_thinking", "data": "abc" },
+                { "type": "thinking", "thinking": " Its time zone is UTC-5.", "signature": "sig_02" },
+                { "type": "text", "text": "It is noon in Lima." }
+            ])
+        );
+    }
+
+    #[test]
+    async fn test_structured_output_with_output_tool() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"]
+        });
+        let claude = Claude::new().with_options(
+            CallOptions::new()
+                .with_response_format(ResponseFormat::json_schema("place", schema.clone())),
+        );
+        let messages = vec![Message::new_human_message("Where is Machu Picchu?")];
+
+        let payload =
+            serde_json::to_value(claude.build_payload(&messages, false).unwrap()).unwrap();
+        assert_eq!(payload["tools"][0]["name"], "place");
+        assert_eq!(payload["tools"][0]["input_schema"], schema);
+        assert_eq!(
+            payload["tool_choice"],
+            json!({ "type": "tool", "name": "place" })
+        );
+
+        let events = [
+            json!({ "type": "message_start", "message": { "model": "claude-3-5-sonnet-20240620", "usage": { "input_tokens": 25, "output_tokens": 1 } } }),
+            json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "tool_use", "id": "toolu_01", "name": "place", "input": {} } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "input_json_delta", "partial_json": "{\"city\":" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "input_json_delta", "partial_json": " \"Cusco\"}" } }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({ "type": "message_delta", "delta": { "stop_reason": "tool_use" }, "usage": { "output_tokens": 12 } }),
+        ];
+        let mut state = StreamState {
+            output_tool: claude.output_tool().map(|tool| tool.name),
+            ..Default::default()
+        };
+        let mut result = GenerateResult::default();
+        for event in events {
+            if let Some(data) = state.parse_event(event).unwrap() {
+                result.merge_stream_data(&data);
+            }
+        }
+        assert_eq!(result.generation, r#"{"city": "Cusco"}"#);
+        assert!(result.tool_calls.is_empty());
+        assert_eq!(result.finish_reason, Some(FinishReason::Stop));
+
+        // Thinking rules out forcing a tool, the format is asked for in the prompt instead
+        let thinking = Claude::new().with_options(
+            CallOptions::new()
+                .with_response_format(ResponseFormat::json_schema("place", schema))
+                .with_reasoning_effort(ReasoningEffort::Low),
+        );
+        let payload =
+            serde_json::to_value(thinking.build_payload(&messages, false).unwrap()).unwrap();
+        assert!(payload.get("tools").is_none());
+        assert!(payload["system"]
+            .as_str()
+            .unwrap()
+            .contains("\"required\":[\"city\"]"));
+    }
+
     #[test]
     #[ignore]
     async fn test_cloudia_generate() {
@@ -287,4 +1076,40 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    async fn test_generate_and_stream_from_cassette() {
+        let cassette = CassetteServer::new(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/llm/test_data/cassettes/claude.synthetic.json"
+            ),
+            "https://api.anthropic.com",
+        )
+        .start()
+        .await
+        .unwrap();
+        let claude = Claude::new()
+            .with_model("claude-3-5-haiku-20241022")
+            .with_options(CallOptions::new().with_max_tokens(64))
+            .with_base_url(cassette.url());
+        let messages = [Message::new_human_message(
+            "What is the capital of France? Answer in one word.",
+        )];
+
+        let result = claude.generate(&messages).await.unwrap();
+        assert!(result.generation.contains("Paris"));
+        assert_eq!(result.finish_reason, Some(FinishReason::Stop));
+        assert!(result.tokens.unwrap().prompt_tokens > 0);
+
+        let mut streamed = GenerateResult::default();
+        let mut stream = claude.stream(&messages).await.unwrap();
+        while let Some(data) = stream.next().await {
+            streamed.merge_stream_data(&data.unwrap());
+        }
+        assert!(streamed.generation.contains("Paris"));
+        assert_eq!(streamed.finish_reason, Some(FinishReason::Stop));
+        assert!(streamed.tokens.unwrap().completion_tokens > 0);
+        cassette.assert_replayed().await;
+    }
 }
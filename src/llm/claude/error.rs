@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,12 +16,14 @@ pub enum AnthropicError {
     #[error("Anthropic API error: Not found - {0}")]
     NotFoundError(String),
 
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
     #[error("Anthropic API error: Rate limit exceeded - {0}")]
-    RateLimitError(String),
+    RateLimitError(String, Option<Duration>),
 
     #[error("Anthropic API error: Internal error - {0}")]
     ApiError(String),
 
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
     #[error("Anthropic API error: Overloaded - {0}")]
-    OverloadedError(String),
+    OverloadedError(String, Option<Duration>),
 }
@@ -5,3 +5,11 @@ pub use client::*;
 
 mod error;
 pub use error::*;
+
+mod transport;
+pub use transport::*;
+
+#[cfg(feature = "bedrock")]
+mod bedrock;
+#[cfg(feature = "bedrock")]
+pub use bedrock::*;
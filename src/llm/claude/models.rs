@@ -1,37 +1,352 @@
-use serde::{Deserialize, Serialize};
+use serde::{
+    ser::{Error as _, SerializeStruct},
+    Deserialize, Serialize, Serializer,
+};
+use serde_json::Value;
 
-use crate::schemas::{Message, MessageType};
+use crate::{
+    language_models::{LLMError, TokenUsage},
+    schemas::{
+        content::parse_data_url, CacheControl, CacheTtl, ContentPart, FunctionCallBehavior,
+        FunctionCallResponse, FunctionDefinition, FunctionDetail, Message, MessageType,
+        ReasoningBlock,
+    },
+};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Source {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+    Text { media_type: String, data: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+    Image {
+        source: Source,
+    },
+    Document {
+        source: Source,
+    },
+}
+
+impl TryFrom<ContentPart> for ContentBlock {
+    type Error = LLMError;
+
+    fn try_from(part: ContentPart) -> Result<Self, Self::Error> {
+        if let Some(text) = part.file_text() {
+            return Ok(ContentBlock::Document {
+                source: Source::Text {
+                    media_type: "text/plain".to_string(),
+                    data: text,
+                },
+            });
+        }
+        match part {
+            ContentPart::Text { text, .. } => Ok(ContentBlock::Text { text }),
+            ContentPart::ImageUrl { url, .. } => {
+                let source = match parse_data_url(&url) {
+                    Some((media_type, data)) => Source::Base64 {
+                        media_type: media_type.to_string(),
+                        data: data.to_string(),
+                    },
+                    None => Source::Url { url },
+                };
+                Ok(ContentBlock::Image { source })
+            }
+            ContentPart::ImageBase64 {
+                media_type, data, ..
+            } => Ok(ContentBlock::Image {
+                source: Source::Base64 { media_type, data },
+            }),
+            ContentPart::File {
+                media_type, data, ..
+            } if media_type == "application/pdf" => Ok(ContentBlock::Document {
+                source: Source::Base64 { media_type, data },
+            }),
+            ContentPart::File { media_type, .. } => Err(LLMError::UnsupportedContent(format!(
+                "Claude does not accept {} documents",
+                media_type
+            ))),
+            ContentPart::Audio { .. } => Err(LLMError::UnsupportedContent(
+                "Claude does not accept audio".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct ClaudeMessage {
     pub role: String,
-    pub content: String,
+    pub content: Vec<ContentBlock>,
+    /// The `cache_control` of each content block, ending a cached prefix after it.
+    pub cache_controls: Vec<Option<ClaudeCacheControl>>,
 }
+
+impl Serialize for ClaudeMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut content = serde_json::to_value(&self.content).map_err(S::Error::custom)?;
+        if let Some(blocks) = content.as_array_mut() {
+            for (block, cache_control) in blocks.iter_mut().zip(&self.cache_controls) {
+                if let (Some(block), Some(cache_control)) = (block.as_object_mut(), cache_control) {
+                    block.insert(
+                        "cache_control".to_string(),
+                        serde_json::to_value(cache_control).map_err(S::Error::custom)?,
+                    );
+                }
+            }
+        }
+        let mut message = serializer.serialize_struct("ClaudeMessage", 2)?;
+        message.serialize_field("role", &self.role)?;
+        message.serialize_field("content", &content)?;
+        message.end()
+    }
+}
+
 impl ClaudeMessage {
-    pub fn new<S: Into<String>>(role: S, content: S) -> Self {
+    pub fn new<S: Into<String>>(role: S, content: Vec<ContentBlock>) -> Self {
         Self {
             role: role.into(),
-            content: content.into(),
+            cache_controls: vec![None; content.len()],
+            content,
         }
     }
 
-    pub fn from_message(message: &Message) -> Self {
+    pub fn from_message(message: &Message) -> Result<Self, LLMError> {
+        let mut claude_message = Self::from_message_content(message)?;
+        if let Some(cache_control) = message.cache_control {
+            // Thinking blocks cannot be cached on their own
+            let last_block = claude_message.content.last();
+            if !matches!(
+                last_block,
+                None | Some(ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. })
+            ) {
+                if let Some(last) = claude_message.cache_controls.last_mut() {
+                    *last = Some(cache_control.into());
+                }
+            }
+        }
+        Ok(claude_message)
+    }
+
+    /// Appends the content of `other`, keeping the breakpoints of both.
+    pub fn append(&mut self, other: ClaudeMessage) {
+        self.content.extend(other.content);
+        self.cache_controls.extend(other.cache_controls);
+    }
+
+    fn from_message_content(message: &Message) -> Result<Self, LLMError> {
+        let text = || -> Vec<ContentBlock> {
+            if message.content.is_empty() {
+                Vec::new()
+            } else {
+                vec![ContentBlock::Text {
+                    text: message.content.clone(),
+                }]
+            }
+        };
+
         match message.message_type {
-            MessageType::SystemMessage => Self::new("system", &message.content),
-            MessageType::AIMessage => Self::new("assistant", &message.content),
-            MessageType::HumanMessage => Self::new("user", &message.content),
-            MessageType::ToolMessage => Self::new("tool", &message.content),
+            MessageType::SystemMessage => Ok(Self::new("system", text())),
+            MessageType::HumanMessage => {
+                let parts = message.parts();
+                let cache_controls = parts
+                    .iter()
+                    .map(|part| part.cache_control().map(ClaudeCacheControl::from))
+                    .collect();
+                Ok(Self {
+                    role: "user".to_string(),
+                    content: parts
+                        .into_iter()
+                        .map(ContentBlock::try_from)
+                        .collect::<Result<_, _>>()?,
+                    cache_controls,
+                })
+            }
+            MessageType::AIMessage => {
+                let mut content = Vec::new();
+                // Thinking must come first, and is only accepted in the blocks it was
+                // generated in
+                if let Some(reasoning) = message.reasoning.as_ref().filter(|r| r.resend) {
+                    content.extend(reasoning.blocks.iter().map(|block| match block {
+                        ReasoningBlock::Signed { content, signature } => ContentBlock::Thinking {
+                            thinking: content.clone(),
+                            signature: signature.clone(),
+                        },
+                        ReasoningBlock::Redacted { data } => {
+                            ContentBlock::RedactedThinking { data: data.clone() }
+                        }
+                    }));
+                }
+                content.extend(text());
+                if let Some(tool_calls) = &message.tool_calls {
+                    let tool_calls: Vec<FunctionCallResponse> =
+                        serde_json::from_value(tool_calls.clone())?;
+                    for tool_call in tool_calls {
+                        content.push(ContentBlock::ToolUse {
+                            id: tool_call.id,
+                            name: tool_call.function.name,
+                            input: serde_json::from_str(&tool_call.function.arguments)?,
+                        });
+                    }
+                }
+                Ok(Self::new("assistant", content))
+            }
+            // Anthropic has no tool role, tool outputs are sent back as `tool_result` blocks
+            // inside a user turn.
+            MessageType::ToolMessage => Ok(Self::new(
+                "user",
+                vec![ContentBlock::ToolResult {
+                    tool_use_id: message.id.clone().unwrap_or_default(),
+                    content: message.content.clone(),
+                }],
+            )),
+        }
+    }
+
+    pub fn is_tool_result(&self) -> bool {
+        !self.content.is_empty()
+            && self
+                .content
+                .iter()
+                .all(|c| matches!(c, ContentBlock::ToolResult { .. }))
+    }
+}
+
+/// The `cache_control` of a content block, which ends a cached prefix.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ClaudeCacheControl {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+}
+
+impl From<CacheControl> for ClaudeCacheControl {
+    fn from(cache_control: CacheControl) -> Self {
+        Self {
+            kind: "ephemeral".to_string(),
+            // Five minutes is the default
+            ttl: match cache_control.ttl {
+                CacheTtl::FiveMinutes => None,
+                CacheTtl::OneHour => Some("1h".to_string()),
+            },
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// The system prompt, as plain text or as text blocks when it is cached.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum SystemPrompt {
+    Text(String),
+    Blocks(Vec<SystemBlock>),
+}
+
+impl SystemPrompt {
+    pub fn from_message(message: &Message) -> Self {
+        match message.cache_control {
+            Some(cache_control) => SystemPrompt::Blocks(vec![SystemBlock {
+                kind: "text".to_string(),
+                text: message.content.clone(),
+                cache_control: Some(cache_control.into()),
+            }]),
+            None => SystemPrompt::Text(message.content.clone()),
+        }
+    }
+
+    pub fn is_cached(&self) -> bool {
+        matches!(self, SystemPrompt::Blocks(blocks) if blocks.iter().any(|block| block.cache_control.is_some()))
+    }
+
+    pub fn uncache(&mut self) {
+        if let SystemPrompt::Blocks(blocks) = self {
+            blocks
+                .iter_mut()
+                .for_each(|block| block.cache_control = None);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct SystemBlock {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<ClaudeCacheControl>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ClaudeTool {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Value,
+}
+
+impl From<&FunctionDefinition> for ClaudeTool {
+    fn from(function: &FunctionDefinition) -> Self {
+        Self {
+            name: function.name.clone(),
+            description: function.description.clone(),
+            input_schema: function.parameters.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ToolChoice {
+    Auto,
+    Any,
+    Tool { name: String },
+    None,
+}
+
+impl From<&FunctionCallBehavior> for ToolChoice {
+    fn from(behavior: &FunctionCallBehavior) -> Self {
+        match behavior {
+            FunctionCallBehavior::Auto => ToolChoice::Auto,
+            FunctionCallBehavior::None => ToolChoice::None,
+            FunctionCallBehavior::Named(name) => ToolChoice::Tool { name: name.clone() },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Thinking {
+    Enabled { budget_tokens: u32 },
+}
+
+#[derive(Serialize)]
 pub(crate) struct Payload {
     pub model: String,
     pub messages: Vec<ClaudeMessage>,
     pub max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub system: Option<String>,
+    pub system: Option<SystemPrompt>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -42,11 +357,17 @@ pub(crate) struct Payload {
     pub top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_k: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<ClaudeTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<Thinking>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub(crate) struct ApiResponse {
-    pub content: Vec<Content>,
+    pub content: Vec<ContentBlock>,
     pub id: String,
     pub model: String,
     pub role: String,
@@ -57,15 +378,91 @@ pub(crate) struct ApiResponse {
     pub usage: Usage,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct Content {
-    pub text: String,
-    #[serde(rename = "type")]
-    pub content_type: String,
+impl ApiResponse {
+    /// Returns the tool calls requested by the model in the same shape the OpenAI
+    /// client returns them, so agents can treat both providers alike.
+    pub fn tool_calls(&self) -> Vec<FunctionCallResponse> {
+        self.content
+            .iter()
+            .filter_map(|c| match c {
+                ContentBlock::ToolUse { id, name, input } => Some(FunctionCallResponse {
+                    id: id.clone(),
+                    type_field: "function".to_string(),
+                    function: FunctionDetail {
+                        name: name.clone(),
+                        arguments: input.to_string(),
+                    },
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The thinking of the model, `None` when thinking is off.
+    pub fn reasoning(&self) -> Option<String> {
+        let thinking = self
+            .content
+            .iter()
+            .filter_map(|c| match c {
+                ContentBlock::Thinking { thinking, .. } => Some(thinking.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (!thinking.is_empty()).then(|| thinking.join(""))
+    }
+
+    /// The thinking blocks of the response, as they must be sent back.
+    pub fn reasoning_blocks(&self) -> Vec<ReasoningBlock> {
+        self.content
+            .iter()
+            .filter_map(|c| match c {
+                ContentBlock::Thinking {
+                    thinking,
+                    signature,
+                } => Some(ReasoningBlock::Signed {
+                    content: thinking.clone(),
+                    signature: signature.clone(),
+                }),
+                ContentBlock::RedactedThinking { data } => {
+                    Some(ReasoningBlock::Redacted { data: data.clone() })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|c| match c {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("")
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub(crate) struct Usage {
     pub input_tokens: u32,
     pub output_tokens: u32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u32,
+    #[serde(default)]
+    pub cache_read_input_tokens: u32,
+}
+
+impl From<&Usage> for TokenUsage {
+    /// Anthropic leaves the cached tokens out of `input_tokens`.
+    fn from(usage: &Usage) -> Self {
+        TokenUsage::new(
+            usage.input_tokens + usage.cache_creation_input_tokens + usage.cache_read_input_tokens,
+            usage.output_tokens,
+        )
+        .with_cache_tokens(
+            usage.cache_read_input_tokens,
+            usage.cache_creation_input_tokens,
+        )
+    }
 }
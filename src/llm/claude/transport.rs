@@ -0,0 +1,211 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use futures::Stream;
+use reqwest::{Client, Request, Response};
+use serde_json::Value;
+
+use crate::{language_models::LLMError, llm::sse};
+
+/// How requests reach Claude: the endpoint, the authentication and the envelope around the
+/// [Messages API](https://docs.anthropic.com/en/api/messages) request body.
+///
+/// [`Claude`](super::Claude) talks to the Anthropic API by default, see `BedrockTransport`
+/// (with the `bedrock` feature) and [`VertexTransport`] for the cloud providers hosting Claude. Implement it to reach Claude
+/// through anything else, such as a gateway with its own authentication.
+#[async_trait]
+pub trait ClaudeTransport: Send + Sync {
+    /// Builds the request sending `payload`, a Messages API request body including the
+    /// `model` and, for streamed requests, `"stream": true`.
+    async fn build_request(&self, client: &Client, payload: Value) -> Result<Request, LLMError>;
+
+    /// Splits the body of a streamed response into its JSON events. Defaults to the
+    /// server-sent events the Anthropic API sends.
+    fn decode_stream(
+        &self,
+        response: Response,
+    ) -> Pin<Box<dyn Stream<Item = Result<String, LLMError>> + Send>> {
+        Box::pin(sse::data_events(response.bytes_stream()))
+    }
+}
+
+/// The Anthropic API, authenticated with an API key.
+#[derive(Debug, Clone)]
+pub struct AnthropicTransport {
+    base_url: String,
+    api_key: String,
+    anthropic_version: String,
+}
+
+impl AnthropicTransport {
+    /// `base_url` is the API URL without the `/v1/messages` path, such as
+    /// `https://api.anthropic.com` or the URL of a proxy.
+    pub fn new<S: Into<String>>(base_url: S, api_key: S, anthropic_version: S) -> Self {
+        Self {
+            base_url: base_url.into(),
+            api_key: api_key.into(),
+            anthropic_version: anthropic_version.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl ClaudeTransport for AnthropicTransport {
+    async fn build_request(&self, client: &Client, payload: Value) -> Result<Request, LLMError> {
+        Ok(client
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", &self.anthropic_version)
+            .header("content-type", "application/json; charset=utf-8")
+            .json(&payload)
+            .build()?)
+    }
+}
+
+/// Claude on [Google Vertex AI](https://cloud.google.com/vertex-ai/generative-ai/docs/partner-models/use-claude),
+/// authenticated with an OAuth access token, such as the output of
+/// `gcloud auth print-access-token`.
+///
+/// Models are named the Vertex way, for example `claude-3-5-sonnet-v2@20241022`. Access tokens
+/// expire, long running programs should implement [`ClaudeTransport`] over their token source.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let claude = Claude::new()
+///     .with_model("claude-3-5-sonnet-v2@20241022")
+///     .with_transport(VertexTransport::new("my-project", "us-east5", access_token));
+/// ```
+#[derive(Debug, Clone)]
+pub struct VertexTransport {
+    project_id: String,
+    region: String,
+    access_token: String,
+    base_url: Option<String>,
+}
+
+impl VertexTransport {
+    pub const ANTHROPIC_VERSION: &'static str = "vertex-2023-10-16";
+
+    pub fn new<S: Into<String>>(project_id: S, region: S, access_token: S) -> Self {
+        Self {
+            project_id: project_id.into(),
+            region: region.into(),
+            access_token: access_token.into(),
+            base_url: None,
+        }
+    }
+
+    /// Sets the API URL, without the `/v1/projects/...` path. Defaults to the endpoint of the
+    /// region, like `https://us-east5-aiplatform.googleapis.com`.
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    fn base_url(&self) -> String {
+        match (&self.base_url, self.region.as_str()) {
+            (Some(base_url), _) => base_url.clone(),
+            (None, "global") => "https://aiplatform.googleapis.com".to_string(),
+            (None, region) => format!("https://{}-aiplatform.googleapis.com", region),
+        }
+    }
+}
+
+#[async_trait]
+impl ClaudeTransport for VertexTransport {
+    async fn build_request(&self, client: &Client, payload: Value) -> Result<Request, LLMError> {
+        let (model, stream, body) = into_envelope(payload, Self::ANTHROPIC_VERSION);
+        // Vertex still reads `stream` from the body
+        let body = match stream {
+            true => with_field(body, "stream", Value::Bool(true)),
+            false => body,
+        };
+        let method = if stream {
+            "streamRawPredict"
+        } else {
+            "rawPredict"
+        };
+        Ok(client
+            .post(format!(
+                "{}/v1/projects/{}/locations/{}/publishers/anthropic/models/{}:{}",
+                self.base_url(),
+                self.project_id,
+                self.region,
+                model,
+                method
+            ))
+            .bearer_auth(&self.access_token)
+            .json(&body)
+            .build()?)
+    }
+}
+
+/// Cloud providers take the model and the streaming mode from the URL, and the API version
+/// from the body. Returns the model, whether to stream and the body without them.
+pub(crate) fn into_envelope(payload: Value, anthropic_version: &str) -> (String, bool, Value) {
+    let mut payload = payload;
+    let Some(body) = payload.as_object_mut() else {
+        return (String::new(), false, payload);
+    };
+    let model = body
+        .remove("model")
+        .and_then(|model| model.as_str().map(String::from))
+        .unwrap_or_default();
+    let stream = body
+        .remove("stream")
+        .and_then(|stream| stream.as_bool())
+        .unwrap_or_default();
+    body.insert(
+        "anthropic_version".to_string(),
+        Value::String(anthropic_version.to_string()),
+    );
+    (model, stream, payload)
+}
+
+fn with_field(mut body: Value, key: &str, value: Value) -> Value {
+    if let Some(body) = body.as_object_mut() {
+        body.insert(key.to_string(), value);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_vertex_request() {
+        let transport = VertexTransport::new("my-project", "us-east5", "ya29.token");
+        let payload = json!({
+            "model": "claude-3-5-sonnet-v2@20241022",
+            "max_tokens": 10,
+            "stream": true,
+            "messages": []
+        });
+
+        let request = transport
+            .build_request(&Client::new(), payload)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            request.url().as_str(),
+            "https://us-east5-aiplatform.googleapis.com/v1/projects/my-project/locations/us-east5/publishers/anthropic/models/claude-3-5-sonnet-v2@20241022:streamRawPredict"
+        );
+        assert_eq!(request.headers()["authorization"], "Bearer ya29.token");
+        let body: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "anthropic_version": "vertex-2023-10-16",
+                "max_tokens": 10,
+                "stream": true,
+                "messages": []
+            })
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,12 +16,14 @@ pub enum DeepseekError {
     #[error("Deepseek API error: Invalid Parameters - {0}")]
     InvalidParametersError(String),
 
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
     #[error("Deepseek API error: Rate Limit Reached - {0}")]
-    RateLimitError(String),
+    RateLimitError(String, Option<Duration>),
 
     #[error("Deepseek API error: Server Error - {0}")]
     ServerError(String),
 
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
     #[error("Deepseek API error: Server Overloaded - {0}")]
-    ServerOverloadedError(String),
+    ServerOverloadedError(String, Option<Duration>),
 }
@@ -1,5 +1,4 @@
 mod client;
-mod models;
 pub use client::*;
 
 mod error;
@@ -1,112 +0,0 @@
-use crate::schemas::{Message, MessageType};
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub(crate) struct DeepseekMessage {
-    pub role: String,
-    pub content: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub name: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub reasoning_content: Option<String>,
-}
-
-impl DeepseekMessage {
-    pub fn new<S: Into<String>>(role: S, content: S) -> Self {
-        Self {
-            role: role.into(),
-            content: content.into(),
-            name: None,
-            reasoning_content: None,
-        }
-    }
-
-    pub fn from_message(message: &Message) -> Self {
-        match message.message_type {
-            MessageType::SystemMessage => Self::new("system", &message.content),
-            MessageType::AIMessage => Self::new("assistant", &message.content),
-            MessageType::HumanMessage => Self::new("user", &message.content),
-            MessageType::ToolMessage => Self::new("tool", &message.content),
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) struct ResponseFormat {
-    #[serde(rename = "type")]
-    pub format_type: String,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub(crate) struct Payload {
-    pub model: String,
-    pub messages: Vec<DeepseekMessage>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_tokens: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub stream: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub temperature: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub top_p: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub frequency_penalty: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub presence_penalty: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub stop: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub response_format: Option<ResponseFormat>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct Usage {
-    pub prompt_tokens: u32,
-    pub completion_tokens: u32,
-    pub total_tokens: u32,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct Choice {
-    pub message: DeepseekMessage,
-    pub finish_reason: Option<String>,
-    pub index: u32,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct ApiResponse {
-    pub id: String,
-    pub object: String,
-    pub created: u64,
-    pub model: String,
-    pub choices: Vec<Choice>,
-    pub usage: Usage,
-    pub system_fingerprint: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct StreamChoice {
-    pub delta: Delta,
-    pub finish_reason: Option<String>,
-    pub index: u32,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct Delta {
-    #[serde(default)]
-    pub content: String,
-    pub role: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub reasoning_content: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub(crate) struct StreamResponse {
-    pub id: String,
-    pub object: String,
-    pub created: u64,
-    pub model: String,
-    pub choices: Vec<StreamChoice>,
-    pub system_fingerprint: String,
-    pub usage: Option<Usage>,
-}
@@ -0,0 +1,447 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
+use serde_json::Value;
+
+use crate::{
+    language_models::{
+        cancellation::{cancellable, cancellable_stream},
+        llm::LLM,
+        options::CallOptions,
+        FinishReason, GenerateResult, LLMError, TokenUsage,
+    },
+    schemas::{FunctionCallResponse, FunctionDetail, Message, StreamData, ToolCallDelta},
+};
+
+/// A scripted answer of [`FakeLLM`].
+#[derive(Debug, Clone)]
+pub enum FakeResponse {
+    /// Returned as is by `generate`, and streamed as a single chunk after one per block of
+    /// reasoning.
+    Generation(Box<GenerateResult>),
+    /// Streamed chunk by chunk, and merged into one result by `generate`.
+    Chunks(Vec<StreamData>),
+    /// Fails the call with [`LLMError::OtherError`].
+    Error(String),
+}
+
+impl FakeResponse {
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        let generation: String = text.into();
+        FakeResponse::Generation(Box::new(GenerateResult {
+            tokens: Some(TokenUsage::new(0, fake_token_count(&generation))),
+            generation,
+            finish_reason: Some(FinishReason::Stop),
+            model: Some(FakeLLM::MODEL.to_string()),
+            ..Default::default()
+        }))
+    }
+
+    /// A single call of the tool `name` with `arguments` as its JSON input.
+    pub fn tool_call<S: Into<String>>(name: S, arguments: Value) -> Self {
+        Self::tool_calls(vec![(name.into(), arguments)])
+    }
+
+    /// Calls of several tools at once, given as `(name, arguments)`. Call ids are
+    /// `call_0`, `call_1`...
+    pub fn tool_calls<S: Into<String>>(calls: Vec<(S, Value)>) -> Self {
+        let tool_calls = calls
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, arguments))| FunctionCallResponse {
+                id: format!("call_{}", i),
+                type_field: "function".to_string(),
+                function: FunctionDetail {
+                    name: name.into(),
+                    arguments: arguments.to_string(),
+                },
+            })
+            .collect();
+        FakeResponse::Generation(Box::new(GenerateResult {
+            tool_calls,
+            finish_reason: Some(FinishReason::ToolCalls),
+            model: Some(FakeLLM::MODEL.to_string()),
+            ..Default::default()
+        }))
+    }
+
+    /// Text streamed in the given pieces.
+    pub fn chunks<S: Into<String>>(chunks: Vec<S>) -> Self {
+        let mut chunks: Vec<StreamData> = chunks
+            .into_iter()
+            .map(|chunk| StreamData::new(Value::Null, None, chunk))
+            .collect();
+        let completion_tokens = chunks
+            .iter()
+            .map(|chunk| fake_token_count(&chunk.content))
+            .sum();
+        chunks.push(
+            StreamData::new(Value::Null, Some(TokenUsage::new(0, completion_tokens)), "")
+                .with_finish_reason(Some(FinishReason::Stop))
+                .with_model(Some(FakeLLM::MODEL.to_string())),
+        );
+        FakeResponse::Chunks(chunks)
+    }
+
+    pub fn error<S: Into<String>>(message: S) -> Self {
+        FakeResponse::Error(message.into())
+    }
+
+    fn into_result(self) -> Result<GenerateResult, LLMError> {
+        match self {
+            FakeResponse::Generation(result) => Ok(*result),
+            FakeResponse::Chunks(chunks) => {
+                let mut result = GenerateResult::default();
+                for chunk in &chunks {
+                    result.merge_stream_data(chunk);
+                }
+                Ok(result)
+            }
+            FakeResponse::Error(message) => Err(LLMError::OtherError(message)),
+        }
+    }
+
+    fn into_chunks(self) -> Result<Vec<StreamData>, LLMError> {
+        match self {
+            FakeResponse::Generation(result) => {
+                let tool_call_deltas = result
+                    .tool_calls
+                    .iter()
+                    .enumerate()
+                    .map(|(i, tool_call)| ToolCallDelta::from_tool_call(i, tool_call))
+                    .collect();
+                let mut chunks = result.reasoning_chunks();
+                chunks.push(
+                    StreamData::new(Value::Null, result.tokens, result.generation)
+                        .with_tool_call_deltas(tool_call_deltas)
+                        .with_finish_reason(result.finish_reason)
+                        .with_model(result.model),
+                );
+                Ok(chunks)
+            }
+            FakeResponse::Chunks(chunks) => Ok(chunks),
+            FakeResponse::Error(message) => Err(LLMError::OtherError(message)),
+        }
+    }
+}
+
+/// Words as a rough stand-in for tokens.
+fn fake_token_count(text: &str) -> u32 {
+    text.split_whitespace().count() as u32
+}
+
+type Matcher = Arc<dyn Fn(&[Message]) -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct Script {
+    rules: Vec<(Matcher, FakeResponse)>,
+    queue: VecDeque<FakeResponse>,
+    fallback: Option<FakeResponse>,
+    requests: Vec<Vec<Message>>,
+    call_options: Vec<CallOptions>,
+    running: usize,
+    max_running: usize,
+}
+
+/// A deterministic [`LLM`] for tests, answering with scripted responses instead of calling
+/// a model.
+///
+/// Each call is answered by the first rule whose matcher accepts the messages, otherwise by
+/// the next queued response in call order, otherwise by the default response. A call with
+/// nothing left to answer fails with [`LLMError::OtherError`]. Every call is recorded.
+///
+/// Clones share the script and the recorded requests, so a clone can be handed to a chain
+/// or agent while the original is kept to inspect the calls.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let llm = FakeLLM::new()
+///     .with_response_when_contains("weather", FakeResponse::tool_call("forecast", json!({"city": "Lima"})))
+///     .with_response(FakeResponse::text("It is sunny in Lima."));
+/// let agent = OpenAiToolAgentBuilder::new().tools(&[forecast]).build(llm.clone())?;
+/// // ...
+/// assert_eq!(llm.call_count(), 2);
+/// ```
+#[derive(Clone, Default)]
+pub struct FakeLLM {
+    script: Arc<Mutex<Script>>,
+    options: CallOptions,
+    delay: Option<Duration>,
+    context_window: Option<usize>,
+    max_output_tokens: Option<usize>,
+}
+
+impl FakeLLM {
+    /// The model name reported by the scripted results.
+    pub const MODEL: &'static str = "fake";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues responses answered one per call, in order.
+    pub fn with_responses<I: IntoIterator<Item = FakeResponse>>(self, responses: I) -> Self {
+        self.lock().queue.extend(responses);
+        self
+    }
+
+    /// Queues a response answered after the ones already queued.
+    pub fn with_response(self, response: FakeResponse) -> Self {
+        self.with_responses([response])
+    }
+
+    /// Answers every call whose messages satisfy `matcher`, before looking at the queue.
+    pub fn with_response_when<F>(self, matcher: F, response: FakeResponse) -> Self
+    where
+        F: Fn(&[Message]) -> bool + Send + Sync + 'static,
+    {
+        self.lock().rules.push((Arc::new(matcher), response));
+        self
+    }
+
+    /// Answers every call whose last message contains `text`.
+    pub fn with_response_when_contains<S: Into<String>>(
+        self,
+        text: S,
+        response: FakeResponse,
+    ) -> Self {
+        let text = text.into();
+        self.with_response_when(
+            move |messages| {
+                messages
+                    .last()
+                    .is_some_and(|message| message.content.contains(&text))
+            },
+            response,
+        )
+    }
+
+    /// Answers the calls no rule or queued response is left for.
+    pub fn with_default_response(self, response: FakeResponse) -> Self {
+        self.lock().fallback = Some(response);
+        self
+    }
+
+    pub fn with_options(mut self, options: CallOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Makes every call wait for `delay` before answering, to test timeouts and concurrency.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// The limits reported by [`LLM::context_window`] and [`LLM::max_output_tokens`].
+    pub fn with_context_window(mut self, context_window: usize, max_output_tokens: usize) -> Self {
+        self.context_window = Some(context_window);
+        self.max_output_tokens = Some(max_output_tokens);
+        self
+    }
+
+    /// The messages of every call received so far, in order.
+    pub fn requests(&self) -> Vec<Vec<Message>> {
+        self.lock().requests.clone()
+    }
+
+    /// The messages of the latest call.
+    pub fn last_request(&self) -> Option<Vec<Message>> {
+        self.lock().requests.last().cloned()
+    }
+
+    /// The options the latest call was made with, including per-call ones given to
+    /// [`LLM::generate_with_options`].
+    pub fn last_options(&self) -> Option<CallOptions> {
+        self.lock().call_options.last().cloned()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.lock().requests.len()
+    }
+
+    /// The most calls that were waiting on the delay at the same time, see
+    /// [`FakeLLM::with_delay`].
+    pub fn max_concurrent_calls(&self) -> usize {
+        self.lock().max_running
+    }
+
+    /// The options the calls are made with, as set by chains and agents through
+    /// [`LLM::add_options`].
+    pub fn options(&self) -> &CallOptions {
+        &self.options
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Script> {
+        // A test panicking while holding the lock should not hide the script from the others
+        self.script.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn respond(&self, messages: &[Message]) -> Result<FakeResponse, LLMError> {
+        let mut script = self.lock();
+        script.requests.push(messages.to_vec());
+        script.call_options.push(self.options.clone());
+        let rule = script
+            .rules
+            .iter()
+            .find(|(matcher, _)| matcher(messages))
+            .map(|(_, response)| response.clone());
+        rule.or_else(|| script.queue.pop_front())
+            .or_else(|| script.fallback.clone())
+            .ok_or_else(|| {
+                LLMError::OtherError(format!(
+                    "FakeLLM has no response left for call {}",
+                    script.requests.len()
+                ))
+            })
+    }
+
+    async fn wait(&self) {
+        let Some(delay) = self.delay else {
+            return;
+        };
+        let _running = Running::new(self);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Counts a call waiting on the delay until it is done or cancelled.
+struct Running<'a>(&'a FakeLLM);
+
+impl<'a> Running<'a> {
+    fn new(llm: &'a FakeLLM) -> Self {
+        let mut script = llm.lock();
+        script.running += 1;
+        script.max_running = script.max_running.max(script.running);
+        Self(llm)
+    }
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.lock().running -= 1;
+    }
+}
+
+#[async_trait]
+impl LLM for FakeLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let response = self.respond(messages);
+        cancellable(self.options.cancellation_token.as_ref(), async {
+            self.wait().await;
+            let response = response?;
+            if let Some(func) = &self.options.streaming_func {
+                let mut result = GenerateResult::default();
+                for chunk in response.into_chunks()? {
+                    result.merge_stream_data(&chunk);
+                    if !chunk.content.is_empty() {
+                        let mut func = func.lock().await;
+                        let _ = func(chunk.content).await;
+                    }
+                }
+                return Ok(result);
+            }
+            response.into_result()
+        })
+        .await
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let response = self.respond(messages);
+        let token = self.options.cancellation_token.clone();
+        let chunks = cancellable(token.as_ref(), async {
+            self.wait().await;
+            response?.into_chunks()
+        })
+        .await?;
+        Ok(cancellable_stream(
+            Box::pin(stream::iter(chunks).map(Ok)),
+            token,
+        ))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.options.merge_options(options)
+    }
+
+    fn override_options(&mut self, options: CallOptions) {
+        self.options.override_options(options)
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.context_window
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.max_output_tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rules_then_queue_then_default() {
+        let llm = FakeLLM::new()
+            .with_response_when_contains("weather", FakeResponse::text("sunny"))
+            .with_responses([FakeResponse::text("first"), FakeResponse::text("second")])
+            .with_default_response(FakeResponse::error("out of script"));
+        let recorder = llm.clone();
+
+        assert_eq!(llm.invoke("hello").await.unwrap(), "first");
+        assert_eq!(llm.invoke("what is the weather?").await.unwrap(), "sunny");
+        assert_eq!(llm.invoke("hello again").await.unwrap(), "second");
+        assert!(matches!(
+            llm.invoke("and again").await,
+            Err(LLMError::OtherError(e)) if e == "out of script"
+        ));
+
+        assert_eq!(recorder.call_count(), 4);
+        assert_eq!(recorder.requests()[1][0].content, "what is the weather?");
+        assert_eq!(recorder.last_request().unwrap()[0].content, "and again");
+        assert!(FakeLLM::new().invoke("hi").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_and_chunks() {
+        let llm = FakeLLM::new().with_responses([
+            FakeResponse::tool_call("search", json!({"q": "rust"})),
+            FakeResponse::tool_call("search", json!({"q": "rust"})),
+            FakeResponse::chunks(vec!["Hello", " world"]),
+            FakeResponse::chunks(vec!["Hello", " world"]),
+        ]);
+
+        let result = llm.generate(&[]).await.unwrap();
+        assert_eq!(result.tool_calls[0].function.name, "search");
+        assert_eq!(result.tool_calls[0].function.arguments, r#"{"q":"rust"}"#);
+        assert_eq!(result.finish_reason, Some(FinishReason::ToolCalls));
+
+        let chunks: Vec<StreamData> = llm.stream(&[]).await.unwrap().try_collect().await.unwrap();
+        assert_eq!(
+            chunks[0].tool_call_deltas[0].name.as_deref(),
+            Some("search")
+        );
+
+        let chunks: Vec<StreamData> = llm.stream(&[]).await.unwrap().try_collect().await.unwrap();
+        let contents: Vec<&str> = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(contents, vec!["Hello", " world", ""]);
+
+        let result = llm.generate(&[]).await.unwrap();
+        assert_eq!(result.generation, "Hello world");
+        assert_eq!(result.tokens.unwrap().completion_tokens, 2);
+    }
+}
@@ -0,0 +1,410 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::{stream::FuturesUnordered, Stream, StreamExt};
+
+use crate::{
+    language_models::{
+        llm::LLM,
+        options::CallOptions,
+        tokens::{count_message_tokens, Tokenizer},
+        GenerateResult, LLMError,
+    },
+    schemas::{Message, StreamData},
+};
+
+/// How [`FallbackLLM`] spreads a call over its backends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FallbackMode {
+    /// Try the backends one after another, in the order they were added, until one succeeds.
+    #[default]
+    Sequential,
+    /// Send the call to every backend at once and keep the first successful response, the
+    /// remaining requests are dropped. A backend failing with an error its fallback predicate
+    /// rejects ends the race with that error.
+    Race,
+}
+
+/// A generation together with the name of the backend that produced it.
+#[derive(Debug, Clone)]
+pub struct RoutedGeneration {
+    pub backend: String,
+    pub result: GenerateResult,
+}
+
+struct Backend {
+    name: String,
+    llm: Box<dyn LLM>,
+    fallback_if: Arc<dyn Fn(&LLMError) -> bool + Send + Sync>,
+}
+
+impl Clone for Backend {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            llm: self.llm.clone_box(),
+            fallback_if: self.fallback_if.clone(),
+        }
+    }
+}
+
+/// An [`LLM`] that fails over between several backends.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let llm = FallbackLLM::new()
+///     .with_backend("openai", OpenAI::default())
+///     .with_backend_if("deepseek", Deepseek::new(), LLMError::is_retryable)
+///     .with_backend("ollama", Ollama::default())
+///     .with_timeout(Duration::from_secs(30));
+///
+/// let routed = llm.generate_with_backend(&messages).await?;
+/// println!("{} answered: {}", routed.backend, routed.result.generation);
+/// ```
+#[derive(Clone, Default)]
+pub struct FallbackLLM {
+    backends: Vec<Backend>,
+    mode: FallbackMode,
+    timeout: Option<Duration>,
+}
+
+impl FallbackLLM {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a backend that falls through to the next one on any error but cancellation.
+    pub fn with_backend<S: Into<String>, L: Into<Box<dyn LLM>>>(self, name: S, llm: L) -> Self {
+        self.with_backend_if(name, llm, |_| true)
+    }
+
+    /// Adds a backend that only falls through to the next one when `fallback_if` returns true
+    /// for its error, any other error is returned to the caller straight away.
+    pub fn with_backend_if<S, L, F>(mut self, name: S, llm: L, fallback_if: F) -> Self
+    where
+        S: Into<String>,
+        L: Into<Box<dyn LLM>>,
+        F: Fn(&LLMError) -> bool + Send + Sync + 'static,
+    {
+        self.backends.push(Backend {
+            name: name.into(),
+            llm: llm.into(),
+            fallback_if: Arc::new(fallback_if),
+        });
+        self
+    }
+
+    pub fn with_mode(mut self, mode: FallbackMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Timeout for each backend. A backend that times out fails with `LLMError::Timeout`,
+    /// which triggers the fallback like any other error.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Generates like [`LLM::generate`] and also reports which backend served the call.
+    pub async fn generate_with_backend(
+        &self,
+        messages: &[Message],
+    ) -> Result<RoutedGeneration, LLMError> {
+        let (backend, result) = self.route(|llm| llm.generate(messages)).await?;
+        Ok(RoutedGeneration { backend, result })
+    }
+
+    async fn route<'a, T, F, Fut>(&'a self, call: F) -> Result<(String, T), LLMError>
+    where
+        F: Fn(&'a dyn LLM) -> Fut,
+        Fut: Future<Output = Result<T, LLMError>> + 'a,
+    {
+        if self.backends.is_empty() {
+            return Err(LLMError::OtherError(
+                "FallbackLLM has no backends configured".to_string(),
+            ));
+        }
+
+        let attempt = |backend: &'a Backend| {
+            let call = call(backend.llm.as_ref());
+            async move {
+                let result = match self.timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, call)
+                        .await
+                        .map_err(LLMError::from)
+                        .and_then(|result| result),
+                    None => call.await,
+                };
+                result.map(|value| (backend.name.clone(), value))
+            }
+        };
+
+        let mut last_error = None;
+        match self.mode {
+            FallbackMode::Sequential => {
+                for backend in &self.backends {
+                    let result = attempt(backend).await;
+                    if let Some(served) = Self::settle(backend, result, &mut last_error)? {
+                        log::debug!("FallbackLLM call served by {}", served.0);
+                        return Ok(served);
+                    }
+                }
+            }
+            FallbackMode::Race => {
+                let mut calls = self
+                    .backends
+                    .iter()
+                    .map(|backend| async move { (backend, attempt(backend).await) })
+                    .collect::<FuturesUnordered<_>>();
+                while let Some((backend, result)) = calls.next().await {
+                    if let Some(served) = Self::settle(backend, result, &mut last_error)? {
+                        log::debug!("FallbackLLM race won by {}", served.0);
+                        return Ok(served);
+                    }
+                }
+            }
+        }
+        Err(last_error.expect("at least one backend was tried"))
+    }
+
+    /// Returns the value of a successful call, keeps an error the backend falls back on in
+    /// `last_error`, and returns any other error.
+    fn settle<T>(
+        backend: &Backend,
+        result: Result<T, LLMError>,
+        last_error: &mut Option<LLMError>,
+    ) -> Result<Option<T>, LLMError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if !error.is_cancelled() && (backend.fallback_if)(&error) => {
+                log::warn!("FallbackLLM backend {} failed: {}", backend.name, error);
+                *last_error = Some(error);
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[async_trait]
+impl LLM for FallbackLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        self.generate_with_backend(messages)
+            .await
+            .map(|routed| routed.result)
+    }
+
+    /// Falls back only while opening the stream, errors after that are passed through.
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let (_, stream) = self.route(|llm| llm.stream(messages)).await?;
+        Ok(stream)
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        for backend in &mut self.backends {
+            backend.llm.add_options(options.clone());
+        }
+    }
+
+    fn override_options(&mut self, options: CallOptions) {
+        for backend in &mut self.backends {
+            backend.llm.override_options(options.clone());
+        }
+    }
+
+    fn messages_to_string(&self, messages: &[Message]) -> String {
+        match self.backends.first() {
+            Some(backend) => backend.llm.messages_to_string(messages),
+            None => messages
+                .iter()
+                .map(|m| format!("{:?}: {}", m.message_type, m.content))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        }
+    }
+
+    /// Counted with the first backend's tokenizer.
+    fn count_tokens(&self, messages: &[Message]) -> usize {
+        match self.backends.first() {
+            Some(backend) => backend.llm.count_tokens(messages),
+            None => count_message_tokens(Tokenizer::Cl100kBase, messages),
+        }
+    }
+
+    /// The smallest known window among the backends, since any of them may serve the call.
+    fn context_window(&self) -> Option<usize> {
+        self.backends
+            .iter()
+            .filter_map(|backend| backend.llm.context_window())
+            .min()
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.backends
+            .iter()
+            .filter_map(|backend| backend.llm.max_output_tokens())
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone)]
+    struct TestLLM {
+        response: Result<&'static str, &'static str>,
+        delay: Duration,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl TestLLM {
+        fn new(response: Result<&'static str, &'static str>) -> Self {
+            Self {
+                response,
+                delay: Duration::ZERO,
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn with_delay(mut self, delay: Duration) -> Self {
+            self.delay = delay;
+            self
+        }
+    }
+
+    #[async_trait]
+    impl LLM for TestLLM {
+        async fn generate(&self, _messages: &[Message]) -> Result<GenerateResult, LLMError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            match self.response {
+                Ok(generation) => Ok(GenerateResult {
+                    generation: generation.to_string(),
+                    ..Default::default()
+                }),
+                Err(error) => Err(LLMError::OtherError(error.to_string())),
+            }
+        }
+
+        async fn stream(
+            &self,
+            _messages: &[Message],
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError>
+        {
+            let result = self.generate(&[]).await?;
+            Ok(Box::pin(stream::iter(vec![Ok(StreamData::new(
+                serde_json::Value::Null,
+                None,
+                result.generation,
+            ))])))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_in_order() {
+        let second = TestLLM::new(Ok("from second"));
+        let third = TestLLM::new(Ok("from third"));
+        let llm = FallbackLLM::new()
+            .with_backend("first", TestLLM::new(Err("down")))
+            .with_backend("second", second.clone())
+            .with_backend("third", third.clone());
+
+        let routed = llm.generate_with_backend(&[]).await.unwrap();
+
+        assert_eq!(routed.backend, "second");
+        assert_eq!(routed.result.generation, "from second");
+        assert_eq!(third.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_predicate_stops_fallback() {
+        let second = TestLLM::new(Ok("from second"));
+        let llm = FallbackLLM::new()
+            .with_backend_if("first", TestLLM::new(Err("bad request")), |_| false)
+            .with_backend("second", second.clone());
+
+        let result = llm.invoke("hi").await;
+
+        assert!(matches!(result, Err(LLMError::OtherError(e)) if e == "bad request"));
+        assert_eq!(second.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_falls_back() {
+        let llm = FallbackLLM::new()
+            .with_backend(
+                "slow",
+                TestLLM::new(Ok("slow")).with_delay(Duration::from_secs(5)),
+            )
+            .with_backend("fast", TestLLM::new(Ok("fast")))
+            .with_timeout(Duration::from_millis(20));
+
+        let routed = llm.generate_with_backend(&[]).await.unwrap();
+
+        assert_eq!(routed.backend, "fast");
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_error_when_all_fail() {
+        let llm = FallbackLLM::new()
+            .with_backend("first", TestLLM::new(Err("first down")))
+            .with_backend("second", TestLLM::new(Err("second down")));
+
+        let result = llm.invoke("hi").await;
+
+        assert!(matches!(result, Err(LLMError::OtherError(e)) if e == "second down"));
+    }
+
+    #[tokio::test]
+    async fn test_race_takes_first_success() {
+        let llm = FallbackLLM::new()
+            .with_backend(
+                "slow",
+                TestLLM::new(Ok("slow")).with_delay(Duration::from_millis(200)),
+            )
+            .with_backend("failing", TestLLM::new(Err("down")))
+            .with_backend(
+                "fast",
+                TestLLM::new(Ok("fast")).with_delay(Duration::from_millis(10)),
+            )
+            .with_mode(FallbackMode::Race);
+
+        let routed = llm.generate_with_backend(&[]).await.unwrap();
+
+        assert_eq!(routed.backend, "fast");
+        assert_eq!(routed.result.generation, "fast");
+    }
+
+    #[tokio::test]
+    async fn test_race_applies_the_predicates() {
+        let llm = FallbackLLM::new()
+            .with_backend(
+                "slow",
+                TestLLM::new(Ok("slow")).with_delay(Duration::from_millis(200)),
+            )
+            .with_backend_if("invalid", TestLLM::new(Err("bad request")), |_| false)
+            .with_mode(FallbackMode::Race);
+
+        let result = llm.invoke("hi").await;
+
+        assert!(matches!(result, Err(LLMError::OtherError(e)) if e == "bad request"));
+
+        let llm = FallbackLLM::new()
+            .with_backend(
+                "slow",
+                TestLLM::new(Ok("slow")).with_delay(Duration::from_millis(50)),
+            )
+            .with_backend("down", TestLLM::new(Err("down")))
+            .with_mode(FallbackMode::Race);
+
+        assert_eq!(llm.invoke("hi").await.unwrap(), "slow");
+    }
+}
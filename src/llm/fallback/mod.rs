@@ -0,0 +1,2 @@
+mod fallback_llm;
+pub use fallback_llm::*;
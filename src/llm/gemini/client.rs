@@ -0,0 +1,559 @@
+use crate::{
+    language_models::{
+        cancellation::{cancellable, cancellable_stream},
+        llm::LLM,
+        options::CallOptions,
+        tokens::context_window_for_model,
+        GenerateResult, LLMError,
+    },
+    llm::{sse, GeminiError},
+    schemas::{
+        FunctionCallResponse, Message, MessageType, ResponseFormat, StreamData, ToolCallDelta,
+    },
+};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, pin::Pin};
+
+use super::models::{
+    ApiResponse, GeminiContent, GeminiPart, GeminiTool, GenerationConfig, Payload, ThinkingConfig,
+    ToolConfig,
+};
+
+const GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+pub enum GeminiModel {
+    Gemini15Flash,
+    Gemini15Pro,
+    Gemini20Flash,
+    Gemini25Flash,
+    Gemini25Pro,
+}
+
+impl fmt::Display for GeminiModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let model = match self {
+            GeminiModel::Gemini15Flash => "gemini-1.5-flash",
+            GeminiModel::Gemini15Pro => "gemini-1.5-pro",
+            GeminiModel::Gemini20Flash => "gemini-2.0-flash",
+            GeminiModel::Gemini25Flash => "gemini-2.5-flash",
+            GeminiModel::Gemini25Pro => "gemini-2.5-pro",
+        };
+        write!(f, "{}", model)
+    }
+}
+
+/// The base URL of Google's models on Vertex AI, for use with [`Gemini::with_base_url`].
+pub fn vertex_base_url(project: &str, location: &str) -> String {
+    format!(
+        "https://{location}-aiplatform.googleapis.com/v1/projects/{project}/locations/{location}/publishers/google"
+    )
+}
+
+/// Client for Google's Gemini models, through the Gemini API or Vertex AI.
+///
+/// The Gemini API authenticates with an API key, read from `GEMINI_API_KEY` or
+/// `GOOGLE_API_KEY` by default. Vertex AI takes an OAuth access token instead:
+///
+/// ```rust,ignore
+/// let gemini = Gemini::new()
+///     .with_vertex("my-project", "us-central1")
+///     .with_access_token(token);
+/// ```
+#[derive(Clone)]
+pub struct Gemini {
+    model: String,
+    options: CallOptions,
+    api_key: String,
+    access_token: Option<String>,
+    base_url: String,
+}
+
+impl Default for Gemini {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gemini {
+    pub fn new() -> Self {
+        Self {
+            model: GeminiModel::Gemini20Flash.to_string(),
+            options: CallOptions::default(),
+            api_key: std::env::var("GEMINI_API_KEY")
+                .or_else(|_| std::env::var("GOOGLE_API_KEY"))
+                .unwrap_or_default(),
+            access_token: None,
+            base_url: GEMINI_BASE_URL.to_string(),
+        }
+    }
+
+    pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn with_options(mut self, options: CallOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = api_key.into();
+        self
+    }
+
+    /// OAuth access token sent as a bearer token instead of the API key, as Vertex AI requires.
+    pub fn with_access_token<S: Into<String>>(mut self, access_token: S) -> Self {
+        self.access_token = Some(access_token.into());
+        self
+    }
+
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Targets the Gemini models of a Vertex AI project.
+    pub fn with_vertex(self, project: &str, location: &str) -> Self {
+        self.with_base_url(vertex_base_url(project, location))
+    }
+
+    fn request(&self, client: &Client, method: &str) -> RequestBuilder {
+        let request = client
+            .post(format!(
+                "{}/models/{}:{}",
+                self.base_url, self.model, method
+            ))
+            .header("Content-Type", "application/json");
+        match &self.access_token {
+            Some(access_token) => request.bearer_auth(access_token),
+            None => request.header("x-goog-api-key", &self.api_key),
+        }
+    }
+
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let client = Client::new();
+        let payload = self.build_payload(messages)?;
+        let res = self
+            .request(&client, "generateContent")
+            .json(&payload)
+            .send()
+            .await?;
+        let res = check_status(res).await?.json::<Value>().await?;
+        let api_response: ApiResponse = serde_json::from_value(res.clone())?;
+
+        Ok(GenerateResult {
+            tokens: api_response.usage(),
+            generation: api_response.text(),
+            tool_calls: api_response.tool_calls(),
+            finish_reason: api_response.finish_reason(),
+            model: api_response
+                .model_version
+                .clone()
+                .or_else(|| Some(self.model.clone())),
+            reasoning_content: api_response.reasoning(),
+            raw_response: Some(res),
+            ..Default::default()
+        })
+    }
+
+    fn build_payload(&self, messages: &[Message]) -> Result<Payload, LLMError> {
+        let (system_messages, other_messages): (Vec<_>, Vec<_>) = messages
+            .iter()
+            .partition(|m| m.message_type == MessageType::SystemMessage);
+
+        let tool_names = tool_names(&other_messages);
+        let mut contents: Vec<GeminiContent> = Vec::new();
+        for message in other_messages {
+            let content = GeminiContent::from_message(message, &tool_names)?;
+            // Responses to parallel function calls go back together in a single turn
+            match contents.last_mut() {
+                Some(last) if last.is_function_response() && content.is_function_response() => {
+                    last.parts.extend(content.parts)
+                }
+                _ => contents.push(content),
+            }
+        }
+
+        let system_instruction = (!system_messages.is_empty()).then(|| GeminiContent {
+            role: None,
+            parts: system_messages
+                .iter()
+                .map(|m| GeminiPart::text(&m.content))
+                .collect(),
+        });
+
+        let (response_mime_type, response_json_schema) = match &self.options.response_format {
+            Some(ResponseFormat::JsonObject) => (Some("application/json".to_string()), None),
+            Some(ResponseFormat::JsonSchema { schema, .. }) => {
+                (Some("application/json".to_string()), schema.clone())
+            }
+            _ => (None, None),
+        };
+
+        Ok(Payload {
+            contents,
+            system_instruction,
+            tools: self.options.functions.as_ref().map(|functions| {
+                vec![GeminiTool {
+                    function_declarations: functions.iter().map(Into::into).collect(),
+                }]
+            }),
+            tool_config: self
+                .options
+                .function_call_behavior
+                .as_ref()
+                .map(ToolConfig::from),
+            generation_config: Some(GenerationConfig {
+                temperature: self.options.temperature,
+                top_p: self.options.top_p,
+                top_k: self.options.top_k,
+                max_output_tokens: self.options.max_tokens,
+                stop_sequences: self.options.stop_words.clone(),
+                candidate_count: self.options.candidate_count,
+                seed: self.options.seed,
+                presence_penalty: self.options.presence_penalty,
+                frequency_penalty: self.options.frequency_penalty,
+                response_mime_type,
+                response_json_schema,
+                thinking_config: self
+                    .options
+                    .reasoning_budget_tokens()
+                    .map(ThinkingConfig::new),
+            }),
+        })
+    }
+}
+
+#[async_trait]
+impl LLM for Gemini {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        match &self.options.streaming_func {
+            Some(func) => {
+                let mut generate_result = GenerateResult::default();
+                let mut stream = self.stream(messages).await?;
+                while let Some(data) = stream.next().await {
+                    match data {
+                        Ok(value) => {
+                            generate_result.merge_stream_data(&value);
+                            if !value.content.is_empty() {
+                                let mut func = func.lock().await;
+                                let _ = func(value.content).await;
+                            }
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(generate_result)
+            }
+            None => {
+                cancellable(
+                    self.options.cancellation_token.as_ref(),
+                    self.generate(messages),
+                )
+                .await
+            }
+        }
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let client = Client::new();
+        let payload = self.build_payload(messages)?;
+        let request = self
+            .request(&client, "streamGenerateContent?alt=sse")
+            .json(&payload);
+
+        let token = self.options.cancellation_token.clone();
+        let res = cancellable(token.as_ref(), async { Ok(request.send().await?) }).await?;
+        let events = sse::data_events(check_status(res).await?.bytes_stream());
+        // Function calls arrive whole, numbered across the chunks of the stream
+        let mut tool_call_count = 0;
+        let processed_stream = events
+            .map(move |data| data.and_then(|data| parse_stream_chunk(&data, &mut tool_call_count)));
+
+        Ok(cancellable_stream(Box::pin(processed_stream), token))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.options.merge_options(options)
+    }
+
+    fn override_options(&mut self, options: CallOptions) {
+        self.options.override_options(options)
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        context_window_for_model(&self.model)
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.options
+            .max_tokens
+            .map(|max_tokens| max_tokens as usize)
+    }
+}
+
+/// Maps the function name of every tool call in `messages` by its id.
+fn tool_names(messages: &[&Message]) -> HashMap<String, String> {
+    messages
+        .iter()
+        .filter_map(|m| m.tool_calls.clone())
+        .filter_map(|tool_calls| {
+            serde_json::from_value::<Vec<FunctionCallResponse>>(tool_calls).ok()
+        })
+        .flatten()
+        .map(|tool_call| (tool_call.id, tool_call.function.name))
+        .collect()
+}
+
+async fn check_status(res: Response) -> Result<Response, LLMError> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let headers = res.headers().clone();
+    let body = res.json::<Value>().await.unwrap_or_default();
+    Err(LLMError::from(GeminiError::from_status(status.as_u16(), &body)).with_retry_after(&headers))
+}
+
+fn parse_stream_chunk(data: &str, tool_call_count: &mut usize) -> Result<StreamData, LLMError> {
+    let value: Value = serde_json::from_str(data)?;
+    if let Some(code) = value["error"]["code"].as_u64() {
+        return Err(GeminiError::from_status(code as u16, &value).into());
+    }
+    let response: ApiResponse = serde_json::from_value(value.clone())?;
+    let tool_call_deltas = response
+        .tool_calls()
+        .iter()
+        .map(|tool_call| {
+            *tool_call_count += 1;
+            ToolCallDelta::from_tool_call(*tool_call_count - 1, tool_call)
+        })
+        .collect();
+    Ok(StreamData::new(value, response.usage(), response.text())
+        .with_model(response.model_version.clone())
+        .with_finish_reason(response.finish_reason())
+        .with_reasoning_content(response.reasoning())
+        .with_tool_call_deltas(tool_call_deltas))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        language_models::{options::ReasoningEffort, FinishReason, TokenUsage},
+        schemas::{ContentPart, FunctionCallBehavior, FunctionDefinition},
+    };
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn weather_function() -> FunctionDefinition {
+        FunctionDefinition::new(
+            "get_weather",
+            "Get the weather for a city",
+            json!({
+                "type": "object",
+                "properties": { "city": { "type": "string" } },
+                "required": ["city"]
+            }),
+        )
+    }
+
+    #[test]
+    fn test_build_payload() {
+        let gemini = Gemini::new().with_options(
+            CallOptions::new()
+                .with_max_tokens(256)
+                .with_functions(vec![weather_function()])
+                .with_function_call_behavior(FunctionCallBehavior::Named(
+                    "get_weather".to_string(),
+                )),
+        );
+        let tool_calls = json!([{
+            "id": "call_1",
+            "type": "function",
+            "function": { "name": "get_weather", "arguments": "{\"city\":\"Lima\"}" }
+        }]);
+        let messages = vec![
+            Message::new_system_message("You are a weather bot"),
+            Message::new_human_message_with_parts(vec![
+                ContentPart::text("Where is this?"),
+                ContentPart::image_url("data:image/png;base64,iVBORw=="),
+                ContentPart::image_url("gs://bucket/photo.webp"),
+            ]),
+            Message::new_ai_message("").with_tool_calls(tool_calls),
+            Message::new_tool_message("Sunny", "call_1"),
+        ];
+
+        let payload = serde_json::to_value(gemini.build_payload(&messages).unwrap()).unwrap();
+
+        assert_eq!(
+            payload["systemInstruction"],
+            json!({ "parts": [{ "text": "You are a weather bot" }] })
+        );
+        assert_eq!(
+            payload["contents"][0],
+            json!({
+                "role": "user",
+                "parts": [
+                    { "text": "Where is this?" },
+                    { "inlineData": { "mimeType": "image/png", "data": "iVBORw==" } },
+                    { "fileData": { "mimeType": "image/webp", "fileUri": "gs://bucket/photo.webp" } }
+                ]
+            })
+        );
+        assert_eq!(
+            payload["contents"][1]["parts"][0]["functionCall"],
+            json!({ "name": "get_weather", "args": { "city": "Lima" } })
+        );
+        assert_eq!(
+            payload["contents"][2]["parts"][0]["functionResponse"],
+            json!({ "name": "get_weather", "response": { "content": "Sunny" } })
+        );
+        assert_eq!(
+            payload["tools"][0]["functionDeclarations"][0]["name"],
+            "get_weather"
+        );
+        assert_eq!(
+            payload["toolConfig"],
+            json!({ "functionCallingConfig": { "mode": "ANY", "allowedFunctionNames": ["get_weather"] } })
+        );
+        assert_eq!(
+            payload["generationConfig"],
+            json!({ "maxOutputTokens": 256 })
+        );
+
+        let thinking = Gemini::new()
+            .with_options(CallOptions::new().with_reasoning_effort(ReasoningEffort::Low));
+        let payload = serde_json::to_value(thinking.build_payload(&messages).unwrap()).unwrap();
+        assert_eq!(
+            payload["generationConfig"]["thinkingConfig"],
+            json!({ "thinkingBudget": 1024, "includeThoughts": true })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/models/gemini-2.0-flash:generateContent")
+            .match_header("x-goog-api-key", "test-key")
+            .match_body(Matcher::PartialJson(json!({
+                "systemInstruction": { "parts": [{ "text": "Be brief" }] },
+                "contents": [{ "role": "user", "parts": [{ "text": "Weather in Lima?" }] }],
+                "tools": [{ "functionDeclarations": [{ "name": "get_weather" }] }]
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(include_str!("../test_data/gemini_generate.json"))
+            .create_async()
+            .await;
+
+        let gemini = Gemini::new()
+            .with_api_key("test-key")
+            .with_base_url(server.url())
+            .with_options(CallOptions::new().with_functions(vec![weather_function()]));
+        let result = gemini
+            .generate(&[
+                Message::new_system_message("Be brief"),
+                Message::new_human_message("Weather in Lima?"),
+            ])
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(result.generation, "Let me check.");
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.tool_calls[0].function.name, "get_weather");
+        assert_eq!(
+            result.tool_calls[0].function.arguments,
+            r#"{"city":"Lima"}"#
+        );
+        assert_eq!(result.finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(result.model.as_deref(), Some("gemini-2.0-flash-001"));
+        let tokens = result.tokens.unwrap();
+        assert_eq!(
+            (
+                tokens.prompt_tokens,
+                tokens.completion_tokens,
+                tokens.total_tokens
+            ),
+            (12, 8, 20)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock(
+                "POST",
+                "/models/gemini-2.0-flash:streamGenerateContent?alt=sse",
+            )
+            .match_header("authorization", "Bearer vertex-token")
+            .with_header("content-type", "text/event-stream")
+            .with_body(include_str!("../test_data/gemini_stream.txt"))
+            .create_async()
+            .await;
+
+        let gemini = Gemini::new()
+            .with_access_token("vertex-token")
+            .with_base_url(server.url());
+        let mut stream = gemini
+            .stream(&[Message::new_human_message("Hi")])
+            .await
+            .unwrap();
+
+        let mut content = String::new();
+        let mut tokens = None;
+        let mut finish_reason = None;
+        while let Some(data) = stream.next().await {
+            let data = data.unwrap();
+            content.push_str(&data.content);
+            tokens = data.tokens.or(tokens);
+            finish_reason = data.finish_reason.or(finish_reason);
+        }
+
+        assert_eq!(content, "Hello there! How can I help?");
+        assert_eq!(finish_reason, Some(FinishReason::Stop));
+        let tokens: TokenUsage = tokens.unwrap();
+        assert_eq!(tokens.total_tokens, 11);
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/models/gemini-2.0-flash:generateContent")
+            .with_status(429)
+            .with_header("retry-after", "2")
+            .with_body(
+                json!({
+                    "error": {
+                        "code": 429,
+                        "message": "Quota exceeded",
+                        "status": "RESOURCE_EXHAUSTED"
+                    }
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let gemini = Gemini::new().with_base_url(server.url());
+        let error = gemini.invoke("Hi").await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            LLMError::GeminiError(GeminiError::ResourceExhaustedError(..))
+        ));
+        assert!(error.is_retryable());
+        assert_eq!(error.retry_after(), Some(std::time::Duration::from_secs(2)));
+        assert!(error.to_string().contains("Quota exceeded"));
+    }
+}
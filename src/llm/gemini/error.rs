@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GeminiError {
+    #[error("Gemini API error: Invalid argument - {0}")]
+    InvalidArgumentError(String),
+
+    #[error("Gemini API error: Authentication failed - {0}")]
+    AuthenticationError(String),
+
+    #[error("Gemini API error: Permission denied - {0}")]
+    PermissionDeniedError(String),
+
+    #[error("Gemini API error: Not found - {0}")]
+    NotFoundError(String),
+
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
+    #[error("Gemini API error: Resource exhausted - {0}")]
+    ResourceExhaustedError(String, Option<Duration>),
+
+    #[error("Gemini API error: Internal error - {0}")]
+    InternalError(String),
+
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
+    #[error("Gemini API error: Unavailable - {0}")]
+    UnavailableError(String, Option<Duration>),
+
+    #[error("Gemini API error: Deadline exceeded - {0}")]
+    DeadlineExceededError(String),
+
+    #[error("Gemini API error: {0}")]
+    OtherError(String),
+}
+
+impl GeminiError {
+    /// Maps a Google API error body, `{"error": {"code", "message", "status"}}`, to its variant.
+    pub(crate) fn from_status(status: u16, body: &serde_json::Value) -> Self {
+        let message = body["error"]["message"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        match (status, body["error"]["status"].as_str().unwrap_or_default()) {
+            (401, _) | (_, "UNAUTHENTICATED") => GeminiError::AuthenticationError(message),
+            (403, _) | (_, "PERMISSION_DENIED") => GeminiError::PermissionDeniedError(message),
+            (404, _) | (_, "NOT_FOUND") => GeminiError::NotFoundError(message),
+            (429, _) | (_, "RESOURCE_EXHAUSTED") => {
+                GeminiError::ResourceExhaustedError(message, None)
+            }
+            (400, _) | (_, "INVALID_ARGUMENT") | (_, "FAILED_PRECONDITION") => {
+                GeminiError::InvalidArgumentError(message)
+            }
+            (500, _) | (_, "INTERNAL") => GeminiError::InternalError(message),
+            (503, _) | (_, "UNAVAILABLE") => GeminiError::UnavailableError(message, None),
+            (504, _) | (_, "DEADLINE_EXCEEDED") => GeminiError::DeadlineExceededError(message),
+            _ => GeminiError::OtherError(format!("{} {}", status, message)),
+        }
+    }
+}
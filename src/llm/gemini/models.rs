@@ -0,0 +1,426 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    language_models::{FinishReason, LLMError, TokenUsage},
+    schemas::{
+        content::{media_type_from_path, parse_data_url},
+        ContentPart, FunctionCallBehavior, FunctionCallResponse, FunctionDefinition,
+        FunctionDetail, Message, MessageType,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<GeminiPart>,
+}
+
+impl GeminiContent {
+    pub fn new<S: Into<String>>(role: S, parts: Vec<GeminiPart>) -> Self {
+        Self {
+            role: Some(role.into()),
+            parts,
+        }
+    }
+
+    pub fn is_function_response(&self) -> bool {
+        !self.parts.is_empty()
+            && self
+                .parts
+                .iter()
+                .all(|part| part.function_response.is_some())
+    }
+
+    /// Converts a message. `tool_names` maps tool call ids to function names, since Gemini
+    /// matches function responses to calls by name.
+    pub fn from_message(
+        message: &Message,
+        tool_names: &HashMap<String, String>,
+    ) -> Result<Self, LLMError> {
+        match message.message_type {
+            MessageType::SystemMessage => {
+                Ok(Self::new("user", vec![GeminiPart::text(&message.content)]))
+            }
+            MessageType::HumanMessage => Ok(Self::new(
+                "user",
+                message
+                    .parts()
+                    .into_iter()
+                    .map(GeminiPart::try_from)
+                    .collect::<Result<_, _>>()?,
+            )),
+            MessageType::AIMessage => {
+                let mut parts = Vec::new();
+                if !message.content.is_empty() {
+                    parts.push(GeminiPart::text(&message.content));
+                }
+                if let Some(tool_calls) = &message.tool_calls {
+                    let tool_calls: Vec<FunctionCallResponse> =
+                        serde_json::from_value(tool_calls.clone())?;
+                    for tool_call in tool_calls {
+                        parts.push(GeminiPart {
+                            function_call: Some(FunctionCall {
+                                name: tool_call.function.name,
+                                args: serde_json::from_str(&tool_call.function.arguments)?,
+                            }),
+                            ..Default::default()
+                        });
+                    }
+                }
+                Ok(Self::new("model", parts))
+            }
+            MessageType::ToolMessage => {
+                let id = message.id.clone().unwrap_or_default();
+                let name = tool_names.get(&id).cloned().unwrap_or(id);
+                // The response must be a JSON object, anything else is wrapped
+                let response = match serde_json::from_str::<Value>(&message.content) {
+                    Ok(value @ Value::Object(_)) => value,
+                    Ok(value) => serde_json::json!({ "content": value }),
+                    Err(_) => serde_json::json!({ "content": message.content }),
+                };
+                Ok(Self::new(
+                    "user",
+                    vec![GeminiPart {
+                        function_response: Some(FunctionResponse { name, response }),
+                        ..Default::default()
+                    }],
+                ))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiPart {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<Blob>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_data: Option<FileData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_response: Option<FunctionResponse>,
+    /// Set on the parts that hold the model's thoughts rather than its answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thought: Option<bool>,
+}
+
+impl GeminiPart {
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        Self {
+            text: Some(text.into()),
+            ..Default::default()
+        }
+    }
+
+    fn inline_data(mime_type: String, data: String) -> Self {
+        Self {
+            inline_data: Some(Blob { mime_type, data }),
+            ..Default::default()
+        }
+    }
+
+    fn is_thought(&self) -> bool {
+        self.thought.unwrap_or_default()
+    }
+}
+
+impl TryFrom<ContentPart> for GeminiPart {
+    type Error = LLMError;
+
+    fn try_from(part: ContentPart) -> Result<Self, Self::Error> {
+        match part {
+            ContentPart::Text { text, .. } => Ok(GeminiPart::text(text)),
+            ContentPart::ImageUrl { url, .. } => match parse_data_url(&url) {
+                Some((media_type, data)) => Ok(GeminiPart::inline_data(
+                    media_type.to_string(),
+                    data.to_string(),
+                )),
+                None => {
+                    let mime_type = match media_type_from_path(Path::new(&url)) {
+                        "application/octet-stream" => "image/jpeg",
+                        media_type => media_type,
+                    };
+                    Ok(GeminiPart {
+                        file_data: Some(FileData {
+                            mime_type: mime_type.to_string(),
+                            file_uri: url,
+                        }),
+                        ..Default::default()
+                    })
+                }
+            },
+            ContentPart::ImageBase64 {
+                media_type, data, ..
+            }
+            | ContentPart::Audio {
+                media_type, data, ..
+            }
+            | ContentPart::File {
+                media_type, data, ..
+            } => Ok(GeminiPart::inline_data(media_type, data)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Blob {
+    pub mime_type: String,
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileData {
+    pub mime_type: String,
+    pub file_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct FunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub args: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct FunctionResponse {
+    pub name: String,
+    pub response: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GeminiTool {
+    pub function_declarations: Vec<FunctionDeclaration>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct FunctionDeclaration {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+impl From<&FunctionDefinition> for FunctionDeclaration {
+    fn from(function: &FunctionDefinition) -> Self {
+        Self {
+            name: function.name.clone(),
+            description: function.description.clone(),
+            parameters: function.parameters.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ToolConfig {
+    pub function_calling_config: FunctionCallingConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FunctionCallingConfig {
+    pub mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_function_names: Option<Vec<String>>,
+}
+
+impl From<&FunctionCallBehavior> for ToolConfig {
+    fn from(behavior: &FunctionCallBehavior) -> Self {
+        let (mode, allowed_function_names) = match behavior {
+            FunctionCallBehavior::Auto => ("AUTO", None),
+            FunctionCallBehavior::None => ("NONE", None),
+            FunctionCallBehavior::Named(name) => ("ANY", Some(vec![name.clone()])),
+        };
+        Self {
+            function_calling_config: FunctionCallingConfig {
+                mode: mode.to_string(),
+                allowed_function_names,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    /// A standard JSON schema, unlike `responseSchema` which takes an OpenAPI subset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_json_schema: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_config: Option<ThinkingConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ThinkingConfig {
+    pub thinking_budget: u32,
+    pub include_thoughts: bool,
+}
+
+impl ThinkingConfig {
+    /// A budget of `0` turns thinking off on the models that allow it.
+    pub fn new(thinking_budget: u32) -> Self {
+        Self {
+            thinking_budget,
+            include_thoughts: thinking_budget > 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Payload {
+    pub contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<GeminiTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ApiResponse {
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    pub prompt_feedback: Option<PromptFeedback>,
+    pub usage_metadata: Option<UsageMetadata>,
+    pub model_version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Candidate {
+    pub content: Option<GeminiContent>,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PromptFeedback {
+    pub block_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UsageMetadata {
+    #[serde(default)]
+    pub prompt_token_count: u32,
+    #[serde(default)]
+    pub candidates_token_count: u32,
+    #[serde(default)]
+    pub total_token_count: u32,
+    #[serde(default)]
+    pub cached_content_token_count: u32,
+}
+
+impl ApiResponse {
+    fn parts(&self) -> impl Iterator<Item = &GeminiPart> {
+        self.candidates
+            .first()
+            .and_then(|candidate| candidate.content.as_ref())
+            .into_iter()
+            .flat_map(|content| content.parts.iter())
+    }
+
+    pub fn text(&self) -> String {
+        self.parts()
+            .filter(|part| !part.is_thought())
+            .filter_map(|part| part.text.as_deref())
+            .collect()
+    }
+
+    pub fn reasoning(&self) -> Option<String> {
+        let reasoning: String = self
+            .parts()
+            .filter(|part| part.is_thought())
+            .filter_map(|part| part.text.as_deref())
+            .collect();
+        (!reasoning.is_empty()).then_some(reasoning)
+    }
+
+    /// Returns the function calls in the same shape the OpenAI client returns them. Gemini
+    /// does not id its calls, so the function name is used as the id.
+    pub fn tool_calls(&self) -> Vec<FunctionCallResponse> {
+        self.parts()
+            .filter_map(|part| part.function_call.as_ref())
+            .map(|call| FunctionCallResponse {
+                id: call.name.clone(),
+                type_field: "function".to_string(),
+                function: FunctionDetail {
+                    name: call.name.clone(),
+                    arguments: call.args.to_string(),
+                },
+            })
+            .collect()
+    }
+
+    pub fn finish_reason(&self) -> Option<FinishReason> {
+        if self
+            .prompt_feedback
+            .as_ref()
+            .is_some_and(|feedback| feedback.block_reason.is_some())
+        {
+            return Some(FinishReason::ContentFilter);
+        }
+        let reason = self.candidates.first()?.finish_reason.as_deref()?;
+        if reason == "STOP" && self.parts().any(|part| part.function_call.is_some()) {
+            return Some(FinishReason::ToolCalls);
+        }
+        Some(match reason {
+            "STOP" => FinishReason::Stop,
+            "MAX_TOKENS" => FinishReason::Length,
+            "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => {
+                FinishReason::ContentFilter
+            }
+            other => FinishReason::Other(other.to_string()),
+        })
+    }
+
+    pub fn usage(&self) -> Option<TokenUsage> {
+        self.usage_metadata.as_ref().map(|usage| {
+            TokenUsage {
+                prompt_tokens: usage.prompt_token_count,
+                completion_tokens: usage.candidates_token_count,
+                total_tokens: usage.total_token_count,
+                ..Default::default()
+            }
+            .with_cache_tokens(usage.cached_content_token_count, 0)
+        })
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    fs::File,
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use candle_core::{quantized::gguf_file, DType, Device, Tensor};
+use candle_transformers::{
+    generation::{LogitsProcessor, Sampling},
+    models::{quantized_llama, quantized_qwen2, quantized_qwen3},
+    utils::apply_repeat_penalty,
+};
+use futures::{Stream, StreamExt};
+use tokenizers::Tokenizer;
+use tokio::sync::mpsc;
+
+use crate::{
+    language_models::{
+        cancellation::{cancellable_stream, CancellationToken},
+        llm::LLM,
+        options::CallOptions,
+        FinishReason, GenerateResult, LLMError, TokenUsage,
+    },
+    schemas::{Message, StreamData},
+};
+
+use super::{ChatTemplate, GgufError};
+
+/// The number of tokens generated when the options do not set `max_tokens`.
+const DEFAULT_MAX_TOKENS: u32 = 512;
+
+/// The number of previous tokens the repetition penalty applies to.
+const REPEAT_LAST_N: usize = 64;
+
+enum Weights {
+    Llama(quantized_llama::ModelWeights),
+    Qwen2(quantized_qwen2::ModelWeights),
+    Qwen3(quantized_qwen3::ModelWeights),
+}
+
+impl Weights {
+    /// The logits of the next token, `position` being the index of the first input token.
+    fn forward(&mut self, input: &[u32], position: usize) -> candle_core::Result<Tensor> {
+        let input = Tensor::new(input, &Device::Cpu)?.unsqueeze(0)?;
+        let logits = match self {
+            Weights::Llama(model) => model.forward(&input, position)?,
+            Weights::Qwen2(model) => model.forward(&input, position)?,
+            Weights::Qwen3(model) => {
+                if position == 0 {
+                    model.clear_kv_cache();
+                }
+                model.forward(&input, position)?
+            }
+        };
+        logits.squeeze(0)?.to_dtype(DType::F32)
+    }
+}
+
+/// A quantized model in a GGUF file, run in process on the CPU. Llama (and Mistral), Qwen 2
+/// and Qwen 3 architectures are supported, which covers tiny models such as SmolLM or
+/// Qwen 2.5 0.5B for tests.
+///
+/// Calls share the model and run one at a time. Tools are not supported, and JSON response
+/// formats are asked for in the prompt.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let llm = Gguf::load("qwen2.5-0.5b-instruct-q4_k_m.gguf", "tokenizer.json")?
+///     .with_options(CallOptions::new().with_max_tokens(256).with_temperature(0.7));
+/// let answer = llm.invoke("Capital of Peru?").await?;
+/// ```
+#[derive(Clone)]
+pub struct Gguf {
+    model: Arc<Mutex<Weights>>,
+    tokenizer: Arc<Tokenizer>,
+    model_name: String,
+    template: ChatTemplate,
+    eos_token_ids: Vec<u32>,
+    context_length: Option<usize>,
+    options: CallOptions,
+}
+
+impl Gguf {
+    /// Loads the model at `model_path` and its `tokenizer.json`. This reads the whole model
+    /// into memory, call it from a blocking context.
+    pub fn load<M: AsRef<Path>, T: AsRef<Path>>(
+        model_path: M,
+        tokenizer_path: T,
+    ) -> Result<Self, GgufError> {
+        let tokenizer = Tokenizer::from_file(tokenizer_path)
+            .map_err(|e| GgufError::TokenizerError(e.to_string()))?;
+
+        let mut file = File::open(model_path.as_ref())?;
+        let content = gguf_file::Content::read(&mut file)?;
+        let metadata = |key: &str| content.metadata.get(key);
+        let architecture = metadata("general.architecture")
+            .and_then(|value| value.to_string().ok())
+            .cloned()
+            .unwrap_or_default();
+        let model_name = metadata("general.name")
+            .and_then(|value| value.to_string().ok())
+            .cloned()
+            .or_else(|| {
+                let stem = model_path.as_ref().file_stem()?;
+                Some(stem.to_string_lossy().to_string())
+            })
+            .unwrap_or_default();
+        let context_length = metadata(&format!("{}.context_length", architecture))
+            .and_then(|value| value.to_u32().ok())
+            .map(|context_length| context_length as usize);
+        let template = metadata("tokenizer.chat_template")
+            .and_then(|value| value.to_string().ok())
+            .and_then(|chat_template| ChatTemplate::detect(chat_template))
+            .unwrap_or(match architecture.as_str() {
+                "llama" if tokenizer.token_to_id("<|eot_id|>").is_some() => ChatTemplate::Llama3,
+                "llama" => ChatTemplate::Mistral,
+                _ => ChatTemplate::ChatML,
+            });
+        let mut eos_token_ids: Vec<u32> = metadata("tokenizer.ggml.eos_token_id")
+            .and_then(|value| value.to_u32().ok())
+            .into_iter()
+            .collect();
+        eos_token_ids.extend(
+            template
+                .stop_tokens()
+                .iter()
+                .filter_map(|token| tokenizer.token_to_id(token)),
+        );
+
+        let device = Device::Cpu;
+        let model = match architecture.as_str() {
+            "llama" => Weights::Llama(quantized_llama::ModelWeights::from_gguf(
+                content, &mut file, &device,
+            )?),
+            "qwen2" => Weights::Qwen2(quantized_qwen2::ModelWeights::from_gguf(
+                content, &mut file, &device,
+            )?),
+            "qwen3" => Weights::Qwen3(quantized_qwen3::ModelWeights::from_gguf(
+                content, &mut file, &device,
+            )?),
+            architecture => {
+                return Err(GgufError::UnsupportedArchitecture(architecture.to_string()))
+            }
+        };
+
+        Ok(Self {
+            model: Arc::new(Mutex::new(model)),
+            tokenizer: Arc::new(tokenizer),
+            model_name,
+            template,
+            eos_token_ids,
+            context_length,
+            options: CallOptions::default(),
+        })
+    }
+
+    pub fn with_options(mut self, options: CallOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Overrides the chat template detected from the model.
+    pub fn with_chat_template(mut self, template: ChatTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    fn prompt(&self, messages: &[Message]) -> String {
+        let messages = match &self.options.response_format {
+            Some(response_format) => response_format.with_instructions(messages),
+            None => messages.to_vec(),
+        };
+        self.template.format(&messages)
+    }
+
+    fn encode(&self, text: &str) -> Result<Vec<u32>, GgufError> {
+        // The templates already hold the special tokens
+        Ok(self
+            .tokenizer
+            .encode(text, false)
+            .map_err(|e| GgufError::TokenizerError(e.to_string()))?
+            .get_ids()
+            .to_vec())
+    }
+
+    fn sampling(&self) -> Sampling {
+        let temperature = self.options.temperature.unwrap_or(0.0) as f64;
+        if temperature <= 0.0 {
+            return Sampling::ArgMax;
+        }
+        match (self.options.top_k, self.options.top_p) {
+            (None, None) => Sampling::All { temperature },
+            (Some(k), None) => Sampling::TopK { k, temperature },
+            (None, Some(p)) => Sampling::TopP {
+                p: p as f64,
+                temperature,
+            },
+            (Some(k), Some(p)) => Sampling::TopKThenTopP {
+                k,
+                p: p as f64,
+                temperature,
+            },
+        }
+    }
+
+    /// Generates the answer to `messages`, passing each new piece of text to `on_text` until
+    /// it returns `false`. Blocks for the whole generation.
+    fn run<F: FnMut(&str) -> bool>(
+        &self,
+        messages: &[Message],
+        cancellation_token: Option<&CancellationToken>,
+        mut on_text: F,
+    ) -> Result<GenerateResult, LLMError> {
+        let prompt = self.encode(&self.prompt(messages))?;
+        let max_tokens = self.options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) as usize;
+        let stop_words = self.options.stop_words.clone().unwrap_or_default();
+        let mut text = TextStream::new(&stop_words);
+        let mut processor = LogitsProcessor::from_sampling(
+            self.options.seed.unwrap_or(299792458) as u64,
+            self.sampling(),
+        );
+
+        let mut model = self
+            .model
+            .lock()
+            .map_err(|e| GgufError::TaskError(e.to_string()))?;
+        let mut logits = model.forward(&prompt, 0).map_err(GgufError::from)?;
+        let mut tokens: Vec<u32> = Vec::new();
+        let mut finish_reason = FinishReason::Length;
+        while tokens.len() < max_tokens
+            && self
+                .context_length
+                .is_none_or(|context_length| prompt.len() + tokens.len() < context_length)
+        {
+            if cancellation_token.is_some_and(CancellationToken::is_cancelled) {
+                let partial = GenerateResult {
+                    generation: text.text().to_string(),
+                    tokens: Some(TokenUsage::new(prompt.len() as u32, tokens.len() as u32)),
+                    ..Default::default()
+                };
+                return Err(LLMError::cancelled(partial));
+            }
+
+            if let Some(penalty) = self.options.repetition_penalty.filter(|p| *p != 1.0) {
+                let start = tokens.len().saturating_sub(REPEAT_LAST_N);
+                logits = apply_repeat_penalty(&logits, penalty, &tokens[start..])
+                    .map_err(GgufError::from)?;
+            }
+            let token = processor.sample(&logits).map_err(GgufError::from)?;
+            if self.eos_token_ids.contains(&token) {
+                finish_reason = FinishReason::Stop;
+                break;
+            }
+            tokens.push(token);
+
+            let decoded = self
+                .tokenizer
+                .decode(&tokens, true)
+                .map_err(|e| GgufError::TokenizerError(e.to_string()))?;
+            let stopped = text.update(decoded);
+            let delta = text.take_ready();
+            if !delta.is_empty() && !on_text(&delta) {
+                break;
+            }
+            if stopped {
+                finish_reason = FinishReason::Stop;
+                break;
+            }
+
+            logits = model
+                .forward(&[token], prompt.len() + tokens.len() - 1)
+                .map_err(GgufError::from)?;
+        }
+        drop(model);
+
+        let rest = text.take_rest();
+        if !rest.is_empty() {
+            on_text(&rest);
+        }
+        let mut generation = text.text().to_string();
+        if let Some(response_format) = &self.options.response_format {
+            generation = response_format.validate(&generation)?;
+        }
+
+        Ok(GenerateResult {
+            generation,
+            tokens: Some(TokenUsage::new(prompt.len() as u32, tokens.len() as u32)),
+            finish_reason: Some(finish_reason),
+            model: Some(self.model_name.clone()),
+            ..Default::default()
+        })
+    }
+}
+
+/// Turns the text decoded so far into deltas, holding back what could be the start of a stop
+/// word and any incomplete character.
+struct TextStream<'a> {
+    stop_words: &'a [String],
+    text: String,
+    emitted: usize,
+}
+
+impl<'a> TextStream<'a> {
+    fn new(stop_words: &'a [String]) -> Self {
+        Self {
+            stop_words,
+            text: String::new(),
+            emitted: 0,
+        }
+    }
+
+    /// Replaces the text, cutting it at the first stop word. Returns whether one was found.
+    fn update(&mut self, text: String) -> bool {
+        self.text = text;
+        let stop = self
+            .stop_words
+            .iter()
+            .filter_map(|stop_word| self.text.find(stop_word.as_str()))
+            .min();
+        match stop {
+            Some(stop) => {
+                self.text.truncate(stop);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn take_ready(&mut self) -> String {
+        // The tokenizer yields a replacement character for bytes of an unfinished character
+        let text = self.text.trim_end_matches('\u{FFFD}');
+        let held = self
+            .stop_words
+            .iter()
+            .flat_map(|stop_word| {
+                stop_word
+                    .char_indices()
+                    .skip(1)
+                    .map(|(i, _)| &stop_word[..i])
+                    .filter(|prefix| text.ends_with(prefix))
+                    .map(str::len)
+            })
+            .max()
+            .unwrap_or_default();
+        self.take_until(text.len() - held)
+    }
+
+    fn take_rest(&mut self) -> String {
+        self.take_until(self.text.len())
+    }
+
+    fn take_until(&mut self, end: usize) -> String {
+        if end <= self.emitted {
+            return String::new();
+        }
+        let delta = self.text[self.emitted..end].to_string();
+        self.emitted = end;
+        delta
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[async_trait]
+impl LLM for Gguf {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        if let Some(func) = &self.options.streaming_func {
+            let mut generate_result = GenerateResult::default();
+            let mut stream = self.stream(messages).await?;
+            while let Some(data) = stream.next().await {
+                let data = data?;
+                generate_result.merge_stream_data(&data);
+                if !data.content.is_empty() {
+                    let mut func = func.lock().await;
+                    let _ = func(data.content).await;
+                }
+            }
+            return Ok(generate_result);
+        }
+
+        let llm = self.clone();
+        let messages = messages.to_vec();
+        let token = self.options.cancellation_token.clone();
+        tokio::task::spawn_blocking(move || llm.run(&messages, token.as_ref(), |_| true))
+            .await
+            .map_err(|e| GgufError::TaskError(e.to_string()))?
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let (sender, mut receiver) = mpsc::channel(32);
+        let llm = self.clone();
+        let messages = messages.to_vec();
+        let token = self.options.cancellation_token.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = llm.run(&messages, token.as_ref(), |text| {
+                sender
+                    .blocking_send(Ok(StreamData::new(text.into(), None, text)))
+                    .is_ok()
+            });
+            let last = result.map(|result| {
+                StreamData::new(serde_json::Value::Null, result.tokens, "")
+                    .with_finish_reason(result.finish_reason)
+                    .with_model(result.model)
+            });
+            let _ = sender.blocking_send(last);
+        });
+
+        let stream = async_stream::stream! {
+            while let Some(data) = receiver.recv().await {
+                yield data;
+            }
+        };
+        Ok(cancellable_stream(
+            Box::pin(stream),
+            self.options.cancellation_token.clone(),
+        ))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.options.merge_options(options)
+    }
+
+    fn override_options(&mut self, options: CallOptions) {
+        self.options.override_options(options)
+    }
+
+    fn count_tokens(&self, messages: &[Message]) -> usize {
+        self.encode(&self.prompt(messages))
+            .map(|tokens| tokens.len())
+            .unwrap_or_default()
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.context_length
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        Some(self.options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_stream_holds_back_stop_words() {
+        let stop_words = vec!["\nUser:".to_string()];
+        let mut text = TextStream::new(&stop_words);
+        let mut streamed = String::new();
+
+        for decoded in ["Lima is", "Lima is the capital", "Lima is the capital\nUs"] {
+            assert!(!text.update(decoded.to_string()));
+            streamed.push_str(&text.take_ready());
+        }
+        assert_eq!(streamed, "Lima is the capital");
+        assert!(text.update("Lima is the capital\nUser: and".to_string()));
+        streamed.push_str(&text.take_ready());
+        streamed.push_str(&text.take_rest());
+        assert_eq!(streamed, "Lima is the capital");
+        assert_eq!(text.text(), "Lima is the capital");
+
+        let mut text = TextStream::new(&[]);
+        text.update("caf\u{FFFD}".to_string());
+        assert_eq!(text.take_ready(), "caf");
+        text.update("café".to_string());
+        assert_eq!(text.take_ready(), "é");
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_gguf_generate() {
+        // Set GGUF_MODEL and GGUF_TOKENIZER, e.g. to Qwen 2.5 0.5B Instruct
+        let llm = Gguf::load(
+            std::env::var("GGUF_MODEL").unwrap(),
+            std::env::var("GGUF_TOKENIZER").unwrap(),
+        )
+        .unwrap()
+        .with_options(CallOptions::new().with_max_tokens(32));
+
+        let result = llm
+            .generate(&[Message::new_human_message("Capital of Peru?")])
+            .await
+            .unwrap();
+        assert!(result.generation.contains("Lima"));
+        let tokens = result.tokens.unwrap();
+        assert!(tokens.prompt_tokens > 0);
+        assert!(tokens.completion_tokens > 0 && tokens.completion_tokens <= 32);
+        assert!(result.finish_reason.is_some());
+
+        let mut stream = llm
+            .stream(&[Message::new_human_message("Capital of Peru?")])
+            .await
+            .unwrap();
+        let mut streamed = String::new();
+        while let Some(data) = stream.next().await {
+            streamed.push_str(&data.unwrap().content);
+        }
+        // Decoding is greedy without a temperature
+        assert_eq!(streamed, result.generation);
+    }
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GgufError {
+    #[error("Model error: {0}")]
+    CandleError(#[from] candle_core::Error),
+
+    #[error("Tokenizer error: {0}")]
+    TokenizerError(String),
+
+    #[error("Unsupported model architecture: {0}")]
+    UnsupportedArchitecture(String),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Generation task failed: {0}")]
+    TaskError(String),
+}
@@ -0,0 +1,8 @@
+mod client;
+pub use client::*;
+
+mod template;
+pub use template::*;
+
+mod error;
+pub use error::*;
@@ -0,0 +1,139 @@
+use crate::schemas::{Message, MessageType};
+
+/// How a conversation is laid out in the prompt of a local model. Picked from the chat
+/// template stored in the GGUF file when possible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatTemplate {
+    /// `<|im_start|>role ... <|im_end|>`, used by Qwen, SmolLM, Phi and many fine-tunes.
+    ChatML,
+    /// `<|start_header_id|>role<|end_header_id|> ... <|eot_id|>`, used by Llama 3.
+    Llama3,
+    /// `[INST] ... [/INST]`, used by Mistral and Llama 2.
+    Mistral,
+}
+
+impl ChatTemplate {
+    /// Recognizes the template from the Jinja chat template of a model.
+    pub fn detect(chat_template: &str) -> Option<Self> {
+        if chat_template.contains("<|im_start|>") {
+            Some(ChatTemplate::ChatML)
+        } else if chat_template.contains("<|start_header_id|>") {
+            Some(ChatTemplate::Llama3)
+        } else if chat_template.contains("[INST]") {
+            Some(ChatTemplate::Mistral)
+        } else {
+            None
+        }
+    }
+
+    /// The prompt for `messages`, ending where the assistant answer starts.
+    pub fn format(&self, messages: &[Message]) -> String {
+        match self {
+            ChatTemplate::ChatML => {
+                let mut prompt = String::new();
+                for message in messages {
+                    prompt.push_str(&format!(
+                        "<|im_start|>{}\n{}<|im_end|>\n",
+                        role(&message.message_type, "tool"),
+                        message.content
+                    ));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+                prompt
+            }
+            ChatTemplate::Llama3 => {
+                let mut prompt = "<|begin_of_text|>".to_string();
+                for message in messages {
+                    prompt.push_str(&format!(
+                        "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                        role(&message.message_type, "ipython"),
+                        message.content
+                    ));
+                }
+                prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+                prompt
+            }
+            ChatTemplate::Mistral => {
+                // There is no system role, the system prompt opens the first instruction
+                let mut prompt = String::new();
+                let mut system = String::new();
+                for message in messages {
+                    match message.message_type {
+                        MessageType::SystemMessage => {
+                            system.push_str(&message.content);
+                            system.push_str("\n\n");
+                        }
+                        MessageType::AIMessage => {
+                            prompt.push_str(&format!("{}</s>", message.content));
+                        }
+                        MessageType::HumanMessage | MessageType::ToolMessage => {
+                            prompt.push_str(&format!(
+                                "<s>[INST] {}{} [/INST]",
+                                std::mem::take(&mut system),
+                                message.content
+                            ));
+                        }
+                    }
+                }
+                prompt
+            }
+        }
+    }
+
+    /// The tokens ending the assistant turn.
+    pub fn stop_tokens(&self) -> &'static [&'static str] {
+        match self {
+            ChatTemplate::ChatML => &["<|im_end|>", "<|endoftext|>"],
+            ChatTemplate::Llama3 => &["<|eot_id|>", "<|end_of_text|>"],
+            ChatTemplate::Mistral => &["</s>"],
+        }
+    }
+}
+
+fn role(message_type: &MessageType, tool_role: &'static str) -> &'static str {
+    match message_type {
+        MessageType::SystemMessage => "system",
+        MessageType::HumanMessage => "user",
+        MessageType::AIMessage => "assistant",
+        MessageType::ToolMessage => tool_role,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_and_detect() {
+        let messages = vec![
+            Message::new_system_message("Be brief"),
+            Message::new_human_message("Hi"),
+            Message::new_ai_message("Hello"),
+            Message::new_human_message("Capital of Peru?"),
+        ];
+
+        assert_eq!(
+            ChatTemplate::ChatML.format(&messages),
+            "<|im_start|>system\nBe brief<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\nHello<|im_end|>\n<|im_start|>user\nCapital of Peru?<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+        assert!(ChatTemplate::Llama3.format(&messages).ends_with(
+            "Capital of Peru?<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n"
+        ));
+        assert_eq!(
+            ChatTemplate::Mistral.format(&messages),
+            "<s>[INST] Be brief\n\nHi [/INST]Hello</s><s>[INST] Capital of Peru? [/INST]"
+        );
+
+        assert_eq!(
+            ChatTemplate::detect("{% for m in messages %}<|im_start|>{{ m.role }}"),
+            Some(ChatTemplate::ChatML)
+        );
+        assert_eq!(
+            ChatTemplate::detect("{{ '[INST] ' + message['content'] + ' [/INST]' }}"),
+            Some(ChatTemplate::Mistral)
+        );
+        assert_eq!(ChatTemplate::detect("{{ messages }}"), None);
+    }
+}
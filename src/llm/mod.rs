@@ -1,6 +1,9 @@
 pub mod openai;
 pub use openai::*;
 
+pub mod openai_compatible;
+pub use openai_compatible::*;
+
 pub mod claude;
 pub use claude::*;
 
@@ -12,3 +15,27 @@ pub use qwen::*;
 
 pub mod deepseek;
 pub use deepseek::*;
+
+#[cfg(feature = "gemini")]
+pub mod gemini;
+#[cfg(feature = "gemini")]
+pub use gemini::*;
+
+#[cfg(feature = "gguf")]
+pub mod gguf;
+#[cfg(feature = "gguf")]
+pub use gguf::*;
+
+pub mod retry;
+pub use retry::*;
+
+pub mod fallback;
+pub use fallback::*;
+
+pub mod cache;
+pub use cache::*;
+
+pub mod fake;
+pub use fake::*;
+
+mod sse;
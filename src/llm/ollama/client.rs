@@ -1,10 +1,17 @@
 use crate::{
-    language_models::{llm::LLM, GenerateResult, LLMError, TokenUsage},
-    schemas::{Message, MessageType, StreamData},
+    language_models::{
+        cancellation::{cancellable, cancellable_stream, CancellationToken},
+        llm::LLM,
+        options::CallOptions,
+        FinishReason, GenerateResult, LLMError, TokenUsage,
+    },
+    schemas::{
+        content::parse_data_url, ContentPart, Message, MessageType, ResponseFormat, StreamData,
+    },
 };
 use async_trait::async_trait;
 use futures::Stream;
-use ollama_rs::generation::images::Image;
+use ollama_rs::generation::{images::Image, parameters::FormatType};
 pub use ollama_rs::{
     error::OllamaError,
     generation::{
@@ -22,6 +29,8 @@ pub struct Ollama {
     pub(crate) client: Arc<OllamaClient>,
     pub(crate) model: String,
     pub(crate) options: Option<GenerationOptions>,
+    pub(crate) cancellation_token: Option<CancellationToken>,
+    pub(crate) response_format: Option<ResponseFormat>,
 }
 
 /// [llama3.2](https://ollama.com/library/llama3.2) is a 3B parameters, 2.0GB model.
@@ -37,6 +46,8 @@ impl Ollama {
             client,
             model: model.into(),
             options,
+            cancellation_token: None,
+            response_format: None,
         }
     }
 
@@ -50,29 +61,66 @@ impl Ollama {
         self
     }
 
-    fn generate_request(&self, messages: &[Message]) -> ChatMessageRequest {
-        let mapped_messages = messages.iter().map(|message| message.into()).collect();
-        ChatMessageRequest::new(self.model.clone(), mapped_messages)
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    /// Asks for JSON responses. Ollama enforces JSON, the schema is given in the prompt and
+    /// non streamed responses are checked against it.
+    pub fn with_response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    fn generate_request(&self, messages: &[Message]) -> Result<ChatMessageRequest, LLMError> {
+        let messages = match &self.response_format {
+            Some(response_format) => response_format.with_instructions(messages),
+            None => messages.to_vec(),
+        };
+        let mapped_messages = messages
+            .iter()
+            .map(ChatMessage::try_from)
+            .collect::<Result<_, _>>()?;
+        let request = ChatMessageRequest::new(self.model.clone(), mapped_messages);
+        Ok(match &self.response_format {
+            Some(response_format) if response_format.is_json() => request.format(FormatType::Json),
+            _ => request,
+        })
     }
 }
 
-impl From<&Message> for ChatMessage {
-    fn from(message: &Message) -> Self {
-        let images = match message.images.clone() {
-            Some(images) => {
-                let images = images
-                    .iter()
-                    .map(|image| Image::from_base64(&image.image_url))
-                    .collect();
-                Some(images)
+impl TryFrom<&Message> for ChatMessage {
+    type Error = LLMError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let mut content = Vec::new();
+        let mut images = Vec::new();
+        for part in message.parts() {
+            if let Some(text) = part.file_text() {
+                content.push(text);
+                continue;
+            }
+            match part {
+                ContentPart::Text { text, .. } => content.push(text),
+                ContentPart::ImageBase64 { data, .. } => images.push(Image::from_base64(&data)),
+                // Ollama only takes base64 images, plain `images` have always been passed as is
+                ContentPart::ImageUrl { url, .. } => match parse_data_url(&url) {
+                    Some((_, data)) => images.push(Image::from_base64(data)),
+                    None => images.push(Image::from_base64(&url)),
+                },
+                ContentPart::Audio { .. } | ContentPart::File { .. } => {
+                    return Err(LLMError::UnsupportedContent(
+                        "Ollama only accepts text and images".to_string(),
+                    ))
+                }
             }
-            None => None,
-        };
-        ChatMessage {
-            content: message.content.clone(),
-            images,
-            role: message.message_type.clone().into(),
         }
+        Ok(ChatMessage {
+            content: content.join("\n"),
+            images: (!images.is_empty()).then_some(images),
+            role: message.message_type.clone().into(),
+        })
     }
 }
 
@@ -97,41 +145,64 @@ impl Default for Ollama {
 #[async_trait]
 impl LLM for Ollama {
     async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
-        let request = self.generate_request(messages);
-        let result = self.client.send_chat_messages(request).await?;
+        let request = self.generate_request(messages)?;
+        let result = cancellable(self.cancellation_token.as_ref(), async {
+            Ok(self.client.send_chat_messages(request).await?)
+        })
+        .await?;
+        let raw_response = serde_json::to_value(&result)?;
+        let model = Some(result.model.clone());
+        let finish_reason = result.done.then_some(FinishReason::Stop);
 
         let generation = match result.message {
             Some(message) => message.content,
             None => return Err(OllamaError::from("No message in response".to_string()).into()),
         };
+        let generation = match &self.response_format {
+            Some(response_format) => response_format.validate(&generation)?,
+            None => generation,
+        };
 
         let tokens = result.final_data.map(|final_data| {
-            let prompt_tokens = final_data.prompt_eval_count as u32;
-            let completion_tokens = final_data.eval_count as u32;
-            TokenUsage {
-                prompt_tokens,
-                completion_tokens,
-                total_tokens: prompt_tokens + completion_tokens,
-            }
+            TokenUsage::new(
+                final_data.prompt_eval_count as u32,
+                final_data.eval_count as u32,
+            )
         });
 
-        Ok(GenerateResult { tokens, generation })
+        Ok(GenerateResult {
+            tokens,
+            generation,
+            finish_reason,
+            model,
+            raw_response: Some(raw_response),
+            ..Default::default()
+        })
     }
 
     async fn stream(
         &self,
         messages: &[Message],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
-        let request = self.generate_request(messages);
-        let result = self.client.send_chat_messages_stream(request).await?;
+        let request = self.generate_request(messages)?;
+        let result = cancellable(self.cancellation_token.as_ref(), async {
+            Ok(self.client.send_chat_messages_stream(request).await?)
+        })
+        .await?;
 
         let stream = result.map(|data| match data {
             Ok(data) => match data.message.clone() {
-                Some(message) => Ok(StreamData::new(
-                    serde_json::to_value(data).unwrap_or_default(),
-                    None,
-                    message.content,
-                )),
+                Some(message) => {
+                    let model = Some(data.model.clone());
+                    let finish_reason = data.done.then_some(FinishReason::Stop);
+                    Ok(StreamData::new(
+                        serde_json::to_value(data).unwrap_or_default(),
+                        None,
+                        message.content,
+                    )
+                    .with_model(model)
+                    .with_finish_reason(finish_reason))
+                }
                 // TODO: no need to return error, see https://github.com/Abraxas-365/langchain-rust/issues/140
                 None => Err(LLMError::ContentNotFound(
                     "No message in response".to_string(),
@@ -140,7 +211,26 @@ impl LLM for Ollama {
             Err(_) => Err(OllamaError::from("Stream error".to_string()).into()),
         });
 
-        Ok(Box::pin(stream))
+        Ok(cancellable_stream(
+            Box::pin(stream),
+            self.cancellation_token.clone(),
+        ))
+    }
+
+    /// Only the response format is taken from `options`, generation parameters are set with
+    /// [`Ollama::with_options`].
+    fn add_options(&mut self, options: CallOptions) {
+        if options.response_format.is_some() {
+            self.response_format = options.response_format;
+        }
+    }
+
+    /// Also takes the cancellation token, which only applies to the call it is given for.
+    fn override_options(&mut self, options: CallOptions) {
+        if options.cancellation_token.is_some() {
+            self.cancellation_token = options.cancellation_token.clone();
+        }
+        self.add_options(options)
     }
 }
 
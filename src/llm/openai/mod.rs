@@ -1,4 +1,4 @@
-use std::pin::Pin;
+use std::{pin::Pin, time::Duration};
 
 pub use async_openai::config::{AzureConfig, Config, OpenAIConfig};
 
@@ -6,24 +6,37 @@ use async_openai::types::{ChatCompletionToolChoiceOption, ResponseFormat};
 use async_openai::{
     error::OpenAIError,
     types::{
-        ChatChoiceStream, ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
-        ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPartImageArgs,
+        BatchCompletionWindow, BatchEndpoint, BatchRequest, BatchRequestInput,
+        BatchRequestInputMethod, BatchRequestOutput, BatchStatus, ChatChoiceStream,
+        ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
         ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs,
         ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent,
-        ChatCompletionRequestUserMessageContentPart, ChatCompletionStreamOptions,
-        CreateChatCompletionRequest, CreateChatCompletionRequestArgs,
+        ChatCompletionRequestUserMessageContentPart, ChatCompletionStreamOptions, CompletionUsage,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
+        CreateFileRequest, FileInput, FilePurpose,
     },
     Client,
 };
 use async_trait::async_trait;
 use futures::{Stream, StreamExt};
 
-use crate::schemas::convert::{LangchainIntoOpenAI, TryLangchainIntoOpenAI};
+use crate::schemas::convert::{
+    LangchainFromOpenAI, LangchainIntoOpenAI, OpenAIFromLangchain, OpenAiIntoLangchain,
+    TryLangchainIntoOpenAI,
+};
 use crate::{
-    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError, TokenUsage},
+    language_models::{
+        cancellation::{cancellable, cancellable_stream, cancelled},
+        llm::{generate_concurrently, DEFAULT_BATCH_CONCURRENCY, LLM},
+        options::{CallOptions, ReasoningEffort},
+        tokens::{context_window_for_model, count_message_tokens, tokenizer_for_model},
+        FinishReason, GenerateResult, LLMError, TokenUsage,
+    },
     schemas::{
+        merge_tool_call_delta,
         messages::{Message, MessageType},
-        StreamData,
+        FunctionCallResponse, StreamData, ToolCallDelta,
     },
 };
 
@@ -59,6 +72,7 @@ pub struct OpenAI<C: Config> {
     config: C,
     options: CallOptions,
     model: String,
+    batch_poll_interval: Option<Duration>,
 }
 
 impl<C: Config> OpenAI<C> {
@@ -67,6 +81,7 @@ impl<C: Config> OpenAI<C> {
             config,
             options: CallOptions::default(),
             model: OpenAIModel::Gpt4oMini.to_string(),
+            batch_poll_interval: None,
         }
     }
 
@@ -84,6 +99,14 @@ impl<C: Config> OpenAI<C> {
         self.options = options;
         self
     }
+
+    /// Sends [`LLM::generate_batch`] through the [Batch API](https://platform.openai.com/docs/guides/batch),
+    /// checking for completion every `poll_interval`. Batches cost less but may take up to 24
+    /// hours, so this suits offline jobs. Without it batches are sent as concurrent requests.
+    pub fn with_batch_api(mut self, poll_interval: Duration) -> Self {
+        self.batch_poll_interval = Some(poll_interval);
+        self
+    }
 }
 
 impl Default for OpenAI<OpenAIConfig> {
@@ -97,20 +120,29 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
     async fn generate(&self, prompt: &[Message]) -> Result<GenerateResult, LLMError> {
         let client = Client::with_config(self.config.clone());
         let request = self.generate_request(prompt, self.options.streaming_func.is_some())?;
+        let token = self.options.cancellation_token.as_ref();
         match &self.options.streaming_func {
             Some(func) => {
-                let mut stream = client.chat().create_stream(request).await?;
+                let mut stream = cancellable(token, async {
+                    Ok(client.chat().create_stream(request).await?)
+                })
+                .await?;
                 let mut generate_result = GenerateResult::default();
-                while let Some(result) = stream.next().await {
+                loop {
+                    let result = tokio::select! {
+                        biased;
+                        _ = cancelled(token) => return Err(LLMError::cancelled(generate_result)),
+                        result = stream.next() => result,
+                    };
+                    let Some(result) = result else {
+                        break;
+                    };
                     match result {
                         Ok(response) => {
                             if let Some(usage) = response.usage {
-                                generate_result.tokens = Some(TokenUsage {
-                                    prompt_tokens: usage.prompt_tokens,
-                                    completion_tokens: usage.completion_tokens,
-                                    total_tokens: usage.total_tokens,
-                                });
+                                generate_result.tokens = Some(token_usage(usage));
                             }
+                            generate_result.model = Some(response.model);
                             for chat_choice in response.choices.iter() {
                                 let chat_choice: ChatChoiceStream = chat_choice.clone();
                                 {
@@ -123,6 +155,16 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
                                 if let Some(content) = chat_choice.delta.content {
                                     generate_result.generation.push_str(&content);
                                 }
+                                if let Some(tool_call_chunks) = &chat_choice.delta.tool_calls {
+                                    merge_tool_call_chunks(
+                                        &mut generate_result.tool_calls,
+                                        tool_call_chunks,
+                                    );
+                                }
+                                if let Some(finish_reason) = chat_choice.finish_reason {
+                                    generate_result.finish_reason =
+                                        Some(finish_reason.into_langchain());
+                                }
                             }
                         }
                         Err(err) => {
@@ -133,32 +175,23 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
                 Ok(generate_result)
             }
             None => {
-                let response = client.chat().create(request).await?;
-                let mut generate_result = GenerateResult::default();
-
-                if let Some(usage) = response.usage {
-                    generate_result.tokens = Some(TokenUsage {
-                        prompt_tokens: usage.prompt_tokens,
-                        completion_tokens: usage.completion_tokens,
-                        total_tokens: usage.total_tokens,
-                    });
-                }
-
-                if let Some(choice) = &response.choices.first() {
-                    generate_result.generation = choice.message.content.clone().unwrap_or_default();
-                    if let Some(function) = &choice.message.tool_calls {
-                        generate_result.generation =
-                            serde_json::to_string(&function).unwrap_or_default();
-                    }
-                } else {
-                    generate_result.generation = "".to_string();
-                }
-
-                Ok(generate_result)
+                let response =
+                    cancellable(token, async { Ok(client.chat().create(request).await?) }).await?;
+                generate_result(response)
             }
         }
     }
 
+    async fn generate_batch(
+        &self,
+        batch: &[Vec<Message>],
+    ) -> Result<Vec<Result<GenerateResult, LLMError>>, LLMError> {
+        match self.batch_poll_interval {
+            Some(poll_interval) => self.generate_with_batch_api(batch, poll_interval).await,
+            None => Ok(generate_concurrently(self, batch, DEFAULT_BATCH_CONCURRENCY).await),
+        }
+    }
+
     async fn invoke(&self, prompt: &str) -> Result<String, LLMError> {
         self.generate(&[Message::new_human_message(prompt)])
             .await
@@ -172,16 +205,38 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
         let client = Client::with_config(self.config.clone());
         let request = self.generate_request(messages, true)?;
 
-        let original_stream = client.chat().create_stream(request).await?;
+        let token = self.options.cancellation_token.clone();
+        let original_stream = cancellable(token.as_ref(), async {
+            Ok(client.chat().create_stream(request).await?)
+        })
+        .await?;
 
         let new_stream = original_stream.map(|result| match result {
             Ok(completion) => {
+                let model = Some(completion.model.clone());
+                let finish_reason = completion
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.finish_reason)
+                    .map(|reason| reason.into_langchain());
+                let logprobs = completion
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.logprobs.clone())
+                    .map(serde_json::to_value)
+                    .transpose()?;
+                let deltas = completion
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.delta.tool_calls.as_deref())
+                    .map(tool_call_deltas)
+                    .unwrap_or_default();
+                let usage = completion.usage.clone().map(token_usage);
                 let value_completion = serde_json::to_value(completion).map_err(LLMError::from)?;
-                let usage = value_completion.pointer("/usage");
-                if usage.is_some() && !usage.unwrap().is_null() {
-                    let usage = serde_json::from_value::<TokenUsage>(usage.unwrap().clone())
-                        .map_err(LLMError::from)?;
-                    return Ok(StreamData::new(value_completion, Some(usage), ""));
+                if usage.is_some() {
+                    return Ok(StreamData::new(value_completion, usage, "")
+                        .with_model(model)
+                        .with_finish_reason(finish_reason));
                 }
                 let content = value_completion
                     .pointer("/choices/0/delta/content")
@@ -190,24 +245,348 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
                     ))?
                     .clone();
 
-                Ok(StreamData::new(
-                    value_completion,
-                    None,
-                    content.as_str().unwrap_or(""),
-                ))
+                Ok(
+                    StreamData::new(value_completion, None, content.as_str().unwrap_or(""))
+                        .with_model(model)
+                        .with_finish_reason(finish_reason)
+                        .with_logprobs(logprobs)
+                        .with_tool_call_deltas(deltas),
+                )
             }
             Err(e) => Err(LLMError::from(e)),
         });
 
-        Ok(Box::pin(new_stream))
+        Ok(cancellable_stream(Box::pin(new_stream), token))
     }
 
     fn add_options(&mut self, options: CallOptions) {
         self.options.merge_options(options)
     }
+
+    fn override_options(&mut self, options: CallOptions) {
+        self.options.override_options(options)
+    }
+
+    fn count_tokens(&self, messages: &[Message]) -> usize {
+        count_message_tokens(tokenizer_for_model(&self.model), messages)
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        context_window_for_model(&self.model)
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.options
+            .max_tokens
+            .map(|max_tokens| max_tokens as usize)
+    }
+}
+
+impl LangchainFromOpenAI<async_openai::types::FinishReason> for FinishReason {
+    fn from_openai(openai: async_openai::types::FinishReason) -> Self {
+        match openai {
+            async_openai::types::FinishReason::Stop => FinishReason::Stop,
+            async_openai::types::FinishReason::Length => FinishReason::Length,
+            async_openai::types::FinishReason::ToolCalls
+            | async_openai::types::FinishReason::FunctionCall => FinishReason::ToolCalls,
+            async_openai::types::FinishReason::ContentFilter => FinishReason::ContentFilter,
+        }
+    }
+}
+
+impl OpenAIFromLangchain<ReasoningEffort> for async_openai::types::ReasoningEffort {
+    fn from_langchain(langchain: ReasoningEffort) -> Self {
+        match langchain {
+            ReasoningEffort::Low => async_openai::types::ReasoningEffort::Low,
+            ReasoningEffort::Medium => async_openai::types::ReasoningEffort::Medium,
+            ReasoningEffort::High => async_openai::types::ReasoningEffort::High,
+        }
+    }
+}
+
+fn generate_result(response: CreateChatCompletionResponse) -> Result<GenerateResult, LLMError> {
+    let mut generate_result = GenerateResult {
+        model: Some(response.model.clone()),
+        raw_response: Some(serde_json::to_value(&response)?),
+        ..Default::default()
+    };
+
+    generate_result.tokens = response.usage.map(token_usage);
+
+    if let Some(choice) = response.choices.into_iter().next() {
+        generate_result.generation = choice.message.content.unwrap_or_default();
+        generate_result.tool_calls = choice
+            .message
+            .tool_calls
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tool_call| tool_call.into_langchain())
+            .collect();
+        generate_result.finish_reason = choice.finish_reason.map(|reason| reason.into_langchain());
+        generate_result.logprobs = choice.logprobs.map(serde_json::to_value).transpose()?;
+    }
+
+    Ok(generate_result)
+}
+
+/// OpenAI caches long prompts on its own, and reports the cached part of the prompt.
+fn token_usage(usage: CompletionUsage) -> TokenUsage {
+    let cache_read_tokens = usage
+        .prompt_tokens_details
+        .and_then(|details| details.cached_tokens)
+        .unwrap_or_default();
+    TokenUsage {
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        total_tokens: usage.total_tokens,
+        ..Default::default()
+    }
+    .with_cache_tokens(cache_read_tokens, 0)
+}
+
+/// Cancels a batch nobody waits for any more, so that it stops being billed. Its input file is
+/// kept, as the batch may still read it until the cancellation goes through.
+async fn abandon_batch<C: Config>(
+    client: &Client<C>,
+    batch_id: &str,
+    input_file_id: &str,
+    error: LLMError,
+) -> LLMError {
+    if let Err(e) = client.batches().cancel(batch_id).await {
+        log::warn!("Could not cancel the OpenAI batch {}: {}", batch_id, e);
+    }
+    log::warn!(
+        "Keeping the input file {} of the abandoned OpenAI batch {}",
+        input_file_id,
+        batch_id
+    );
+    error
+}
+
+/// The `custom_id` and result of a line of the output or error file of a batch. A line that
+/// does not parse only fails its own request, when its `custom_id` can be read.
+fn batch_line_result(line: &str) -> (Option<String>, Result<GenerateResult, LLMError>) {
+    match serde_json::from_str::<BatchRequestOutput>(line) {
+        Ok(output) => (Some(output.custom_id.clone()), batch_output_result(output)),
+        Err(e) => {
+            let custom_id = serde_json::from_str::<serde_json::Value>(line)
+                .ok()
+                .and_then(|line| line["custom_id"].as_str().map(str::to_string));
+            let error = LLMError::OtherError(format!(
+                "Batch request {} has a malformed output: {}",
+                custom_id.as_deref().unwrap_or("?"),
+                e
+            ));
+            (custom_id, Err(error))
+        }
+    }
+}
+
+/// The result of one request of a batch, from its line in the output or error file.
+fn batch_output_result(output: BatchRequestOutput) -> Result<GenerateResult, LLMError> {
+    match (output.response, output.error) {
+        (Some(response), _) if response.status_code == 200 => {
+            generate_result(serde_json::from_value(response.body)?)
+        }
+        (Some(response), _) => Err(LLMError::OtherError(format!(
+            "Batch request {} failed with status {}: {}",
+            output.custom_id, response.status_code, response.body
+        ))),
+        (None, Some(error)) => Err(LLMError::OtherError(format!(
+            "Batch request {} failed: {} ({})",
+            output.custom_id, error.message, error.code
+        ))),
+        (None, None) => Err(LLMError::OtherError(format!(
+            "Batch request {} has no response",
+            output.custom_id
+        ))),
+    }
+}
+
+/// Accumulates streamed tool call fragments into complete calls. The first chunk of each call
+/// carries its id and name, the following ones only pieces of the arguments.
+fn merge_tool_call_chunks(
+    tool_calls: &mut Vec<FunctionCallResponse>,
+    chunks: &[ChatCompletionMessageToolCallChunk],
+) {
+    for delta in tool_call_deltas(chunks) {
+        merge_tool_call_delta(tool_calls, &delta);
+    }
+}
+
+pub(crate) fn tool_call_deltas(
+    chunks: &[ChatCompletionMessageToolCallChunk],
+) -> Vec<ToolCallDelta> {
+    chunks
+        .iter()
+        .map(|chunk| {
+            let function = chunk.function.as_ref();
+            ToolCallDelta {
+                index: chunk.index as usize,
+                id: chunk.id.clone(),
+                name: function.and_then(|function| function.name.clone()),
+                arguments: function
+                    .and_then(|function| function.arguments.clone())
+                    .unwrap_or_default(),
+            }
+        })
+        .collect()
 }
 
 impl<C: Config> OpenAI<C> {
+    async fn generate_with_batch_api(
+        &self,
+        batch: &[Vec<Message>],
+        poll_interval: Duration,
+    ) -> Result<Vec<Result<GenerateResult, LLMError>>, LLMError> {
+        let client = Client::with_config(self.config.clone());
+
+        let mut lines = Vec::with_capacity(batch.len());
+        for (i, messages) in batch.iter().enumerate() {
+            let input = BatchRequestInput {
+                custom_id: i.to_string(),
+                method: BatchRequestInputMethod::POST,
+                url: BatchEndpoint::V1ChatCompletions,
+                body: Some(serde_json::to_value(
+                    self.generate_request(messages, false)?,
+                )?),
+            };
+            lines.push(serde_json::to_string(&input)?);
+        }
+        let file = client
+            .files()
+            .create(CreateFileRequest {
+                file: FileInput::from_vec_u8("batch.jsonl".to_string(), lines.join("\n").into()),
+                purpose: FilePurpose::Batch,
+            })
+            .await?;
+
+        // The files count against the storage of the organization, whatever the outcome
+        let mut files = Vec::new();
+        let results = self
+            .run_batch(&client, file.id, batch.len(), poll_interval, &mut files)
+            .await;
+        for file_id in files {
+            if let Err(e) = client.files().delete(&file_id).await {
+                log::warn!("Could not delete the batch file {}: {}", file_id, e);
+            }
+        }
+        results
+    }
+
+    /// Runs a batch on the uploaded `input_file_id`, adding the files it produced to `files`.
+    /// The input file is only added once no batch reads it any more.
+    async fn run_batch(
+        &self,
+        client: &Client<C>,
+        input_file_id: String,
+        len: usize,
+        poll_interval: Duration,
+        files: &mut Vec<String>,
+    ) -> Result<Vec<Result<GenerateResult, LLMError>>, LLMError> {
+        let token = self.options.cancellation_token.as_ref();
+        let job = client
+            .batches()
+            .create(BatchRequest {
+                input_file_id: input_file_id.clone(),
+                endpoint: BatchEndpoint::V1ChatCompletions,
+                completion_window: BatchCompletionWindow::W24H,
+                metadata: None,
+            })
+            .await;
+        let mut job = match job {
+            Ok(job) => job,
+            Err(e) => {
+                files.push(input_file_id);
+                return Err(e.into());
+            }
+        };
+
+        loop {
+            match job.status {
+                BatchStatus::Completed => break,
+                BatchStatus::Failed | BatchStatus::Expired | BatchStatus::Cancelled => {
+                    files.push(input_file_id);
+                    files.extend(job.output_file_id.clone());
+                    files.extend(job.error_file_id.clone());
+                    let errors = job
+                        .errors
+                        .map(|errors| {
+                            errors
+                                .data
+                                .into_iter()
+                                .map(|error| error.message)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        })
+                        .unwrap_or_default();
+                    return Err(LLMError::OtherError(format!(
+                        "OpenAI batch {} ended as {:?}: {}",
+                        job.id, job.status, errors
+                    )));
+                }
+                _ => {}
+            }
+            let waited = cancellable(token, async {
+                tokio::time::sleep(poll_interval).await;
+                Ok(())
+            })
+            .await;
+            if let Err(e) = waited {
+                return Err(abandon_batch(client, &job.id, &input_file_id, e).await);
+            }
+            job = match client.batches().retrieve(&job.id).await {
+                Ok(job) => job,
+                Err(e) => {
+                    let e = LLMError::from(e);
+                    if !e.is_retryable() {
+                        return Err(abandon_batch(client, &job.id, &input_file_id, e).await);
+                    }
+                    log::warn!("Could not poll the OpenAI batch {}: {}", job.id, e);
+                    continue;
+                }
+            };
+        }
+        files.push(input_file_id);
+
+        let output_files: Vec<String> = [job.output_file_id, job.error_file_id]
+            .into_iter()
+            .flatten()
+            .collect();
+        files.extend(output_files.iter().cloned());
+
+        let mut results: Vec<Option<Result<GenerateResult, LLMError>>> =
+            (0..len).map(|_| None).collect();
+        for file_id in output_files {
+            let content = client.files().content(&file_id).await?;
+            for line in String::from_utf8_lossy(&content).lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (custom_id, result) = batch_line_result(line);
+                if let Some(result_slot) = custom_id
+                    .and_then(|custom_id| custom_id.parse::<usize>().ok())
+                    .and_then(|i| results.get_mut(i))
+                {
+                    *result_slot = Some(result);
+                }
+            }
+        }
+
+        Ok(results
+            .into_iter()
+            .enumerate()
+            .map(|(i, result)| {
+                result.unwrap_or_else(|| {
+                    Err(LLMError::OtherError(format!(
+                        "Batch request {} is missing from the output",
+                        i
+                    )))
+                })
+            })
+            .collect())
+    }
+
     fn to_openai_messages(
         &self,
         messages: &[Message],
@@ -231,24 +610,17 @@ impl<C: Config> OpenAI<C> {
                         .into(),
                 }),
                 MessageType::HumanMessage => {
-                    let content: ChatCompletionRequestUserMessageContent = match m.images.clone() {
-                        Some(images) => {
-                            let content: Result<
+                    let content: ChatCompletionRequestUserMessageContent = if m.is_multimodal() {
+                        m.parts()
+                            .into_iter()
+                            .map(|part| part.try_into_openai())
+                            .collect::<Result<
                                 Vec<ChatCompletionRequestUserMessageContentPart>,
-                                OpenAIError,
-                            > = images
-                                .into_iter()
-                                .map(|image| {
-                                    Ok(ChatCompletionRequestMessageContentPartImageArgs::default()
-                                        .image_url(image.image_url)
-                                        .build()?
-                                        .into())
-                                })
-                                .collect();
-
-                            content?.into()
-                        }
-                        None => m.content.clone().into(),
+                                LLMError,
+                            >>()?
+                            .into()
+                    } else {
+                        m.content.clone().into()
                     };
 
                     openai_messages.push(
@@ -315,6 +687,12 @@ impl<C: Config> OpenAI<C> {
                 .tool_choice::<ChatCompletionToolChoiceOption>(behavior.clone().into_openai());
         }
 
+        // o-series models take an effort level, budgets are mapped to the closest one
+        if let Some(effort) = self.options.reasoning_effort_level() {
+            request_builder
+                .reasoning_effort::<async_openai::types::ReasoningEffort>(effort.into_openai());
+        }
+
         if let Some(response_format) = &self.options.response_format {
             request_builder
                 .response_format::<ResponseFormat>(response_format.clone().into_openai());
@@ -326,7 +704,8 @@ impl<C: Config> OpenAI<C> {
 }
 #[cfg(test)]
 mod tests {
-    use crate::schemas::FunctionDefinition;
+    use crate::schemas::{ContentPart, FunctionDefinition};
+    use crate::testing::CassetteServer;
 
     use super::*;
 
@@ -336,6 +715,63 @@ mod tests {
     use tokio::sync::Mutex;
     use tokio::test;
 
+    #[test]
+    async fn test_content_parts_to_openai_messages() {
+        let messages = vec![Message::new_human_message_with_parts(vec![
+            ContentPart::text("Describe this"),
+            ContentPart::image_base64("image/png", "iVBORw=="),
+            ContentPart::audio_bytes("audio/wav", b"RIFF"),
+        ])];
+
+        let openai_messages = OpenAI::default().to_openai_messages(&messages).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&openai_messages[0]).unwrap()["content"],
+            json!([
+                { "type": "text", "text": "Describe this" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw==", "detail": null } },
+                { "type": "input_audio", "input_audio": { "data": "UklGRg==", "format": "wav" } }
+            ])
+        );
+    }
+
+    #[test]
+    async fn test_reasoning_effort_request() {
+        let openai = OpenAI::default()
+            .with_model("o3-mini")
+            .with_options(CallOptions::new().with_reasoning_budget(10000));
+        let request = openai
+            .generate_request(&[Message::new_human_message("Hi")], false)
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["reasoning_effort"],
+            "high"
+        );
+    }
+
+    #[test]
+    async fn test_merge_tool_call_chunks() {
+        let chunks: Vec<Vec<ChatCompletionMessageToolCallChunk>> = serde_json::from_value(json!([
+            [{ "index": 0, "id": "call_1", "type": "function", "function": { "name": "cli", "arguments": "" } }],
+            [{ "index": 0, "function": { "arguments": "{\"command\":" } }],
+            [{ "index": 0, "function": { "arguments": "\"ls\"}" } }],
+            [{ "index": 1, "id": "call_2", "type": "function", "function": { "name": "date", "arguments": "{}" } }]
+        ]))
+        .unwrap();
+
+        let mut tool_calls = Vec::new();
+        for chunk in &chunks {
+            merge_tool_call_chunks(&mut tool_calls, chunk);
+        }
+
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(tool_calls[0].function.name, "cli");
+        assert_eq!(tool_calls[0].function.arguments, "{\"command\":\"ls\"}");
+        assert_eq!(tool_calls[1].id, "call_2");
+        assert_eq!(tool_calls[1].function.name, "date");
+    }
+
     #[test]
     #[ignore]
     async fn test_invoke() {
@@ -502,4 +938,260 @@ mod tests {
         let response = open_ai.generate(&messages).await.unwrap();
         println!("Response: {:?}", response);
     }
+
+    #[test]
+    async fn test_generate_and_stream_from_cassette() {
+        let cassette = CassetteServer::new(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/llm/test_data/cassettes/openai.synthetic.json"
+            ),
+            "https://api.openai.com",
+        )
+        .start()
+        .await
+        .unwrap();
+        let openai =
+            OpenAI::new(OpenAIConfig::default().with_api_base(format!("{}/v1", cassette.url())))
+                .with_model(OpenAIModel::Gpt4oMini.to_string())
+                .with_options(CallOptions::new().with_stream_usage(true));
+        let messages = [Message::new_human_message(
+            "What is the capital of France? Answer in one word.",
+        )];
+
+        let result = openai.generate(&messages).await.unwrap();
+        assert!(result.generation.contains("Paris"));
+        assert_eq!(result.finish_reason, Some(FinishReason::Stop));
+        assert!(result.tokens.unwrap().prompt_tokens > 0);
+
+        let mut streamed = GenerateResult::default();
+        let mut stream = openai.stream(&messages).await.unwrap();
+        while let Some(data) = stream.next().await {
+            streamed.merge_stream_data(&data.unwrap());
+        }
+        assert!(streamed.generation.contains("Paris"));
+        assert_eq!(streamed.finish_reason, Some(FinishReason::Stop));
+        assert!(streamed.tokens.unwrap().completion_tokens > 0);
+        cassette.assert_replayed().await;
+    }
+
+    #[test]
+    async fn test_generate_batch_with_batch_api() {
+        let mut server = mockito::Server::new_async().await;
+        let upload = server
+            .mock("POST", "/files")
+            .with_body(
+                json!({
+                    "id": "file-in", "object": "file", "bytes": 120, "created_at": 0,
+                    "filename": "batch.jsonl", "purpose": "batch"
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let batch = |status: &str| {
+            json!({
+                "id": "batch_1", "object": "batch", "endpoint": "/v1/chat/completions",
+                "input_file_id": "file-in", "completion_window": "24h", "status": status,
+                "created_at": 0, "output_file_id": "file-out"
+            })
+            .to_string()
+        };
+        let create = server
+            .mock("POST", "/batches")
+            .match_body(mockito::Matcher::PartialJson(
+                json!({"input_file_id": "file-in", "endpoint": "/v1/chat/completions"}),
+            ))
+            .with_body(batch("validating"))
+            .create_async()
+            .await;
+        let in_progress = server
+            .mock("GET", "/batches/batch_1")
+            .with_body(batch("in_progress"))
+            .expect(1)
+            .create_async()
+            .await;
+        let completed = server
+            .mock("GET", "/batches/batch_1")
+            .with_body(batch("completed"))
+            .create_async()
+            .await;
+        let completion = |text: &str| {
+            json!({
+                "id": "chatcmpl-1", "object": "chat.completion", "created": 0,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0, "finish_reason": "stop",
+                    "message": {"role": "assistant", "content": text}
+                }],
+                "usage": {"prompt_tokens": 5, "completion_tokens": 1, "total_tokens": 6}
+            })
+        };
+        // Outputs come back in any order, and matched by custom_id
+        let output = [
+            json!({"id": "r1", "custom_id": "1", "response": {
+                "status_code": 400, "request_id": "req_1",
+                "body": {"error": {"message": "bad request"}}
+            }, "error": null}),
+            json!({"id": "r2", "custom_id": "2", "response": {
+                "status_code": 200, "request_id": "req_2", "body": completion("Paris")
+            }, "error": null}),
+            json!({"id": "r0", "custom_id": "0", "response": {
+                "status_code": 200, "request_id": "req_0", "body": completion("Lima")
+            }, "error": null}),
+            json!({"id": "r3", "custom_id": "3", "response": "truncated"}),
+        ]
+        .map(|line| line.to_string())
+        .join("\n");
+        let content = server
+            .mock("GET", "/files/file-out/content")
+            .with_body(output)
+            .create_async()
+            .await;
+        let deletes = [
+            deleted_file(&mut server, "file-in").await,
+            deleted_file(&mut server, "file-out").await,
+        ];
+
+        let openai = OpenAI::new(OpenAIConfig::default().with_api_base(server.url()))
+            .with_batch_api(Duration::from_millis(10));
+        let results = openai
+            .generate_batch(&[
+                vec![Message::new_human_message("Capital of Peru?")],
+                vec![Message::new_human_message("Capital of ?")],
+                vec![Message::new_human_message("Capital of France?")],
+                vec![Message::new_human_message("Capital of Atlantis?")],
+                vec![Message::new_human_message("Capital of Mu?")],
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 5);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.generation, "Lima");
+        assert_eq!(first.tokens.as_ref().unwrap().total_tokens, 6);
+        assert!(matches!(&results[1], Err(LLMError::OtherError(e)) if e.contains("400")));
+        assert_eq!(results[2].as_ref().unwrap().generation, "Paris");
+        assert!(matches!(&results[3], Err(LLMError::OtherError(e)) if e.contains("malformed")));
+        assert!(matches!(&results[4], Err(LLMError::OtherError(e)) if e.contains("missing")));
+        for mock in [upload, create, in_progress, completed, content] {
+            mock.assert_async().await;
+        }
+        for mock in deletes {
+            mock.assert_async().await;
+        }
+    }
+
+    #[test]
+    async fn test_batch_api_deletes_the_files_of_a_failed_batch() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/files")
+            .with_body(
+                json!({
+                    "id": "file-in", "object": "file", "bytes": 120, "created_at": 0,
+                    "filename": "batch.jsonl", "purpose": "batch"
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/batches")
+            .with_body(
+                json!({
+                    "id": "batch_1", "object": "batch", "endpoint": "/v1/chat/completions",
+                    "input_file_id": "file-in", "completion_window": "24h",
+                    "status": "failed", "created_at": 0, "error_file_id": "file-err",
+                    "errors": {"object": "list", "data": [{"code": "invalid", "message": "bad input"}]}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let deletes = [
+            deleted_file(&mut server, "file-in").await,
+            deleted_file(&mut server, "file-err").await,
+        ];
+
+        let openai = OpenAI::new(OpenAIConfig::default().with_api_base(server.url()))
+            .with_batch_api(Duration::from_millis(10));
+        let error = openai
+            .generate_batch(&[vec![Message::new_human_message("Capital of Peru?")]])
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("bad input"));
+        for mock in deletes {
+            mock.assert_async().await;
+        }
+    }
+
+    #[test]
+    async fn test_batch_api_cancels_a_batch_it_cannot_poll() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/files")
+            .with_body(
+                json!({
+                    "id": "file-in", "object": "file", "bytes": 120, "created_at": 0,
+                    "filename": "batch.jsonl", "purpose": "batch"
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let batch = json!({
+            "id": "batch_1", "object": "batch", "endpoint": "/v1/chat/completions",
+            "input_file_id": "file-in", "completion_window": "24h",
+            "status": "in_progress", "created_at": 0
+        })
+        .to_string();
+        server
+            .mock("POST", "/batches")
+            .with_body(&batch)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/batches/batch_1")
+            .with_status(404)
+            .with_body(
+                json!({"error": {"message": "No batch found", "type": "invalid_request_error"}})
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+        let cancel = server
+            .mock("POST", "/batches/batch_1/cancel")
+            .with_body(batch.replace("in_progress", "cancelling"))
+            .expect(1)
+            .create_async()
+            .await;
+        // The batch may still read its input until the cancellation goes through
+        let delete = server
+            .mock("DELETE", "/files/file-in")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let openai = OpenAI::new(OpenAIConfig::default().with_api_base(server.url()))
+            .with_batch_api(Duration::from_millis(10));
+        let error = openai
+            .generate_batch(&[vec![Message::new_human_message("Capital of Peru?")]])
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("No batch found"));
+        cancel.assert_async().await;
+        delete.assert_async().await;
+    }
+
+    async fn deleted_file(server: &mut mockito::ServerGuard, file_id: &str) -> mockito::Mock {
+        server
+            .mock("DELETE", format!("/files/{}", file_id).as_str())
+            .with_body(json!({"id": file_id, "object": "file", "deleted": true}).to_string())
+            .expect(1)
+            .create_async()
+            .await
+    }
 }
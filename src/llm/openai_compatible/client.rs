@@ -0,0 +1,992 @@
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+
+use async_openai::types::{
+    ChatCompletionMessageToolCallChunk, ChatCompletionTool, ChatCompletionToolChoiceOption,
+};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    language_models::{
+        cancellation::{cancellable, cancellable_stream},
+        llm::LLM,
+        options::CallOptions,
+        tokens::context_window_for_model,
+        FinishReason, GenerateResult, LLMError, TokenUsage,
+    },
+    llm::{openai::tool_call_deltas, sse, OpenAICompatibleError},
+    schemas::{
+        convert::{OpenAIFromLangchain, TryOpenAiFromLangchain},
+        Message, ResponseFormat, StreamData,
+    },
+};
+
+use super::models::{ApiResponse, ChatMessage, Payload, Usage};
+
+/// How requests are authenticated.
+#[derive(Clone)]
+pub enum AuthScheme {
+    /// No credentials, for local servers.
+    None,
+    /// `Authorization: Bearer <token>`, used by most providers.
+    Bearer(String),
+    /// The key sent in a custom header, e.g. `api-key`.
+    Header { name: String, value: String },
+}
+
+/// What an OpenAI compatible server supports beyond plain text chat. Unsupported message
+/// content is downgraded or rejected before the request is sent.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// Sends `tools` and `tool_choice`, and tool calls and results as such. Without it tool
+    /// results are sent as user messages.
+    pub tools: bool,
+    /// Sends `response_format` for JSON mode and JSON schemas. Without it the format is asked
+    /// for in the prompt and the response checked against it.
+    pub json_mode: bool,
+    /// Accepts `json_schema` response formats. Without it JSON mode is used and the schema is
+    /// given in the prompt.
+    pub json_schema: bool,
+    /// Accepts image parts.
+    pub vision: bool,
+    /// Accepts `input_audio` parts.
+    pub audio: bool,
+    /// The message and delta field holding the reasoning of thinking models, e.g.
+    /// `reasoning_content` or `reasoning`.
+    pub reasoning_field: Option<String>,
+    /// Accepts `stream_options`, which is needed to get the token usage of a stream.
+    pub stream_usage: bool,
+    /// How the reasoning options of [`CallOptions`] are sent, `None` when they are ignored.
+    pub reasoning_params: Option<ReasoningParams>,
+}
+
+/// The request fields a server reads the reasoning effort or budget from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReasoningParams {
+    /// `"reasoning_effort": "low" | "medium" | "high"`, as OpenAI does. Budgets are mapped to
+    /// the closest effort.
+    Effort,
+    /// `"reasoning": { "effort": .. }` or `"reasoning": { "max_tokens": .. }`, as OpenRouter
+    /// does.
+    Object,
+    /// `"enable_thinking": true, "thinking_budget": ..`, as Qwen does.
+    EnableThinking,
+}
+
+impl ReasoningParams {
+    fn fields(&self, options: &CallOptions) -> Map<String, Value> {
+        let mut fields = Map::new();
+        match self {
+            ReasoningParams::Effort => {
+                if let Some(effort) = options.reasoning_effort_level() {
+                    fields.insert("reasoning_effort".to_string(), json!(effort.as_str()));
+                }
+            }
+            ReasoningParams::Object => {
+                let reasoning = match (options.reasoning_budget, options.reasoning_effort) {
+                    (Some(0), _) => Some(json!({ "enabled": false })),
+                    (Some(budget), _) => Some(json!({ "max_tokens": budget })),
+                    (None, Some(effort)) => Some(json!({ "effort": effort.as_str() })),
+                    (None, None) => None,
+                };
+                if let Some(reasoning) = reasoning {
+                    fields.insert("reasoning".to_string(), reasoning);
+                }
+            }
+            ReasoningParams::EnableThinking => {
+                if let Some(budget) = options.reasoning_budget_tokens() {
+                    fields.insert("enable_thinking".to_string(), json!(budget > 0));
+                    if budget > 0 {
+                        fields.insert("thinking_budget".to_string(), json!(budget));
+                    }
+                }
+            }
+        }
+        fields
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            tools: true,
+            json_mode: true,
+            json_schema: true,
+            vision: false,
+            audio: false,
+            reasoning_field: None,
+            stream_usage: false,
+            reasoning_params: None,
+        }
+    }
+}
+
+impl Capabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tools(mut self, tools: bool) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn with_json_mode(mut self, json_mode: bool) -> Self {
+        self.json_mode = json_mode;
+        self
+    }
+
+    pub fn with_json_schema(mut self, json_schema: bool) -> Self {
+        self.json_schema = json_schema;
+        self
+    }
+
+    pub fn with_vision(mut self, vision: bool) -> Self {
+        self.vision = vision;
+        self
+    }
+
+    pub fn with_audio(mut self, audio: bool) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    pub fn with_reasoning_field<S: Into<String>>(mut self, reasoning_field: S) -> Self {
+        self.reasoning_field = Some(reasoning_field.into());
+        self
+    }
+
+    pub fn with_stream_usage(mut self, stream_usage: bool) -> Self {
+        self.stream_usage = stream_usage;
+        self
+    }
+
+    pub fn with_reasoning_params(mut self, reasoning_params: ReasoningParams) -> Self {
+        self.reasoning_params = Some(reasoning_params);
+        self
+    }
+}
+
+type ErrorHandler = Arc<dyn Fn(u16, &Value) -> LLMError + Send + Sync>;
+
+/// Client for any server exposing the OpenAI chat completions API, such as Groq, Together,
+/// OpenRouter, vLLM, the llama.cpp server or LM Studio.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let llm = OpenAICompatible::new("Fireworks", "https://api.fireworks.ai/inference/v1")
+///     .with_api_key(std::env::var("FIREWORKS_API_KEY").unwrap())
+///     .with_model("llama-3.1-70b")
+///     .with_model_name("llama-3.1-70b", "accounts/fireworks/models/llama-v3p1-70b-instruct")
+///     .with_capabilities(Capabilities::new().with_vision(true));
+///
+/// let groq = OpenAICompatible::groq().with_model("llama-3.3-70b-versatile");
+/// ```
+#[derive(Clone)]
+pub struct OpenAICompatible {
+    provider: String,
+    base_url: String,
+    chat_path: String,
+    auth: AuthScheme,
+    headers: Vec<(String, String)>,
+    pub(crate) model: String,
+    model_names: HashMap<String, String>,
+    capabilities: Capabilities,
+    context_window: Option<usize>,
+    pub(crate) options: CallOptions,
+    error_handler: Option<ErrorHandler>,
+}
+
+impl OpenAICompatible {
+    /// A client for the server at `base_url`. `provider` names it in error messages.
+    pub fn new<P: Into<String>, U: Into<String>>(provider: P, base_url: U) -> Self {
+        Self {
+            provider: provider.into(),
+            base_url: base_url.into(),
+            chat_path: "/chat/completions".to_string(),
+            auth: AuthScheme::None,
+            headers: Vec::new(),
+            model: String::new(),
+            model_names: HashMap::new(),
+            capabilities: Capabilities::default(),
+            context_window: None,
+            options: CallOptions::default(),
+            error_handler: None,
+        }
+    }
+
+    /// [Groq](https://console.groq.com/docs/openai), authenticated with `GROQ_API_KEY`.
+    pub fn groq() -> Self {
+        Self::new("Groq", "https://api.groq.com/openai/v1")
+            .with_api_key(std::env::var("GROQ_API_KEY").unwrap_or_default())
+            .with_capabilities(
+                Capabilities::new()
+                    .with_vision(true)
+                    .with_reasoning_field("reasoning")
+                    .with_reasoning_params(ReasoningParams::Effort)
+                    .with_stream_usage(true),
+            )
+    }
+
+    /// [Together AI](https://docs.together.ai), authenticated with `TOGETHER_API_KEY`.
+    pub fn together() -> Self {
+        Self::new("Together", "https://api.together.xyz/v1")
+            .with_api_key(std::env::var("TOGETHER_API_KEY").unwrap_or_default())
+            .with_capabilities(
+                Capabilities::new()
+                    .with_vision(true)
+                    .with_stream_usage(true),
+            )
+    }
+
+    /// [OpenRouter](https://openrouter.ai/docs), authenticated with `OPENROUTER_API_KEY`.
+    pub fn openrouter() -> Self {
+        Self::new("OpenRouter", "https://openrouter.ai/api/v1")
+            .with_api_key(std::env::var("OPENROUTER_API_KEY").unwrap_or_default())
+            .with_capabilities(
+                Capabilities::new()
+                    .with_vision(true)
+                    .with_audio(true)
+                    .with_reasoning_field("reasoning")
+                    .with_reasoning_params(ReasoningParams::Object)
+                    .with_stream_usage(true),
+            )
+    }
+
+    /// A local [vLLM](https://docs.vllm.ai) server.
+    pub fn vllm() -> Self {
+        Self::new("vLLM", "http://localhost:8000/v1").with_capabilities(
+            Capabilities::new()
+                .with_vision(true)
+                .with_reasoning_field("reasoning_content")
+                .with_reasoning_params(ReasoningParams::Effort)
+                .with_stream_usage(true),
+        )
+    }
+
+    /// A local [llama.cpp](https://github.com/ggml-org/llama.cpp) server.
+    pub fn llama_cpp() -> Self {
+        Self::new("llama.cpp", "http://localhost:8080/v1").with_capabilities(
+            Capabilities::new()
+                .with_reasoning_field("reasoning_content")
+                .with_stream_usage(true),
+        )
+    }
+
+    /// A local [LM Studio](https://lmstudio.ai/docs) server.
+    pub fn lm_studio() -> Self {
+        Self::new("LM Studio", "http://localhost:1234/v1")
+            .with_capabilities(Capabilities::new().with_stream_usage(true))
+    }
+
+    pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Sends `provider_model` whenever `model` is selected, so the same model names can be
+    /// used across providers.
+    pub fn with_model_name<M: Into<String>, P: Into<String>>(
+        mut self,
+        model: M,
+        provider_model: P,
+    ) -> Self {
+        self.model_names.insert(model.into(), provider_model.into());
+        self
+    }
+
+    pub fn with_options(mut self, options: CallOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Authenticates with `Authorization: Bearer <api_key>`.
+    pub fn with_api_key<S: Into<String>>(self, api_key: S) -> Self {
+        self.with_auth(AuthScheme::Bearer(api_key.into()))
+    }
+
+    pub fn with_auth(mut self, auth: AuthScheme) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Adds a header to every request, e.g. the `HTTP-Referer` OpenRouter uses for rankings.
+    pub fn with_header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// The path of the chat completions endpoint under the base URL, `/chat/completions` by
+    /// default.
+    pub fn with_chat_path<S: Into<String>>(mut self, chat_path: S) -> Self {
+        self.chat_path = chat_path.into();
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The model's context window, for models missing from the built in table.
+    pub fn with_context_window(mut self, context_window: usize) -> Self {
+        self.context_window = Some(context_window);
+        self
+    }
+
+    /// Maps error responses, from their status code and JSON body, to errors. By default
+    /// they become [`OpenAICompatibleError`]s.
+    pub fn with_error_handler<F>(mut self, error_handler: F) -> Self
+    where
+        F: Fn(u16, &Value) -> LLMError + Send + Sync + 'static,
+    {
+        self.error_handler = Some(Arc::new(error_handler));
+        self
+    }
+
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    fn request(&self, client: &Client) -> RequestBuilder {
+        let mut request = client
+            .post(format!("{}{}", self.base_url, self.chat_path))
+            .header("Content-Type", "application/json");
+        request = match &self.auth {
+            AuthScheme::None => request,
+            AuthScheme::Bearer(token) => request.bearer_auth(token),
+            AuthScheme::Header { name, value } => request.header(name, value),
+        };
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+    }
+
+    async fn send(&self, payload: &Payload) -> Result<Response, LLMError> {
+        let res = self.request(&Client::new()).json(payload).send().await?;
+        let status = res.status().as_u16();
+        if res.status().is_success() {
+            return Ok(res);
+        }
+        let headers = res.headers().clone();
+        let text = res.text().await?;
+        let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+        let error = match &self.error_handler {
+            Some(error_handler) => error_handler(status, &body),
+            None => OpenAICompatibleError::from_status(&self.provider, status, &body).into(),
+        };
+        Err(error.with_retry_after(&headers))
+    }
+
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let payload = self.build_payload(messages, false)?;
+        let raw_response = self.send(&payload).await?.json::<Value>().await?;
+        let response: ApiResponse = serde_json::from_value(raw_response.clone())?;
+
+        let choice = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| LLMError::ContentNotFound("/choices/0".to_string()))?;
+        let reasoning_content = self
+            .capabilities
+            .reasoning_field
+            .as_ref()
+            .and_then(|field| choice.message.extra.get(field))
+            .and_then(Value::as_str)
+            .filter(|reasoning| !reasoning.is_empty())
+            .map(String::from);
+        let mut generation = choice.message.content.unwrap_or_default();
+        if let Some(response_format) = self.prompted_format() {
+            if choice.message.tool_calls.is_none() {
+                generation = response_format.validate(&generation)?;
+            }
+        }
+
+        Ok(GenerateResult {
+            tokens: response.usage.map(TokenUsage::from),
+            generation,
+            tool_calls: choice.message.tool_calls.unwrap_or_default(),
+            finish_reason: choice.finish_reason.as_deref().map(FinishReason::from),
+            model: response.model,
+            reasoning_content,
+            logprobs: choice.logprobs.filter(|logprobs| !logprobs.is_null()),
+            raw_response: Some(raw_response),
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn build_payload(
+        &self,
+        messages: &[Message],
+        stream: bool,
+    ) -> Result<Payload, LLMError> {
+        let capabilities = &self.capabilities;
+        let options = &self.options;
+
+        let tools = match &options.functions {
+            Some(functions) if capabilities.tools => Some(serde_json::to_value(
+                functions
+                    .iter()
+                    .cloned()
+                    .map(ChatCompletionTool::try_from_langchain)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )?),
+            _ => None,
+        };
+        let tool_choice = match &options.function_call_behavior {
+            Some(behavior) if tools.is_some() => Some(serde_json::to_value(
+                ChatCompletionToolChoiceOption::from_langchain(behavior.clone()),
+            )?),
+            _ => None,
+        };
+        let response_format = match &options.response_format {
+            Some(ResponseFormat::JsonSchema { .. })
+                if capabilities.json_mode && !capabilities.json_schema =>
+            {
+                Some(json!({ "type": "json_object" }))
+            }
+            Some(response_format) if capabilities.json_mode => Some(serde_json::to_value(
+                async_openai::types::ResponseFormat::from_langchain(response_format.clone()),
+            )?),
+            _ => None,
+        };
+        let messages = match self.prompted_format() {
+            Some(response_format) => response_format.with_instructions(messages),
+            None => messages.to_vec(),
+        };
+
+        Ok(Payload {
+            model: self
+                .model_names
+                .get(&self.model)
+                .unwrap_or(&self.model)
+                .clone(),
+            messages: messages
+                .iter()
+                .map(|message| ChatMessage::from_message(message, &self.provider, capabilities))
+                .collect::<Result<Vec<_>, _>>()?,
+            max_tokens: options.max_tokens,
+            stream: stream.then_some(true),
+            stream_options: (stream
+                && capabilities.stream_usage
+                && options.stream_usage.unwrap_or(true))
+            .then(|| json!({ "include_usage": true })),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            frequency_penalty: options.frequency_penalty,
+            presence_penalty: options.presence_penalty,
+            stop: options.stop_words.clone(),
+            seed: options.seed,
+            n: options.n,
+            response_format,
+            tools,
+            tool_choice,
+            extra: capabilities
+                .reasoning_params
+                .map(|params| params.fields(options))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The response format to ask for in the prompt, when the server cannot enforce it.
+    fn prompted_format(&self) -> Option<&ResponseFormat> {
+        self.options
+            .response_format
+            .as_ref()
+            .filter(|response_format| match response_format {
+                ResponseFormat::Text => false,
+                ResponseFormat::JsonObject => !self.capabilities.json_mode,
+                ResponseFormat::JsonSchema { .. } => {
+                    !self.capabilities.json_mode || !self.capabilities.json_schema
+                }
+            })
+    }
+
+    fn parse_stream_chunk(
+        data: &str,
+        reasoning_field: Option<&str>,
+    ) -> Option<Result<StreamData, LLMError>> {
+        if data == "[DONE]" {
+            return None;
+        }
+        let chunk: Value = match serde_json::from_str(data) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return Some(Err(LLMError::ParsingError(format!(
+                    "Failed to parse SSE data: {}",
+                    e
+                ))))
+            }
+        };
+
+        let choice = chunk.pointer("/choices/0");
+        let delta = choice.and_then(|c| c.get("delta"));
+        let content = delta
+            .and_then(|d| d.get("content"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let reasoning = reasoning_field
+            .and_then(|field| delta.and_then(|d| d.get(field)))
+            .and_then(Value::as_str)
+            .filter(|r| !r.is_empty());
+        let tool_call_deltas = delta
+            .and_then(|d| d.get("tool_calls"))
+            .filter(|tool_calls| !tool_calls.is_null())
+            .and_then(|tool_calls| {
+                serde_json::from_value::<Vec<ChatCompletionMessageToolCallChunk>>(
+                    tool_calls.clone(),
+                )
+                .ok()
+            })
+            .map(|chunks| tool_call_deltas(&chunks))
+            .unwrap_or_default();
+        let finish_reason = choice
+            .and_then(|c| c.get("finish_reason"))
+            .and_then(Value::as_str)
+            .map(FinishReason::from);
+        let usage = chunk
+            .get("usage")
+            .filter(|u| !u.is_null())
+            .and_then(|usage| serde_json::from_value::<Usage>(usage.clone()).ok())
+            .map(TokenUsage::from);
+
+        if content.is_empty()
+            && reasoning.is_none()
+            && tool_call_deltas.is_empty()
+            && finish_reason.is_none()
+            && usage.is_none()
+        {
+            return None;
+        }
+
+        let model = chunk.get("model").and_then(Value::as_str).map(String::from);
+        let reasoning = reasoning.map(String::from);
+        Some(Ok(StreamData::new(chunk.clone(), usage, content)
+            .with_model(model)
+            .with_finish_reason(finish_reason)
+            .with_reasoning_content(reasoning)
+            .with_tool_call_deltas(tool_call_deltas)))
+    }
+}
+
+#[async_trait]
+impl LLM for OpenAICompatible {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        match &self.options.streaming_func {
+            Some(func) => {
+                let mut generate_result = GenerateResult::default();
+                let mut stream = self.stream(messages).await?;
+                while let Some(data) = stream.next().await {
+                    match data {
+                        Ok(value) => {
+                            generate_result.merge_stream_data(&value);
+                            if !value.content.is_empty() {
+                                let mut func = func.lock().await;
+                                let _ = func(value.content).await;
+                            }
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(generate_result)
+            }
+            None => {
+                cancellable(
+                    self.options.cancellation_token.as_ref(),
+                    self.generate(messages),
+                )
+                .await
+            }
+        }
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let payload = self.build_payload(messages, true)?;
+        let token = self.options.cancellation_token.clone();
+        let res = cancellable(token.as_ref(), self.send(&payload)).await?;
+
+        let reasoning_field = self.capabilities.reasoning_field.clone();
+        let processed_stream = sse::data_events(res.bytes_stream()).filter_map(move |data| {
+            let data = match data {
+                Ok(data) => Self::parse_stream_chunk(&data, reasoning_field.as_deref()),
+                Err(e) => Some(Err(e)),
+            };
+            async move { data }
+        });
+
+        Ok(cancellable_stream(Box::pin(processed_stream), token))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.options.merge_options(options)
+    }
+
+    fn override_options(&mut self, options: CallOptions) {
+        self.options.override_options(options)
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.context_window
+            .or_else(|| context_window_for_model(&self.model))
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.options
+            .max_tokens
+            .map(|max_tokens| max_tokens as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language_models::{options::ReasoningEffort, structured::StructuredOutput};
+    use crate::schemas::{ContentPart, FunctionCallBehavior, FunctionDefinition, ResponseFormat};
+    use mockito::Matcher;
+
+    fn completion() -> String {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "llama-3.3-70b-versatile",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "reasoning": "The user wants the weather.",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "get_weather", "arguments": "{\"city\":\"Lima\"}" }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": { "prompt_tokens": 20, "completion_tokens": 10, "total_tokens": 30 }
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_tools() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .match_header("authorization", "Bearer test-key")
+            .match_header("x-title", "langchain-rust")
+            .match_body(Matcher::PartialJson(json!({
+                "model": "llama-3.3-70b-versatile",
+                "tools": [{ "type": "function", "function": { "name": "get_weather" } }],
+                "tool_choice": "auto"
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(completion())
+            .create_async()
+            .await;
+
+        let llm = OpenAICompatible::groq()
+            .with_base_url(server.url())
+            .with_api_key("test-key")
+            .with_header("X-Title", "langchain-rust")
+            .with_model("llama")
+            .with_model_name("llama", "llama-3.3-70b-versatile")
+            .with_options(
+                CallOptions::new()
+                    .with_functions(vec![FunctionDefinition::new(
+                        "get_weather",
+                        "Get the weather for a city",
+                        json!({ "type": "object", "properties": { "city": { "type": "string" } } }),
+                    )])
+                    .with_function_call_behavior(FunctionCallBehavior::Auto),
+            );
+        let result = llm
+            .generate(&[Message::new_human_message("Weather in Lima?")])
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(result.generation, "");
+        assert_eq!(result.tool_calls[0].id, "call_1");
+        assert_eq!(
+            result.tool_calls[0].function.arguments,
+            r#"{"city":"Lima"}"#
+        );
+        assert_eq!(result.finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(
+            result.reasoning_content.as_deref(),
+            Some("The user wants the weather.")
+        );
+        assert_eq!(result.tokens.unwrap().total_tokens, 30);
+    }
+
+    #[tokio::test]
+    async fn test_stream_with_tool_calls() {
+        let chunks = [
+            json!({ "model": "m", "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "" } }] }),
+            json!({ "model": "m", "choices": [{ "index": 0, "delta": { "reasoning_content": "Think" } }] }),
+            json!({ "model": "m", "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 0, "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "{\"city\":" } }] } }] }),
+            json!({ "model": "m", "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 0, "function": { "arguments": "\"Lima\"}" } }] } }] }),
+            json!({ "model": "m", "choices": [{ "index": 0, "delta": {}, "finish_reason": "tool_calls" }] }),
+            json!({ "model": "m", "choices": [], "usage": { "prompt_tokens": 5, "completion_tokens": 7, "total_tokens": 12 } }),
+        ];
+        let body = chunks
+            .iter()
+            .map(|chunk| format!("data: {}\n\n", chunk))
+            .collect::<String>()
+            + "data: [DONE]\n\n";
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(Matcher::PartialJson(json!({
+                "stream": true,
+                "stream_options": { "include_usage": true }
+            })))
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let llm = OpenAICompatible::vllm()
+            .with_base_url(format!("{}/v1", server.url()))
+            .with_model("m")
+            .with_options(CallOptions::new().with_streaming_func(|_| async { Ok(()) }));
+        let result = LLM::generate(&llm, &[Message::new_human_message("Hi")])
+            .await
+            .unwrap();
+
+        assert_eq!(result.reasoning_content.as_deref(), Some("Think"));
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.tool_calls[0].function.name, "get_weather");
+        assert_eq!(
+            result.tool_calls[0].function.arguments,
+            r#"{"city":"Lima"}"#
+        );
+        assert_eq!(result.finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(result.tokens.unwrap().total_tokens, 12);
+    }
+
+    #[test]
+    fn test_build_payload_follows_capabilities() {
+        let messages = vec![
+            Message::new_human_message_with_parts(vec![
+                ContentPart::text("Summarize"),
+                ContentPart::file_bytes("text/plain", b"notes", None),
+            ]),
+            Message::new_tool_message("Sunny", "call_1"),
+        ];
+        let text_only = OpenAICompatible::new("Local", "http://localhost")
+            .with_capabilities(Capabilities::new().with_tools(false).with_json_mode(false))
+            .with_options(CallOptions::new().with_response_format(ResponseFormat::JsonObject));
+
+        let payload =
+            serde_json::to_value(text_only.build_payload(&messages, false).unwrap()).unwrap();
+
+        assert_eq!(
+            payload["messages"],
+            json!([
+                { "role": "system", "content": ResponseFormat::JsonObject.instructions().unwrap() },
+                { "role": "user", "content": "Summarize\nnotes" },
+                { "role": "user", "content": "Sunny" }
+            ])
+        );
+        assert!(payload.get("response_format").is_none());
+
+        let image = vec![Message::new_human_message_with_parts(vec![
+            ContentPart::image_url("https://example.com/cat.png"),
+        ])];
+        assert!(matches!(
+            text_only.build_payload(&image, false),
+            Err(LLMError::UnsupportedContent(e)) if e == "Local does not accept images"
+        ));
+
+        let payload = serde_json::to_value(
+            OpenAICompatible::openrouter()
+                .build_payload(&image, false)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            payload["messages"][0]["content"],
+            json!([{ "type": "image_url", "image_url": { "url": "https://example.com/cat.png" } }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_structured_output_fallbacks() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"]
+        });
+        let format = ResponseFormat::json_schema("place", schema.clone());
+        let messages = vec![Message::new_human_message("Where is Machu Picchu?")];
+
+        let json_object_only = OpenAICompatible::new("Local", "http://localhost")
+            .with_capabilities(Capabilities::new().with_json_schema(false))
+            .with_options(CallOptions::new().with_response_format(format.clone()));
+        let payload =
+            serde_json::to_value(json_object_only.build_payload(&messages, false).unwrap())
+                .unwrap();
+        assert_eq!(payload["response_format"], json!({ "type": "json_object" }));
+        assert_eq!(payload["messages"][0]["role"], "system");
+        assert!(payload["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .contains(&schema.to_string()));
+
+        let payload = serde_json::to_value(
+            OpenAICompatible::new("Local", "http://localhost")
+                .with_options(CallOptions::new().with_response_format(format.clone()))
+                .build_payload(&messages, false)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(payload["response_format"]["type"], "json_schema");
+        assert_eq!(payload["messages"].as_array().unwrap().len(), 1);
+
+        let mut server = mockito::Server::new_async().await;
+        let response = |content: &str| {
+            json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string()
+        };
+        let mock = server
+            .mock("POST", "/chat/completions")
+            .with_body(response(
+                "Here you go:\n```json\n{\"city\": \"Cusco\"}\n```",
+            ))
+            .create_async()
+            .await;
+        let text_only = OpenAICompatible::new("Local", server.url())
+            .with_capabilities(Capabilities::new().with_json_mode(false));
+
+        #[derive(serde::Deserialize)]
+        struct Place {
+            city: String,
+        }
+        let place: Place = text_only
+            .generate_structured(&messages, format.clone())
+            .await
+            .unwrap();
+        assert_eq!(place.city, "Cusco");
+        mock.assert_async().await;
+        mock.remove_async().await;
+
+        server
+            .mock("POST", "/chat/completions")
+            .with_body(response("{\"country\": \"Peru\"}"))
+            .create_async()
+            .await;
+        let result = text_only
+            .with_options(CallOptions::new().with_response_format(format))
+            .generate(&messages)
+            .await;
+        assert!(matches!(result, Err(LLMError::ParsingError(_))));
+    }
+
+    #[test]
+    fn test_build_payload_reasoning_params() {
+        let messages = vec![Message::new_human_message("Hi")];
+        let payload = |llm: OpenAICompatible, options: CallOptions| {
+            serde_json::to_value(
+                llm.with_options(options)
+                    .build_payload(&messages, false)
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let effort = CallOptions::new().with_reasoning_effort(ReasoningEffort::Low);
+        let budget = CallOptions::new().with_reasoning_budget(2048);
+
+        assert_eq!(
+            payload(OpenAICompatible::groq(), budget.clone())["reasoning_effort"],
+            "low"
+        );
+        assert_eq!(
+            payload(OpenAICompatible::openrouter(), effort.clone())["reasoning"],
+            json!({ "effort": "low" })
+        );
+        assert_eq!(
+            payload(OpenAICompatible::openrouter(), budget.clone())["reasoning"],
+            json!({ "max_tokens": 2048 })
+        );
+        let thinking = OpenAICompatible::new("Local", "http://localhost").with_capabilities(
+            Capabilities::new().with_reasoning_params(ReasoningParams::EnableThinking),
+        );
+        let payload_thinking = payload(thinking.clone(), effort);
+        assert_eq!(payload_thinking["enable_thinking"], true);
+        assert_eq!(payload_thinking["thinking_budget"], 1024);
+        let disabled = payload(thinking, CallOptions::new().with_reasoning_budget(0));
+        assert_eq!(disabled["enable_thinking"], false);
+        assert!(disabled.get("thinking_budget").is_none());
+        assert!(payload(OpenAICompatible::together(), budget)
+            .get("reasoning_effort")
+            .is_none());
+    }
+
+    #[test]
+    fn test_usage_with_cached_tokens() {
+        let openai: Usage = serde_json::from_value(json!({
+            "prompt_tokens": 2000, "completion_tokens": 10, "total_tokens": 2010,
+            "prompt_tokens_details": { "cached_tokens": 1920 }
+        }))
+        .unwrap();
+        assert_eq!(
+            TokenUsage::from(openai),
+            TokenUsage::new(2000, 10).with_cache_tokens(1920, 0)
+        );
+
+        let deepseek: Usage = serde_json::from_value(json!({
+            "prompt_tokens": 100, "completion_tokens": 10, "total_tokens": 110,
+            "prompt_cache_hit_tokens": 64, "prompt_cache_miss_tokens": 36
+        }))
+        .unwrap();
+        assert_eq!(TokenUsage::from(deepseek).cache_read_tokens, 64);
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .with_body(json!({ "error": { "message": "Slow down" } }).to_string())
+            .create_async()
+            .await;
+
+        let llm = OpenAICompatible::together().with_base_url(server.url());
+        let error = llm.invoke("Hi").await.unwrap_err();
+
+        assert!(error.is_retryable());
+        assert_eq!(error.retry_after(), Some(std::time::Duration::from_secs(1)));
+        assert_eq!(
+            error.to_string(),
+            "OpenAI compatible API error: Rate limit exceeded - Together: Slow down"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum OpenAICompatibleError {
+    #[error("Invalid request - {0}")]
+    InvalidRequestError(String),
+
+    #[error("Authentication failed - {0}")]
+    AuthenticationError(String),
+
+    #[error("Permission denied - {0}")]
+    PermissionError(String),
+
+    #[error("Not found - {0}")]
+    NotFoundError(String),
+
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
+    #[error("Rate limit exceeded - {0}")]
+    RateLimitError(String, Option<Duration>),
+
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
+    #[error("Server error - {0}")]
+    ServerError(String, Option<Duration>),
+
+    #[error("Unexpected status {status} - {message}")]
+    OtherError { status: u16, message: String },
+}
+
+impl OpenAICompatibleError {
+    /// Maps an error response by status code. The message names the provider and carries the
+    /// `error.message` of the body when the server sent one.
+    pub(crate) fn from_status(provider: &str, status: u16, body: &Value) -> Self {
+        let message = format!("{}: {}", provider, error_message(body));
+        match status {
+            400 | 422 => OpenAICompatibleError::InvalidRequestError(message),
+            401 => OpenAICompatibleError::AuthenticationError(message),
+            403 => OpenAICompatibleError::PermissionError(message),
+            404 => OpenAICompatibleError::NotFoundError(message),
+            429 => OpenAICompatibleError::RateLimitError(message, None),
+            500..=599 => OpenAICompatibleError::ServerError(message, None),
+            status => OpenAICompatibleError::OtherError { status, message },
+        }
+    }
+}
+
+/// The human readable message of an error body, which OpenAI compatible servers put either in
+/// `error.message`, in `message` or send as a bare string.
+pub(crate) fn error_message(body: &Value) -> String {
+    body.pointer("/error/message")
+        .or_else(|| body.get("message"))
+        .or_else(|| body.get("error"))
+        .unwrap_or(body)
+        .as_str()
+        .map(String::from)
+        .unwrap_or_else(|| body.to_string())
+}
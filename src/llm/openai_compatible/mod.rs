@@ -0,0 +1,6 @@
+mod client;
+mod models;
+pub use client::*;
+
+mod error;
+pub use error::*;
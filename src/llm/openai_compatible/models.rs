@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    language_models::{LLMError, TokenUsage},
+    schemas::{ContentPart, FunctionCallResponse, Message, MessageType},
+};
+
+use super::Capabilities;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub(crate) enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ChatContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    InputAudio { input_audio: InputAudio },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ImageUrl {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct InputAudio {
+    pub data: String,
+    pub format: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ChatMessage {
+    pub role: String,
+    pub content: Option<ChatContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<FunctionCallResponse>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new<S: Into<String>>(role: S, content: &str) -> Self {
+        Self {
+            role: role.into(),
+            content: Some(ChatContent::Text(content.to_string())),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    /// Converts a message to the chat completions format, downgrading what the provider does
+    /// not support: without tools, tool results are sent as user messages, and without
+    /// vision or audio only text and text files are accepted.
+    pub fn from_message(
+        message: &Message,
+        provider: &str,
+        capabilities: &Capabilities,
+    ) -> Result<Self, LLMError> {
+        Ok(match message.message_type {
+            MessageType::SystemMessage => Self::new("system", &message.content),
+            MessageType::AIMessage => match &message.tool_calls {
+                Some(tool_calls) if capabilities.tools => Self {
+                    role: "assistant".to_string(),
+                    content: (!message.content.is_empty())
+                        .then(|| ChatContent::Text(message.content.clone())),
+                    tool_calls: Some(serde_json::from_value(tool_calls.clone())?),
+                    tool_call_id: None,
+                },
+                _ => Self::new("assistant", &message.content),
+            },
+            MessageType::HumanMessage if message.is_multimodal() => Self {
+                role: "user".to_string(),
+                content: Some(Self::multimodal_content(message, provider, capabilities)?),
+                tool_calls: None,
+                tool_call_id: None,
+            },
+            MessageType::HumanMessage => Self::new("user", &message.content),
+            MessageType::ToolMessage if capabilities.tools => Self {
+                tool_call_id: message.id.clone(),
+                ..Self::new("tool", &message.content)
+            },
+            MessageType::ToolMessage => Self::new("user", &message.content),
+        })
+    }
+
+    fn multimodal_content(
+        message: &Message,
+        provider: &str,
+        capabilities: &Capabilities,
+    ) -> Result<ChatContent, LLMError> {
+        let parts = message
+            .parts()
+            .into_iter()
+            .map(|part| ChatContentPart::from_part(part, provider, capabilities))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if capabilities.vision || capabilities.audio {
+            return Ok(ChatContent::Parts(parts));
+        }
+        // Text only providers get a plain string, which every server accepts
+        let text = parts
+            .into_iter()
+            .filter_map(|part| match part {
+                ChatContentPart::Text { text } => Some(text),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(ChatContent::Text(text))
+    }
+}
+
+impl ChatContentPart {
+    fn from_part(
+        part: ContentPart,
+        provider: &str,
+        capabilities: &Capabilities,
+    ) -> Result<Self, LLMError> {
+        if let Some(text) = part.file_text() {
+            return Ok(ChatContentPart::Text { text });
+        }
+        let url = part.to_url().unwrap_or_default();
+        match part {
+            ContentPart::Text { text, .. } => Ok(ChatContentPart::Text { text }),
+            ContentPart::ImageUrl { detail, .. } if capabilities.vision => {
+                Ok(ChatContentPart::ImageUrl {
+                    image_url: ImageUrl { url, detail },
+                })
+            }
+            ContentPart::ImageBase64 { .. } if capabilities.vision => {
+                Ok(ChatContentPart::ImageUrl {
+                    image_url: ImageUrl { url, detail: None },
+                })
+            }
+            ContentPart::Audio { .. } if capabilities.audio => Ok(ChatContentPart::InputAudio {
+                input_audio: InputAudio {
+                    format: part.audio_format().unwrap_or_default().to_string(),
+                    data: url,
+                },
+            }),
+            ContentPart::ImageUrl { .. } | ContentPart::ImageBase64 { .. } => Err(
+                LLMError::UnsupportedContent(format!("{} does not accept images", provider)),
+            ),
+            ContentPart::Audio { .. } => Err(LLMError::UnsupportedContent(format!(
+                "{} does not accept audio",
+                provider
+            ))),
+            ContentPart::File { media_type, .. } => Err(LLMError::UnsupportedContent(format!(
+                "{} does not accept {} files",
+                provider, media_type
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Payload {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<Value>,
+    /// Provider specific fields, such as the reasoning options.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+    #[serde(default)]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    /// How DeepSeek reports the prompt tokens found in its cache.
+    #[serde(default)]
+    pub prompt_cache_hit_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct PromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: Option<u32>,
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        let cache_read_tokens = usage
+            .prompt_tokens_details
+            .and_then(|details| details.cached_tokens)
+            .or(usage.prompt_cache_hit_tokens)
+            .unwrap_or_default();
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            ..Default::default()
+        }
+        .with_cache_tokens(cache_read_tokens, 0)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct ResponseMessage {
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<FunctionCallResponse>>,
+    /// Provider specific fields, such as the reasoning of thinking models.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct Choice {
+    pub message: ResponseMessage,
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub logprobs: Option<Value>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct ApiResponse {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    pub usage: Option<Usage>,
+}
@@ -1,15 +1,15 @@
 use crate::{
-    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError, TokenUsage},
-    llm::QwenError,
+    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError},
+    llm::{
+        error_message, Capabilities, OpenAICompatible, OpenAICompatibleError, QwenError,
+        ReasoningParams,
+    },
     schemas::{Message, StreamData},
 };
 use async_trait::async_trait;
-use futures::{Stream, StreamExt};
-use reqwest::Client;
+use futures::Stream;
 use serde_json::Value;
-use std::{pin::Pin, str, str::from_utf8};
-
-use super::models::{ApiResponse, ErrorResponse, Payload, QwenMessage};
+use std::pin::Pin;
 
 /// Parse error from JSON response and return appropriate QwenError
 fn parse_error_response(code: &str, message: &str) -> LLMError {
@@ -27,7 +27,7 @@ fn parse_error_response(code: &str, message: &str) -> LLMError {
 
         // 429 errors
         "ModelServingError" => {
-            LLMError::QwenError(QwenError::ModelServingError(message.to_string()))
+            LLMError::QwenError(QwenError::ModelServingError(message.to_string(), None))
         }
         "PrepaidBillOverdue" => {
             LLMError::QwenError(QwenError::PrepaidBillOverdueError(message.to_string()))
@@ -68,7 +68,7 @@ fn parse_error_response(code: &str, message: &str) -> LLMError {
 
         // 503 errors
         "ModelUnavailable" => {
-            LLMError::QwenError(QwenError::ModelUnavailableError(message.to_string()))
+            LLMError::QwenError(QwenError::ModelUnavailableError(message.to_string(), None))
         }
 
         // Other errors
@@ -188,13 +188,10 @@ impl ToString for QwenModel {
     }
 }
 
-/// Qwen client
+/// Qwen client for the DashScope OpenAI compatible mode, built on [`OpenAICompatible`]
 #[derive(Clone)]
 pub struct Qwen {
-    model: String,
-    options: CallOptions,
-    api_key: String,
-    base_url: String,
+    client: OpenAICompatible,
 }
 
 impl Default for Qwen {
@@ -206,278 +203,100 @@ impl Default for Qwen {
 impl Qwen {
     /// Create a new Qwen client with default settings
     pub fn new() -> Self {
-        Self {
-            model: QwenModel::QwenTurbo.to_string(), // Default to Turbo model
-            options: CallOptions::default(),
-            api_key: std::env::var("QWEN_API_KEY").unwrap_or_default(),
-            base_url: "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions"
-                .to_string(),
-        }
+        let client = OpenAICompatible::new(
+            "Qwen",
+            "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions",
+        )
+        .with_chat_path("")
+        .with_api_key(std::env::var("QWEN_API_KEY").unwrap_or_default())
+        .with_model(QwenModel::QwenTurbo.to_string()) // Default to Turbo model
+        .with_capabilities(
+            Capabilities::new()
+                .with_json_schema(false)
+                .with_vision(true)
+                .with_audio(true)
+                .with_reasoning_field("reasoning_content")
+                .with_reasoning_params(ReasoningParams::EnableThinking)
+                .with_stream_usage(true),
+        )
+        .with_error_handler(qwen_error);
+        Self { client }
     }
 
     /// Set the model
     pub fn with_model<S: Into<String>>(mut self, model: S) -> Self {
-        self.model = model.into();
+        self.client = self.client.with_model(model);
         self
     }
 
     /// Set call options
     pub fn with_options(mut self, options: CallOptions) -> Self {
-        self.options = options;
+        self.client = self.client.with_options(options);
         self
     }
 
     /// Set API key
     pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
-        self.api_key = api_key.into();
+        self.client = self.client.with_api_key(api_key);
         self
     }
 
-    /// Set the base URL
+    /// Set the full URL of the chat completions endpoint
     pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
-        self.base_url = base_url.into();
+        self.client = self.client.with_base_url(base_url);
         self
     }
-
-    /// Generates text using the Qwen API
-    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
-        let client = Client::new();
-        let is_stream = self.options.streaming_func.is_some();
-
-        let payload = self.build_payload(messages, is_stream);
-        let res = client
-            .post(&self.base_url)
-            .header("Authorization", format!("Bearer {}", &self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await?;
-
-        match res.status().as_u16() {
-            200 => {
-                let api_response = res.json::<ApiResponse>().await?;
-
-                // Extract the first choice content
-                let generation = match api_response.choices.first() {
-                    Some(choice) => choice.message.content.clone(),
-                    None => {
-                        return Err(LLMError::ContentNotFound(
-                            "No content returned from API".to_string(),
-                        ))
-                    }
-                };
-
-                let tokens = Some(TokenUsage {
-                    prompt_tokens: api_response.usage.prompt_tokens,
-                    completion_tokens: api_response.usage.completion_tokens,
-                    total_tokens: api_response.usage.total_tokens,
-                });
-
-                Ok(GenerateResult { tokens, generation })
-            }
-            400 => {
-                let error = res.json::<ErrorResponse>().await?;
-                Err(parse_error_response(error.code.as_str(), &error.message))
-            }
-            401 => {
-                let error = res.json::<ErrorResponse>().await?;
-                Err(parse_error_response(error.code.as_str(), &error.message))
-            }
-            429 => {
-                let error = res.json::<ErrorResponse>().await?;
-                Err(parse_error_response(error.code.as_str(), &error.message))
-            }
-            500 => {
-                let error = res.json::<ErrorResponse>().await?;
-                Err(parse_error_response(error.code.as_str(), &error.message))
-            }
-            503 => {
-                let error = res.json::<ErrorResponse>().await?;
-                Err(parse_error_response(error.code.as_str(), &error.message))
-            }
-            _ => {
-                let error = res.json::<ErrorResponse>().await?;
-                Err(parse_error_response(error.code.as_str(), &error.message))
-            }
-        }
-    }
-
-    /// Builds the API payload from messages
-    fn build_payload(&self, messages: &[Message], stream: bool) -> Payload {
-        let mut payload = Payload {
-            model: self.model.clone(),
-            messages: messages
-                .iter()
-                .map(QwenMessage::from_message)
-                .collect::<Vec<_>>(),
-            max_tokens: self.options.max_tokens,
-            stream: None,
-            stop: self.options.stop_words.clone(),
-            temperature: self.options.temperature,
-            top_p: self.options.top_p,
-            seed: None,          // Optional
-            result_format: None, // Optional
-        };
-
-        if stream {
-            payload.stream = Some(true);
-        }
-
-        payload
-    }
-
-    /// Parse Server-Sent Events (SSE) chunks
-    fn parse_sse_chunk(bytes: &[u8]) -> Result<Vec<Value>, LLMError> {
-        let text = from_utf8(bytes).map_err(|e| LLMError::OtherError(e.to_string()))?;
-        let mut values = Vec::new();
-
-        for line in text.lines() {
-            if line.starts_with("data: ") {
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    continue;
-                }
-
-                match serde_json::from_str::<Value>(data) {
-                    Ok(value) => values.push(value),
-                    Err(e) => {
-                        return Err(LLMError::OtherError(format!(
-                            "Failed to parse SSE data: {}, data: {}",
-                            e, data
-                        )));
-                    }
-                }
-            }
-        }
-
-        Ok(values)
-    }
 }
 
 #[async_trait]
 impl LLM for Qwen {
     async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
-        match &self.options.streaming_func {
-            Some(func) => {
-                let mut complete_response = String::new();
-                let mut stream = self.stream(messages).await?;
-                while let Some(data) = stream.next().await {
-                    match data {
-                        Ok(value) => {
-                            let mut func = func.lock().await;
-                            complete_response.push_str(&value.content);
-                            let _ = func(value.content).await;
-                        }
-                        Err(e) => return Err(e),
-                    }
-                }
-                let mut generate_result = GenerateResult::default();
-                generate_result.generation = complete_response;
-                Ok(generate_result)
-            }
-            None => self.generate(messages).await,
-        }
+        self.client.generate(messages).await
     }
 
     async fn stream(
         &self,
         messages: &[Message],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
-        let client = Client::new();
-        let payload = self.build_payload(messages, true);
-        let request = client
-            .post(&self.base_url)
-            .header("Authorization", format!("Bearer {}", &self.api_key))
-            .header("Content-Type", "application/json")
-            .header("Accept", "text/event-stream")
-            .json(&payload)
-            .build()?;
-
-        let stream = client.execute(request).await?;
-        let stream = stream.bytes_stream();
-
-        let processed_stream = stream
-            .then(move |result| {
-                async move {
-                    match result {
-                        Ok(bytes) => {
-                            // Parse SSE chunk format
-                            let bytes_str = from_utf8(&bytes)
-                                .map_err(|e| LLMError::OtherError(e.to_string()))?;
-                            let chunks = Self::parse_sse_chunk(&bytes)?;
+        self.client.stream(messages).await
+    }
 
-                            for chunk in chunks {
-                                if let Some(choices) =
-                                    chunk.get("choices").and_then(|c| c.as_array())
-                                {
-                                    if let Some(choice) = choices.first() {
-                                        if let Some(delta) = choice.get("delta") {
-                                            // Extract content from delta
-                                            if let Some(content) =
-                                                delta.get("content").and_then(|c| c.as_str())
-                                            {
-                                                if !content.is_empty() {
-                                                    let usage =
-                                                        if let Some(usage) = chunk.get("usage") {
-                                                            Some(TokenUsage {
-                                                                prompt_tokens: usage
-                                                                    .get("prompt_tokens")
-                                                                    .and_then(|t| t.as_u64())
-                                                                    .unwrap_or(0)
-                                                                    as u32,
-                                                                completion_tokens: usage
-                                                                    .get("completion_tokens")
-                                                                    .and_then(|t| t.as_u64())
-                                                                    .unwrap_or(0)
-                                                                    as u32,
-                                                                total_tokens: usage
-                                                                    .get("total_tokens")
-                                                                    .and_then(|t| t.as_u64())
-                                                                    .unwrap_or(0)
-                                                                    as u32,
-                                                            })
-                                                        } else {
-                                                            None
-                                                        };
+    fn add_options(&mut self, options: CallOptions) {
+        self.client.add_options(options)
+    }
 
-                                                    return Ok(StreamData::new(
-                                                        chunk.clone(),
-                                                        usage,
-                                                        content,
-                                                    ));
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+    fn override_options(&mut self, options: CallOptions) {
+        self.client.override_options(options)
+    }
 
-                            // If we didn't return within the loop, return an empty stream data
-                            Ok(StreamData::new(Value::Null, None, ""))
-                        }
-                        Err(e) => Err(LLMError::RequestError(e)),
-                    }
-                }
-            })
-            .filter_map(|result| async move {
-                match result {
-                    Ok(data) if !data.content.is_empty() => Some(Ok(data)),
-                    Ok(_) => None,
-                    Err(e) => Some(Err(e)),
-                }
-            });
+    fn context_window(&self) -> Option<usize> {
+        self.client.context_window()
+    }
 
-        Ok(Box::pin(processed_stream))
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.client.max_output_tokens()
     }
+}
 
-    fn add_options(&mut self, options: CallOptions) {
-        self.options.merge_options(options)
+/// DashScope sends its error code either at the top level or, in the OpenAI format, under
+/// `error`.
+fn qwen_error(status: u16, body: &Value) -> LLMError {
+    let code = body
+        .get("code")
+        .or_else(|| body.pointer("/error/code"))
+        .and_then(Value::as_str);
+    match code {
+        Some(code) => parse_error_response(code, &error_message(body)),
+        None => OpenAICompatibleError::from_status("Qwen", status, body).into(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::language_models::FinishReason;
+    use crate::testing::CassetteServer;
+    use futures::StreamExt;
     use tokio::test;
 
     #[test]
@@ -508,4 +327,33 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    async fn test_generate_from_cassette() {
+        let cassette = CassetteServer::new(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/llm/test_data/cassettes/qwen.synthetic.json"
+            ),
+            "https://dashscope.aliyuncs.com",
+        )
+        .start()
+        .await
+        .unwrap();
+        let qwen = Qwen::new().with_base_url(format!(
+            "{}/compatible-mode/v1/chat/completions",
+            cassette.url()
+        ));
+
+        let result = qwen
+            .generate(&[Message::new_human_message(
+                "What is the capital of France? Answer in one word.",
+            )])
+            .await
+            .unwrap();
+        assert!(result.generation.contains("Paris"));
+        assert_eq!(result.finish_reason, Some(FinishReason::Stop));
+        assert!(result.tokens.unwrap().total_tokens > 0);
+        cassette.assert_replayed().await;
+    }
 }
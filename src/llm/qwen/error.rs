@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,11 +13,13 @@ pub enum QwenError {
     #[error("Qwen API error: Network error - {0}")]
     NetworkError(String),
 
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
     #[error("Qwen API error: Model Unavailable - {0}")]
-    ModelUnavailableError(String),
+    ModelUnavailableError(String, Option<Duration>),
 
+    /// Carries the delay of the `Retry-After` header, when the API sent one.
     #[error("Qwen API error: Rate limit exceeded - {0}")]
-    ModelServingError(String),
+    ModelServingError(String, Option<Duration>),
 
     #[error("Qwen API error: Internal error - {0}")]
     InternalError(String),
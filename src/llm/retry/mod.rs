@@ -0,0 +1,5 @@
+mod rate_limiter;
+pub use rate_limiter::*;
+
+mod retry_llm;
+pub use retry_llm::*;
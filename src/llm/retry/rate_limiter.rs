@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use tokio::{sync::Mutex, time::Instant};
+
+/// A bucket that refills continuously at `capacity` units per minute.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: u32) -> Self {
+        let capacity = limit.max(1) as f64;
+        Self {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Takes `amount` from the bucket if it is available, otherwise returns how long to wait
+    /// before trying again.
+    fn try_take(&mut self, amount: f64) -> Option<Duration> {
+        self.refill();
+        // A single request bigger than the whole bucket would never fit, let it through once
+        // the bucket is full instead.
+        let amount = amount.min(self.capacity);
+        if self.available >= amount {
+            self.available -= amount;
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (amount - self.available) / self.refill_per_sec,
+            ))
+        }
+    }
+
+    /// Charges usage that was only known after the fact. The bucket may go negative, which
+    /// delays the following requests until the debt is paid back.
+    fn consume(&mut self, amount: f64) {
+        self.refill();
+        self.available -= amount;
+    }
+}
+
+/// Client side throttling with token-bucket limits on requests per minute and tokens per
+/// minute. A single `RateLimiter` can be shared between several wrapped LLMs that draw from
+/// the same provider quota.
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests: Option<Mutex<TokenBucket>>,
+    tokens: Option<Mutex<TokenBucket>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> Self {
+        Self {
+            requests: requests_per_minute.map(|limit| Mutex::new(TokenBucket::per_minute(limit))),
+            tokens: tokens_per_minute.map(|limit| Mutex::new(TokenBucket::per_minute(limit))),
+        }
+    }
+
+    /// Whether a tokens per minute limit is set, callers can skip estimating prompt sizes
+    /// otherwise.
+    pub fn limits_tokens(&self) -> bool {
+        self.tokens.is_some()
+    }
+
+    /// Waits until one request using `estimated_tokens` tokens fits in the configured limits.
+    pub async fn acquire(&self, estimated_tokens: u32) {
+        if let Some(bucket) = &self.requests {
+            wait_for(bucket, 1.0).await;
+        }
+        if let Some(bucket) = &self.tokens {
+            wait_for(bucket, estimated_tokens as f64).await;
+        }
+    }
+
+    /// Charges tokens that were not part of the estimate given to `acquire`, usually the
+    /// completion tokens reported by the provider.
+    pub async fn record_tokens(&self, tokens: u32) {
+        if let Some(bucket) = &self.tokens {
+            bucket.lock().await.consume(tokens as f64);
+        }
+    }
+}
+
+async fn wait_for(bucket: &Mutex<TokenBucket>, amount: f64) {
+    loop {
+        let wait = bucket.lock().await.try_take(amount);
+        match wait {
+            Some(wait) => tokio::time::sleep(wait).await,
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_waits_when_empty() {
+        let mut bucket = TokenBucket::per_minute(60);
+        for _ in 0..60 {
+            assert!(bucket.try_take(1.0).is_none());
+        }
+
+        let wait = bucket.try_take(1.0).expect("bucket should be empty");
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_token_bucket_oversized_request_and_debt() {
+        let mut bucket = TokenBucket::per_minute(100);
+        // Bigger than the bucket, allowed because the bucket is full
+        assert!(bucket.try_take(500.0).is_none());
+        assert!(bucket.try_take(1.0).is_some());
+
+        let mut bucket = TokenBucket::per_minute(100);
+        bucket.consume(150.0);
+        let wait = bucket.try_take(10.0).expect("bucket should be in debt");
+        assert!(wait > Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+
+use crate::{
+    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError},
+    schemas::{Message, StreamData},
+};
+
+use super::RateLimiter;
+
+/// Wraps any [`LLM`] with retries, exponential backoff, per-request timeouts and client side
+/// rate limiting.
+///
+/// Transient failures (see [`LLMError::is_retryable`]) are retried with exponential backoff
+/// and jitter. When the provider sent a `Retry-After` header that delay is used instead,
+/// capped at the maximum backoff so that a single call cannot hang for as long as the
+/// provider asks.
+/// Cancelled calls are never retried.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let llm = RetryLLM::new(OpenAI::default())
+///     .with_max_retries(5)
+///     .with_timeout(Duration::from_secs(60))
+///     .with_requests_per_minute(500)
+///     .with_tokens_per_minute(200_000);
+/// let response = llm.invoke("hola").await?;
+/// ```
+pub struct RetryLLM {
+    llm: Box<dyn LLM>,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    backoff_multiplier: f64,
+    jitter: bool,
+    timeout: Option<Duration>,
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u32>,
+    rate_limiter: Arc<RateLimiter>,
+    retry_if: Arc<dyn Fn(&LLMError) -> bool + Send + Sync>,
+}
+
+impl Clone for RetryLLM {
+    fn clone(&self) -> Self {
+        Self {
+            llm: self.llm.clone_box(),
+            max_retries: self.max_retries,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            backoff_multiplier: self.backoff_multiplier,
+            jitter: self.jitter,
+            timeout: self.timeout,
+            requests_per_minute: self.requests_per_minute,
+            tokens_per_minute: self.tokens_per_minute,
+            rate_limiter: self.rate_limiter.clone(),
+            retry_if: self.retry_if.clone(),
+        }
+    }
+}
+
+impl RetryLLM {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        Self {
+            llm: llm.into(),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            backoff_multiplier: 2.0,
+            jitter: true,
+            timeout: None,
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            rate_limiter: Arc::new(RateLimiter::default()),
+            retry_if: Arc::new(LLMError::is_retryable),
+        }
+    }
+
+    /// Number of retries after the first attempt, `0` disables retrying.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// The longest wait between attempts, 30 seconds by default. Also caps the delays asked
+    /// for by the provider.
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_backoff_multiplier(mut self, backoff_multiplier: f64) -> Self {
+        self.backoff_multiplier = backoff_multiplier;
+        self
+    }
+
+    /// Randomises each backoff between zero and its computed value, so that many clients
+    /// failing at once do not retry in lockstep. Enabled by default.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Timeout for each attempt. For streams it bounds opening the stream and then the wait
+    /// for every chunk. A timed out attempt fails with `LLMError::Timeout` and is retried.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_requests_per_minute(mut self, requests_per_minute: u32) -> Self {
+        self.requests_per_minute = Some(requests_per_minute);
+        self.rate_limiter = Arc::new(RateLimiter::new(
+            self.requests_per_minute,
+            self.tokens_per_minute,
+        ));
+        self
+    }
+
+    /// Limits prompt and completion tokens per minute. Prompt tokens are estimated with
+    /// [`LLM::count_tokens`] before sending, completion tokens are charged once the provider
+    /// reports them.
+    pub fn with_tokens_per_minute(mut self, tokens_per_minute: u32) -> Self {
+        self.tokens_per_minute = Some(tokens_per_minute);
+        self.rate_limiter = Arc::new(RateLimiter::new(
+            self.requests_per_minute,
+            self.tokens_per_minute,
+        ));
+        self
+    }
+
+    /// Uses a rate limiter shared with other LLMs, replacing the per minute limits set on
+    /// this one.
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.requests_per_minute = None;
+        self.tokens_per_minute = None;
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Overrides which errors are retried, by default [`LLMError::is_retryable`].
+    pub fn with_retry_if<F>(mut self, retry_if: F) -> Self
+    where
+        F: Fn(&LLMError) -> bool + Send + Sync + 'static,
+    {
+        self.retry_if = Arc::new(retry_if);
+        self
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff =
+            self.initial_backoff.as_secs_f64() * self.backoff_multiplier.powi(attempt as i32);
+        let backoff = backoff.min(self.max_backoff.as_secs_f64());
+        if self.jitter {
+            Duration::from_secs_f64(backoff * random_fraction())
+        } else {
+            Duration::from_secs_f64(backoff)
+        }
+    }
+
+    fn estimate_tokens(&self, messages: &[Message]) -> u32 {
+        if !self.rate_limiter.limits_tokens() {
+            return 0;
+        }
+        self.llm.count_tokens(messages) as u32
+    }
+
+    async fn retry<T, F, Fut>(&self, estimated_tokens: u32, mut call: F) -> Result<T, LLMError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, LLMError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.rate_limiter.acquire(estimated_tokens).await;
+            let result = match self.timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, call()).await {
+                    Ok(result) => result,
+                    Err(elapsed) => Err(LLMError::from(elapsed)),
+                },
+                None => call().await,
+            };
+
+            match result {
+                Ok(value) => return Ok(value),
+                Err(error)
+                    if attempt < self.max_retries
+                        && !error.is_cancelled()
+                        && (self.retry_if)(&error) =>
+                {
+                    let delay = match error.retry_after() {
+                        Some(retry_after) => retry_after.min(self.max_backoff),
+                        None => self.backoff(attempt),
+                    };
+                    attempt += 1;
+                    log::warn!(
+                        "LLM call failed: {}, retrying in {:?} ({}/{})",
+                        error,
+                        delay,
+                        attempt,
+                        self.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl LLM for RetryLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let estimated_tokens = self.estimate_tokens(messages);
+        let result = self
+            .retry(estimated_tokens, || self.llm.generate(messages))
+            .await?;
+        if let Some(tokens) = &result.tokens {
+            self.rate_limiter
+                .record_tokens(tokens.completion_tokens)
+                .await;
+        }
+        Ok(result)
+    }
+
+    /// Retries opening the stream. Errors after the first chunk are passed through, since the
+    /// partial output has already been consumed.
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let estimated_tokens = self.estimate_tokens(messages);
+        let mut stream = self
+            .retry(estimated_tokens, || self.llm.stream(messages))
+            .await?;
+
+        let Some(timeout) = self.timeout else {
+            return Ok(stream);
+        };
+        let stream = async_stream::stream! {
+            loop {
+                match tokio::time::timeout(timeout, stream.next()).await {
+                    Ok(Some(item)) => yield item,
+                    Ok(None) => break,
+                    Err(elapsed) => {
+                        yield Err(LLMError::from(elapsed));
+                        break;
+                    }
+                }
+            }
+        };
+        Ok(Box::pin(stream))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.llm.add_options(options)
+    }
+
+    fn override_options(&mut self, options: CallOptions) {
+        self.llm.override_options(options)
+    }
+
+    fn messages_to_string(&self, messages: &[Message]) -> String {
+        self.llm.messages_to_string(messages)
+    }
+
+    fn count_tokens(&self, messages: &[Message]) -> usize {
+        self.llm.count_tokens(messages)
+    }
+
+    fn context_window(&self) -> Option<usize> {
+        self.llm.context_window()
+    }
+
+    fn max_output_tokens(&self) -> Option<usize> {
+        self.llm.max_output_tokens()
+    }
+}
+
+/// A number in `[0, 1)`, good enough for jitter without pulling in a random number crate.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        language_models::cancellation::CancellationToken,
+        llm::{Deepseek, DeepseekError},
+    };
+    use serde_json::json;
+
+    fn completion() -> String {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "deepseek-chat",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hello!" },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7 },
+            "system_fingerprint": "fp_1"
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_retries_rate_limited_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(2)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(completion())
+            .expect(1)
+            .create_async()
+            .await;
+
+        let llm = RetryLLM::new(Deepseek::new().with_base_url(server.url()))
+            .with_initial_backoff(Duration::from_secs(60));
+
+        let result = llm.invoke("Hi").await.unwrap();
+
+        assert_eq!(result, "Hello!");
+        rate_limited.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_keeps_the_provider_error() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "3")
+            .create_async()
+            .await;
+
+        let error = Deepseek::new()
+            .with_base_url(server.url())
+            .invoke("Hi")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error,
+            LLMError::DeepseekError(DeepseekError::RateLimitError(..))
+        ));
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(3)));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_capped_at_max_backoff() {
+        let mut server = mockito::Server::new_async().await;
+        let limited = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "3600")
+            .expect(2)
+            .create_async()
+            .await;
+
+        let llm = RetryLLM::new(Deepseek::new().with_base_url(server.url()))
+            .with_max_retries(1)
+            .with_max_backoff(Duration::from_millis(10));
+
+        let result = tokio::time::timeout(Duration::from_secs(5), llm.invoke("Hi"))
+            .await
+            .expect("the retry should wait at most the max backoff");
+
+        assert!(matches!(
+            result,
+            Err(LLMError::DeepseekError(DeepseekError::RateLimitError(..)))
+        ));
+        limited.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let mut server = mockito::Server::new_async().await;
+        let failing = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(500)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let llm = RetryLLM::new(Deepseek::new().with_base_url(server.url()))
+            .with_max_retries(2)
+            .with_initial_backoff(Duration::from_millis(1));
+
+        let result = llm.invoke("Hi").await;
+
+        assert!(matches!(result, Err(LLMError::DeepseekError(_))));
+        failing.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let unauthorized = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(401)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let llm = RetryLLM::new(Deepseek::new().with_base_url(server.url()))
+            .with_initial_backoff(Duration::from_millis(1));
+
+        assert!(llm.invoke("Hi").await.is_err());
+        unauthorized.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_times_out_slow_requests() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(300));
+                w.write_all(completion().as_bytes())
+            })
+            .create_async()
+            .await;
+
+        let llm = RetryLLM::new(Deepseek::new().with_base_url(server.url()))
+            .with_max_retries(1)
+            .with_initial_backoff(Duration::from_millis(1))
+            .with_timeout(Duration::from_millis(50));
+
+        let result = llm.invoke("Hi").await;
+
+        assert!(matches!(result, Err(LLMError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_cancelled_requests() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(300));
+                w.write_all(completion().as_bytes())
+            })
+            .create_async()
+            .await;
+
+        let token = CancellationToken::new();
+        let llm = RetryLLM::new(Deepseek::new().with_base_url(server.url()))
+            .with_initial_backoff(Duration::from_millis(1))
+            .with_retry_if(|_| true);
+        let options = CallOptions::new().with_cancellation_token(token.clone());
+
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        let started = std::time::Instant::now();
+        let result = llm
+            .generate_with_options(&[Message::new_human_message("Hi")], &options)
+            .await;
+
+        assert!(matches!(result, Err(LLMError::Cancelled { .. })));
+        assert!(started.elapsed() < Duration::from_millis(300));
+    }
+}
@@ -0,0 +1,66 @@
+use futures::{Stream, StreamExt};
+
+use crate::language_models::LLMError;
+
+/// Splits a server-sent events byte stream into the `data` payload of each event. Events may
+/// be split across network chunks, so bytes are buffered until an event is complete.
+pub(crate) fn data_events<S, B>(bytes: S) -> impl Stream<Item = Result<String, LLMError>> + Send
+where
+    S: Stream<Item = Result<B, reqwest::Error>> + Send + 'static,
+    B: AsRef<[u8]> + Send,
+{
+    async_stream::stream! {
+        let mut bytes = Box::pin(bytes);
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = bytes.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    buffer.extend(chunk.as_ref().iter().filter(|byte| **byte != b'\r'));
+                    while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+                        let event: Vec<u8> = buffer.drain(..end + 2).collect();
+                        if let Some(data) = event_data(&event) {
+                            yield Ok(data);
+                        }
+                    }
+                }
+                Err(e) => {
+                    yield Err(LLMError::RequestError(e));
+                    return;
+                }
+            }
+        }
+        if let Some(data) = event_data(&buffer) {
+            yield Ok(data);
+        }
+    }
+}
+
+fn event_data(event: &[u8]) -> Option<String> {
+    let data = String::from_utf8_lossy(event)
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data).to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    (!data.is_empty()).then_some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_data_events_across_chunks() {
+        let chunks: Vec<Result<&[u8], reqwest::Error>> = vec![
+            Ok(&b": keep-alive\n\ndata: {\"a\":"[..]),
+            Ok(&b"1}\r\n\r\ndata: [DONE]"[..]),
+        ];
+
+        let events: Vec<String> = data_events(futures::stream::iter(chunks))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(events, vec!["{\"a\":1}", "[DONE]"]);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::schemas::{messages::Message, prompt::PromptValue};
+use crate::schemas::{
+    messages::{CacheControl, Message},
+    prompt::PromptValue,
+};
 
 use super::{
     FormatPrompter, MessageFormatter, PromptArgs, PromptError, PromptFromatter, PromptTemplate,
@@ -17,16 +20,27 @@ use super::{
 #[derive(Clone)]
 pub struct HumanMessagePromptTemplate {
     prompt: PromptTemplate,
+    cache_control: Option<CacheControl>,
 }
 
 impl HumanMessagePromptTemplate {
     pub fn new(prompt: PromptTemplate) -> Self {
-        Self { prompt }
+        Self {
+            prompt,
+            cache_control: None,
+        }
+    }
+
+    /// Marks the formatted message as a prompt cache breakpoint, see [`CacheControl`].
+    pub fn with_cache_breakpoint(mut self) -> Self {
+        self.cache_control = Some(CacheControl::default());
+        self
     }
 }
 impl MessageFormatter for HumanMessagePromptTemplate {
     fn format_messages(&self, input_variables: PromptArgs) -> Result<Vec<Message>, PromptError> {
-        let message = Message::new_human_message(self.prompt.format(input_variables)?);
+        let mut message = Message::new_human_message(self.prompt.format(input_variables)?);
+        message.cache_control = self.cache_control;
         log::debug!("message: {:?}", message);
         Ok(vec![message])
     }
@@ -59,11 +73,21 @@ impl FormatPrompter for HumanMessagePromptTemplate {
 #[derive(Clone)]
 pub struct SystemMessagePromptTemplate {
     prompt: PromptTemplate,
+    cache_control: Option<CacheControl>,
 }
 
 impl SystemMessagePromptTemplate {
     pub fn new(prompt: PromptTemplate) -> Self {
-        Self { prompt }
+        Self {
+            prompt,
+            cache_control: None,
+        }
+    }
+
+    /// Marks the formatted message as a prompt cache breakpoint, see [`CacheControl`].
+    pub fn with_cache_breakpoint(mut self) -> Self {
+        self.cache_control = Some(CacheControl::default());
+        self
     }
 }
 
@@ -79,7 +103,8 @@ impl FormatPrompter for SystemMessagePromptTemplate {
 
 impl MessageFormatter for SystemMessagePromptTemplate {
     fn format_messages(&self, input_variables: PromptArgs) -> Result<Vec<Message>, PromptError> {
-        let message = Message::new_system_message(self.prompt.format(input_variables)?);
+        let mut message = Message::new_system_message(self.prompt.format(input_variables)?);
+        message.cache_control = self.cache_control;
         log::debug!("message: {:?}", message);
         Ok(vec![message])
     }
@@ -101,6 +126,7 @@ impl MessageFormatter for SystemMessagePromptTemplate {
 #[derive(Clone)]
 pub struct AIMessagePromptTemplate {
     prompt: PromptTemplate,
+    cache_control: Option<CacheControl>,
 }
 
 impl FormatPrompter for AIMessagePromptTemplate {
@@ -115,7 +141,8 @@ impl FormatPrompter for AIMessagePromptTemplate {
 
 impl MessageFormatter for AIMessagePromptTemplate {
     fn format_messages(&self, input_variables: PromptArgs) -> Result<Vec<Message>, PromptError> {
-        let message = Message::new_ai_message(self.prompt.format(input_variables)?);
+        let mut message = Message::new_ai_message(self.prompt.format(input_variables)?);
+        message.cache_control = self.cache_control;
         log::debug!("message: {:?}", message);
         Ok(vec![message])
     }
@@ -126,7 +153,16 @@ impl MessageFormatter for AIMessagePromptTemplate {
 
 impl AIMessagePromptTemplate {
     pub fn new(prompt: PromptTemplate) -> Self {
-        Self { prompt }
+        Self {
+            prompt,
+            cache_control: None,
+        }
+    }
+
+    /// Marks the formatted message as a prompt cache breakpoint, see [`CacheControl`].
+    pub fn with_cache_breakpoint(mut self) -> Self {
+        self.cache_control = Some(CacheControl::default());
+        self
     }
 }
 
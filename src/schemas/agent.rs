@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 
+use super::MessageReasoning;
+
 pub enum ToolInput {
     //Will implement this in the future
     StrInput(String),
@@ -21,6 +23,9 @@ pub struct AgentAction {
 pub struct LogTools {
     pub tool_id: String,
     pub tools: String,
+    /// The reasoning that led to the tool calls, when the agent keeps it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<MessageReasoning>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
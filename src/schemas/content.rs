@@ -0,0 +1,367 @@
+use std::{fs, io, path::Path};
+
+use async_openai::types::{
+    ChatCompletionRequestMessageContentPartAudio, ChatCompletionRequestMessageContentPartImage,
+    ChatCompletionRequestMessageContentPartText, ChatCompletionRequestUserMessageContentPart,
+    ImageDetail, ImageUrl, InputAudio, InputAudioFormat,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    language_models::LLMError,
+    schemas::{convert::TryOpenAiFromLangchain, CacheControl},
+};
+
+/// A typed piece of message content. A [`Message`](super::Message) may hold an ordered list
+/// of them to mix text with images, audio and documents.
+///
+/// Binary data is kept base64 encoded, as every provider expects it that way.
+///
+/// # Usage
+/// ```rust,ignore
+/// let message = Message::new_human_message_with_parts(vec![
+///     ContentPart::text("What is in this picture?"),
+///     ContentPart::image_from_path("cat.png")?,
+/// ]);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text {
+        text: String,
+        /// Ends a prefix of the prompt the provider should cache, see [`CacheControl`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ImageUrl {
+        url: String,
+        detail: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ImageBase64 {
+        media_type: String,
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Audio {
+        media_type: String,
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    File {
+        media_type: String,
+        data: String,
+        name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+}
+
+impl ContentPart {
+    pub fn text<S: Into<String>>(text: S) -> Self {
+        ContentPart::Text {
+            text: text.into(),
+            cache_control: None,
+        }
+    }
+
+    pub fn image_url<S: Into<String>>(url: S) -> Self {
+        ContentPart::ImageUrl {
+            url: url.into(),
+            detail: None,
+            cache_control: None,
+        }
+    }
+
+    pub fn image_base64<M: Into<String>, D: Into<String>>(media_type: M, data: D) -> Self {
+        ContentPart::ImageBase64 {
+            media_type: media_type.into(),
+            data: data.into(),
+            cache_control: None,
+        }
+    }
+
+    pub fn image_bytes<M: Into<String>>(media_type: M, bytes: &[u8]) -> Self {
+        Self::image_base64(media_type, STANDARD.encode(bytes))
+    }
+
+    pub fn audio_bytes<M: Into<String>>(media_type: M, bytes: &[u8]) -> Self {
+        ContentPart::Audio {
+            media_type: media_type.into(),
+            data: STANDARD.encode(bytes),
+            cache_control: None,
+        }
+    }
+
+    pub fn file_bytes<M: Into<String>>(media_type: M, bytes: &[u8], name: Option<String>) -> Self {
+        ContentPart::File {
+            media_type: media_type.into(),
+            data: STANDARD.encode(bytes),
+            name,
+            cache_control: None,
+        }
+    }
+
+    /// Reads an image from disk, guessing its MIME type from the file extension.
+    pub fn image_from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        Ok(Self::image_bytes(
+            media_type_from_path(path),
+            &fs::read(path)?,
+        ))
+    }
+
+    /// Reads a file from disk as an image, audio or file part depending on the MIME type
+    /// guessed from its extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let media_type = media_type_from_path(path);
+        let bytes = fs::read(path)?;
+        Ok(if media_type.starts_with("image/") {
+            Self::image_bytes(media_type, &bytes)
+        } else if media_type.starts_with("audio/") {
+            Self::audio_bytes(media_type, &bytes)
+        } else {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned());
+            Self::file_bytes(media_type, &bytes, name)
+        })
+    }
+
+    pub fn with_detail<S: Into<String>>(self, detail: S) -> Self {
+        match self {
+            ContentPart::ImageUrl {
+                url, cache_control, ..
+            } => ContentPart::ImageUrl {
+                url,
+                detail: Some(detail.into()),
+                cache_control,
+            },
+            other => other,
+        }
+    }
+
+    /// Marks the end of this part as a cache breakpoint, to cache a prefix ending in the
+    /// middle of a message, such as a long document followed by a question.
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        match &mut self {
+            ContentPart::Text {
+                cache_control: part_cache_control,
+                ..
+            }
+            | ContentPart::ImageUrl {
+                cache_control: part_cache_control,
+                ..
+            }
+            | ContentPart::ImageBase64 {
+                cache_control: part_cache_control,
+                ..
+            }
+            | ContentPart::Audio {
+                cache_control: part_cache_control,
+                ..
+            }
+            | ContentPart::File {
+                cache_control: part_cache_control,
+                ..
+            } => *part_cache_control = Some(cache_control),
+        }
+        self
+    }
+
+    /// Marks the end of this part as a cache breakpoint with the default lifetime.
+    pub fn with_cache_breakpoint(self) -> Self {
+        self.with_cache_control(CacheControl::default())
+    }
+
+    pub fn cache_control(&self) -> Option<CacheControl> {
+        match self {
+            ContentPart::Text { cache_control, .. }
+            | ContentPart::ImageUrl { cache_control, .. }
+            | ContentPart::ImageBase64 { cache_control, .. }
+            | ContentPart::Audio { cache_control, .. }
+            | ContentPart::File { cache_control, .. } => *cache_control,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ContentPart::Text { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The contents of a plain text file part (`text/*`, JSON), for providers that only
+    /// accept documents inlined as text.
+    pub fn file_text(&self) -> Option<String> {
+        match self {
+            ContentPart::File {
+                media_type, data, ..
+            } if media_type.starts_with("text/") || media_type == "application/json" => STANDARD
+                .decode(data)
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok()),
+            _ => None,
+        }
+    }
+
+    /// A `data:` URL for base64 parts, or the URL itself for [`ContentPart::ImageUrl`].
+    pub fn to_url(&self) -> Option<String> {
+        match self {
+            ContentPart::ImageUrl { url, .. } => Some(url.clone()),
+            ContentPart::ImageBase64 {
+                media_type, data, ..
+            }
+            | ContentPart::Audio {
+                media_type, data, ..
+            }
+            | ContentPart::File {
+                media_type, data, ..
+            } => Some(format!("data:{};base64,{}", media_type, data)),
+            ContentPart::Text { .. } => None,
+        }
+    }
+
+    /// The short audio format name (`wav`, `mp3`, ...) providers expect next to audio data.
+    pub fn audio_format(&self) -> Option<&str> {
+        match self {
+            ContentPart::Audio { media_type, .. } => {
+                Some(match media_type.trim_start_matches("audio/") {
+                    "mpeg" | "mp3" => "mp3",
+                    "x-wav" | "wave" | "wav" => "wav",
+                    other => other,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl TryOpenAiFromLangchain<ContentPart> for ChatCompletionRequestUserMessageContentPart {
+    type Error = LLMError;
+    fn try_from_langchain(langchain: ContentPart) -> Result<Self, Self::Error> {
+        if let Some(text) = langchain.file_text() {
+            return Ok(ChatCompletionRequestMessageContentPartText { text }.into());
+        }
+        let url = langchain.to_url();
+        match langchain {
+            ContentPart::Text { text, .. } => {
+                Ok(ChatCompletionRequestMessageContentPartText { text }.into())
+            }
+            ContentPart::ImageUrl { detail, .. } => {
+                Ok(ChatCompletionRequestMessageContentPartImage {
+                    image_url: ImageUrl {
+                        url: url.unwrap_or_default(),
+                        detail: detail.map(|detail| match detail.as_str() {
+                            "low" => ImageDetail::Low,
+                            "high" => ImageDetail::High,
+                            _ => ImageDetail::Auto,
+                        }),
+                    },
+                }
+                .into())
+            }
+            ContentPart::ImageBase64 { .. } => Ok(ChatCompletionRequestMessageContentPartImage {
+                image_url: ImageUrl {
+                    url: url.unwrap_or_default(),
+                    detail: None,
+                },
+            }
+            .into()),
+            ContentPart::Audio { ref data, .. } => {
+                let format = match langchain.audio_format() {
+                    Some("wav") => InputAudioFormat::Wav,
+                    Some("mp3") => InputAudioFormat::Mp3,
+                    other => {
+                        return Err(LLMError::UnsupportedContent(format!(
+                            "OpenAI only accepts wav and mp3 audio, got {:?}",
+                            other
+                        )))
+                    }
+                };
+                Ok(ChatCompletionRequestMessageContentPartAudio {
+                    input_audio: InputAudio {
+                        data: data.clone(),
+                        format,
+                    },
+                }
+                .into())
+            }
+            ContentPart::File { media_type, .. } => Err(LLMError::UnsupportedContent(format!(
+                "OpenAI chat completions do not accept {} files",
+                media_type
+            ))),
+        }
+    }
+}
+
+/// Splits a `data:<media type>;base64,<data>` URL into its media type and data.
+pub(crate) fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+    let media_type = header.strip_suffix(";base64")?;
+    Some((media_type, data))
+}
+
+pub(crate) fn media_type_from_path(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "pdf" => "application/pdf",
+        "json" => "application/json",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path_guesses_media_type() {
+        let dir = std::env::temp_dir().join("langchain_rust_content_test");
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("pixel.PNG");
+        let notes = dir.join("notes.txt");
+        fs::write(&image, [137, 80, 78, 71]).unwrap();
+        fs::write(&notes, "hello").unwrap();
+
+        assert_eq!(
+            ContentPart::from_path(&image).unwrap(),
+            ContentPart::image_base64("image/png", "iVBORw==")
+        );
+        let notes = ContentPart::from_path(&notes).unwrap();
+        assert_eq!(notes.file_text().as_deref(), Some("hello"));
+        assert_eq!(
+            notes.to_url().as_deref(),
+            Some("data:text/plain;base64,aGVsbG8=")
+        );
+    }
+
+    #[test]
+    fn test_parse_data_url() {
+        assert_eq!(
+            parse_data_url("data:image/png;base64,iVBORw=="),
+            Some(("image/png", "iVBORw=="))
+        );
+        assert_eq!(parse_data_url("https://example.com/cat.png"), None);
+    }
+}
@@ -2,6 +2,8 @@ use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Value;
 
+use super::ContentPart;
+
 /// Enum `MessageType` represents the type of a message.
 /// It can be a `SystemMessage`, `AIMessage`, or `HumanMessage`.
 ///
@@ -56,6 +58,92 @@ impl<S: AsRef<str>> From<S> for ImageContent {
     }
 }
 
+/// The reasoning attached to an AI message.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct MessageReasoning {
+    pub content: String,
+    /// The reasoning as the provider split and signed it. Claude only accepts its reasoning
+    /// back in these blocks, unchanged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<ReasoningBlock>,
+    /// Whether the reasoning is sent back to the model with the message. Providers that do
+    /// not accept reasoning in the history ignore it.
+    #[serde(default)]
+    pub resend: bool,
+}
+
+/// A block of reasoning as returned by providers that sign their reasoning, like Claude.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReasoningBlock {
+    /// Reasoning text with the signature it was generated with.
+    Signed { content: String, signature: String },
+    /// Reasoning the provider encrypted, only meant to be sent back to it.
+    Redacted { data: String },
+}
+
+/// A cache breakpoint: the prompt up to and including the message carrying it is cached by
+/// the provider, so that later requests starting the same way are cheaper and faster.
+///
+/// Providers caching explicitly, like Anthropic, only cache up to breakpoints and take a
+/// handful of them per request. Providers caching on their own, like OpenAI, ignore them but
+/// still benefit from prompts starting the same way. The tool list comes before the system
+/// prompt, so a breakpoint on the system prompt caches the tools as well. To cache part of a
+/// message, set the breakpoint on one of its parts with [`ContentPart::with_cache_control`].
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheControl {
+    pub ttl: CacheTtl,
+}
+
+impl CacheControl {
+    pub fn new(ttl: CacheTtl) -> Self {
+        Self { ttl }
+    }
+}
+
+/// How long a cached prefix is kept after it was last used.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CacheTtl {
+    #[default]
+    FiveMinutes,
+    OneHour,
+}
+
+/// What happens to the reasoning of thinking models once a turn is over.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReasoningRetention {
+    /// The reasoning is dropped, only the answer is kept.
+    #[default]
+    Discard,
+    /// The reasoning is stored with the answer but not sent back to the model.
+    Persist,
+    /// The reasoning is stored and sent back to the model with the answer. Claude needs this
+    /// to keep thinking across tool calls.
+    Resend,
+}
+
+impl ReasoningRetention {
+    /// The reasoning to attach to an AI message, `None` when it is discarded or empty.
+    pub fn reasoning(
+        &self,
+        content: Option<&str>,
+        blocks: &[ReasoningBlock],
+    ) -> Option<MessageReasoning> {
+        let content = content.unwrap_or_default();
+        if content.is_empty() && blocks.is_empty() {
+            return None;
+        }
+        match self {
+            ReasoningRetention::Discard => None,
+            ReasoningRetention::Persist | ReasoningRetention::Resend => Some(MessageReasoning {
+                content: content.to_string(),
+                blocks: blocks.to_vec(),
+                resend: *self == ReasoningRetention::Resend,
+            }),
+        }
+    }
+}
+
 /// Struct `Message` represents a message with its content and type.
 ///
 /// # Usage
@@ -70,7 +158,20 @@ pub struct Message {
     pub message_type: MessageType,
     pub id: Option<String>,
     pub tool_calls: Option<Value>,
+    /// Images sent along with `content`. Prefer `content_parts`, which also keeps the order
+    /// of text and images.
     pub images: Option<Vec<ImageContent>>,
+    /// Ordered multimodal content. When set it is sent instead of `content` and `images`,
+    /// and `content` holds its text for consumers that only understand text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_parts: Option<Vec<ContentPart>>,
+    /// The reasoning of a thinking model that produced this AI message, kept according to
+    /// the [`ReasoningRetention`] of the chain or agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<MessageReasoning>,
+    /// Ends a prefix of the prompt the provider should cache, see [`CacheControl`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 impl Message {
@@ -82,6 +183,9 @@ impl Message {
             id: None,
             tool_calls: None,
             images: None,
+            content_parts: None,
+            reasoning: None,
+            cache_control: None,
         }
     }
 
@@ -92,9 +196,16 @@ impl Message {
             id: None,
             tool_calls: None,
             images: Some(images.into_iter().map(|i| i.into()).collect()),
+            content_parts: None,
+            reasoning: None,
+            cache_control: None,
         }
     }
 
+    pub fn new_human_message_with_parts(parts: Vec<ContentPart>) -> Self {
+        Message::new_human_message("").with_content_parts(parts)
+    }
+
     // Function to create a new System message with a generic type that implements Display
     pub fn new_system_message<T: std::fmt::Display>(content: T) -> Self {
         Message {
@@ -103,6 +214,9 @@ impl Message {
             id: None,
             tool_calls: None,
             images: None,
+            content_parts: None,
+            reasoning: None,
+            cache_control: None,
         }
     }
 
@@ -114,6 +228,9 @@ impl Message {
             id: None,
             tool_calls: None,
             images: None,
+            content_parts: None,
+            reasoning: None,
+            cache_control: None,
         }
     }
 
@@ -125,6 +242,9 @@ impl Message {
             id: Some(id.into()),
             tool_calls: None,
             images: None,
+            content_parts: None,
+            reasoning: None,
+            cache_control: None,
         }
     }
 
@@ -142,6 +262,59 @@ impl Message {
         self
     }
 
+    pub fn with_reasoning(mut self, reasoning: Option<MessageReasoning>) -> Self {
+        self.reasoning = reasoning;
+        self
+    }
+
+    pub fn with_cache_control(mut self, cache_control: CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    /// Marks the end of this message as a cache breakpoint with the default lifetime.
+    pub fn with_cache_breakpoint(self) -> Self {
+        self.with_cache_control(CacheControl::default())
+    }
+
+    /// Sets ordered multimodal content, replacing `content` with the text of its parts.
+    pub fn with_content_parts(mut self, parts: Vec<ContentPart>) -> Self {
+        self.content = parts
+            .iter()
+            .filter_map(ContentPart::as_text)
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.content_parts = Some(parts);
+        self
+    }
+
+    /// The content to send, in order: `content_parts` if set, otherwise `content` followed by
+    /// any `images`.
+    pub fn parts(&self) -> Vec<ContentPart> {
+        if let Some(parts) = &self.content_parts {
+            return parts.clone();
+        }
+        let mut parts = Vec::new();
+        if !self.content.is_empty() {
+            parts.push(ContentPart::text(self.content.clone()));
+        }
+        for image in self.images.iter().flatten() {
+            parts.push(ContentPart::ImageUrl {
+                url: image.image_url.clone(),
+                detail: image.detail.clone(),
+                cache_control: None,
+            });
+        }
+        parts
+    }
+
+    /// Whether the message carries anything besides text.
+    pub fn is_multimodal(&self) -> bool {
+        self.parts()
+            .iter()
+            .any(|part| !matches!(part, ContentPart::Text { .. }))
+    }
+
     pub fn messages_from_value(value: &Value) -> Result<Vec<Message>, serde_json::error::Error> {
         serde_json::from_value(value.clone())
     }
@@ -7,6 +7,9 @@ pub use memory::*;
 pub mod messages;
 pub use messages::*;
 
+pub mod content;
+pub use content::*;
+
 pub mod prompt;
 pub use prompt::*;
 
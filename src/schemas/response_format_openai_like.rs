@@ -1,4 +1,10 @@
-use crate::schemas::convert::OpenAIFromLangchain;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::{
+    language_models::LLMError,
+    schemas::{convert::OpenAIFromLangchain, Message, MessageType},
+};
 
 #[derive(Clone, Debug)]
 pub enum ResponseFormat {
@@ -12,6 +18,150 @@ pub enum ResponseFormat {
     },
 }
 
+impl ResponseFormat {
+    pub fn json_schema<S: Into<String>>(name: S, schema: Value) -> Self {
+        ResponseFormat::JsonSchema {
+            description: None,
+            name: name.into(),
+            schema: Some(schema),
+            strict: None,
+        }
+    }
+
+    /// A JSON schema derived from `T`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct Movie {
+    ///     title: String,
+    ///     year: u32,
+    /// }
+    ///
+    /// let llm = Claude::new().with_options(
+    ///     CallOptions::new().with_response_format(ResponseFormat::from_type::<Movie>()),
+    /// );
+    /// ```
+    #[cfg(feature = "schemars")]
+    pub fn from_type<T: schemars::JsonSchema>() -> Self {
+        let mut schema = schemars::schema_for!(T).to_value();
+        if let Some(schema) = schema.as_object_mut() {
+            schema.remove("$schema");
+        }
+        let description = schema["description"].as_str().map(String::from);
+        ResponseFormat::JsonSchema {
+            description,
+            name: T::schema_name().replace(|c: char| !c.is_alphanumeric() && c != '_', "_"),
+            schema: Some(schema),
+            strict: None,
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        !matches!(self, ResponseFormat::Text)
+    }
+
+    /// The schema the output must follow, `None` for plain text and free form JSON.
+    pub fn schema(&self) -> Option<&Value> {
+        match self {
+            ResponseFormat::JsonSchema { schema, .. } => schema.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The instructions asking for this format, for providers without a native JSON mode.
+    pub fn instructions(&self) -> Option<String> {
+        match self {
+            ResponseFormat::Text => None,
+            ResponseFormat::JsonObject => {
+                Some("Respond only with a valid JSON object, without any other text.".to_string())
+            }
+            ResponseFormat::JsonSchema {
+                description,
+                schema,
+                ..
+            } => {
+                let mut instructions = "Respond only with a valid JSON object, without any other text, that follows this JSON schema:".to_string();
+                if let Some(description) = description {
+                    instructions = format!("{}\n{}", description, instructions);
+                }
+                let schema = schema
+                    .clone()
+                    .unwrap_or_else(|| json!({ "type": "object" }));
+                Some(format!("{}\n{}", instructions, schema))
+            }
+        }
+    }
+
+    /// Adds the [`ResponseFormat::instructions`] to the system message of `messages`, or
+    /// starts the conversation with them.
+    pub fn with_instructions(&self, messages: &[Message]) -> Vec<Message> {
+        let mut messages = messages.to_vec();
+        let Some(instructions) = self.instructions() else {
+            return messages;
+        };
+        match messages.first_mut() {
+            Some(system) if system.message_type == MessageType::SystemMessage => {
+                system.content = format!("{}\n\n{}", system.content, instructions);
+            }
+            _ => messages.insert(0, Message::new_system_message(instructions)),
+        }
+        messages
+    }
+
+    /// Extracts the JSON of a response, dropping markdown fences and text around it, and
+    /// checks it is an object holding the required properties of the schema. Used for
+    /// providers that are only asked for JSON in the prompt.
+    pub fn validate(&self, text: &str) -> Result<String, LLMError> {
+        if !self.is_json() {
+            return Ok(text.to_string());
+        }
+        let json = extract_json(text).ok_or_else(|| {
+            LLMError::ParsingError(format!("Expected a JSON object, got: {}", text))
+        })?;
+        let value: Value = serde_json::from_str(json)?;
+        let Some(object) = value.as_object() else {
+            return Err(LLMError::ParsingError(format!(
+                "Expected a JSON object, got: {}",
+                json
+            )));
+        };
+        let required = self
+            .schema()
+            .and_then(|schema| schema["required"].as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str);
+        for property in required {
+            if !object.contains_key(property) {
+                return Err(LLMError::ParsingError(format!(
+                    "Missing required property `{}` in: {}",
+                    property, json
+                )));
+            }
+        }
+        Ok(json.to_string())
+    }
+
+    /// Deserializes a response generated with this format.
+    pub fn parse<T: DeserializeOwned>(&self, text: &str) -> Result<T, LLMError> {
+        Ok(serde_json::from_str(&self.validate(text)?)?)
+    }
+}
+
+/// The outermost JSON object or array of `text`.
+fn extract_json(text: &str) -> Option<&str> {
+    let start = text.find(['{', '['])?;
+    let close = if text[start..].starts_with('{') {
+        '}'
+    } else {
+        ']'
+    };
+    let end = text.rfind(close)?;
+    (end > start).then(|| &text[start..=end])
+}
+
 impl OpenAIFromLangchain<ResponseFormat> for async_openai::types::ResponseFormat {
     fn from_langchain(langchain: ResponseFormat) -> Self {
         match langchain {
@@ -33,3 +183,74 @@ impl OpenAIFromLangchain<ResponseFormat> for async_openai::types::ResponseFormat
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Movie {
+        title: String,
+        year: u32,
+    }
+
+    #[test]
+    fn test_validate_and_parse() {
+        let format = ResponseFormat::json_schema(
+            "movie",
+            json!({
+                "type": "object",
+                "properties": { "title": { "type": "string" }, "year": { "type": "integer" } },
+                "required": ["title", "year"]
+            }),
+        );
+
+        let text = "Sure!\n```json\n{\"title\": \"Heat\", \"year\": 1995}\n```";
+        assert_eq!(
+            format.validate(text).unwrap(),
+            r#"{"title": "Heat", "year": 1995}"#
+        );
+        let movie: Movie = format.parse(text).unwrap();
+        assert_eq!(movie.title, "Heat");
+        assert_eq!(movie.year, 1995);
+
+        assert!(matches!(
+            format.validate(r#"{"title": "Heat"}"#),
+            Err(LLMError::ParsingError(e)) if e.contains("`year`")
+        ));
+        assert!(format.validate("I don't know").is_err());
+        assert_eq!(ResponseFormat::Text.validate("hi").unwrap(), "hi");
+
+        let messages = format.with_instructions(&[Message::new_human_message("Heat?")]);
+        assert_eq!(messages[0].message_type, MessageType::SystemMessage);
+        assert!(messages[0]
+            .content
+            .contains("\"required\":[\"title\",\"year\"]"));
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_from_type() {
+        /// A movie
+        #[derive(Deserialize, schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Film {
+            title: String,
+            year: u32,
+        }
+
+        let format = ResponseFormat::from_type::<Film>();
+        let ResponseFormat::JsonSchema {
+            name, description, ..
+        } = &format
+        else {
+            panic!("Expected a JSON schema");
+        };
+        assert_eq!(name, "Film");
+        assert_eq!(description.as_deref(), Some("A movie"));
+        let schema = format.schema().unwrap();
+        assert!(schema.get("$schema").is_none());
+        assert_eq!(schema["required"], json!(["title", "year"]));
+    }
+}
@@ -1,13 +1,27 @@
 use serde_json::Value;
 use std::io::{self, Write};
 
-use crate::language_models::TokenUsage;
+use crate::{
+    language_models::{FinishReason, TokenUsage},
+    schemas::{FunctionCallResponse, FunctionDetail},
+};
 
 #[derive(Debug, Clone)]
 pub struct StreamData {
     pub value: Value,
     pub tokens: Option<TokenUsage>,
     pub content: String,
+    pub finish_reason: Option<FinishReason>,
+    pub model: Option<String>,
+    pub reasoning_content: Option<String>,
+    /// The signature of the reasoning streamed since the previous block, sent by Claude at
+    /// the end of each thinking block.
+    pub reasoning_signature: Option<String>,
+    /// A block of encrypted reasoning, sent by Claude.
+    pub reasoning_redacted: Option<String>,
+    pub logprobs: Option<Value>,
+    /// Fragments of the tool calls the model is making, see [`ToolCallAccumulator`].
+    pub tool_call_deltas: Vec<ToolCallDelta>,
 }
 
 impl StreamData {
@@ -16,9 +30,76 @@ impl StreamData {
             value,
             tokens,
             content: content.into(),
+            finish_reason: None,
+            model: None,
+            reasoning_content: None,
+            reasoning_signature: None,
+            reasoning_redacted: None,
+            logprobs: None,
+            tool_call_deltas: Vec::new(),
         }
     }
 
+    pub fn with_finish_reason(mut self, finish_reason: Option<FinishReason>) -> Self {
+        self.finish_reason = finish_reason;
+        self
+    }
+
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_reasoning_content(mut self, reasoning_content: Option<String>) -> Self {
+        self.reasoning_content = reasoning_content;
+        self
+    }
+
+    pub fn with_reasoning_signature(mut self, reasoning_signature: Option<String>) -> Self {
+        self.reasoning_signature = reasoning_signature;
+        self
+    }
+
+    pub fn with_reasoning_redacted(mut self, reasoning_redacted: Option<String>) -> Self {
+        self.reasoning_redacted = reasoning_redacted;
+        self
+    }
+
+    pub fn with_logprobs(mut self, logprobs: Option<Value>) -> Self {
+        self.logprobs = logprobs;
+        self
+    }
+
+    pub fn with_tool_call_deltas(mut self, tool_call_deltas: Vec<ToolCallDelta>) -> Self {
+        self.tool_call_deltas = tool_call_deltas;
+        self
+    }
+
+    /// The typed events carried by this chunk, in the order they happened: reasoning, text,
+    /// tool calls, then usage and the stop reason. Empty for chunks only holding metadata.
+    pub fn events(&self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        if let Some(reasoning) = self.reasoning_content.as_ref().filter(|r| !r.is_empty()) {
+            events.push(StreamEvent::ReasoningDelta(reasoning.clone()));
+        }
+        if !self.content.is_empty() {
+            events.push(StreamEvent::TextDelta(self.content.clone()));
+        }
+        events.extend(
+            self.tool_call_deltas
+                .iter()
+                .cloned()
+                .map(StreamEvent::ToolCallDelta),
+        );
+        if let Some(tokens) = &self.tokens {
+            events.push(StreamEvent::Usage(tokens.clone()));
+        }
+        if let Some(finish_reason) = &self.finish_reason {
+            events.push(StreamEvent::Stop(finish_reason.clone()));
+        }
+        events
+    }
+
     pub fn to_stdout(&self) -> io::Result<()> {
         let stdout = io::stdout();
         let mut handle = stdout.lock();
@@ -26,3 +107,183 @@ impl StreamData {
         handle.flush()
     }
 }
+
+/// A provider independent streaming event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// A piece of the answer.
+    TextDelta(String),
+    /// A piece of the reasoning of thinking models.
+    ReasoningDelta(String),
+    /// A piece of a tool call, reassembled with [`ToolCallAccumulator`].
+    ToolCallDelta(ToolCallDelta),
+    /// The token usage of the call so far. Most providers report it once at the end, others
+    /// with every chunk, in which case the last one holds the totals.
+    Usage(TokenUsage),
+    /// The model stopped generating.
+    Stop(FinishReason),
+}
+
+/// A fragment of a streamed tool call. The first fragment of each call carries its id and
+/// name, the following ones pieces of the JSON arguments. `index` is the position of the
+/// call among the tool calls of the response.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub arguments: String,
+}
+
+impl ToolCallDelta {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_arguments<S: Into<String>>(mut self, arguments: S) -> Self {
+        self.arguments = arguments.into();
+        self
+    }
+
+    /// A single fragment holding a whole call, for providers that do not split tool calls.
+    pub fn from_tool_call(index: usize, tool_call: &FunctionCallResponse) -> Self {
+        Self::new(index)
+            .with_id(tool_call.id.clone())
+            .with_name(tool_call.function.name.clone())
+            .with_arguments(tool_call.function.arguments.clone())
+    }
+}
+
+/// Reassembles [`ToolCallDelta`]s into complete tool calls.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let mut tool_calls = ToolCallAccumulator::new();
+/// while let Some(data) = stream.next().await {
+///     tool_calls.extend(&data?.tool_call_deltas);
+/// }
+/// let tool_calls: Vec<FunctionCallResponse> = tool_calls.into_tool_calls();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallAccumulator {
+    tool_calls: Vec<FunctionCallResponse>,
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, delta: &ToolCallDelta) {
+        merge_tool_call_delta(&mut self.tool_calls, delta);
+    }
+
+    pub fn extend<'a, I: IntoIterator<Item = &'a ToolCallDelta>>(&mut self, deltas: I) {
+        for delta in deltas {
+            self.push(delta);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tool_calls.is_empty()
+    }
+
+    /// The tool calls received so far. The arguments of the last one may still be incomplete
+    /// while the stream is running.
+    pub fn tool_calls(&self) -> &[FunctionCallResponse] {
+        &self.tool_calls
+    }
+
+    pub fn into_tool_calls(self) -> Vec<FunctionCallResponse> {
+        self.tool_calls
+    }
+}
+
+pub(crate) fn merge_tool_call_delta(
+    tool_calls: &mut Vec<FunctionCallResponse>,
+    delta: &ToolCallDelta,
+) {
+    while tool_calls.len() <= delta.index {
+        tool_calls.push(FunctionCallResponse {
+            id: String::new(),
+            type_field: "function".to_string(),
+            function: FunctionDetail {
+                name: String::new(),
+                arguments: String::new(),
+            },
+        });
+    }
+    let tool_call = &mut tool_calls[delta.index];
+    if let Some(id) = &delta.id {
+        tool_call.id = id.clone();
+    }
+    if let Some(name) = &delta.name {
+        tool_call.function.name.push_str(name);
+    }
+    tool_call.function.arguments.push_str(&delta.arguments);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_and_tool_call_reassembly() {
+        let chunks = [
+            StreamData::new(Value::Null, None, "")
+                .with_reasoning_content(Some("Needs weather".to_string())),
+            StreamData::new(Value::Null, None, "Checking").with_tool_call_deltas(vec![
+                ToolCallDelta::new(0)
+                    .with_id("call_1")
+                    .with_name("get_weather")
+                    .with_arguments("{\"city\":"),
+            ]),
+            StreamData::new(Value::Null, None, "").with_tool_call_deltas(vec![
+                ToolCallDelta::new(0).with_arguments("\"Lima\"}"),
+                ToolCallDelta::new(1)
+                    .with_id("call_2")
+                    .with_name("get_time")
+                    .with_arguments("{}"),
+            ]),
+            StreamData::new(Value::Null, Some(TokenUsage::new(10, 5)), "")
+                .with_finish_reason(Some(FinishReason::ToolCalls)),
+        ];
+
+        let events: Vec<StreamEvent> = chunks.iter().flat_map(StreamData::events).collect();
+        assert_eq!(events.len(), 7);
+        assert_eq!(
+            events[0],
+            StreamEvent::ReasoningDelta("Needs weather".to_string())
+        );
+        assert_eq!(events[1], StreamEvent::TextDelta("Checking".to_string()));
+        assert!(matches!(events[2], StreamEvent::ToolCallDelta(_)));
+        assert_eq!(events[5], StreamEvent::Usage(TokenUsage::new(10, 5)));
+        assert_eq!(events[6], StreamEvent::Stop(FinishReason::ToolCalls));
+
+        let mut accumulator = ToolCallAccumulator::new();
+        for event in &events {
+            if let StreamEvent::ToolCallDelta(delta) = event {
+                accumulator.push(delta);
+            }
+        }
+        let tool_calls = accumulator.into_tool_calls();
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(tool_calls[0].function.arguments, r#"{"city":"Lima"}"#);
+        assert_eq!(tool_calls[1].function.name, "get_time");
+    }
+}
@@ -1,8 +1,9 @@
-use crate::schemas::convert::{OpenAIFromLangchain, TryOpenAiFromLangchain};
+use crate::schemas::convert::{LangchainFromOpenAI, OpenAIFromLangchain, TryOpenAiFromLangchain};
 use crate::tools::Tool;
 use async_openai::types::{
-    ChatCompletionNamedToolChoice, ChatCompletionTool, ChatCompletionToolArgs,
-    ChatCompletionToolChoiceOption, ChatCompletionToolType, FunctionName, FunctionObjectArgs,
+    ChatCompletionMessageToolCall, ChatCompletionNamedToolChoice, ChatCompletionTool,
+    ChatCompletionToolArgs, ChatCompletionToolChoiceOption, ChatCompletionToolType, FunctionName,
+    FunctionObjectArgs,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -77,7 +78,7 @@ impl TryOpenAiFromLangchain<FunctionDefinition> for ChatCompletionTool {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FunctionCallResponse {
     pub id: String,
     #[serde(rename = "type")]
@@ -85,7 +86,7 @@ pub struct FunctionCallResponse {
     pub function: FunctionDetail,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FunctionDetail {
     pub name: String,
     ///this should be an string, and this should be passed to the tool, to
@@ -98,3 +99,16 @@ impl FunctionCallResponse {
         serde_json::from_str(s)
     }
 }
+
+impl LangchainFromOpenAI<ChatCompletionMessageToolCall> for FunctionCallResponse {
+    fn from_openai(openai: ChatCompletionMessageToolCall) -> Self {
+        FunctionCallResponse {
+            id: openai.id,
+            type_field: "function".to_string(),
+            function: FunctionDetail {
+                name: openai.function.name,
+                arguments: openai.function.arguments,
+            },
+        }
+    }
+}
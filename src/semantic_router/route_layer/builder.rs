@@ -45,7 +45,7 @@ pub struct RouteLayerBuilder {
     embedder: Option<Arc<dyn Embedder>>,
     routes: Vec<Router>,
     threshold: Option<f64>,
-    index: Option<Box<dyn Index>>,
+    index: Option<Box<dyn Index + Send + Sync>>,
     llm: Option<LLMChain>,
     top_k: usize,
     aggregation_method: AggregationMethod,
@@ -102,7 +102,9 @@ Tool Input:
         self
     }
 
-    pub fn index<I: Index + 'static>(mut self, index: I) -> Self {
+    /// The index must be `Send` and `Sync` for the layer to route a
+    /// [`RouterChain`](crate::chain::RouterChain).
+    pub fn index<I: Index + Send + Sync + 'static>(mut self, index: I) -> Self {
         self.index = Some(Box::new(index));
         self
     }
@@ -36,7 +36,7 @@ pub struct RouteChoise {
 
 pub struct RouteLayer {
     pub(crate) embedder: Arc<dyn Embedder>,
-    pub(crate) index: Box<dyn Index>,
+    pub(crate) index: Box<dyn Index + Send + Sync>,
     pub(crate) threshold: f64,
     pub(crate) llm: LLMChain,
     pub(crate) top_k: usize,
@@ -209,10 +209,57 @@ impl RouteLayer {
 #[cfg(test)]
 mod tests {
 
-    use crate::{embedding::openai::OpenAiEmbedder, semantic_router::RouteLayerBuilder};
+    use serde_json::json;
+
+    use crate::{
+        embedding::{openai::OpenAiEmbedder, FakeEmbedder},
+        llm::{FakeLLM, FakeResponse},
+        semantic_router::{MemoryIndex, RouteLayerBuilder},
+    };
 
     use super::*;
 
+    #[tokio::test]
+    async fn test_route_layer_offline() {
+        let llm = FakeLLM::new().with_response(FakeResponse::text(r#"{"city": "Lima"}"#));
+        let router_layer = RouteLayerBuilder::new()
+            .embedder(FakeEmbedder::new())
+            .llm(llm.clone())
+            .index(MemoryIndex::new())
+            .threshold(0.6)
+            .add_route(Router::new(
+                "captial",
+                &[
+                    "Capital of France is Paris.",
+                    "What is the captial of France?",
+                ],
+            ))
+            .add_route(
+                Router::new(
+                    "temperature",
+                    &["What is the temperature?", "Is it raining?"],
+                )
+                .with_tool_description("Gets the weather of a city"),
+            )
+            .aggregation_method(AggregationMethod::Max)
+            .build()
+            .await
+            .unwrap();
+
+        let route = router_layer
+            .call("What is the temperature in Lima?")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(route.route, "temperature");
+        assert_eq!(route.tool_input, Some(json!({"city": "Lima"})));
+        assert!(llm.last_request().unwrap()[0]
+            .content
+            .contains("Gets the weather of a city"));
+
+        assert!(router_layer.call("Tell me a joke").await.unwrap().is_none());
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_route_layer_builder() {
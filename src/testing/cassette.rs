@@ -0,0 +1,567 @@
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{ACCEPT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, HOST},
+    server::conn::http1,
+    service::service_fn,
+    Request, Response,
+};
+use hyper_util::rt::TokioIo;
+use mockito::{Matcher, Mock, ServerGuard};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{net::TcpListener, sync::Mutex, task::JoinHandle};
+
+use super::CassetteError;
+
+/// Replaces secrets in recorded exchanges.
+pub const REDACTED: &str = "<REDACTED>";
+
+/// Query parameters holding credentials, such as Gemini's `key`.
+const SECRET_PARAMS: [&str; 5] = ["key", "api_key", "apikey", "access_token", "token"];
+
+/// JSON fields holding credentials.
+const SECRET_FIELDS: [&str; 4] = ["api_key", "apikey", "access_token", "secret"];
+
+/// Suffixes of the environment variables whose values are redacted wherever they appear.
+const SECRET_ENV_SUFFIXES: [&str; 4] = ["_API_KEY", "_TOKEN", "_SECRET", "_SECRET_KEY"];
+
+/// Whether a [`CassetteServer`] talks to the real API or replays its cassette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Forward the requests to the upstream API and record the exchanges, replacing the
+    /// cassette.
+    Record,
+    /// Answer the requests from the cassette, without network access.
+    Replay,
+}
+
+impl CassetteMode {
+    /// [`CassetteMode::Record`] when `LANGCHAIN_RECORD_CASSETTES` is set to anything but `0`
+    /// or `false`, [`CassetteMode::Replay`] otherwise.
+    pub fn from_env() -> Self {
+        match std::env::var("LANGCHAIN_RECORD_CASSETTES") {
+            Ok(value) if !value.is_empty() && value != "0" && value != "false" => {
+                CassetteMode::Record
+            }
+            _ => CassetteMode::Replay,
+        }
+    }
+}
+
+/// HTTP exchanges recorded in a JSON file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CassetteError> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CassetteError> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// A request without its headers, which is where credentials usually are.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// The path and query.
+    pub path: String,
+    /// The body as JSON when it parses, as a string otherwise.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// The body as JSON for JSON responses, as a string otherwise, e.g. for server-sent
+    /// events.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub body: Value,
+}
+
+impl RecordedResponse {
+    fn is_json(&self) -> bool {
+        is_json(self.content_type.as_deref())
+    }
+
+    fn body_bytes(&self) -> String {
+        match &self.body {
+            Value::Null => String::new(),
+            Value::String(body) if !self.is_json() => body.clone(),
+            body => body.to_string(),
+        }
+    }
+}
+
+fn is_json(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| content_type.contains("json"))
+}
+
+/// A local server standing in for a provider API in tests, so that the real request and
+/// response code of a client runs without network access.
+///
+/// In [`CassetteMode::Replay`] the server answers the requests with the responses of the
+/// cassette, in recorded order. In [`CassetteMode::Record`] it forwards them to `upstream`
+/// and saves each exchange to the cassette. Request headers are never recorded, and the
+/// values of credential query parameters and JSON fields, of the secrets given with
+/// [`CassetteServer::with_secret`] and of environment variables such as `OPENAI_API_KEY`
+/// are replaced with [`REDACTED`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let cassette = CassetteServer::new("tests/cassettes/claude.json", "https://api.anthropic.com")
+///     .start()
+///     .await?;
+/// let claude = Claude::new().with_base_url(cassette.url());
+/// let answer = claude.invoke("Capital of France?").await?;
+/// cassette.assert_replayed().await;
+/// ```
+///
+/// Run the tests with `LANGCHAIN_RECORD_CASSETTES=1` and real credentials to record the
+/// cassettes again.
+///
+/// The cassettes of this crate's own tests, the `*.synthetic.json` files under
+/// `src/llm/test_data/cassettes`, are written by hand after the documented API formats
+/// rather than recorded, so their ids and token counts are made up.
+pub struct CassetteServer {
+    path: PathBuf,
+    upstream: String,
+    mode: CassetteMode,
+    secrets: Vec<String>,
+    match_bodies: bool,
+}
+
+impl CassetteServer {
+    /// A server for the cassette at `path`, recording from the API at `upstream`. The mode
+    /// comes from [`CassetteMode::from_env`].
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(path: P, upstream: S) -> Self {
+        Self {
+            path: path.into(),
+            upstream: upstream.into(),
+            mode: CassetteMode::from_env(),
+            secrets: Vec::new(),
+            match_bodies: true,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: CassetteMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Redacts `secret` wherever it appears in the recorded paths and bodies.
+    pub fn with_secret<S: Into<String>>(mut self, secret: S) -> Self {
+        self.secrets.push(secret.into());
+        self
+    }
+
+    /// Whether a replayed request must have the recorded body. Defaults to `true`, so that
+    /// changes to the payloads fail the tests.
+    pub fn with_body_matching(mut self, match_bodies: bool) -> Self {
+        self.match_bodies = match_bodies;
+        self
+    }
+
+    pub async fn start(self) -> Result<CassetteSession, CassetteError> {
+        match self.mode {
+            CassetteMode::Replay => self.replay().await,
+            CassetteMode::Record => self.record().await,
+        }
+    }
+
+    async fn replay(self) -> Result<CassetteSession, CassetteError> {
+        let cassette = Cassette::load(&self.path)?;
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for Interaction { request, response } in cassette.interactions {
+            // Query parameters may be redacted, the path is enough to match
+            let path = request.path.split('?').next().unwrap_or_default();
+            let mut mock = server
+                .mock(
+                    &request.method,
+                    Matcher::Regex(format!(r"^{}(\?.*)?$", regex::escape(path))),
+                )
+                .with_status(response.status as usize)
+                .with_body(response.body_bytes())
+                .expect(1);
+            if let Some(content_type) = &response.content_type {
+                mock = mock.with_header("content-type", content_type);
+            }
+            if self.match_bodies {
+                mock = match request.body {
+                    Value::Null => mock,
+                    Value::String(body) => mock.match_body(Matcher::Exact(body)),
+                    body => mock.match_body(Matcher::Json(body)),
+                };
+            }
+            mocks.push(mock.create_async().await);
+        }
+
+        Ok(CassetteSession {
+            url: server.url(),
+            mode: CassetteMode::Replay,
+            mocks,
+            server: Some(server),
+            proxy: None,
+        })
+    }
+
+    async fn record(self) -> Result<CassetteSession, CassetteError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let recorder = Arc::new(Recorder {
+            path: self.path,
+            upstream: self.upstream.trim_end_matches('/').to_string(),
+            redactor: Redactor::new(self.secrets),
+            cassette: Mutex::new(Cassette::default()),
+            client: reqwest::Client::new(),
+        });
+
+        let proxy = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorder = recorder.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        let recorder = recorder.clone();
+                        async move { recorder.forward(request).await }
+                    });
+                    if let Err(e) = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        log::warn!("Cassette connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(CassetteSession {
+            url,
+            mode: CassetteMode::Record,
+            mocks: Vec::new(),
+            server: None,
+            proxy: Some(proxy),
+        })
+    }
+}
+
+/// A running [`CassetteServer`], stopped when dropped.
+pub struct CassetteSession {
+    url: String,
+    mode: CassetteMode,
+    mocks: Vec<Mock>,
+    server: Option<ServerGuard>,
+    proxy: Option<JoinHandle<()>>,
+}
+
+impl CassetteSession {
+    /// The base URL to point the client at, without a trailing slash.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// Panics unless every recorded exchange was replayed. Does nothing when recording.
+    pub async fn assert_replayed(&self) {
+        for mock in &self.mocks {
+            mock.assert_async().await;
+        }
+    }
+}
+
+impl Drop for CassetteSession {
+    fn drop(&mut self) {
+        if let Some(proxy) = &self.proxy {
+            proxy.abort();
+        }
+    }
+}
+
+struct Recorder {
+    path: PathBuf,
+    upstream: String,
+    redactor: Redactor,
+    cassette: Mutex<Cassette>,
+    client: reqwest::Client,
+}
+
+impl Recorder {
+    async fn forward(
+        &self,
+        request: Request<Incoming>,
+    ) -> Result<Response<Full<Bytes>>, Infallible> {
+        Ok(self.exchange(request).await.unwrap_or_else(|e| {
+            log::warn!("Cassette recording failed: {}", e);
+            let mut response = Response::new(Full::new(Bytes::from(e.to_string())));
+            *response.status_mut() = hyper::StatusCode::BAD_GATEWAY;
+            response
+        }))
+    }
+
+    async fn exchange(
+        &self,
+        request: Request<Incoming>,
+    ) -> Result<Response<Full<Bytes>>, CassetteError> {
+        let (parts, body) = request.into_parts();
+        let body = body.collect().await?.to_bytes();
+        let path = parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+
+        let mut headers = parts.headers.clone();
+        for header in [HOST, CONTENT_LENGTH, ACCEPT_ENCODING] {
+            headers.remove(header);
+        }
+        let upstream = self
+            .client
+            .request(parts.method.clone(), format!("{}{}", self.upstream, path))
+            .headers(headers)
+            .body(body.clone())
+            .send()
+            .await?;
+        let status = upstream.status();
+        let content_type = upstream
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .map(String::from);
+        let response_body = upstream.bytes().await?;
+
+        let interaction = Interaction {
+            request: RecordedRequest {
+                method: parts.method.to_string(),
+                path: self.redactor.path(path),
+                body: self.redactor.body(&body, true),
+            },
+            response: RecordedResponse {
+                status: status.as_u16(),
+                body: self
+                    .redactor
+                    .body(&response_body, is_json(content_type.as_deref())),
+                content_type: content_type.clone(),
+            },
+        };
+        {
+            let mut cassette = self.cassette.lock().await;
+            cassette.interactions.push(interaction);
+            cassette.save(&self.path)?;
+        }
+
+        let mut response = Response::new(Full::new(response_body));
+        *response.status_mut() = status;
+        if let Some(content_type) = content_type.and_then(|value| value.parse().ok()) {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        Ok(response)
+    }
+}
+
+struct Redactor {
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    fn new(mut secrets: Vec<String>) -> Self {
+        secrets.extend(std::env::vars().filter_map(|(name, value)| {
+            let name = name.to_uppercase();
+            let is_secret = SECRET_ENV_SUFFIXES
+                .iter()
+                .any(|suffix| name.ends_with(suffix));
+            // Short values would redact unrelated text
+            (is_secret && value.len() >= 8).then_some(value)
+        }));
+        secrets.retain(|secret| !secret.is_empty());
+        Self { secrets }
+    }
+
+    fn text(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+    }
+
+    fn path(&self, path: &str) -> String {
+        let path = self.text(path);
+        let Some((path, query)) = path.split_once('?') else {
+            return path;
+        };
+        let query = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if SECRET_PARAMS.contains(&name.to_lowercase().as_str()) => {
+                    format!("{}={}", name, REDACTED)
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        format!("{}?{}", path, query)
+    }
+
+    fn body(&self, body: &[u8], json: bool) -> Value {
+        let text = self.text(&String::from_utf8_lossy(body));
+        if json {
+            if let Ok(mut value) = serde_json::from_str::<Value>(&text) {
+                redact_fields(&mut value);
+                return value;
+            }
+        }
+        if text.is_empty() {
+            Value::Null
+        } else {
+            Value::String(text)
+        }
+    }
+}
+
+fn redact_fields(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if value.is_string() && SECRET_FIELDS.contains(&name.to_lowercase().as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_fields(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_fields),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_redactor() {
+        let redactor = Redactor::new(vec!["sk-live-123456".to_string()]);
+
+        assert_eq!(
+            redactor.path("/v1beta/models/gemini:generateContent?alt=sse&key=abc"),
+            format!(
+                "/v1beta/models/gemini:generateContent?alt=sse&key={}",
+                REDACTED
+            )
+        );
+        assert_eq!(
+            redactor.body(
+                br#"{"api_key":"abc","messages":[{"content":"my key is sk-live-123456"}]}"#,
+                true
+            ),
+            json!({"api_key": REDACTED, "messages": [{"content": format!("my key is {}", REDACTED)}]})
+        );
+        assert_eq!(
+            redactor.body(b"data: {\"token\":\"Hello\"}\n\n", false),
+            json!("data: {\"token\":\"Hello\"}\n\n")
+        );
+        assert_eq!(redactor.body(b"", true), Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let mut upstream = mockito::Server::new_async().await;
+        upstream
+            .mock("POST", "/v1/chat?key=secret-key")
+            .match_header("authorization", "Bearer sk-live-123456")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"answer":"Paris"}"#)
+            .create_async()
+            .await;
+        upstream
+            .mock("GET", "/v1/stream")
+            .with_header("content-type", "text/event-stream")
+            .with_body("data: {\"delta\":\"Par\"}\n\ndata: {\"delta\":\"is\"}\n\n")
+            .create_async()
+            .await;
+        let path =
+            std::env::temp_dir().join(format!("langchain-cassette-{}.json", uuid::Uuid::new_v4()));
+
+        let exchange = |url: String| async move {
+            let client = reqwest::Client::new();
+            let answer: Value = client
+                .post(format!("{}/v1/chat?key=secret-key", url))
+                .bearer_auth("sk-live-123456")
+                .json(&json!({"question": "Capital of France?"}))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            let events = client
+                .get(format!("{}/v1/stream", url))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+            (answer, events)
+        };
+
+        let recording = CassetteServer::new(&path, upstream.url())
+            .with_mode(CassetteMode::Record)
+            .with_secret("sk-live-123456")
+            .start()
+            .await
+            .unwrap();
+        let recorded = exchange(recording.url()).await;
+        drop(recording);
+
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(cassette.interactions.len(), 2);
+        assert_eq!(
+            cassette.interactions[0].request.path,
+            format!("/v1/chat?key={}", REDACTED)
+        );
+        assert!(!std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("sk-live-123456"));
+
+        let replaying = CassetteServer::new(&path, upstream.url())
+            .with_mode(CassetteMode::Replay)
+            .start()
+            .await
+            .unwrap();
+        let replayed = exchange(replaying.url()).await;
+        replaying.assert_replayed().await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recorded, replayed);
+        assert_eq!(replayed.0, json!({"answer": "Paris"}));
+    }
+}
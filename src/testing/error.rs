@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CassetteError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid cassette: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error("Upstream request failed: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] hyper::Error),
+}
@@ -0,0 +1,5 @@
+mod cassette;
+pub use cassette::*;
+
+mod error;
+pub use error::*;